@@ -0,0 +1,340 @@
+//! Generation of the firecracker models and endpoints from the pinned
+//! `firecracker.yaml` swagger file, behind the `codegen` feature.
+//!
+//! `firecracker.yaml` is the swagger file of firecracker v1.7.0, vendored as
+//! is from `src/api_server/swagger/firecracker.yaml` of the release. Replace
+//! it with the file of another release to move the pin.
+//!
+//! The output lands in `$OUT_DIR/firecracker_api.rs` and is included by
+//! `models::generated`. Every definition gets the module and type name of
+//! the hand-written model it corresponds to, so that one can stand in for
+//! the other. Drift between the spec and the hand-written models is checked
+//! by `tests/openapi_drift` whether the feature is on or not.
+
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::PathBuf};
+
+use serde_yaml::{Mapping, Value};
+
+const SPEC: &str = "firecracker.yaml";
+
+/// Module and type name of every definition of the spec, after the
+/// hand-written models
+const DEFINITIONS: &[(&str, &str, &str)] = &[
+    ("Balloon", "balloon", "Balloon"),
+    ("BalloonUpdate", "balloon_update", "BalloonUpdate"),
+    ("BalloonStats", "balloon_stats", "BalloonStatistics"),
+    (
+        "BalloonStatsUpdate",
+        "balloon_stats_update",
+        "BalloonStatsUpdate",
+    ),
+    ("BootSource", "boot_source", "BootSource"),
+    ("CpuTemplate", "cpu_template", "CPUTemplateString"),
+    ("CpuConfig", "cpu_template", "CPUConfig"),
+    ("Drive", "drive", "Drive"),
+    ("EntropyDevice", "entropy_device", "EntropyDevice"),
+    ("Error", "error", "InternalError"),
+    (
+        "FirecrackerVersion",
+        "firecracker_version",
+        "FirecrackerVersion",
+    ),
+    (
+        "FullVmConfiguration",
+        "full_vm_configuration",
+        "FullVmConfiguration",
+    ),
+    (
+        "InstanceActionInfo",
+        "instance_action_info",
+        "InstanceActionInfo",
+    ),
+    ("InstanceInfo", "instance_info", "InstanceInfo"),
+    ("Logger", "logger", "Logger"),
+    (
+        "MachineConfiguration",
+        "machine_configuration",
+        "MachineConfiguration",
+    ),
+    ("MemoryBackend", "memory_backend", "MemoryBackend"),
+    ("Metrics", "metrics", "Metrics"),
+    ("MmdsConfig", "mmds_config", "MmdsConfig"),
+    ("MmdsContentsObject", "mmds_config", "MmdsContents"),
+    ("NetworkInterface", "network_interface", "NetworkInterface"),
+    ("PartialDrive", "partial_drive", "PartialDrive"),
+    (
+        "PartialNetworkInterface",
+        "partial_network_interface",
+        "PartialNetworkInterface",
+    ),
+    ("RateLimiter", "rate_limiter", "RateLimiter"),
+    (
+        "SnapshotCreateParams",
+        "snapshot_create_params",
+        "SnapshotCreateParams",
+    ),
+    (
+        "SnapshotLoadParams",
+        "snapshot_load_params",
+        "SnapshotLoadParams",
+    ),
+    ("TokenBucket", "token_bucket", "TokenBucket"),
+    ("Vm", "vm", "Vm"),
+    ("Vsock", "vsock", "Vsock"),
+];
+
+/// Inline enums not named after their property, by definition and property
+const ENUMS: &[(&str, &str, &str)] = &[("Logger", "level", "LogLevel")];
+
+/// Variants not named after their value, by enum and value
+const VARIANTS: &[(&str, &str, &str)] = &[
+    ("CacheType", "Writeback", "WriteBack"),
+    ("HugePages", "2M", "Hugetlbfs2M"),
+];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if env::var_os("CARGO_FEATURE_CODEGEN").is_none() {
+        return;
+    }
+    println!("cargo:rerun-if-changed={SPEC}");
+
+    let spec = fs::read_to_string(SPEC).unwrap_or_else(|e| panic!("Fail to read {SPEC}: {e}"));
+    let spec: Value =
+        serde_yaml::from_str(&spec).unwrap_or_else(|e| panic!("Fail to parse {SPEC}: {e}"));
+
+    let mut out = format!(
+        "// Generated by build.rs from {SPEC} (firecracker v{}), do not edit\n",
+        str_of(&spec["info"]["version"])
+    );
+    generate_models(&mut out, mapping(&spec["definitions"]));
+    generate_endpoints(&mut out, mapping(&spec["paths"]));
+
+    let path = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("firecracker_api.rs");
+    fs::write(&path, out).unwrap_or_else(|e| panic!("Fail to write {}: {e}", path.display()));
+}
+
+fn mapping(value: &Value) -> &Mapping {
+    value
+        .as_mapping()
+        .unwrap_or_else(|| panic!("{SPEC}: expected a mapping, got {value:?}"))
+}
+
+fn str_of(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => panic!("{SPEC}: expected a scalar, got {value:?}"),
+    }
+}
+
+/// Module and type name of definition `def`
+fn names(def: &str) -> (&'static str, &'static str) {
+    DEFINITIONS
+        .iter()
+        .find(|(d, _, _)| *d == def)
+        .map(|(_, module, name)| (*module, *name))
+        .unwrap_or_else(|| panic!("Definition {def} of {SPEC} has no name in build.rs"))
+}
+
+/// `snake_case` to `CamelCase`, also turning other separators into word breaks
+fn camel(s: &str) -> String {
+    s.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+/// `camelCase` (with acronyms) to `SCREAMING_SNAKE_CASE`
+fn screaming(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 && c.is_ascii_uppercase() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if !prev.is_ascii_uppercase() || next_lower {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_uppercase());
+    }
+    out
+}
+
+/// `description` of a schema as doc comment lines, wrapped
+fn doc(out: &mut String, indent: &str, schema: &Value) {
+    let Some(description) = schema.get("description") else {
+        return;
+    };
+    let mut line = String::new();
+    for word in str_of(description).split_whitespace() {
+        if !line.is_empty() && line.len() + word.len() > 80 {
+            writeln!(out, "{indent}///{line}").unwrap();
+            line.clear();
+        }
+        line.push(' ');
+        line.push_str(word);
+    }
+    writeln!(out, "{indent}///{line}").unwrap();
+}
+
+fn generate_models(out: &mut String, definitions: &Mapping) {
+    let mut modules: BTreeMap<&str, String> = BTreeMap::new();
+    for (def, schema) in definitions {
+        let def = str_of(def);
+        let (module, name) = names(&def);
+        let items = modules.entry(module).or_default();
+        if schema.get("enum").is_some() {
+            generate_enum(items, name, schema);
+        } else if let Some(properties) = schema.get("properties") {
+            generate_struct(items, &def, name, schema, mapping(properties));
+        } else {
+            doc(items, "    ", schema);
+            writeln!(
+                items,
+                "    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n    \
+                 pub struct {name}(pub serde_json::Value);\n"
+            )
+            .unwrap();
+        }
+    }
+    for (module, items) in modules {
+        writeln!(
+            out,
+            "\npub mod {module} {{\n    use serde::{{Deserialize, Serialize}};\n\n{}}}",
+            items.trim_end().to_string() + "\n"
+        )
+        .unwrap();
+    }
+}
+
+fn generate_enum(out: &mut String, name: &str, schema: &Value) {
+    doc(out, "    ", schema);
+    writeln!(
+        out,
+        "    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]\n    \
+         pub enum {name} {{"
+    )
+    .unwrap();
+    for value in schema["enum"].as_sequence().unwrap() {
+        let value = str_of(value);
+        let variant = VARIANTS
+            .iter()
+            .find(|(e, v, _)| *e == name && *v == value)
+            .map_or_else(|| camel(&value), |(_, _, variant)| variant.to_string());
+        writeln!(
+            out,
+            "        #[serde(rename = {value:?})]\n        {variant},"
+        )
+        .unwrap();
+    }
+    writeln!(out, "    }}\n").unwrap();
+}
+
+fn generate_struct(out: &mut String, def: &str, name: &str, schema: &Value, properties: &Mapping) {
+    let required: Vec<String> = schema
+        .get("required")
+        .and_then(Value::as_sequence)
+        .map(|required| required.iter().map(str_of).collect())
+        .unwrap_or_default();
+    let mut enums = String::new();
+    doc(out, "    ", schema);
+    writeln!(
+        out,
+        "    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n    \
+         #[cfg_attr(feature = \"strict-decode\", serde(deny_unknown_fields))]\n    \
+         pub struct {name} {{"
+    )
+    .unwrap();
+    for (property, property_schema) in properties {
+        let property = str_of(property);
+        let mut ty = rust_type(&mut enums, def, &property, property_schema);
+        let skip = if required.contains(&property) {
+            ""
+        } else {
+            ty = format!("Option<{ty}>");
+            ", skip_serializing_if = \"Option::is_none\""
+        };
+        doc(out, "        ", property_schema);
+        writeln!(
+            out,
+            "        #[serde(rename = {property:?}{skip})]\n        pub {}: {ty},",
+            property.replace('-', "_")
+        )
+        .unwrap();
+    }
+    writeln!(out, "    }}\n").unwrap();
+    out.push_str(&enums);
+}
+
+/// Rust type of a property, generating the enum of inline enums into `enums`
+fn rust_type(enums: &mut String, def: &str, property: &str, schema: &Value) -> String {
+    if let Some(reference) = schema.get("$ref") {
+        let reference = str_of(reference);
+        let def = reference
+            .strip_prefix("#/definitions/")
+            .unwrap_or_else(|| panic!("{SPEC}: unsupported reference {reference}"));
+        let (module, name) = names(def);
+        return format!("super::{module}::{name}");
+    }
+    match schema.get("type").map(str_of).as_deref() {
+        Some("string") if schema.get("enum").is_some() => {
+            let name = ENUMS
+                .iter()
+                .find(|(d, p, _)| *d == def && *p == property)
+                .map_or_else(|| camel(property), |(_, _, name)| name.to_string());
+            generate_enum(enums, &name, schema);
+            name
+        }
+        Some("string") => "String".into(),
+        Some("integer") if schema.get("format").map(str_of).as_deref() == Some("int32") => {
+            "i32".into()
+        }
+        Some("integer") => "i64".into(),
+        Some("number") => "f64".into(),
+        Some("boolean") => "bool".into(),
+        Some("object") => "serde_json::Value".into(),
+        Some("array") => format!("Vec<{}>", rust_type(enums, def, property, &schema["items"])),
+        ty => panic!("{SPEC}: unsupported type {ty:?} of {def}.{property}"),
+    }
+}
+
+fn generate_endpoints(out: &mut String, paths: &Mapping) {
+    let mut all = Vec::new();
+    out.push_str("\npub mod endpoints {\n    use super::Endpoint;\n\n");
+    for (path, operations) in paths {
+        let path = str_of(path);
+        for (method, operation) in mapping(operations) {
+            let method = str_of(method).to_ascii_uppercase();
+            let operation_id = str_of(&operation["operationId"]);
+            let constant = screaming(&operation_id);
+            if let Some(summary) = operation.get("summary") {
+                writeln!(out, "    /// {}", str_of(summary)).unwrap();
+            }
+            writeln!(
+                out,
+                "    pub const {constant}: Endpoint = Endpoint {{\n        \
+                 operation_id: {operation_id:?},\n        \
+                 method: {method:?},\n        \
+                 path: {path:?},\n    }};\n"
+            )
+            .unwrap();
+            all.push(constant);
+        }
+    }
+    writeln!(
+        out,
+        "    /// Every endpoint of the spec, in order\n    pub const ALL: &[Endpoint] = &["
+    )
+    .unwrap();
+    for constant in all {
+        writeln!(out, "        {constant},").unwrap();
+    }
+    out.push_str("    ];\n}\n");
+}
@@ -1,7 +1,7 @@
 use crate::{
     micro_http::{Http, HttpMethod},
-    ser::Serde,
-    RtckResult,
+    ser::{Empty, Serde},
+    RtckError, RtckErrorClass, RtckResult,
 };
 
 pub struct Command {
@@ -10,11 +10,45 @@ pub struct Command {
     pub(crate) data: Box<dyn Serde>,
 }
 
+impl Command {
+    /// Command to an arbitrary endpoint. `path` must be absolute and fit
+    /// in the request line.
+    pub(crate) fn raw(
+        method: HttpMethod,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> RtckResult<Self> {
+        if !path.starts_with('/') {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Request path {path:?} must start with '/'"),
+            ));
+        }
+        if path
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || c.is_ascii_control())
+        {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Request path {path:?} contains whitespace or control characters"),
+            ));
+        }
+        Ok(Self {
+            method,
+            url: path.into(),
+            data: match body {
+                Some(body) => Box::new(body),
+                None => Box::new(Empty {}),
+            },
+        })
+    }
+}
+
 impl Http for Command {
     fn encode(&self) -> RtckResult<String> {
         let s = self.data.encode()?;
         Ok(format!(
-            "{} {} HTTP/1.1\r\nContent-Length: {}\r\nContent-Type: application/json\r\nAccept: application/json\r\n\r\n{}",
+            "{} {} HTTP/1.1\r\nContent-Length: {}\r\nContent-Type: application/json\r\nAccept: application/json\r\nConnection: keep-alive\r\n\r\n{}",
             self.method.as_str(),
             self.url,
             s.len(),
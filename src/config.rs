@@ -1,11 +1,329 @@
-use std::path::PathBuf;
+use std::{
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
 
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{models::*, RtckError, RtckErrorClass, RtckResult};
+use crate::{
+    micro_http::{ApiTrace, Redact, RedactionPolicy},
+    models::*,
+    network::{Bridge, TapDevice, TapSubnet},
+    retry::RetryPolicy,
+    RtckError, RtckErrorClass, RtckResult,
+};
+
+fn into_result(errors: Vec<RtckError>) -> Result<(), Vec<RtckError>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Fail-fast view of collected errors, which are never empty
+fn first_error(mut errors: Vec<RtckError>) -> RtckError {
+    errors.swap_remove(0)
+}
+
+/// Problem of a configuration entry, see `ConfigCheck`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// Entry at fault, e.g. `jailer_config.exec_file`
+    pub field: String,
+    /// What the entry should hold
+    pub expected: String,
+    /// What is wrong with the entry
+    pub hint: String,
+}
+
+impl std::fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} (expecting {})",
+            self.field, self.hint, self.expected
+        )
+    }
+}
+
+/// Every problem found checking a configuration, rather than the first one
+/// only, so that a configuration is fixed in a single round.
+///
+/// ```
+/// use rustcracker::config::GlobalConfig;
+///
+/// let check = GlobalConfig::default().check();
+/// assert!(check.issues().iter().any(|issue| issue.field == "frck_bin"));
+/// assert!(check.issues().iter().any(|issue| issue.field == "socket_path"));
+/// ```
+#[derive(Debug, Default)]
+pub struct ConfigCheck {
+    issues: Vec<ConfigIssue>,
+    errors: Vec<RtckError>,
+}
+
+impl ConfigCheck {
+    /// Record `error`, found checking `field`
+    fn push(&mut self, field: &str, expected: &str, error: RtckError) {
+        self.issues.push(ConfigIssue {
+            field: field.into(),
+            expected: expected.into(),
+            hint: error.to_string(),
+        });
+        self.errors.push(error);
+    }
+
+    /// Record the error of `res`, if any
+    fn collect(&mut self, field: &str, expected: &str, res: RtckResult<()>) {
+        if let Err(e) = res {
+            self.push(field, expected, e);
+        }
+    }
+
+    /// Record the issues of `check`, on entries nested in `prefix`
+    fn nest(&mut self, prefix: &str, check: ConfigCheck) {
+        self.issues
+            .extend(check.issues.into_iter().map(|issue| ConfigIssue {
+                field: format!("{prefix}.{}", issue.field),
+                ..issue
+            }));
+        self.errors.extend(check.errors);
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn issues(&self) -> &[ConfigIssue] {
+        &self.issues
+    }
+
+    /// The error of each issue, in the order found
+    pub fn into_errors(self) -> Result<(), Vec<RtckError>> {
+        into_result(self.errors)
+    }
+
+    /// A single error listing every issue
+    pub fn into_result(mut self) -> RtckResult<()> {
+        match self.issues.len() {
+            0 => Ok(()),
+            1 => Err(self.errors.swap_remove(0)),
+            n => Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "{n} configuration issues: {}",
+                    self.issues
+                        .iter()
+                        .map(|issue| issue.to_string())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ),
+            )),
+        }
+    }
+}
+
+/// Ids seen more than once among `ids`, sorted
+fn duplicate_ids<'a>(ids: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut seen = std::collections::HashSet::new();
+    let mut duplicates: Vec<&str> = ids.filter(|id| !seen.insert(*id)).collect();
+    duplicates.sort_unstable();
+    duplicates.dedup();
+    duplicates
+}
+
+/// Device firecracker runs the machines with
+pub const KVM_PATH: &str = "/dev/kvm";
+
+/// Whether the effective user may access `path` with `mode`, e.g. `libc::X_OK`
+fn accessible(path: &Path, mode: libc::c_int) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), mode, libc::AT_EACCESS) == 0 }
+}
+
+/// Resolve `path`, configured as `field`, to an executable file, `what`
+/// naming it in the error. Bare names such as `firecracker` are looked up
+/// in the `PATH`, like a shell would.
+pub(crate) fn resolve_executable(what: &str, field: &str, path: &str) -> RtckResult<PathBuf> {
+    let error = |reason: &str| {
+        Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("{what} {reason}, {field} = {path:?}"),
+        ))
+    };
+    if !path.is_empty() && !path.contains('/') {
+        let dirs = std::env::var_os("PATH").unwrap_or_default();
+        return match std::env::split_paths(&dirs)
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.is_file() && accessible(candidate, libc::X_OK))
+        {
+            Some(resolved) => Ok(resolved),
+            None => error("not found in PATH"),
+        };
+    }
+    match std::fs::metadata(path) {
+        Err(_) => error("missing"),
+        Ok(metadata) if !metadata.is_file() => error("is not a file"),
+        Ok(_) if !accessible(Path::new(path), libc::X_OK) => error("is not executable"),
+        Ok(_) => Ok(PathBuf::from(path)),
+    }
+}
+
+/// Check that `path`, configured as `field`, is an executable file,
+/// `what` naming it in the error
+fn check_executable(what: &str, field: &str, path: &str) -> RtckResult<()> {
+    resolve_executable(what, field, path).map(|_| ())
+}
+
+/// Check that the directory of the socket `path`, configured as `field`,
+/// exists and is writable, creating it if `create_dirs`
+fn check_socket_dir(field: &str, path: &str, create_dirs: bool) -> RtckResult<()> {
+    let dir = Path::new(path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if !dir.exists() && create_dirs {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Fail to create socket directory {dir:?} of {field} = {path:?}, {e}"),
+            )
+            .with_source(e)
+        })?;
+    }
+    let reason = if !dir.is_dir() {
+        "missing"
+    } else if !accessible(dir, libc::W_OK | libc::X_OK) {
+        "not writable"
+    } else {
+        return Ok(());
+    };
+    Err(RtckError::new(
+        RtckErrorClass::ConfigError,
+        format!("Socket directory {dir:?} {reason}, {field} = {path:?}"),
+    ))
+}
+
+/// Check that the KVM device at `path` may be opened for reading and writing,
+/// by the user `owner` (uid, gid) if given and the effective user otherwise
+fn check_kvm(path: &Path, owner: Option<(u32, u32)>) -> RtckResult<()> {
+    use std::os::unix::fs::MetadataExt;
+    let error = |reason: String| {
+        Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("KVM device {path:?} {reason}"),
+        ))
+    };
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return error("missing, is KVM enabled on the host?".into());
+    };
+    let allowed = match owner {
+        None => accessible(path, libc::R_OK | libc::W_OK),
+        Some((0, _)) => true,
+        Some((uid, _)) if uid == metadata.uid() => metadata.mode() & 0o600 == 0o600,
+        Some((_, gid)) if gid == metadata.gid() => metadata.mode() & 0o060 == 0o060,
+        Some(_) => metadata.mode() & 0o006 == 0o006,
+    };
+    if allowed {
+        return Ok(());
+    }
+    match owner {
+        Some((uid, gid)) => error(format!(
+            "not accessible by the jailer uid {uid} and gid {gid}"
+        )),
+        None => error("not accessible, is the user in the kvm group?".into()),
+    }
+}
+
+/// Format of a config file, told by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> RtckResult<Self> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Ok(Self::Json),
+            Some("toml") => Ok(Self::Toml),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            _ => Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Unknown config format of {path:?}, expecting .json, .toml or .yaml"),
+            )),
+        }
+    }
+
+    /// Parse `content`, errors telling the line and column at fault
+    pub fn parse<T: DeserializeOwned>(self, content: &str) -> RtckResult<T> {
+        match self {
+            Self::Json => Ok(serde_json::from_str(content)?),
+            Self::Toml => Ok(toml::from_str(content)?),
+            Self::Yaml => serde_yaml::from_str(content).map_err(|e| {
+                RtckError::new(RtckErrorClass::SerdeError, e.to_string()).with_source(e)
+            }),
+        }
+    }
+
+    pub fn render<T: Serialize>(self, value: &T) -> RtckResult<String> {
+        let serde_error =
+            |e: &dyn std::fmt::Display| RtckError::new(RtckErrorClass::SerdeError, e.to_string());
+        match self {
+            Self::Json => Ok(serde_json::to_string_pretty(value)?),
+            Self::Toml => toml::to_string_pretty(value).map_err(|e| serde_error(&e)),
+            Self::Yaml => serde_yaml::to_string(value).map_err(|e| serde_error(&e)),
+        }
+    }
+}
+
+/// Load a config in `format` from `path`
+fn load_config<T: DeserializeOwned, P: AsRef<Path>>(
+    path: P,
+    format: ConfigFormat,
+) -> RtckResult<T> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)?;
+    format.parse(&content).map_err(|e| {
+        RtckError::new(e.class().clone(), format!("Invalid config {path:?}: {e}")).with_source(e)
+    })
+}
+
+/// Save a config to `path`, in the format told by its extension
+fn save_config<T: Serialize, P: AsRef<Path>>(config: &T, path: P) -> RtckResult<()> {
+    let path = path.as_ref();
+    let content = ConfigFormat::from_path(path)?.render(config)?;
+    Ok(std::fs::write(path, content)?)
+}
+
+/// CNI network a machine joins when created, see `network::cni::CniNetwork`
+#[cfg(feature = "cni")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(default)]
+pub struct CniConfig {
+    /// File or directory of the network configuration, default to
+    /// `/etc/cni/net.d`
+    pub config_dir: Option<PathBuf>,
+
+    /// Directory of the plugins, default to `/opt/cni/bin`
+    pub bin_dir: Option<PathBuf>,
+
+    /// Name of the network in the configuration
+    pub network_name: String,
+
+    /// Interface the plugins create in the namespace, default to `eth0`
+    pub if_name: Option<String>,
+}
 
 /// Firecracker configuration
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(default)]
 pub struct FirecrackerConfig {
     // logger defines the logger for microVM.
     pub logger: Option<logger::Logger>,
@@ -46,7 +364,7 @@ pub struct FirecrackerConfig {
     pub net_ns: Option<String>,
 
     // mmds_address is IPv4 address used by guest applications when issuing requests to MMDS.
-    // It is possible to use a valid IPv4 link-local address (169.254.0.0/16).
+    // It must be link-local (169.254.0.0/16), and every network interface forwards to it.
     // If not provided, the default address (169.254.169.254) will be used.
     pub mmds_address: Option<std::net::Ipv4Addr>,
 
@@ -57,20 +375,239 @@ pub struct FirecrackerConfig {
     pub entropy_device: Option<entropy_device::EntropyDevice>,
 
     // init_metadata is initial metadata that is to be assigned to the machine
-    pub init_metadata: Option<String>,
+    pub init_metadata: Option<mmds_config::MmdsContents>,
+
+    // auto_cid makes rustcracker allocate the guest CIDs of the vsock devices,
+    // unique among the machines of the process, instead of using the configured
+    // ones. Default to false.
+    pub auto_cid: bool,
+
+    // disable_validation skips every check of the configuration, including
+    // those of the global config, e.g. to run against a mocked firecracker
+    // without real kernel or rootfs files. Default to false.
+    pub disable_validation: bool,
+
+    // cni sets the network of the machine up with CNI plugins when creating
+    // it, in the namespace net_ns, created after the vmid if not provided.
+    // Firecracker must be jailed to run in the namespace.
+    #[cfg(feature = "cni")]
+    pub cni: Option<CniConfig>,
 }
 
 impl FirecrackerConfig {
+    /// Load the config from a JSON, TOML or YAML file, by its extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> RtckResult<Self> {
+        let format = ConfigFormat::from_path(&path)?;
+        load_config(path, format)
+    }
+
+    /// Save the config to a JSON, TOML or YAML file, by its extension
+    pub fn to_path<P: AsRef<Path>>(&self, path: P) -> RtckResult<()> {
+        save_config(self, path)
+    }
+
+    pub fn with_disable_validation(mut self, disable_validation: bool) -> Self {
+        self.disable_validation = disable_validation;
+        self
+    }
+
+    pub fn with_auto_cid(mut self, auto_cid: bool) -> Self {
+        self.auto_cid = auto_cid;
+        self
+    }
+
+    pub fn with_logger(mut self, logger: logger::Logger) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    /// Add the network interface `iface_id` on the tap device `host_dev_name`
+    /// with the static IP configuration `static_ip`, turned into the `ip=`
+    /// kernel parameter by `effective_boot_source`, or nothing on error. The
+    /// boot source must be set and its arguments not configure the network
+    /// otherwise. Interfaces show up in the guest in the order they are
+    /// configured, as `eth0`, `eth1`...
+    pub fn static_ip<S: Into<String>, H: Into<String>>(
+        mut self,
+        iface_id: S,
+        host_dev_name: H,
+        static_ip: network_interface::StaticIpConfig,
+    ) -> RtckResult<Self> {
+        let network_interface = network_interface::NetworkInterface {
+            guest_mac: None,
+            host_dev_name: host_dev_name.into(),
+            iface_id: iface_id.into(),
+            num_queues: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            static_ip: Some(static_ip),
+        };
+        self.add_network_interface(network_interface)?;
+        Ok(self)
+    }
+
+    /// Add `network_interface`, or nothing on error. With a static IP
+    /// configuration, the boot source must be set and `effective_boot_source`
+    /// able to merge it.
+    pub(crate) fn add_network_interface(
+        &mut self,
+        network_interface: network_interface::NetworkInterface,
+    ) -> RtckResult<()> {
+        if network_interface.static_ip.is_some() && self.boot_source.is_none() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Kernel ip= parameter set without boot source",
+            ));
+        }
+        let network_interfaces = self.network_interfaces.get_or_insert_with(Vec::new);
+        if network_interfaces
+            .iter()
+            .any(|configured| configured.iface_id == network_interface.iface_id)
+        {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Network interface {:?} already configured",
+                    network_interface.iface_id
+                ),
+            ));
+        }
+        network_interface.validate()?;
+
+        let static_ip = network_interface.static_ip.is_some();
+        network_interfaces.push(network_interface);
+        if static_ip {
+            if let Err(e) = self.effective_boot_source() {
+                self.network_interfaces.as_mut().map(Vec::pop);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a tap device for the network interface `iface_id`, owned by
+    /// the effective user, and add the interface with `static_ip`. In the
+    /// `subnet`, e.g. `172.16.0.0/30`, the tap takes the first address,
+    /// also the gateway of the guest, and the guest the second one. The tap
+    /// is named after the vmid, generated if missing, and `iface_id`, and
+    /// deleted when the returned device is dropped.
+    pub fn auto_tap<S: Into<String>>(
+        mut self,
+        iface_id: S,
+        subnet: &str,
+    ) -> RtckResult<(Self, TapDevice)> {
+        let iface_id = iface_id.into();
+        let subnet: TapSubnet = subnet.parse()?;
+        let name = self.tap_name(&iface_id);
+
+        let static_ip = network_interface::StaticIpConfig {
+            address: subnet.guest.into(),
+            netmask: subnet.prefix_len,
+            gateway: Some(subnet.host.into()),
+            nameservers: Vec::new(),
+            hostname: None,
+        };
+        let config = self.static_ip(iface_id, name.clone(), static_ip)?;
+
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let tap = TapDevice::create(name, uid, gid)?;
+        tap.add_address(subnet.host.into(), subnet.prefix_len)?;
+        Ok((config, tap))
+    }
+
+    /// Create a tap device for the network interface `iface_id`, owned by
+    /// the effective user, attach it to `bridge`, and add the interface with
+    /// `static_ip`: the guest takes `guest_ip` in the subnet of the bridge,
+    /// whose address is the gateway. Pair with `network::Nat` for outbound
+    /// connectivity. The tap is deleted when the returned device is dropped.
+    pub fn bridged_network<S: Into<String>>(
+        mut self,
+        iface_id: S,
+        bridge: &Bridge,
+        guest_ip: Ipv4Addr,
+    ) -> RtckResult<(Self, TapDevice)> {
+        if !bridge.contains(guest_ip) || guest_ip == bridge.address() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Guest address {guest_ip} is not a free address in the subnet {}/{} of bridge {}",
+                    bridge.address(),
+                    bridge.prefix_len(),
+                    bridge.name()
+                ),
+            ));
+        }
+        let iface_id = iface_id.into();
+        let name = self.tap_name(&iface_id);
+
+        let static_ip = network_interface::StaticIpConfig {
+            address: guest_ip.into(),
+            netmask: bridge.prefix_len(),
+            gateway: Some(bridge.address().into()),
+            nameservers: Vec::new(),
+            hostname: None,
+        };
+        let config = self.static_ip(iface_id, name.clone(), static_ip)?;
+
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let tap = TapDevice::create(name, uid, gid)?;
+        tap.attach_to_bridge(bridge.name())?;
+        Ok((config, tap))
+    }
+
+    /// Name of the tap device of `iface_id`, after the vmid, generated if
+    /// missing, and `iface_id`
+    fn tap_name(&mut self, iface_id: &str) -> String {
+        let vmid = self
+            .vmid
+            .get_or_insert_with(|| format!("rtck-{}", uuid::Uuid::new_v4()));
+        let hash = mac_address::fnv1a(&[vmid.as_bytes(), iface_id.as_bytes()]);
+        format!("rtck{:08x}", hash as u32)
+    }
+
+    /// Set the CPU template of the machine configuration, which must be set
+    pub fn with_cpu_template(
+        mut self,
+        cpu_template: cpu_template::CPUTemplateString,
+    ) -> RtckResult<Self> {
+        let machine_config = self.machine_config.as_mut().ok_or(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "CPU template set without machine configuration",
+        ))?;
+        machine_config.cpu_template = Some(cpu_template::CPUTemplate(cpu_template));
+        Ok(self)
+    }
+
+    /// Check the configuration, the error lists every problem at once
     pub fn validate(&self) -> RtckResult<()> {
+        self.check().into_result()
+    }
+
+    /// Check the configuration, reporting every problem at once
+    pub fn validate_all(&self) -> Result<(), Vec<RtckError>> {
+        self.check().into_errors()
+    }
+
+    /// Check the configuration, collecting every problem by entry
+    pub fn check(&self) -> ConfigCheck {
+        let mut check = ConfigCheck::default();
+        if self.disable_validation {
+            return check;
+        }
+
         match &self.logger {
             None => (),
             Some(logger) => {
                 let path = PathBuf::from(&logger.log_path);
                 if path.exists() {
-                    return Err(RtckError::new(
-                        RtckErrorClass::ConfigError,
-                        "Log path already occupied".to_string(),
-                    ));
+                    check.push(
+                        "logger.log_path",
+                        "path not in use",
+                        RtckError::new(
+                            RtckErrorClass::ConfigError,
+                            "Log path already occupied".to_string(),
+                        ),
+                    );
                 }
             }
         }
@@ -80,10 +617,14 @@ impl FirecrackerConfig {
             Some(metrics) => {
                 let path = PathBuf::from(&metrics.metrics_path);
                 if path.exists() {
-                    return Err(RtckError::new(
-                        RtckErrorClass::ConfigError,
-                        "Metrics path already occupied".to_string(),
-                    ));
+                    check.push(
+                        "metrics.metrics_path",
+                        "path not in use",
+                        RtckError::new(
+                            RtckErrorClass::ConfigError,
+                            "Metrics path already occupied".to_string(),
+                        ),
+                    );
                 }
             }
         }
@@ -93,23 +634,340 @@ impl FirecrackerConfig {
             Some(boot_source) => {
                 let path = PathBuf::from(&boot_source.kernel_image_path);
                 if !path.exists() || !path.is_file() {
-                    return Err(RtckError::new(
-                        RtckErrorClass::ConfigError,
-                        "Kernel image file missing".to_string(),
-                    ));
+                    check.push(
+                        "boot_source.kernel_image_path",
+                        "path of the kernel image",
+                        RtckError::new(
+                            RtckErrorClass::ConfigError,
+                            "Kernel image file missing".to_string(),
+                        ),
+                    );
                 }
             }
         }
 
-        Ok(())
+        if let Some(machine_config) = &self.machine_config {
+            check.collect(
+                "machine_config",
+                "machine configuration accepted by firecracker",
+                machine_config.validate(),
+            );
+            if machine_config.uses_huge_pages() && self.balloon.is_some() {
+                check.push(
+                    "balloon",
+                    "no balloon with huge pages",
+                    RtckError::new(
+                        RtckErrorClass::ConfigError,
+                        "Balloon devices are not supported with huge pages",
+                    ),
+                );
+            }
+        }
+
+        if let Some(balloon) = &self.balloon {
+            check.collect(
+                "balloon",
+                "balloon accepted by firecracker",
+                balloon.validate(),
+            );
+        }
+
+        if self.mmds_address.is_some() {
+            check.collect(
+                "mmds_address",
+                "IPv4 link-local address and network interfaces",
+                self.mmds_config().map(drop),
+            );
+        }
+
+        for (i, drive) in self.drives.iter().flatten().enumerate() {
+            check.collect(
+                &format!("drives[{i}]"),
+                "drive accepted by firecracker",
+                drive.validate(),
+            );
+        }
+
+        for (i, network_interface) in self.network_interfaces.iter().flatten().enumerate() {
+            check.collect(
+                &format!("network_interfaces[{i}]"),
+                "network interface accepted by firecracker",
+                network_interface.validate(),
+            );
+        }
+
+        for (i, vsock) in self.vsock_devices.iter().flatten().enumerate() {
+            check.collect(
+                &format!("vsock_devices[{i}]"),
+                "vsock accepted by firecracker",
+                vsock.validate(),
+            );
+        }
+
+        if let Some(entropy_device) = &self.entropy_device {
+            check.collect(
+                "entropy_device",
+                "entropy device accepted by firecracker",
+                entropy_device.validate(),
+            );
+        }
+
+        self.check_device_ids_into(&mut check);
+        check
+    }
+
+    /// Check that no two devices of a kind share an id and that at most one
+    /// drive is the root device. Firecracker would silently replace the
+    /// first device with the second one.
+    pub fn check_device_ids(&self) -> RtckResult<()> {
+        let mut check = ConfigCheck::default();
+        self.check_device_ids_into(&mut check);
+        check.into_errors().map_err(first_error)
+    }
+
+    fn check_device_ids_into(&self, check: &mut ConfigCheck) {
+        let drives = self.drives.iter().flatten();
+        let duplicates = [
+            (
+                "drives",
+                "drive ids",
+                duplicate_ids(drives.clone().map(|drive| drive.drive_id.as_str())),
+            ),
+            (
+                "network_interfaces",
+                "network interface ids",
+                duplicate_ids(
+                    self.network_interfaces
+                        .iter()
+                        .flatten()
+                        .map(|iface| iface.iface_id.as_str()),
+                ),
+            ),
+            (
+                "vsock_devices",
+                "vsock ids",
+                duplicate_ids(
+                    self.vsock_devices
+                        .iter()
+                        .flatten()
+                        .filter_map(|vsock| vsock.vsock_id.as_deref()),
+                ),
+            ),
+        ];
+        for (field, kind, ids) in duplicates {
+            if !ids.is_empty() {
+                check.push(
+                    field,
+                    "unique ids",
+                    RtckError::new(
+                        RtckErrorClass::ConfigError,
+                        format!("Duplicate {kind}: {}", ids.join(", ")),
+                    ),
+                );
+            }
+        }
+
+        let roots: Vec<&str> = drives
+            .filter(|drive| drive.is_root_device)
+            .map(|drive| drive.drive_id.as_str())
+            .collect();
+        if roots.len() > 1 {
+            check.push(
+                "drives",
+                "at most one root device",
+                RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    format!("More than one root device: {}", roots.join(", ")),
+                ),
+            );
+        }
+    }
+
+    /// Boot source with the `ip=` kernel parameter of the network interface
+    /// having a static IP configuration merged into its arguments. The
+    /// kernel configures a single interface, the one showing up as
+    /// `eth<index>`, and an `ip=` already set must be the same.
+    pub fn effective_boot_source(&self) -> RtckResult<Option<boot_source::BootSource>> {
+        let Some(boot_source) = &self.boot_source else {
+            return Ok(None);
+        };
+        let mut static_ips = self
+            .network_interfaces
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(index, iface)| Some((index, iface.static_ip.as_ref()?)));
+        let Some((index, static_ip)) = static_ips.next() else {
+            return Ok(Some(boot_source.clone()));
+        };
+        if static_ips.next().is_some() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Static IP configured on more than one network interface",
+            ));
+        }
+
+        let value = static_ip.ip_boot_param(&format!("eth{index}")).to_value()?;
+        let mut kernel_args =
+            kernel_args::KernelArgs::from(boot_source.boot_args.as_deref().unwrap_or_default());
+        match kernel_args.get("ip") {
+            Some(Some(set)) if set == value => (),
+            Some(set) => {
+                return Err(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    format!(
+                        "Kernel args set ip={}, conflicting with the static IP ip={value}",
+                        set.unwrap_or_default()
+                    ),
+                ))
+            }
+            None => kernel_args.insert("ip", Some(value)),
+        }
+        Ok(Some(boot_source::BootSource {
+            boot_args: Some(kernel_args.to_string()),
+            ..boot_source.clone()
+        }))
+    }
+
+    /// MMDS configuration setting `mmds_address`, forwarded to by every
+    /// network interface, `None` if no address is set
+    pub fn mmds_config(&self) -> RtckResult<Option<mmds_config::MmdsConfig>> {
+        let Some(address) = self.mmds_address else {
+            return Ok(None);
+        };
+        let network_interfaces: Vec<_> = self
+            .network_interfaces
+            .iter()
+            .flatten()
+            .map(|iface| iface.iface_id.clone())
+            .collect();
+        if network_interfaces.is_empty() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "MMDS address set without network interfaces to reach it",
+            ));
+        }
+        let mmds_config = mmds_config::MmdsConfig {
+            version: None,
+            ipv4_address: None,
+            network_interfaces,
+        };
+        Ok(Some(mmds_config.with_address(address)?))
     }
 
+    /// Serialize the config as a firecracker config file, see
+    /// `effective_boot_source`
     pub fn to_vec(&self) -> RtckResult<Vec<u8>> {
-        Ok(serde_json::to_vec(&self)?)
+        let config = Self {
+            boot_source: self.effective_boot_source()?,
+            ..self.clone()
+        };
+        Ok(serde_json::to_vec(&config)?)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+impl Redact for FirecrackerConfig {
+    /// Config with the secrets of the kernel arguments and of the initial
+    /// metadata masked
+    fn redacted(&self, policy: &RedactionPolicy) -> Self {
+        Self {
+            boot_source: self.boot_source.as_ref().map(|b| b.redacted(policy)),
+            init_metadata: self.init_metadata.as_ref().map(|m| m.redacted(policy)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Configuration exported from a running machine, e.g. to boot an identical
+/// one later. Paths are the ones firecracker sees, so those of a jailed
+/// machine are relative to its jail and must be made reachable again.
+/// Firecracker only reports the MMDS address, not which interfaces forward
+/// to the MMDS, so a configuration naming such interfaces is refused rather
+/// than silently dropped.
+impl TryFrom<full_vm_configuration::FullVmConfiguration> for FirecrackerConfig {
+    type Error = RtckError;
+
+    fn try_from(full: full_vm_configuration::FullVmConfiguration) -> RtckResult<Self> {
+        let mmds_address = match full.mmds_config {
+            None => None,
+            Some(mmds_config) if !mmds_config.network_interfaces.is_empty() => {
+                return Err(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "MMDS network interfaces cannot be carried by FirecrackerConfig",
+                ))
+            }
+            Some(mmds_config) => {
+                mmds_config.validate()?;
+                mmds_config
+                    .ipv4_address
+                    .map(|address| address.parse())
+                    .transpose()
+                    .map_err(|_| {
+                        RtckError::new(RtckErrorClass::ConfigError, "Malformed MMDS address")
+                    })?
+            }
+        };
+        Ok(Self {
+            logger: full.logger,
+            metrics: full.metrics,
+            boot_source: full.boot_source,
+            drives: full.drives.filter(|drives| !drives.is_empty()),
+            network_interfaces: full.network_interfaces.filter(|ifaces| !ifaces.is_empty()),
+            vsock_devices: full.vsock.map(|vsock| vec![vsock]),
+            cpu_config: full.cpu_config,
+            machine_config: full.machine_config,
+            mmds_address,
+            balloon: full.balloon,
+            entropy_device: full.entropy,
+            ..Default::default()
+        })
+    }
+}
+
+/// Configuration as firecracker would export it once applied. The fields
+/// only used by rustcracker (`vmid`, `net_ns`, `init_metadata`, `auto_cid`
+/// and `disable_validation`) are left out, and firecracker accepting a
+/// single vsock device, more than one is refused.
+impl TryFrom<FirecrackerConfig> for full_vm_configuration::FullVmConfiguration {
+    type Error = RtckError;
+
+    fn try_from(config: FirecrackerConfig) -> RtckResult<Self> {
+        let mut vsock_devices = config.vsock_devices.unwrap_or_default();
+        if vsock_devices.len() > 1 {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Firecracker supports a single vsock device",
+            ));
+        }
+        let mmds_config = match config.mmds_address {
+            None => None,
+            Some(address) => Some(
+                mmds_config::MmdsConfig {
+                    version: None,
+                    ipv4_address: None,
+                    network_interfaces: Vec::new(),
+                }
+                .with_address(address)?,
+            ),
+        };
+        Ok(Self {
+            balloon: config.balloon,
+            drives: config.drives,
+            boot_source: config.boot_source,
+            cpu_config: config.cpu_config,
+            logger: config.logger,
+            machine_config: config.machine_config,
+            metrics: config.metrics,
+            mmds_config,
+            network_interfaces: config.network_interfaces,
+            vsock: vsock_devices.pop(),
+            entropy: config.entropy_device,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(default)]
 pub struct JailerConfig {
     // `gid` the jailer switches to as it execs the target binary.
     pub gid: Option<u32>,
@@ -141,10 +999,28 @@ pub struct JailerConfig {
     // `daemonize` is set to true, call setsid() and redirect STDIN, STDOUT, and
     // STDERR to /dev/null
     pub daemonize: Option<bool>,
+
+    // `new_pid_ns` forks firecracker into a new PID namespace. The jailer then
+    // exits and reports the pid of firecracker in `<exec_file name>.pid` at the
+    // root of the jail, which rustcracker reads to keep track of the process.
+    pub new_pid_ns: Option<bool>,
 }
 
 impl JailerConfig {
+    /// Check the configuration, the error lists every problem at once
     pub fn validate(&self) -> RtckResult<()> {
+        self.check().into_result()
+    }
+
+    /// Check the configuration, reporting every problem at once
+    pub fn validate_all(&self) -> Result<(), Vec<RtckError>> {
+        self.check().into_errors()
+    }
+
+    /// Check the configuration, collecting every problem by entry
+    pub fn check(&self) -> ConfigCheck {
+        let mut check = ConfigCheck::default();
+
         match &self.exec_file {
             None => {
                 log::error!(
@@ -152,23 +1028,24 @@ impl JailerConfig {
                     file!(),
                     line!()
                 );
-                return Err(RtckError::new(
-                    RtckErrorClass::ConfigError,
-                    "Executable file (firecracker) must be specified in configuration".to_string(),
-                ));
+                check.push(
+                    "exec_file",
+                    "path of the firecracker binary",
+                    RtckError::new(
+                        RtckErrorClass::ConfigError,
+                        "Executable file (firecracker) must be specified in configuration"
+                            .to_string(),
+                    ),
+                );
             }
             Some(path) => {
-                let path = PathBuf::from(path);
-                if !path.exists() || !path.is_file() {
+                if let Err(e) = check_executable("Executable file of jailer", "exec_file", path) {
                     log::error!(
-                        "[Rustcracker {}:{}:JailerConfig::validate missing exec_file]",
+                        "[Rustcracker {}:{}:JailerConfig::validate bad exec_file]",
                         file!(),
                         line!()
                     );
-                    return Err(RtckError::new(
-                        RtckErrorClass::ConfigError,
-                        "Missing executable file in jailer".to_string(),
-                    ));
+                    check.push("exec_file", "path of the firecracker binary", e);
                 }
             }
         }
@@ -180,27 +1057,49 @@ impl JailerConfig {
                     file!(),
                     line!()
                 );
-                return Err(RtckError::new(
-                    RtckErrorClass::ConfigError,
-                    "Jailer binary must be specified in configuration".to_string(),
-                ));
+                check.push(
+                    "jailer_bin",
+                    "path of the jailer binary",
+                    RtckError::new(
+                        RtckErrorClass::ConfigError,
+                        "Jailer binary must be specified in configuration".to_string(),
+                    ),
+                );
             }
             Some(path) => {
-                let path = PathBuf::from(path);
-                if !path.exists() || !path.is_file() {
+                if let Err(e) = check_executable("Jailer binary", "jailer_bin", path) {
                     log::error!(
-                        "[Rustcracker {}:{}:JailerConfig::validate missing jailer_bin]",
+                        "[Rustcracker {}:{}:JailerConfig::validate bad jailer_bin]",
                         file!(),
                         line!()
                     );
-                    return Err(RtckError::new(
-                        RtckErrorClass::ConfigError,
-                        "Missing jailer binary".to_string(),
-                    ));
+                    check.push("jailer_bin", "path of the jailer binary", e);
                 }
             }
         }
 
+        if self.id.is_none() {
+            check.push(
+                "id",
+                "id of the jail",
+                RtckError::new(RtckErrorClass::ConfigError, "Missing jailer id entry"),
+            );
+        }
+        if self.uid.is_none() {
+            check.push(
+                "uid",
+                "uid to run firecracker as",
+                RtckError::new(RtckErrorClass::ConfigError, "Missing jailer uid entry"),
+            );
+        }
+        if self.gid.is_none() {
+            check.push(
+                "gid",
+                "gid to run firecracker as",
+                RtckError::new(RtckErrorClass::ConfigError, "Missing jailer gid entry"),
+            );
+        }
+
         match &self.chroot_base_dir {
             None => (),
             Some(path) => log::info!(
@@ -211,7 +1110,7 @@ impl JailerConfig {
             ),
         }
 
-        Ok(())
+        check
     }
 
     pub fn to_vec(&self) -> RtckResult<Vec<u8>> {
@@ -223,21 +1122,159 @@ impl JailerConfig {
     }
 }
 
+/// How a standard stream of the firecracker (or jailer) process is set up
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct GlobalConfig {
-    pub using_jailer: Option<bool>,
-    pub jailer_bin: Option<String>,
-    pub jailer_config: Option<JailerConfig>,
+pub enum StdioTypes {
+    /// Inherit the stream from rustcracker
+    Inherit,
+    /// Connect the stream to /dev/null
+    Null,
+    /// Redirect the stream to a file, which is created (or truncated) for output
+    /// and opened read-only for input
+    File(PathBuf),
+    /// Pipe the stream, handles are taken from the machine afterwards
+    Piped,
+}
 
-    pub socket_path: Option<String>,
+impl StdioTypes {
+    /// Open the stream for a child process. Output files are created.
+    pub fn open_io(&self, input: bool) -> RtckResult<std::process::Stdio> {
+        Ok(match self {
+            StdioTypes::Inherit => std::process::Stdio::inherit(),
+            StdioTypes::Null => std::process::Stdio::null(),
+            StdioTypes::Piped => std::process::Stdio::piped(),
+            StdioTypes::File(path) if input => std::fs::File::open(path)?.into(),
+            StdioTypes::File(path) => std::fs::File::create(path)?.into(),
+        })
+    }
+}
 
-    pub frck_bin: Option<String>,
-    pub frck_config: Option<FirecrackerConfig>,
+/// Working directory, umask and environment variables of the firecracker (or
+/// jailer) process, those of rustcracker being inherited when not set
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProcessEnv {
+    pub work_dir: Option<PathBuf>,
+    pub umask: Option<u32>,
+    /// Variables set in the environment of the process
+    pub env: Vec<(String, String)>,
+    /// Start from an empty environment instead of the one of rustcracker
+    pub clear_env: bool,
+}
 
-    // Where to put firecracker exported config
-    pub frck_export_path: Option<String>,
+impl ProcessEnv {
+    /// Set up `cmd` to spawn the process in this environment
+    pub fn apply(&self, cmd: &mut std::process::Command) {
+        use std::os::unix::process::CommandExt;
+        if let Some(dir) = &self.work_dir {
+            cmd.current_dir(dir);
+        }
+        if self.clear_env {
+            cmd.env_clear();
+        }
+        cmd.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if let Some(mask) = self.umask {
+            // umask(2) is async-signal-safe, fine between fork and exec
+            unsafe {
+                cmd.pre_exec(move || {
+                    libc::umask(mask as libc::mode_t);
+                    Ok(())
+                });
+            }
+        }
+    }
 
-    // log_clear defines whether rustcracker should remove log files after microVM
+    /// `apply` for tokio
+    #[cfg(feature = "tokio")]
+    pub fn apply_async(&self, cmd: &mut tokio::process::Command) {
+        if let Some(dir) = &self.work_dir {
+            cmd.current_dir(dir);
+        }
+        if self.clear_env {
+            cmd.env_clear();
+        }
+        cmd.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if let Some(mask) = self.umask {
+            unsafe {
+                cmd.pre_exec(move || {
+                    libc::umask(mask as libc::mode_t);
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+// Every field is optional: missing keys fall back to `GlobalConfig::default()`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(default)]
+pub struct GlobalConfig {
+    pub using_jailer: Option<bool>,
+    pub jailer_bin: Option<String>,
+    pub jailer_config: Option<JailerConfig>,
+
+    pub socket_path: Option<String>,
+
+    pub frck_bin: Option<String>,
+    pub frck_config: Option<FirecrackerConfig>,
+
+    // Where to put firecracker exported config
+    pub frck_export_path: Option<String>,
+
+    // console_output redirects stdout of firecracker, where the guest serial
+    // console (`console=ttyS0`) lands, to the given file. Has no effect
+    // if the jailer daemonizes firecracker.
+    pub console_output: Option<PathBuf>,
+
+    // Standard streams of firecracker (or jailer), inherited if not set.
+    // `stdout` conflicts with `console_output`.
+    pub stdin: Option<StdioTypes>,
+    pub stdout: Option<StdioTypes>,
+    pub stderr: Option<StdioTypes>,
+
+    // work_dir and umask are the working directory and the file mode creation
+    // mask of firecracker (or jailer), so that relative paths and the mode of
+    // the files it creates do not depend on the caller. Both inherited if not
+    // set. work_dir must be an existing directory. Without the jailer, the
+    // socket, log and metrics paths, which rustcracker opens as well, must
+    // then be absolute.
+    pub work_dir: Option<PathBuf>,
+    pub umask: Option<u32>,
+
+    // env lists the environment variables set for firecracker (or jailer),
+    // added to those of rustcracker unless clear_env, which keeps the host
+    // environment and whatever secrets it holds out of the process. clear_env
+    // defaults to false.
+    pub env: Option<Vec<(String, String)>>,
+    pub clear_env: Option<bool>,
+
+    // poll_status_secs defines how often the state of the microVM is polled
+    // while waiting for it to change. Default to 1 second.
+    pub poll_status_secs: Option<u64>,
+
+    // Timeouts in seconds, each overridden by an environment variable holding
+    // a (possibly fractional) number of seconds:
+    //
+    //	init_timeout_secs    (RUSTCRACKER_INIT_TIMEOUT): waiting for the API socket
+    //	                     after launching firecracker. Default to 3 seconds.
+    //	request_timeout_secs (RUSTCRACKER_REQUEST_TIMEOUT): every request to the
+    //	                     API socket. Unbounded by default.
+    //	launch_timeout_secs  (RUSTCRACKER_LAUNCH_TIMEOUT): `start` waits that long
+    //	                     for the microVM to run. Not waiting by default.
+    pub init_timeout_secs: Option<u64>,
+    pub request_timeout_secs: Option<u64>,
+    pub launch_timeout_secs: Option<u64>,
+
+    // mmds_size_limit is the maximum size in bytes of the MMDS contents, passed
+    // to firecracker as `--mmds-size-limit`. Default to 51200 bytes, the
+    // default of firecracker.
+    pub mmds_size_limit: Option<usize>,
+
+    // boot_timer passes `--boot-timer` to firecracker, which then logs how
+    // long the guest took to boot once it signals so, see
+    // `Machine::boot_time`. Default to false.
+    pub boot_timer: Option<bool>,
+
+    // log_clear defines whether rustcracker should remove log files after microVM
     // was removed. Default to false.
     pub log_clear: Option<bool>,
 
@@ -249,6 +1286,54 @@ pub struct GlobalConfig {
     // after the microVM is removed. Default to false.
     pub network_clear: Option<bool>,
 
+    // create_dirs makes validation create the missing directory of the socket
+    // path. Default to false.
+    pub create_dirs: Option<bool>,
+
+    // run_dir is where machines without a socket path get their own directory,
+    // holding `firecracker.socket`. Default to `rustcracker` in the temporary
+    // directory of the host.
+    pub run_dir: Option<String>,
+
+    // reclaim_stale_socket makes creating a machine remove a configured socket
+    // nobody listens on anymore, left by a firecracker that died, instead of
+    // refusing it. Default to false.
+    pub reclaim_stale_socket: Option<bool>,
+
+    // check_version makes creating a machine refuse a firecracker older than
+    // the oldest version supported by rustcracker, killing it. Default to false.
+    pub check_version: Option<bool>,
+
+    // vsock_control_port is the vsock port an agent in the guest listens on
+    // for messages of rustcracker, e.g. to resync the clock of the guest with
+    // `notify_resume_time`. Not set by default.
+    pub vsock_control_port: Option<u32>,
+
+    // trace_api logs every request sent to firecracker and its response at
+    // debug level, under the `rustcracker::api` target, e.g. to find out why
+    // a device is rejected. trace_api_redact_mmds hides the MMDS contents from
+    // the trace. Both default to false.
+    pub trace_api: Option<bool>,
+    pub trace_api_redact_mmds: Option<bool>,
+
+    // trace_api_redact_keys lists the keys whose values are masked in what is
+    // logged, the traced MMDS bodies and the kernel arguments, see
+    // `RedactionPolicy`. The keys given match exactly, case included. If not
+    // set, any key containing password, token, secret or ssh-keys, ignoring
+    // case, is masked.
+    pub trace_api_redact_keys: Option<Vec<String>>,
+
+    // log_sensitive logs the kernel arguments and the MMDS contents as they
+    // are, secrets included, for debugging. Default to false.
+    pub log_sensitive: Option<bool>,
+
+    // api_retry retries the requests to firecracker which fail at the
+    // connection level, such as a reset right after firecracker restarted.
+    // Only idempotent ones are, see `RetryPolicy`. Not retried by default.
+    // Connecting to firecracker and pinging it are retried on transient
+    // errors as told by api_retry, `RetryPolicy::default()` if not set.
+    pub api_retry: Option<RetryPolicy>,
+
     // seccomp_level specifies whether seccomp filters should be installed and how
     // restrictive they should be. Possible values are:
     //
@@ -269,93 +1354,528 @@ impl Default for GlobalConfig {
             frck_bin: None,
             frck_config: None,
             frck_export_path: None,
+            console_output: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            work_dir: None,
+            umask: None,
+            env: None,
+            clear_env: None,
+            poll_status_secs: None,
+            init_timeout_secs: None,
+            request_timeout_secs: None,
+            launch_timeout_secs: None,
+            mmds_size_limit: None,
+            boot_timer: None,
             log_clear: None,
             metrics_clear: None,
             network_clear: None,
+            create_dirs: None,
+            run_dir: None,
+            reclaim_stale_socket: None,
+            check_version: None,
+            vsock_control_port: None,
+            trace_api: None,
+            trace_api_redact_mmds: None,
+            trace_api_redact_keys: None,
+            log_sensitive: None,
+            api_retry: None,
             seccomp_level: None,
         }
     }
 }
 
 impl GlobalConfig {
-    pub fn validate(&self) -> RtckResult<()> {
-        if self.using_jailer.is_none() || *self.using_jailer.as_ref().unwrap() {
-            match &self.jailer_bin {
-                Some(path) if !PathBuf::from(path).exists() => {
+    pub const DEFAULT_POLL_STATUS_SECS: u64 = 1;
+    pub const DEFAULT_INIT_TIMEOUT_SECS: u64 = 3;
+    pub const DEFAULT_MMDS_SIZE_LIMIT: usize = mmds_config::MmdsContents::DEFAULT_SIZE_LIMIT;
+    pub const DEFAULT_JAILED_SOCKET_PATH: &str = "run/firecracker.socket";
+
+    pub const ENV_INIT_TIMEOUT: &str = "RUSTCRACKER_INIT_TIMEOUT";
+    pub const ENV_REQUEST_TIMEOUT: &str = "RUSTCRACKER_REQUEST_TIMEOUT";
+    pub const ENV_LAUNCH_TIMEOUT: &str = "RUSTCRACKER_LAUNCH_TIMEOUT";
+
+    /// Load the config from a JSON, TOML or YAML file, by its extension,
+    /// missing fields take their default
+    pub fn from_path<P: AsRef<Path>>(path: P) -> RtckResult<Self> {
+        let format = ConfigFormat::from_path(&path)?;
+        load_config(path, format)
+    }
+
+    /// Save the config to a JSON, TOML or YAML file, by its extension
+    pub fn to_path<P: AsRef<Path>>(&self, path: P) -> RtckResult<()> {
+        save_config(self, path)
+    }
+
+    /// Load the config from a TOML file, missing fields take their default
+    pub fn from_toml_file<P: AsRef<Path>>(path: P) -> RtckResult<Self> {
+        load_config(path, ConfigFormat::Toml)
+    }
+
+    /// Load the config from a JSON file, missing fields take their default
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> RtckResult<Self> {
+        load_config(path, ConfigFormat::Json)
+    }
+
+    /// Directory of the machines without a socket path
+    pub fn run_dir(&self) -> PathBuf {
+        match &self.run_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::temp_dir().join("rustcracker"),
+        }
+    }
+
+    /// Make the config usable by one machine among others created from the
+    /// same config, done when creating the machine:
+    ///
+    /// - A missing jailer id (or vmid without the jailer) is generated as
+    ///   `rtck-<uuid>`, and the vmid defaults to it.
+    /// - Without the jailer a missing socket path becomes
+    ///   `<run_dir>/<id>/firecracker.socket`, the directory being created.
+    ///   A configured socket still in use is refused, and a stale one is
+    ///   removed if `reclaim_stale_socket` and refused otherwise.
+    /// - Jailed, a missing socket path takes the default of the jail, which
+    ///   is private to the id.
+    pub fn assign_instance(&mut self) -> RtckResult<()> {
+        let generated = || format!("rtck-{}", uuid::Uuid::new_v4());
+        let vmid = self.frck_config.as_ref().and_then(|c| c.vmid.clone());
+        let using_jailer = self.using_jailer.is_none() || *self.using_jailer.as_ref().unwrap();
+        let id = match self.jailer_config.as_mut() {
+            Some(jailer_config) if using_jailer => {
+                jailer_config.id.get_or_insert_with(generated).clone()
+            }
+            _ => vmid.unwrap_or_else(generated),
+        };
+        if let Some(frck_config) = self.frck_config.as_mut() {
+            frck_config.vmid.get_or_insert(id.clone());
+        }
+
+        match &self.socket_path {
+            None if using_jailer => {
+                self.socket_path = Some(Self::DEFAULT_JAILED_SOCKET_PATH.to_string());
+            }
+            None => {
+                let dir = self.run_dir().join(&id);
+                std::fs::create_dir_all(&dir).map_err(|e| {
+                    RtckError::new(
+                        RtckErrorClass::ConfigError,
+                        format!("Fail to create run directory {dir:?}, {e}"),
+                    )
+                    .with_source(e)
+                })?;
+                self.socket_path = Some(dir.join("firecracker.socket").to_string_lossy().into());
+            }
+            Some(path) if !using_jailer && Path::new(path).exists() => {
+                if std::os::unix::net::UnixStream::connect(path).is_ok() {
                     return Err(RtckError::new(
                         RtckErrorClass::ConfigError,
-                        "Jailer bin missing".to_string(),
+                        format!("Socket {path} is in use by another firecracker"),
                     ));
                 }
-                None => {
+                if self.reclaim_stale_socket != Some(true) {
                     return Err(RtckError::new(
                         RtckErrorClass::ConfigError,
-                        "Jailer bin missing".to_string(),
-                    ))
+                        format!("Stale socket {path}, set reclaim_stale_socket to remove it"),
+                    ));
                 }
-                _ => (),
+                log::warn!("[GlobalConfig::assign_instance removing stale socket {path}]");
+                std::fs::remove_file(path)?;
+            }
+            Some(_) => (),
+        }
+        Ok(())
+    }
+
+    /// Timeout waiting for the API socket of firecracker
+    pub fn init_timeout(&self) -> RtckResult<std::time::Duration> {
+        Ok(
+            timeout_override(Self::ENV_INIT_TIMEOUT, self.init_timeout_secs)?.unwrap_or(
+                std::time::Duration::from_secs(Self::DEFAULT_INIT_TIMEOUT_SECS),
+            ),
+        )
+    }
+
+    /// Timeout of every request to firecracker, `None` if unbounded
+    pub fn request_timeout(&self) -> RtckResult<Option<std::time::Duration>> {
+        timeout_override(Self::ENV_REQUEST_TIMEOUT, self.request_timeout_secs)
+    }
+
+    /// How long `start` waits for the microVM to run, `None` if not waiting
+    pub fn launch_timeout(&self) -> RtckResult<Option<std::time::Duration>> {
+        timeout_override(Self::ENV_LAUNCH_TIMEOUT, self.launch_timeout_secs)
+    }
+
+    /// Tracing of the exchanges with firecracker, `None` unless `trace_api`
+    pub fn api_trace(&self) -> Option<ApiTrace> {
+        self.trace_api.unwrap_or(false).then(|| ApiTrace {
+            redact_mmds: self.trace_api_redact_mmds.unwrap_or(false),
+            redaction: self.redaction_policy(),
+            sensitive: self.log_sensitive.unwrap_or(false),
+        })
+    }
+
+    /// Keys masked in what is logged, see `trace_api_redact_keys`
+    pub fn redaction_policy(&self) -> RedactionPolicy {
+        match &self.trace_api_redact_keys {
+            Some(keys) => RedactionPolicy::new(keys.iter().cloned()),
+            None => RedactionPolicy::default(),
+        }
+    }
+
+    /// `value` as it should be logged, redacted unless `log_sensitive`
+    pub fn loggable<T: Redact + Clone>(&self, value: &T) -> T {
+        match self.log_sensitive.unwrap_or(false) {
+            true => value.clone(),
+            false => value.redacted(&self.redaction_policy()),
+        }
+    }
+
+    /// Maximum size in bytes of the MMDS contents
+    pub fn mmds_size_limit(&self) -> usize {
+        self.mmds_size_limit
+            .unwrap_or(Self::DEFAULT_MMDS_SIZE_LIMIT)
+    }
+
+    /// Check `content` fits in the MMDS once serialized, as firecracker
+    /// would otherwise reject it
+    pub fn check_mmds_size(&self, content: &mmds_config::MmdsContents) -> RtckResult<()> {
+        content.check_size(self.mmds_size_limit())
+    }
+
+    /// Interval between two polls of the microVM state
+    pub fn poll_status_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(
+            self.poll_status_secs
+                .unwrap_or(Self::DEFAULT_POLL_STATUS_SECS),
+        )
+    }
+
+    /// Check the global configuration, the error lists every problem at
+    /// once. The firecracker configuration is not checked, see `check`.
+    pub fn validate(&self) -> RtckResult<()> {
+        self.global_check().into_result()
+    }
+
+    /// Check the global configuration along with the firecracker one,
+    /// reporting every problem at once
+    pub fn validate_all(&self) -> Result<(), Vec<RtckError>> {
+        self.check().into_errors()
+    }
+
+    /// Check the global configuration along with the firecracker one,
+    /// collecting every problem by entry
+    pub fn check(&self) -> ConfigCheck {
+        let mut check = self.global_check();
+        if let Some(frck_config) = &self.frck_config {
+            check.nest("frck_config", frck_config.check());
+        }
+        check
+    }
+
+    fn global_check(&self) -> ConfigCheck {
+        let mut check = ConfigCheck::default();
+        if self
+            .frck_config
+            .as_ref()
+            .is_some_and(|config| config.disable_validation)
+        {
+            return check;
+        }
+
+        let using_jailer = self.using_jailer.is_none() || *self.using_jailer.as_ref().unwrap();
+        if using_jailer {
+            match &self.jailer_bin {
+                Some(path) => check.collect(
+                    "jailer_bin",
+                    "path of the jailer binary",
+                    check_executable("Jailer bin", "jailer_bin", path),
+                ),
+                None => check.push(
+                    "jailer_bin",
+                    "path of the jailer binary",
+                    RtckError::new(
+                        RtckErrorClass::ConfigError,
+                        "Jailer bin missing".to_string(),
+                    ),
+                ),
             }
 
             match &self.jailer_config {
-                None => {
-                    return Err(RtckError::new(
+                None => check.push(
+                    "jailer_config",
+                    "jailer configuration, as using_jailer is set",
+                    RtckError::new(
                         RtckErrorClass::ConfigError,
                         "Using jailer but no jailer config specified".to_string(),
-                    ))
-                }
-                Some(config) => config.validate()?,
+                    ),
+                ),
+                Some(config) => check.nest("jailer_config", config.check()),
             }
         }
 
+        #[cfg(feature = "cni")]
+        if !using_jailer
+            && self
+                .frck_config
+                .as_ref()
+                .is_some_and(|config| config.cni.is_some())
+        {
+            check.push(
+                "frck_config.cni",
+                "no CNI network without the jailer",
+                RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "CNI network needs the jailer to run firecracker in its namespace",
+                ),
+            );
+        }
+
         match &self.frck_bin {
-            None => {
-                return Err(RtckError::new(
+            None => check.push(
+                "frck_bin",
+                "path of the firecracker binary",
+                RtckError::new(
                     RtckErrorClass::ConfigError,
                     "Missing firecracker bin entry".to_string(),
-                ))
-            }
+                ),
+            ),
+            Some(path) => check.collect(
+                "frck_bin",
+                "path of the firecracker binary",
+                check_executable("Firecracker bin", "frck_bin", path),
+            ),
+        }
+
+        // Jailed, firecracker runs as the jailer uid and gid
+        let kvm_owner = self
+            .jailer_config
+            .as_ref()
+            .filter(|_| using_jailer)
+            .and_then(|config| config.uid.zip(config.gid));
+        check.collect(
+            KVM_PATH,
+            "KVM device accessible to firecracker",
+            check_kvm(Path::new(KVM_PATH), kvm_owner),
+        );
+
+        if self.frck_export_path.is_some() && self.frck_config.is_none() {
+            check.push(
+                "frck_export_path",
+                "firecracker configuration to export",
+                RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "Set exporting config but no config".to_string(),
+                ),
+            );
+        }
+
+        match &self.socket_path {
+            None => check.push(
+                "socket_path",
+                "path of the API socket",
+                RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "Missing socket path entry".to_string(),
+                ),
+            ),
             Some(path) => {
-                let path = PathBuf::from(path);
-                if !path.exists() || !path.is_file() {
-                    return Err(RtckError::new(
-                        RtckErrorClass::ConfigError,
-                        "Firecracker bin missing".to_string(),
-                    ));
+                if PathBuf::from(path).exists() {
+                    check.push(
+                        "socket_path",
+                        "path not in use",
+                        RtckError::new(
+                            RtckErrorClass::ConfigError,
+                            "Socket already exists".to_string(),
+                        ),
+                    );
+                } else if !using_jailer {
+                    // Jailed, the socket path is within the jail yet to be built
+                    check.collect(
+                        "socket_path",
+                        "path in a writable directory",
+                        check_socket_dir("socket_path", path, self.create_dirs == Some(true)),
+                    );
                 }
             }
         }
 
-        if self.frck_export_path.is_some() {
-            match &self.frck_config {
-                None => {
-                    return Err(RtckError::new(
-                        RtckErrorClass::ConfigError,
-                        "Set exporting config but no config".to_string(),
-                    ))
+        check.collect(
+            "init_timeout_secs",
+            "positive number of seconds",
+            self.init_timeout().map(drop),
+        );
+        check.collect(
+            "request_timeout_secs",
+            "positive number of seconds",
+            self.request_timeout().map(drop),
+        );
+        check.collect(
+            "launch_timeout_secs",
+            "positive number of seconds",
+            self.launch_timeout().map(drop),
+        );
+
+        if let Some(content) = self
+            .frck_config
+            .as_ref()
+            .and_then(|config| config.init_metadata.as_ref())
+        {
+            check.collect(
+                "frck_config.init_metadata",
+                "metadata fitting in the MMDS",
+                self.check_mmds_size(content),
+            );
+        }
+
+        if self.console_output.is_some() && self.stdout.is_some() {
+            check.push(
+                "console_output",
+                "either console output or stdout",
+                RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "Both console output and stdout specified",
+                ),
+            );
+        }
+
+        if let Some(dir) = self.work_dir.as_ref().filter(|dir| !dir.is_dir()) {
+            check.push(
+                "work_dir",
+                "existing directory",
+                RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    format!("Working directory {dir:?} missing"),
+                ),
+            );
+        }
+
+        if self.work_dir.is_some() && !using_jailer {
+            // Firecracker would resolve these against work_dir, rustcracker
+            // against its own working directory
+            let logger = self.frck_config.as_ref().and_then(|c| c.logger.as_ref());
+            let metrics = self.frck_config.as_ref().and_then(|c| c.metrics.as_ref());
+            let paths = [
+                ("socket_path", self.socket_path.as_deref()),
+                (
+                    "frck_config.logger.log_path",
+                    logger.map(|l| l.log_path.as_str()),
+                ),
+                (
+                    "frck_config.metrics.metrics_path",
+                    metrics.map(|m| m.metrics_path.as_str()),
+                ),
+            ];
+            for (field, path) in paths {
+                if let Some(path) = path.filter(|path| Path::new(path).is_relative()) {
+                    check.push(
+                        field,
+                        "absolute path when work_dir is set",
+                        RtckError::new(
+                            RtckErrorClass::ConfigError,
+                            format!("Relative path {path:?} with a working directory"),
+                        ),
+                    );
                 }
-                Some(_) => (),
             }
         }
 
-        match &self.socket_path {
-            None => {
-                return Err(RtckError::new(
+        if let Some(mask) = self.umask.filter(|mask| *mask > 0o777) {
+            check.push(
+                "umask",
+                "permission bits",
+                RtckError::new(
                     RtckErrorClass::ConfigError,
-                    "Missing socket path entry".to_string(),
-                ))
-            }
-            Some(path) => {
-                let path = PathBuf::from(path);
-                if path.exists() {
-                    return Err(RtckError::new(
+                    format!("Invalid umask {mask:o}, expecting at most 777 in octal"),
+                ),
+            );
+        }
+
+        for (key, _) in self.env.iter().flatten() {
+            if key.is_empty() || key.contains(['=', '\0']) {
+                check.push(
+                    "env",
+                    "variable names without '=' nor NUL",
+                    RtckError::new(
                         RtckErrorClass::ConfigError,
-                        "Socket already exists".to_string(),
-                    ));
-                }
+                        format!("Invalid environment variable name {key:?}"),
+                    ),
+                );
             }
         }
 
-        Ok(())
+        check
+    }
+
+    /// Log secrets as they are, see `log_sensitive`
+    pub fn with_log_sensitive(mut self, log_sensitive: bool) -> Self {
+        self.log_sensitive = Some(log_sensitive);
+        self
+    }
+
+    pub fn with_stdin(mut self, stdin: StdioTypes) -> Self {
+        self.stdin = Some(stdin);
+        self
+    }
+
+    pub fn with_stdout(mut self, stdout: StdioTypes) -> Self {
+        self.stdout = Some(stdout);
+        self
+    }
+
+    pub fn with_stderr(mut self, stderr: StdioTypes) -> Self {
+        self.stderr = Some(stderr);
+        self
+    }
+
+    pub fn stdin_null(self) -> Self {
+        self.with_stdin(StdioTypes::Null)
+    }
+
+    pub fn stdin_piped(self) -> Self {
+        self.with_stdin(StdioTypes::Piped)
+    }
+
+    pub fn stdout_file<P: Into<PathBuf>>(self, path: P) -> Self {
+        self.with_stdout(StdioTypes::File(path.into()))
+    }
+
+    pub fn stdout_null(self) -> Self {
+        self.with_stdout(StdioTypes::Null)
+    }
+
+    pub fn stdout_piped(self) -> Self {
+        self.with_stdout(StdioTypes::Piped)
+    }
+
+    pub fn stderr_file<P: Into<PathBuf>>(self, path: P) -> Self {
+        self.with_stderr(StdioTypes::File(path.into()))
+    }
+
+    pub fn stderr_null(self) -> Self {
+        self.with_stderr(StdioTypes::Null)
+    }
+
+    pub fn stderr_piped(self) -> Self {
+        self.with_stderr(StdioTypes::Piped)
+    }
+
+    /// Effective stdout of firecracker, where `console_output` lands
+    pub fn stdout_type(&self) -> Option<StdioTypes> {
+        self.console_output
+            .clone()
+            .map(StdioTypes::File)
+            .or(self.stdout.clone())
+    }
+
+    /// Working directory, umask and environment of firecracker (or jailer)
+    pub fn process_env(&self) -> ProcessEnv {
+        ProcessEnv {
+            work_dir: self.work_dir.clone(),
+            umask: self.umask,
+            env: self.env.clone().unwrap_or_default(),
+            clear_env: self.clear_env.unwrap_or(false),
+        }
     }
 
     /// Export the firecracker config
@@ -395,10 +1915,74 @@ impl GlobalConfig {
     }
 }
 
+/// Config of a whole deployment in a single file, the machine configuration
+/// being given apart from the rest
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct DeploymentConfig {
+    pub hypervisor: GlobalConfig,
+    pub microvm: FirecrackerConfig,
+}
+
+impl DeploymentConfig {
+    /// Load the config from a JSON, TOML or YAML file, by its extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> RtckResult<Self> {
+        let format = ConfigFormat::from_path(&path)?;
+        load_config(path, format)
+    }
+
+    /// Save the config to a JSON, TOML or YAML file, by its extension
+    pub fn to_path<P: AsRef<Path>>(&self, path: P) -> RtckResult<()> {
+        save_config(self, path)
+    }
+
+    /// Config to create the machine with
+    pub fn into_global_config(self) -> GlobalConfig {
+        GlobalConfig {
+            frck_config: Some(self.microvm),
+            ..self.hypervisor
+        }
+    }
+}
+
+/// Timeout set by environment variable `var`, falling back to `secs` from the config
+fn timeout_override(var: &str, secs: Option<u64>) -> RtckResult<Option<std::time::Duration>> {
+    parse_timeout_override(var, std::env::var(var), secs)
+}
+
+fn parse_timeout_override(
+    var: &str,
+    value: Result<String, std::env::VarError>,
+    secs: Option<u64>,
+) -> RtckResult<Option<std::time::Duration>> {
+    let value = match value {
+        Ok(value) => value,
+        Err(std::env::VarError::NotPresent) => return Ok(secs.map(std::time::Duration::from_secs)),
+        Err(e) => {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Invalid {var}: {e}"),
+            ))
+        }
+    };
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| std::time::Duration::try_from_secs_f64(secs).ok())
+        .map(Some)
+        .ok_or(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Invalid {var} {value:?}, expecting a non-negative number of seconds"),
+        ))
+}
+
 // Global Config for Firecracker
 
 #[cfg(test)]
 mod test {
+    use serde_json::json;
+
     use crate::{
         config::boot_source,
         models::{
@@ -407,11 +1991,13 @@ mod test {
             logger::{self, LogLevel},
             machine_configuration::MachineConfiguration,
             metrics,
+            mmds_config::MmdsContents,
             network_interface::NetworkInterface,
         },
+        RtckErrorClass,
     };
 
-    use super::{FirecrackerConfig, GlobalConfig};
+    use super::{FirecrackerConfig, GlobalConfig, JailerConfig, StdioTypes, KVM_PATH};
 
     #[test]
     fn test_write_config_consistent() {
@@ -448,13 +2034,16 @@ mod test {
                 guest_mac: Some("06:00:AC:10:00:02".to_string()),
                 host_dev_name: "tap0".to_string(),
                 iface_id: "net1".to_string(),
+                num_queues: None,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                static_ip: None,
             }]),
             vsock_devices: None,
             cpu_config: None,
             machine_config: Some(MachineConfiguration {
                 cpu_template: None,
+                huge_pages: None,
                 ht_enabled: None,
                 mem_size_mib: 256,
                 track_dirty_pages: None,
@@ -469,7 +2058,13 @@ mod test {
                 stats_polling_interval_s: None,
             }),
             entropy_device: None,
-            init_metadata: Some("This is initial metadata".to_string()),
+            init_metadata: Some(MmdsContents::new(
+                json!({"latest": "This is initial metadata"}),
+            )),
+            auto_cid: false,
+            disable_validation: false,
+            #[cfg(feature = "cni")]
+            cni: None,
         };
 
         let config = GlobalConfig {
@@ -480,9 +2075,33 @@ mod test {
             frck_bin: Some("/usr/bin/firecracker".to_string()),
             frck_config: Some(frck_config),
             frck_export_path: Some(SAVE_PATH.to_string()),
+            console_output: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            work_dir: None,
+            umask: None,
+            env: None,
+            clear_env: None,
+            poll_status_secs: None,
+            init_timeout_secs: None,
+            request_timeout_secs: None,
+            launch_timeout_secs: None,
+            mmds_size_limit: None,
+            boot_timer: None,
             log_clear: Some(false),
             metrics_clear: Some(false),
             network_clear: Some(false),
+            create_dirs: None,
+            run_dir: None,
+            reclaim_stale_socket: None,
+            check_version: None,
+            vsock_control_port: None,
+            trace_api: None,
+            trace_api_redact_mmds: None,
+            trace_api_redact_keys: None,
+            log_sensitive: None,
+            api_retry: None,
             seccomp_level: None,
         };
 
@@ -495,4 +2114,1105 @@ mod test {
 
         assert_eq!(config.frck_config, Some(config_));
     }
+
+    #[test]
+    fn test_stdio_types() {
+        use std::{io::Read, process::Command};
+
+        let echo = |stdout: &StdioTypes| {
+            Command::new("echo")
+                .arg("hello")
+                .stdout(stdout.open_io(false).unwrap())
+                .spawn()
+                .unwrap()
+        };
+
+        let mut child = echo(&StdioTypes::Inherit);
+        assert!(child.stdout.is_none());
+        assert!(child.wait().unwrap().success());
+
+        let child = echo(&StdioTypes::Null);
+        assert!(child.stdout.is_none());
+        assert!(child.wait_with_output().unwrap().stdout.is_empty());
+
+        let mut child = echo(&StdioTypes::Piped);
+        let mut output = String::new();
+        child
+            .stdout
+            .take()
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+        assert_eq!(output, "hello\n");
+
+        let path = std::env::temp_dir().join(format!("rtck-stdio-{}", uuid::Uuid::new_v4()));
+        let file = StdioTypes::File(path.clone());
+        assert!(echo(&file).wait().unwrap().success());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello\n");
+
+        // Input files are read, not truncated
+        let output = Command::new("cat")
+            .stdin(file.open_io(true).unwrap())
+            .stdout(StdioTypes::Piped.open_io(false).unwrap())
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, b"hello\n");
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(StdioTypes::File("/nonexistent/rtck/stdio".into())
+            .open_io(false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_stdio_builder() {
+        let config = GlobalConfig {
+            using_jailer: Some(false),
+            frck_bin: Some("/bin/sleep".to_string()),
+            socket_path: Some(format!("/tmp/rtck-stdio-{}.sock", uuid::Uuid::new_v4())),
+            ..Default::default()
+        }
+        .stdin_null()
+        .stdout_piped()
+        .stderr_file("/tmp/firecracker.err");
+        assert_eq!(config.stdin, Some(StdioTypes::Null));
+        assert_eq!(config.stdout_type(), Some(StdioTypes::Piped));
+        assert_eq!(
+            config.stderr,
+            Some(StdioTypes::File("/tmp/firecracker.err".into()))
+        );
+        assert!(config.validate().is_ok());
+
+        let config = GlobalConfig {
+            console_output: Some("/tmp/console".into()),
+            ..config
+        };
+        assert!(config.validate().is_err());
+        let config = GlobalConfig {
+            stdout: None,
+            ..config
+        };
+        assert_eq!(
+            config.stdout_type(),
+            Some(StdioTypes::File("/tmp/console".into()))
+        );
+    }
+
+    #[test]
+    fn test_loggable() {
+        let frck_config = FirecrackerConfig {
+            boot_source: Some(boot_source::BootSource {
+                boot_args: Some("console=ttyS0 password=hunter2".to_string()),
+                initrd_path: None,
+                kernel_image_path: "/vmlinux".to_string(),
+            }),
+            init_metadata: Some(MmdsContents::new(json!({
+                "latest": { "api_token": "t0k3n", "user": "rtck" }
+            }))),
+            ..Default::default()
+        };
+
+        let logged = format!("{:?}", GlobalConfig::default().loggable(&frck_config));
+        assert!(logged.contains("console=ttyS0 password=***"), "{logged}");
+        assert!(logged.contains("rtck"), "{logged}");
+        assert!(
+            !logged.contains("hunter2") && !logged.contains("t0k3n"),
+            "{logged}"
+        );
+
+        let config = GlobalConfig::default().with_log_sensitive(true);
+        assert_eq!(config.loggable(&frck_config), frck_config);
+    }
+
+    #[test]
+    fn test_process_env() {
+        let config = GlobalConfig {
+            using_jailer: Some(false),
+            frck_bin: Some("/bin/sleep".to_string()),
+            socket_path: Some(format!("/tmp/rtck-env-{}.sock", uuid::Uuid::new_v4())),
+            work_dir: Some(std::env::temp_dir()),
+            umask: Some(0o022),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let mut cmd = std::process::Command::new("/bin/sleep");
+        config.process_env().apply(&mut cmd);
+        assert_eq!(cmd.get_current_dir(), Some(std::env::temp_dir().as_path()));
+
+        let e = GlobalConfig {
+            work_dir: Some("/nonexistent/rtck".into()),
+            ..config.clone()
+        }
+        .validate()
+        .unwrap_err();
+        assert!(e.to_string().contains("Working directory"), "{e}");
+        let e = GlobalConfig {
+            umask: Some(0o1777),
+            ..config.clone()
+        }
+        .validate()
+        .unwrap_err();
+        assert!(e.to_string().contains("Invalid umask 1777"), "{e}");
+
+        let check = GlobalConfig {
+            socket_path: Some("rtck-env.sock".to_string()),
+            ..config.clone()
+        }
+        .check();
+        assert!(check
+            .issues()
+            .iter()
+            .any(|issue| issue.field == "socket_path" && issue.hint.contains("Relative path")));
+        // Without a working directory both sides agree on the caller's
+        let check = GlobalConfig {
+            socket_path: Some("rtck-env.sock".to_string()),
+            work_dir: None,
+            ..config
+        }
+        .check();
+        assert!(check.issues().is_empty(), "{:?}", check.issues());
+    }
+
+    #[test]
+    fn test_clear_env() {
+        let config = GlobalConfig {
+            env: Some(vec![("RTCK_ENV".to_string(), "rtck".to_string())]),
+            clear_env: Some(true),
+            ..Default::default()
+        };
+        let mut cmd = std::process::Command::new("/usr/bin/env");
+        config.process_env().apply(&mut cmd);
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "RTCK_ENV=rtck\n");
+
+        // Added to the inherited ones otherwise
+        let config = GlobalConfig {
+            clear_env: None,
+            ..config
+        };
+        let mut cmd = std::process::Command::new("/usr/bin/env");
+        config.process_env().apply(&mut cmd);
+        let output = String::from_utf8(cmd.output().unwrap().stdout).unwrap();
+        assert!(
+            output.lines().any(|line| line == "RTCK_ENV=rtck"),
+            "{output}"
+        );
+        assert!(
+            output.lines().any(|line| line.starts_with("PATH=")),
+            "{output}"
+        );
+
+        let e = GlobalConfig {
+            using_jailer: Some(false),
+            frck_bin: Some("/bin/sleep".to_string()),
+            socket_path: Some(format!("/tmp/rtck-env-{}.sock", uuid::Uuid::new_v4())),
+            env: Some(vec![("RTCK=ENV".to_string(), "rtck".to_string())]),
+            ..Default::default()
+        }
+        .validate()
+        .unwrap_err();
+        assert!(
+            e.to_string().contains("Invalid environment variable"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn test_load_minimal_toml() {
+        let dir = std::env::temp_dir().join(format!("rtck-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let toml_path = dir.join("config.toml");
+        std::fs::write(
+            &toml_path,
+            "frck_bin = \"/usr/bin/firecracker\"\nsocket_path = \"/run/firecracker.sock\"\n",
+        )
+        .unwrap();
+
+        let config = GlobalConfig::from_toml_file(&toml_path).unwrap();
+        assert_eq!(
+            config,
+            GlobalConfig {
+                frck_bin: Some("/usr/bin/firecracker".to_string()),
+                socket_path: Some("/run/firecracker.sock".to_string()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            config.poll_status_interval(),
+            std::time::Duration::from_secs(GlobalConfig::DEFAULT_POLL_STATUS_SECS)
+        );
+
+        // Nested tables are partial as well
+        std::fs::write(
+            &toml_path,
+            "using_jailer = true\n[jailer_config]\nid = \"vm0\"\n[frck_config]\nvmid = \"vm0\"\n",
+        )
+        .unwrap();
+        let config = GlobalConfig::from_toml_file(&toml_path).unwrap();
+        assert_eq!(config.jailer_config.unwrap().id, Some("vm0".to_string()));
+        assert_eq!(config.frck_config.unwrap().vmid, Some("vm0".to_string()));
+
+        // Log levels are matched ignoring case
+        std::fs::write(
+            &toml_path,
+            "[frck_config.logger]\nlog_path = \"/tmp/fc.log\"\nlevel = \"debug\"\n",
+        )
+        .unwrap();
+        let config = GlobalConfig::from_toml_file(&toml_path).unwrap();
+        assert_eq!(
+            config.frck_config.unwrap().logger,
+            Some(
+                logger::Logger::builder()
+                    .path("/tmp/fc.log")
+                    .level(LogLevel::Debug)
+                    .build()
+                    .unwrap()
+            )
+        );
+
+        // Round trip through the JSON loader
+        let config = GlobalConfig {
+            poll_status_secs: Some(3),
+            stdout: Some(StdioTypes::Null),
+            ..Default::default()
+        };
+        let json_path = dir.join("config.json");
+        std::fs::write(&json_path, serde_json::to_vec(&config).unwrap()).unwrap();
+        assert_eq!(GlobalConfig::from_json_file(&json_path).unwrap(), config);
+        std::fs::write(&json_path, r#"{"frck_bin":"/usr/bin/firecracker"}"#).unwrap();
+        assert_eq!(
+            GlobalConfig::from_json_file(&json_path).unwrap().frck_bin,
+            Some("/usr/bin/firecracker".to_string())
+        );
+
+        std::fs::write(&toml_path, "poll_status_secs = \"soon\"\n").unwrap();
+        let e = GlobalConfig::from_toml_file(&toml_path).unwrap_err();
+        assert!(e.to_string().contains("config.toml"), "{e}");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_files() {
+        use super::{ConfigFormat, DeploymentConfig};
+
+        let dir = std::env::temp_dir().join(format!("rtck-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let microvm = FirecrackerConfig {
+            vmid: Some("vm0".into()),
+            machine_config: Some(
+                MachineConfiguration::builder()
+                    .vcpu_count(2)
+                    .mem_size_mib(256)
+                    .build()
+                    .unwrap(),
+            ),
+            ..Default::default()
+        }
+        .with_logger(
+            logger::Logger::builder()
+                .path("/tmp/fc.log")
+                .level(LogLevel::Info)
+                .build()
+                .unwrap(),
+        );
+        let deployment = DeploymentConfig {
+            hypervisor: GlobalConfig {
+                frck_bin: Some("/usr/bin/firecracker".into()),
+                stdout: Some(StdioTypes::Null),
+                poll_status_secs: Some(2),
+                ..Default::default()
+            },
+            microvm: microvm.clone(),
+        };
+
+        // Round trip in every format
+        for ext in ["json", "toml", "yaml", "yml"] {
+            let path = dir.join(format!("deployment.{ext}"));
+            deployment.to_path(&path).unwrap();
+            assert_eq!(DeploymentConfig::from_path(&path).unwrap(), deployment);
+
+            let path = dir.join(format!("microvm.{ext}"));
+            microvm.to_path(&path).unwrap();
+            assert_eq!(FirecrackerConfig::from_path(&path).unwrap(), microvm);
+
+            let path = dir.join(format!("hypervisor.{ext}"));
+            deployment.hypervisor.to_path(&path).unwrap();
+            assert_eq!(
+                GlobalConfig::from_path(&path).unwrap(),
+                deployment.hypervisor
+            );
+        }
+        let config = deployment.clone().into_global_config();
+        assert_eq!(config.frck_config, Some(microvm));
+        assert_eq!(config.poll_status_secs, Some(2));
+
+        // Errors tell the file and where in it
+        let path = dir.join("broken.yaml");
+        std::fs::write(&path, "hypervisor:\n  poll_status_secs: soon\n").unwrap();
+        let e = DeploymentConfig::from_path(&path).unwrap_err().to_string();
+        assert!(e.contains("broken.yaml"), "{e}");
+        assert!(e.contains("line 2"), "{e}");
+        let path = dir.join("broken.json");
+        std::fs::write(&path, "{\n\"frck_bin\": 1}").unwrap();
+        let e = GlobalConfig::from_path(&path).unwrap_err().to_string();
+        assert!(e.contains("broken.json") && e.contains("line 2"), "{e}");
+
+        assert!(ConfigFormat::from_path("config.ini").is_err());
+        assert!(GlobalConfig::from_path(dir.join("config")).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_timeout_env_overrides() {
+        use std::{env::VarError, time::Duration};
+
+        use super::parse_timeout_override;
+
+        const VAR: &str = GlobalConfig::ENV_REQUEST_TIMEOUT;
+
+        // Built-in defaults last
+        let config = GlobalConfig::default();
+        assert_eq!(
+            config.init_timeout().unwrap(),
+            Duration::from_secs(GlobalConfig::DEFAULT_INIT_TIMEOUT_SECS)
+        );
+        assert_eq!(
+            parse_timeout_override(VAR, Err(VarError::NotPresent), None).unwrap(),
+            None
+        );
+
+        // Then the config
+        assert_eq!(
+            parse_timeout_override(VAR, Err(VarError::NotPresent), Some(2)).unwrap(),
+            Some(Duration::from_secs(2))
+        );
+
+        // Environment first, fractional seconds allowed
+        assert_eq!(
+            parse_timeout_override(VAR, Ok("0.5".to_string()), Some(2)).unwrap(),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            parse_timeout_override(VAR, Ok(" 10 ".to_string()), None).unwrap(),
+            Some(Duration::from_secs(10))
+        );
+
+        for malformed in ["soon", "-1", "NaN", ""] {
+            let err = parse_timeout_override(VAR, Ok(malformed.to_string()), Some(2)).unwrap_err();
+            assert_eq!(err.class(), &crate::RtckErrorClass::ConfigError);
+            assert!(err.to_string().contains(VAR));
+        }
+    }
+
+    #[test]
+    fn test_disable_validation() {
+        let frck_config = FirecrackerConfig {
+            boot_source: Some(boot_source::BootSource {
+                boot_args: None,
+                initrd_path: None,
+                kernel_image_path: "/nonexistent/rtck/vmlinux".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert!(frck_config.validate().is_err());
+        let frck_config = frck_config.with_disable_validation(true);
+        assert!(frck_config.validate().is_ok());
+
+        // Pre-flight checks of the global config are skipped as well
+        let mut config = GlobalConfig {
+            using_jailer: Some(false),
+            frck_bin: Some("/nonexistent/rtck/firecracker".to_string()),
+            socket_path: Some("/tmp/rtck-disable-validation.sock".to_string()),
+            frck_config: Some(frck_config.with_disable_validation(false)),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        config.frck_config = config
+            .frck_config
+            .map(|frck_config| frck_config.with_disable_validation(true));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_mmds_size_limit() {
+        let mut config = GlobalConfig {
+            using_jailer: Some(false),
+            frck_bin: Some("/bin/sleep".to_string()),
+            socket_path: Some("/tmp/rtck-mmds-size-limit.sock".to_string()),
+            frck_config: Some(FirecrackerConfig {
+                // Serialized with its quotes
+                init_metadata: Some(MmdsContents::new(json!(
+                    "x".repeat(GlobalConfig::DEFAULT_MMDS_SIZE_LIMIT - 2)
+                ))),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let content = MmdsContents::new(json!("x".repeat(GlobalConfig::DEFAULT_MMDS_SIZE_LIMIT)));
+        let e = config.check_mmds_size(&content).unwrap_err();
+        assert_eq!(e.class(), &crate::RtckErrorClass::ConfigError);
+        assert!(e
+            .to_string()
+            .contains("MMDS contents of 51202 bytes exceed the limit of 51200 bytes"));
+
+        config.frck_config.as_mut().unwrap().init_metadata = Some(content.clone());
+        assert!(config.validate().is_err());
+        config.mmds_size_limit = Some(1 << 20);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_full_vm_configuration_conversion() {
+        use crate::models::{
+            full_vm_configuration::FullVmConfiguration, mmds_config::MmdsConfig, vsock::Vsock,
+        };
+
+        let vsock = Vsock::new(3, "/tmp/v.sock").unwrap();
+        let config = FirecrackerConfig {
+            boot_source: Some(boot_source::BootSource {
+                boot_args: None,
+                initrd_path: None,
+                kernel_image_path: "/vmlinux".into(),
+            }),
+            vsock_devices: Some(vec![vsock.clone()]),
+            mmds_address: Some("169.254.170.2".parse().unwrap()),
+            vmid: Some("vm0".into()),
+            ..Default::default()
+        };
+        let full = FullVmConfiguration::try_from(config.clone()).unwrap();
+        assert_eq!(full.vsock, Some(vsock.clone()));
+        assert_eq!(
+            full.mmds_config.as_ref().unwrap().ipv4_address.as_deref(),
+            Some("169.254.170.2")
+        );
+        // Only `vmid`, known to rustcracker alone, is lost
+        let back = FirecrackerConfig::try_from(full.clone()).unwrap();
+        assert_eq!(
+            back,
+            FirecrackerConfig {
+                vmid: None,
+                ..config.clone()
+            }
+        );
+
+        // Firecracker has a single vsock device
+        let two_vsocks = FirecrackerConfig {
+            vsock_devices: Some(vec![vsock.clone(), vsock]),
+            ..config
+        };
+        assert!(FullVmConfiguration::try_from(two_vsocks).is_err());
+
+        // Interfaces forwarding to the MMDS have no counterpart
+        let mmds_ifaces = FullVmConfiguration {
+            mmds_config: Some(MmdsConfig {
+                version: None,
+                ipv4_address: None,
+                network_interfaces: vec!["eth0".into()],
+            }),
+            ..full
+        };
+        assert!(FirecrackerConfig::try_from(mmds_ifaces).is_err());
+    }
+
+    #[test]
+    fn test_validate_all() {
+        let config = GlobalConfig {
+            using_jailer: Some(false),
+            frck_bin: Some("/nonexistent/firecracker".to_string()),
+            socket_path: None,
+            frck_config: Some(FirecrackerConfig {
+                boot_source: Some(boot_source::BootSource {
+                    boot_args: None,
+                    initrd_path: None,
+                    kernel_image_path: "/nonexistent/vmlinux".to_string(),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let errors = config
+            .validate_all()
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            // Depends on the host
+            .filter(|e| !e.contains("KVM device"))
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert!(errors[0].contains("Firecracker bin missing"));
+        assert!(errors[1].contains("Missing socket path entry"));
+        assert!(errors[2].contains("Kernel image file missing"));
+
+        // Global validation lists them all in one error
+        let e = config.validate().unwrap_err();
+        assert_eq!(e.class(), &RtckErrorClass::ConfigError);
+        assert!(e.to_string().contains("frck_bin: Firecracker bin missing"));
+        assert!(e
+            .to_string()
+            .contains("socket_path: Missing socket path entry"));
+        assert!(!e.to_string().contains("Kernel image"));
+    }
+
+    #[test]
+    fn test_config_check() {
+        let config = GlobalConfig {
+            using_jailer: Some(true),
+            jailer_bin: Some("/bin/sleep".to_string()),
+            jailer_config: Some(JailerConfig {
+                exec_file: Some("/bin/sleep".to_string()),
+                jailer_bin: Some("/bin/sleep".to_string()),
+                uid: Some(1000),
+                ..Default::default()
+            }),
+            frck_bin: Some("/bin/sleep".to_string()),
+            socket_path: Some("/run/firecracker.socket".to_string()),
+            frck_config: Some(FirecrackerConfig {
+                mmds_address: Some("10.0.0.1".parse().unwrap()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let check = config.check();
+        let issues: Vec<_> = check
+            .issues()
+            .iter()
+            // Depends on the host
+            .filter(|issue| issue.field != KVM_PATH)
+            .collect();
+        assert_eq!(
+            issues
+                .iter()
+                .map(|issue| issue.field.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "jailer_config.id",
+                "jailer_config.gid",
+                "frck_config.mmds_address"
+            ]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "jailer_config.id: Missing jailer id entry (expecting id of the jail)"
+        );
+        assert_eq!(
+            check.into_errors().unwrap_err().len(),
+            config.check().issues().len()
+        );
+
+        // A single issue keeps its own error
+        let e = JailerConfig {
+            id: Some("vm0".to_string()),
+            uid: None,
+            gid: Some(1000),
+            ..config.jailer_config.clone().unwrap()
+        }
+        .validate()
+        .unwrap_err();
+        assert_eq!(e.to_string(), "Missing jailer uid entry");
+    }
+
+    #[test]
+    fn test_check_device_ids() {
+        use crate::models::{drive::Drive, vsock::Vsock};
+
+        let drive = |drive_id: &str, is_root_device: bool| Drive {
+            drive_id: drive_id.into(),
+            partuuid: None,
+            is_root_device,
+            cache_type: None,
+            is_read_only: true,
+            path_on_host: format!("/tmp/{drive_id}.img"),
+            rate_limiter: None,
+            io_engine: None,
+            socket: None,
+        };
+        let iface = |iface_id: &str| NetworkInterface {
+            guest_mac: None,
+            host_dev_name: format!("tap-{iface_id}"),
+            iface_id: iface_id.into(),
+            num_queues: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            static_ip: None,
+        };
+        let mut config = FirecrackerConfig {
+            drives: Some(vec![drive("rootfs", true), drive("data", false)]),
+            network_interfaces: Some(vec![iface("eth0"), iface("eth1")]),
+            ..Default::default()
+        };
+        config.check_device_ids().unwrap();
+
+        config.drives.as_mut().unwrap().extend([
+            drive("rootfs", false),
+            drive("data", false),
+            drive("data", false),
+            drive("scratch", true),
+        ]);
+        config
+            .network_interfaces
+            .as_mut()
+            .unwrap()
+            .push(iface("eth0"));
+        let mut vsock = Vsock::new(3, "/tmp/v.sock").unwrap();
+        vsock.vsock_id = Some("vsock0".into());
+        config.vsock_devices = Some(vec![vsock.clone(), vsock]);
+
+        let errors: Vec<String> = config
+            .validate_all()
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .filter(|e| e.contains("Duplicate") || e.contains("root device"))
+            .collect();
+        assert_eq!(errors.len(), 4, "{errors:?}");
+        assert!(errors[0].contains("Duplicate drive ids: data, rootfs"));
+        assert!(errors[1].contains("Duplicate network interface ids: eth0"));
+        assert!(errors[2].contains("Duplicate vsock ids: vsock0"));
+        assert!(errors[3].contains("More than one root device: rootfs, scratch"));
+
+        // Checked before configuring even with validation disabled
+        let e = config
+            .with_disable_validation(true)
+            .check_device_ids()
+            .unwrap_err();
+        assert!(e.to_string().contains("Duplicate drive ids"));
+    }
+
+    #[test]
+    fn test_static_ip() {
+        use crate::models::network_interface::StaticIpConfig;
+
+        let static_ip = StaticIpConfig {
+            address: "172.16.0.2".parse().unwrap(),
+            netmask: 24,
+            gateway: Some("172.16.0.1".parse().unwrap()),
+            nameservers: vec!["8.8.8.8".parse().unwrap()],
+            hostname: Some("vm0".into()),
+        };
+        let config = FirecrackerConfig {
+            boot_source: Some(boot_source::BootSource {
+                boot_args: Some(
+                    "console=ttyS0 console=tty0 reboot=k -- init_arg=1 init_arg=2".into(),
+                ),
+                initrd_path: None,
+                kernel_image_path: "/tmp/vmlinux".into(),
+            }),
+            ..Default::default()
+        };
+
+        // Repeated parameters and the arguments of init are kept
+        let config = config.static_ip("net0", "tap0", static_ip.clone()).unwrap();
+        assert_eq!(
+            config.effective_boot_source().unwrap().unwrap().boot_args.as_deref(),
+            Some("console=ttyS0 console=tty0 reboot=k ip=172.16.0.2::172.16.0.1:255.255.255.0:vm0:eth0:off:8.8.8.8 -- init_arg=1 init_arg=2")
+        );
+        assert_eq!(
+            config.network_interfaces,
+            Some(vec![NetworkInterface {
+                guest_mac: None,
+                host_dev_name: "tap0".into(),
+                iface_id: "net0".into(),
+                num_queues: None,
+                rx_rate_limiter: None,
+                tx_rate_limiter: None,
+                static_ip: Some(static_ip.clone()),
+            }])
+        );
+
+        // A second static IP would conflict with the first one
+        let e = config
+            .clone()
+            .static_ip("net1", "tap1", static_ip.clone())
+            .unwrap_err();
+        assert!(e.to_string().contains("more than one"), "{e}");
+        let mut dhcp = config.clone();
+        dhcp.network_interfaces = None;
+        dhcp.boot_source.as_mut().unwrap().boot_args = Some("ip=dhcp".into());
+        assert!(dhcp.static_ip("net0", "tap0", static_ip.clone()).is_err());
+
+        // Invalid networks and duplicate interfaces are refused
+        let bad = StaticIpConfig {
+            netmask: 33,
+            ..static_ip.clone()
+        };
+        let mut unconfigured = config.clone();
+        unconfigured.network_interfaces.as_mut().unwrap()[0].static_ip = None;
+        assert!(unconfigured.clone().static_ip("net1", "tap1", bad).is_err());
+        assert!(unconfigured
+            .static_ip("net0", "tap1", static_ip.clone())
+            .is_err());
+        assert!(FirecrackerConfig::default()
+            .static_ip("net0", "tap0", static_ip)
+            .is_err());
+    }
+
+    #[test]
+    fn test_mmds_config() {
+        let iface = NetworkInterface {
+            guest_mac: None,
+            host_dev_name: "tap0".into(),
+            iface_id: "net0".into(),
+            num_queues: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            static_ip: None,
+        };
+        let config = FirecrackerConfig {
+            network_interfaces: Some(vec![iface]),
+            ..Default::default()
+        };
+        assert_eq!(config.mmds_config().unwrap(), None);
+
+        let with_address = |address: &str| FirecrackerConfig {
+            mmds_address: Some(address.parse().unwrap()),
+            ..config.clone()
+        };
+        let mmds_config = with_address("169.254.170.2")
+            .mmds_config()
+            .unwrap()
+            .unwrap();
+        assert_eq!(mmds_config.ipv4_address.as_deref(), Some("169.254.170.2"));
+        assert_eq!(mmds_config.network_interfaces, vec!["net0".to_string()]);
+        assert!(with_address("169.254.170.2").validate().is_ok());
+
+        // Not link-local, or with no interface to reach it
+        assert!(with_address("10.0.0.1").mmds_config().is_err());
+        assert!(with_address("10.0.0.1").validate().is_err());
+        let unreachable = FirecrackerConfig {
+            network_interfaces: None,
+            ..with_address("169.254.170.2")
+        };
+        assert!(unreachable.mmds_config().is_err());
+        assert!(unreachable.validate().is_err());
+    }
+
+    #[test]
+    fn test_effective_boot_source() {
+        use crate::models::network_interface::StaticIpConfig;
+
+        let iface = |iface_id: &str, static_ip: Option<StaticIpConfig>| NetworkInterface {
+            guest_mac: None,
+            host_dev_name: format!("tap-{iface_id}"),
+            iface_id: iface_id.into(),
+            num_queues: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            static_ip,
+        };
+        let static_ip = StaticIpConfig {
+            address: "10.0.0.2".parse().unwrap(),
+            netmask: 24,
+            gateway: Some("10.0.0.1".parse().unwrap()),
+            nameservers: vec!["1.1.1.1".parse().unwrap(), "8.8.8.8".parse().unwrap()],
+            hostname: Some("vm0".into()),
+        };
+        let config = |boot_args: &str, ifaces: Vec<NetworkInterface>| FirecrackerConfig {
+            boot_source: Some(boot_source::BootSource {
+                boot_args: Some(boot_args.into()),
+                initrd_path: None,
+                kernel_image_path: "/tmp/vmlinux".into(),
+            }),
+            network_interfaces: Some(ifaces),
+            ..Default::default()
+        };
+        let boot_args = |config: &FirecrackerConfig| {
+            config
+                .effective_boot_source()
+                .unwrap()
+                .unwrap()
+                .boot_args
+                .unwrap()
+        };
+
+        // The device is the one the interface shows up as
+        let config1 = config(
+            "console=ttyS0",
+            vec![iface("net0", None), iface("net1", Some(static_ip.clone()))],
+        );
+        assert_eq!(
+            boot_args(&config1),
+            "console=ttyS0 ip=10.0.0.2::10.0.0.1:255.255.255.0:vm0:eth1:off:1.1.1.1:8.8.8.8"
+        );
+        let config0 = config(
+            "console=ttyS0",
+            vec![iface("net0", Some(static_ip.clone()))],
+        );
+        assert_eq!(
+            boot_args(&config0),
+            "console=ttyS0 ip=10.0.0.2::10.0.0.1:255.255.255.0:vm0:eth0:off:1.1.1.1:8.8.8.8"
+        );
+        // Never sent to firecracker, which still gets the ip= parameter
+        let exported: serde_json::Value =
+            serde_json::from_slice(&config0.to_vec().unwrap()).unwrap();
+        assert!(exported["network_interfaces"][0].get("static_ip").is_none());
+        assert!(exported["boot_source"]["boot_args"]
+            .as_str()
+            .unwrap()
+            .contains("ip=10.0.0.2::"));
+
+        // The same ip= is fine, another one conflicts
+        let same = config(
+            &boot_args(&config0),
+            vec![iface("net0", Some(static_ip.clone()))],
+        );
+        assert_eq!(boot_args(&same), boot_args(&config0));
+        let conflicting = config("ip=dhcp", vec![iface("net0", Some(static_ip.clone()))]);
+        let e = conflicting.effective_boot_source().unwrap_err();
+        assert!(e.to_string().contains("conflicting"), "{e}");
+
+        // The kernel configures a single interface
+        let twice = config(
+            "console=ttyS0",
+            vec![
+                iface("net0", Some(static_ip.clone())),
+                iface("net1", Some(static_ip)),
+            ],
+        );
+        assert!(twice.effective_boot_source().is_err());
+        let untouched = config("console=ttyS0", vec![iface("net0", None)]);
+        assert_eq!(boot_args(&untouched), "console=ttyS0");
+    }
+
+    #[test]
+    fn test_with_cpu_template() {
+        use crate::models::cpu_template::{CPUTemplate, CPUTemplateString};
+
+        assert!(FirecrackerConfig::default()
+            .with_cpu_template(CPUTemplateString::T2S)
+            .is_err());
+
+        let config = FirecrackerConfig {
+            machine_config: Some(
+                MachineConfiguration::builder()
+                    .vcpu_count(2)
+                    .mem_size_mib(256)
+                    .build()
+                    .unwrap(),
+            ),
+            ..Default::default()
+        }
+        .with_cpu_template(CPUTemplateString::T2S)
+        .unwrap();
+        let machine_config = config.machine_config.unwrap();
+        assert_eq!(
+            machine_config.cpu_template,
+            Some(CPUTemplate(CPUTemplateString::T2S))
+        );
+        assert_eq!(
+            serde_json::to_value(&machine_config).unwrap()["cpu_template"],
+            "T2S"
+        );
+    }
+
+    #[test]
+    fn test_check_paths() {
+        use super::{check_executable, check_kvm, check_socket_dir};
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("rtck-check-paths-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bin = dir.join("firecracker");
+        std::fs::write(&bin, "#!/bin/sh\n").unwrap();
+        let bin_str = bin.to_str().unwrap();
+
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let e = check_executable("Firecracker bin", "frck_bin", bin_str).unwrap_err();
+        assert!(
+            e.to_string().contains("Firecracker bin is not executable"),
+            "{e}"
+        );
+        assert!(e.to_string().contains(bin_str), "{e}");
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(check_executable("Firecracker bin", "frck_bin", bin_str).is_ok());
+
+        let dir_str = dir.to_str().unwrap();
+        let e = check_executable("Firecracker bin", "frck_bin", dir_str).unwrap_err();
+        assert!(e.to_string().contains("is not a file"), "{e}");
+
+        // Missing socket directory is only created on request
+        let socket = dir.join("run/firecracker.sock");
+        let socket_str = socket.to_str().unwrap();
+        let e = check_socket_dir("socket_path", socket_str, false).unwrap_err();
+        assert!(e.to_string().contains("missing"), "{e}");
+        assert!(check_socket_dir("socket_path", socket_str, true).is_ok());
+        assert!(dir.join("run").is_dir());
+
+        let e = check_kvm(&dir.join("kvm"), None).unwrap_err();
+        assert!(e.to_string().contains("is KVM enabled"), "{e}");
+        // Jailer uid owning the device needs both read and write bits
+        let kvm = dir.join("kvm");
+        std::fs::write(&kvm, "").unwrap();
+        std::fs::set_permissions(&kvm, std::fs::Permissions::from_mode(0o400)).unwrap();
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let e = check_kvm(&kvm, Some((uid.max(1), gid))).unwrap_err();
+        assert!(
+            e.to_string().contains("not accessible by the jailer"),
+            "{e}"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_assign_instance() {
+        use std::{os::unix::net::UnixListener, path::PathBuf};
+
+        // Short enough for the socket paths within
+        let dir = std::env::temp_dir().join(format!("rtck-run-{}", std::process::id()));
+        let template = GlobalConfig {
+            using_jailer: Some(false),
+            run_dir: Some(dir.to_string_lossy().into_owned()),
+            frck_config: Some(FirecrackerConfig::default()),
+            ..Default::default()
+        };
+
+        // Machines of the same config do not collide
+        let mut first = template.clone();
+        let mut second = template.clone();
+        first.assign_instance().unwrap();
+        second.assign_instance().unwrap();
+        let (first_socket, second_socket) =
+            (first.socket_path.unwrap(), second.socket_path.unwrap());
+        assert_ne!(first_socket, second_socket);
+        let vmid = first.frck_config.unwrap().vmid.unwrap();
+        assert!(vmid.starts_with("rtck-"));
+        assert_eq!(
+            PathBuf::from(&first_socket),
+            dir.join(&vmid).join("firecracker.socket")
+        );
+        assert!(dir.join(&vmid).is_dir());
+
+        // A configured socket in use is refused
+        let mut config = template.clone();
+        config.socket_path = Some(first_socket.clone());
+        let listener = UnixListener::bind(&first_socket).unwrap();
+        let e = config.clone().assign_instance().unwrap_err();
+        assert!(e.to_string().contains("in use"), "{e}");
+
+        // Stale once nobody listens, removed only on request
+        drop(listener);
+        let e = config.clone().assign_instance().unwrap_err();
+        assert!(e.to_string().contains("Stale socket"), "{e}");
+        config.reclaim_stale_socket = Some(true);
+        config.assign_instance().unwrap();
+        assert!(!PathBuf::from(&first_socket).exists());
+
+        // Jailed, the id is generated and the socket lies in the jail
+        let mut config = GlobalConfig {
+            using_jailer: Some(true),
+            jailer_config: Some(super::JailerConfig::default()),
+            ..Default::default()
+        };
+        config.assign_instance().unwrap();
+        assert!(config
+            .jailer_config
+            .unwrap()
+            .id
+            .unwrap()
+            .starts_with("rtck-"));
+        assert_eq!(
+            config.socket_path.as_deref(),
+            Some(GlobalConfig::DEFAULT_JAILED_SOCKET_PATH)
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_auto_tap() {
+        if !std::path::Path::new("/dev/net/tun").exists() {
+            return;
+        }
+        let config = FirecrackerConfig {
+            boot_source: Some(boot_source::BootSource {
+                boot_args: Some("console=ttyS0".into()),
+                initrd_path: None,
+                kernel_image_path: "/tmp/vmlinux".into(),
+            }),
+            vmid: Some(format!("auto-tap-{}", std::process::id())),
+            ..Default::default()
+        };
+        let (config, tap) = match config.auto_tap("net0", "172.31.250.0/30") {
+            Ok(tapped) => tapped,
+            Err(e) if e.to_string().contains("Insufficient privileges") => return,
+            Err(e) => panic!("{e}"),
+        };
+
+        let iface = &config.network_interfaces.as_ref().unwrap()[0];
+        assert_eq!(iface.host_dev_name, tap.name());
+        assert!(tap.name().starts_with("rtck"));
+        assert_eq!(
+            config
+                .effective_boot_source()
+                .unwrap()
+                .unwrap()
+                .boot_args
+                .as_deref(),
+            Some("console=ttyS0 ip=172.31.250.2::172.31.250.1:255.255.255.252::eth0:off")
+        );
+        let sys = std::path::Path::new("/sys/class/net").join(tap.name());
+        assert!(sys.exists());
+        drop(tap);
+        assert!(!sys.exists());
+    }
+
+    #[test]
+    fn test_bridged_network() {
+        if !std::path::Path::new("/dev/net/tun").exists() {
+            return;
+        }
+        let name = format!("rtckbn{}", std::process::id() % 100_000);
+        let bridge = match crate::network::Bridge::ensure(&name, "172.31.240.1/24") {
+            Ok(bridge) => bridge,
+            Err(_) => return,
+        };
+        let config = FirecrackerConfig {
+            boot_source: Some(boot_source::BootSource {
+                boot_args: Some("console=ttyS0".into()),
+                initrd_path: None,
+                kernel_image_path: "/tmp/vmlinux".into(),
+            }),
+            vmid: Some(format!("bridged-{}", std::process::id())),
+            ..Default::default()
+        };
+        // Out of the subnet, or taken by the bridge
+        for taken in ["172.31.241.2", "172.31.240.1"] {
+            let e = config
+                .clone()
+                .bridged_network("net0", &bridge, taken.parse().unwrap())
+                .unwrap_err();
+            assert!(e.to_string().contains("not a free address"), "{e}");
+        }
+
+        let (config, tap) = config
+            .bridged_network("net0", &bridge, "172.31.240.7".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            config
+                .effective_boot_source()
+                .unwrap()
+                .unwrap()
+                .boot_args
+                .as_deref(),
+            Some("console=ttyS0 ip=172.31.240.7::172.31.240.1:255.255.255.0::eth0:off")
+        );
+        let master = std::path::Path::new("/sys/class/net")
+            .join(tap.name())
+            .join("master");
+        assert_eq!(
+            std::fs::read_link(master).unwrap().file_name().unwrap(),
+            name.as_str()
+        );
+
+        drop(tap);
+        bridge.rollback().unwrap().remove().unwrap();
+    }
 }
@@ -1,4 +1,114 @@
+/// Define the event `$name` wrapping the operation `$ops` and its response
+/// `$res`. `$name::new` takes the payload of the request, if any.
+macro_rules! define_event {
+    ($name:ident, $ops:ident, $res:ident) => {
+        define_event!(@event $name, $ops, $res);
+
+        impl $name {
+            pub fn new() -> Self {
+                Self {
+                    ops: $ops::new(),
+                    res: std::cell::Cell::new($res::blank()),
+                }
+            }
+        }
+    };
+    ($name:ident, $ops:ident, $res:ident, $data:ty) => {
+        define_event!(@event $name, $ops, $res);
+
+        impl $name {
+            pub fn new(data: $data) -> Self {
+                Self {
+                    ops: $ops::new(data),
+                    res: std::cell::Cell::new($res::blank()),
+                }
+            }
+        }
+    };
+    (@event $name:ident, $ops:ident, $res:ident) => {
+        pub struct $name {
+            ops: $ops,
+            res: std::cell::Cell<$res>,
+        }
+
+        impl Event<$ops, $res> for $name {
+            fn get_ops(&self) -> &$ops {
+                &self.ops
+            }
+
+            fn get_ops_mut(&mut self) -> &mut $ops {
+                &mut self.ops
+            }
+
+            fn get_res_mut(&mut self) -> &mut $res {
+                self.res.get_mut()
+            }
+
+            fn set_res(&self, res: $res) {
+                self.res.set(res)
+            }
+        }
+    };
+}
+
+/// Same as `define_event!`, for events shared across tasks
+#[cfg(feature = "tokio")]
+macro_rules! define_event_async {
+    ($name:ident, $ops:ident, $res:ident) => {
+        define_event_async!(@event $name, $ops, $res);
+
+        impl $name {
+            pub fn new() -> Self {
+                Self {
+                    ops: $ops::new(),
+                    res: parking_lot::Mutex::new($res::blank()),
+                }
+            }
+        }
+    };
+    ($name:ident, $ops:ident, $res:ident, $data:ty) => {
+        define_event_async!(@event $name, $ops, $res);
+
+        impl $name {
+            pub fn new(data: $data) -> Self {
+                Self {
+                    ops: $ops::new(data),
+                    res: parking_lot::Mutex::new($res::blank()),
+                }
+            }
+        }
+    };
+    (@event $name:ident, $ops:ident, $res:ident) => {
+        pub struct $name {
+            ops: $ops,
+            res: parking_lot::Mutex<$res>,
+        }
+
+        impl EventAsync<$ops, $res> for $name {
+            fn get_ops(&self) -> &$ops {
+                &self.ops
+            }
+
+            fn get_ops_mut(&mut self) -> &mut $ops {
+                &mut self.ops
+            }
+
+            fn get_res(&self) -> parking_lot::MappedMutexGuard<$res> {
+                parking_lot::MutexGuard::map(self.res.lock(), |r| r)
+            }
+
+            fn get_res_mut(&mut self) -> &mut $res {
+                self.res.get_mut()
+            }
+
+            fn set_res(&self, res: $res) {
+                *self.res.lock() = res;
+            }
+        }
+    };
+}
+
 pub mod events;
 
 #[cfg(feature = "tokio")]
-pub mod events_async;
\ No newline at end of file
+pub mod events_async;
@@ -0,0 +1,309 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use prometheus::{IntCounterVec, IntGaugeVec, Opts, Registry};
+
+use crate::{
+    models::{balloon_stats::BalloonStatistics, metrics::FirecrackerMetricsSnapshot},
+    RtckError, RtckErrorClass, RtckResult,
+};
+
+/// Label telling the machines apart in a registry shared by several ones
+pub const VMID_LABEL: &str = "vmid";
+
+fn registry_error(e: prometheus::Error) -> RtckError {
+    RtckError::new(
+        RtckErrorClass::GenericError,
+        format!("Fail to register metrics, {e}"),
+    )
+    .with_source(e)
+}
+
+/// Prometheus metrics of a machine, fed by the metrics firecracker flushes
+/// and by the balloon statistics. Firecracker counters hold what happened
+/// since the previous flush, and are added up into Prometheus counters.
+pub struct MachineMetrics {
+    vmid: String,
+    last_flush_ms: AtomicU64,
+
+    block_bytes: IntCounterVec,
+    block_requests: IntCounterVec,
+    net_bytes: IntCounterVec,
+    net_packets: IntCounterVec,
+    vcpu_exits: IntCounterVec,
+    vcpu_failures: IntCounterVec,
+    seccomp_faults: IntCounterVec,
+
+    balloon_target_mib: IntGaugeVec,
+    balloon_actual_mib: IntGaugeVec,
+    guest_memory_bytes: IntGaugeVec,
+}
+
+impl MachineMetrics {
+    /// Register the metrics of the machine `vmid` in `registry`, other
+    /// machines registering theirs in the same one
+    pub fn register<S: Into<String>>(registry: &Registry, vmid: S) -> RtckResult<Self> {
+        let vmid = vmid.into();
+        let counter = |name: &str, help: &str, labels: &[&str]| -> RtckResult<IntCounterVec> {
+            let opts = Opts::new(name, help).const_label(VMID_LABEL, &vmid);
+            let counter = IntCounterVec::new(opts, labels).map_err(registry_error)?;
+            registry
+                .register(Box::new(counter.clone()))
+                .map_err(registry_error)?;
+            Ok(counter)
+        };
+        let gauge = |name: &str, help: &str, labels: &[&str]| -> RtckResult<IntGaugeVec> {
+            let opts = Opts::new(name, help).const_label(VMID_LABEL, &vmid);
+            let gauge = IntGaugeVec::new(opts, labels).map_err(registry_error)?;
+            registry
+                .register(Box::new(gauge.clone()))
+                .map_err(registry_error)?;
+            Ok(gauge)
+        };
+
+        Ok(Self {
+            block_bytes: counter(
+                "firecracker_block_bytes_total",
+                "Bytes read or written by the block devices",
+                &["drive", "op"],
+            )?,
+            block_requests: counter(
+                "firecracker_block_requests_total",
+                "Read or write requests to the block devices",
+                &["drive", "op"],
+            )?,
+            net_bytes: counter(
+                "firecracker_net_bytes_total",
+                "Bytes received or transmitted by the network devices",
+                &["iface", "direction"],
+            )?,
+            net_packets: counter(
+                "firecracker_net_packets_total",
+                "Packets received or transmitted by the network devices",
+                &["iface", "direction"],
+            )?,
+            vcpu_exits: counter(
+                "firecracker_vcpu_exits_total",
+                "Exits of the vCPUs to firecracker",
+                &["reason"],
+            )?,
+            vcpu_failures: counter(
+                "firecracker_vcpu_failures_total",
+                "Failures of the vCPUs",
+                &[],
+            )?,
+            seccomp_faults: counter(
+                "firecracker_seccomp_faults_total",
+                "Syscalls of firecracker denied by seccomp",
+                &[],
+            )?,
+            balloon_target_mib: gauge(
+                "firecracker_balloon_target_mib",
+                "Memory the balloon aims to hold, in MiB",
+                &[],
+            )?,
+            balloon_actual_mib: gauge(
+                "firecracker_balloon_actual_mib",
+                "Memory the balloon holds, in MiB",
+                &[],
+            )?,
+            guest_memory_bytes: gauge(
+                "firecracker_guest_memory_bytes",
+                "Memory of the guest reported by the balloon statistics",
+                &["kind"],
+            )?,
+            vmid,
+            last_flush_ms: AtomicU64::new(0),
+        })
+    }
+
+    pub fn vmid(&self) -> &str {
+        &self.vmid
+    }
+
+    /// Add up a flush of the metrics. Flushes not newer than the last one
+    /// observed are ignored, so that a metrics file can be read again.
+    pub fn observe(&self, snapshot: &FirecrackerMetricsSnapshot) -> RtckResult<()> {
+        let last = self
+            .last_flush_ms
+            .fetch_max(snapshot.utc_timestamp_ms, Ordering::SeqCst);
+        if snapshot.utc_timestamp_ms <= last {
+            return Ok(());
+        }
+
+        for (drive, block) in snapshot.block_devices()? {
+            let add = |counter: &IntCounterVec, op: &str, value: u64| {
+                counter.with_label_values(&[&drive, op]).inc_by(value)
+            };
+            add(&self.block_bytes, "read", block.read_bytes);
+            add(&self.block_bytes, "write", block.write_bytes);
+            add(&self.block_requests, "read", block.read_count);
+            add(&self.block_requests, "write", block.write_count);
+        }
+        for (iface, net) in snapshot.net_devices()? {
+            let add = |counter: &IntCounterVec, direction: &str, value: u64| {
+                counter
+                    .with_label_values(&[&iface, direction])
+                    .inc_by(value)
+            };
+            add(&self.net_bytes, "rx", net.rx_bytes_count);
+            add(&self.net_bytes, "tx", net.tx_bytes_count);
+            add(&self.net_packets, "rx", net.rx_packets_count);
+            add(&self.net_packets, "tx", net.tx_packets_count);
+        }
+
+        let vcpu = &snapshot.vcpu;
+        for (reason, value) in [
+            ("io_in", vcpu.exit_io_in),
+            ("io_out", vcpu.exit_io_out),
+            ("mmio_read", vcpu.exit_mmio_read),
+            ("mmio_write", vcpu.exit_mmio_write),
+        ] {
+            self.vcpu_exits.with_label_values(&[reason]).inc_by(value);
+        }
+        self.vcpu_failures
+            .with_label_values(&[])
+            .inc_by(vcpu.failures);
+        self.seccomp_faults
+            .with_label_values(&[])
+            .inc_by(snapshot.seccomp.num_faults);
+        Ok(())
+    }
+
+    /// Update the balloon gauges
+    pub fn observe_balloon(&self, stats: &BalloonStatistics) {
+        let set = |gauge: &IntGaugeVec, labels: &[&str], value: u64| {
+            gauge
+                .with_label_values(labels)
+                .set(value.try_into().unwrap_or(i64::MAX))
+        };
+        set(&self.balloon_target_mib, &[], stats.target_mib);
+        set(&self.balloon_actual_mib, &[], stats.actual_mib);
+        for (kind, value) in [
+            ("total", stats.total_memory),
+            ("free", stats.free_memory),
+            ("available", stats.available_memory),
+            ("disk_caches", stats.disk_caches),
+        ] {
+            if let Some(value) = value {
+                set(&self.guest_memory_bytes, &[kind], value);
+            }
+        }
+    }
+}
+
+/// Latest flushes in the metrics file at `path`, which must be a regular
+/// file as reading a named pipe would block
+pub(crate) fn read_metrics_file(
+    path: &std::path::Path,
+) -> RtckResult<Vec<FirecrackerMetricsSnapshot>> {
+    if !path.is_file() {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Metrics path {path:?} is not a regular file"),
+        ));
+    }
+    FirecrackerMetricsSnapshot::parse_lines(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use prometheus::{proto::MetricFamily, Registry};
+
+    use super::MachineMetrics;
+    use crate::models::{balloon_stats::BalloonStatistics, metrics::FirecrackerMetricsSnapshot};
+
+    fn value(families: &[MetricFamily], name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let family = families.iter().find(|family| family.get_name() == name)?;
+        let metric = family.get_metric().iter().find(|metric| {
+            labels.iter().all(|(name, value)| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.get_name() == *name && label.get_value() == *value)
+            })
+        })?;
+        Some(match family.get_field_type() {
+            prometheus::proto::MetricType::COUNTER => metric.get_counter().get_value(),
+            _ => metric.get_gauge().get_value(),
+        })
+    }
+
+    #[test]
+    fn test_machine_metrics() {
+        let registry = Registry::new();
+        let metrics = MachineMetrics::register(&registry, "vm0").unwrap();
+        // Machines share a registry
+        let _other = MachineMetrics::register(&registry, "vm1").unwrap();
+        assert!(MachineMetrics::register(&registry, "vm0").is_err());
+
+        let flush: FirecrackerMetricsSnapshot = serde_json::from_str(
+            r#"{"utc_timestamp_ms":1000,
+                "block_rootfs":{"read_bytes":4096,"write_bytes":512,"read_count":2,"write_count":1},
+                "net_eth0":{"rx_bytes_count":180,"tx_bytes_count":306,"rx_packets_count":3,"tx_packets_count":4},
+                "vcpu":{"exit_io_out":6372},
+                "seccomp":{"num_faults":0}}"#,
+        )
+        .unwrap();
+        metrics.observe(&flush).unwrap();
+        // Seen already
+        metrics.observe(&flush).unwrap();
+        metrics.observe_balloon(&BalloonStatistics {
+            target_pages: 32768,
+            actual_pages: 16384,
+            target_mib: 128,
+            actual_mib: 64,
+            swap_in: None,
+            swap_out: None,
+            major_faults: None,
+            minor_faults: None,
+            free_memory: Some(300 << 20),
+            total_memory: Some(512 << 20),
+            available_memory: None,
+            disk_caches: None,
+            hugetlb_allocations: None,
+            hugetlb_failures: None,
+        });
+
+        let families = registry.gather();
+        let vm0 = ("vmid", "vm0");
+        let get = |name: &str, labels: &[(&str, &str)]| {
+            let mut labels = labels.to_vec();
+            labels.push(vm0);
+            value(&families, name, &labels)
+        };
+        assert_eq!(
+            get(
+                "firecracker_block_bytes_total",
+                &[("drive", "rootfs"), ("op", "read")]
+            ),
+            Some(4096.0)
+        );
+        assert_eq!(
+            get(
+                "firecracker_block_requests_total",
+                &[("drive", "rootfs"), ("op", "write")]
+            ),
+            Some(1.0)
+        );
+        assert_eq!(
+            get(
+                "firecracker_net_bytes_total",
+                &[("iface", "eth0"), ("direction", "tx")]
+            ),
+            Some(306.0)
+        );
+        assert_eq!(
+            get("firecracker_vcpu_exits_total", &[("reason", "io_out")]),
+            Some(6372.0)
+        );
+        assert_eq!(get("firecracker_balloon_actual_mib", &[]), Some(64.0));
+        assert_eq!(
+            get("firecracker_guest_memory_bytes", &[("kind", "free")]),
+            Some((300 << 20) as f64)
+        );
+        assert_eq!(
+            get("firecracker_guest_memory_bytes", &[("kind", "available")]),
+            None
+        );
+    }
+}
@@ -1,11 +1,15 @@
 /// Module for manipulating host firecracker process
 pub mod firecracker {
 
-    use std::{path::PathBuf, sync::Arc};
+    use std::path::Path;
 
-    use parking_lot::{Condvar, Mutex};
+    use crate::{
+        config::{GlobalConfig, ProcessEnv, StdioTypes},
+        local::{handle_entry, wait_for_path},
+        RtckResult,
+    };
 
-    use crate::{config::GlobalConfig, local::handle_entry, RtckError, RtckErrorClass, RtckResult};
+    use super::resolve_bin;
 
     pub struct Firecracker {
         // Path to local firecracker bin
@@ -17,6 +21,20 @@ pub mod firecracker {
 
         // Path to the config file
         config_path: Option<String>,
+
+        // Maximum size of the MMDS contents
+        mmds_size_limit: Option<usize>,
+
+        // Whether the guest boot time is logged, see `GlobalConfig::boot_timer`
+        boot_timer: bool,
+
+        // Standard streams of the process, stdout carries the guest console
+        stdin: Option<StdioTypes>,
+        stdout: Option<StdioTypes>,
+        stderr: Option<StdioTypes>,
+
+        // Working directory and umask of the process
+        process_env: ProcessEnv,
     }
 
     impl Firecracker {
@@ -30,9 +48,15 @@ pub mod firecracker {
             config.validate()?;
 
             Ok(Self {
-                bin: handle_entry(&config.frck_bin)?,
+                bin: resolve_bin(&handle_entry(&config.frck_bin)?)?,
                 socket: handle_entry(&config.socket_path)?,
                 config_path: config.frck_export_path.clone(),
+                mmds_size_limit: config.mmds_size_limit,
+                boot_timer: config.boot_timer.unwrap_or(false),
+                stdin: config.stdin.clone(),
+                stdout: config.stdout_type(),
+                stderr: config.stderr.clone(),
+                process_env: config.process_env(),
             })
         }
 
@@ -43,39 +67,28 @@ pub mod firecracker {
                 Some(config_path) => c = c.arg("--config-file").arg(&config_path),
                 None => (),
             }
+            if let Some(limit) = self.mmds_size_limit {
+                c = c.arg("--mmds-size-limit").arg(limit.to_string());
+            }
+            if self.boot_timer {
+                c = c.arg("--boot-timer");
+            }
+            if let Some(stdin) = &self.stdin {
+                c = c.stdin(stdin.open_io(true)?);
+            }
+            if let Some(stdout) = &self.stdout {
+                c = c.stdout(stdout.open_io(false)?);
+            }
+            if let Some(stderr) = &self.stderr {
+                c = c.stderr(stderr.open_io(false)?);
+            }
+            self.process_env.apply(c);
             Ok(c.spawn()?)
         }
 
         /// Waiting for the socket set by firecracker
         pub fn waiting_socket(&self, timeout: std::time::Duration) -> RtckResult<()> {
-            let pair = Arc::new((Mutex::new(false), Condvar::new()));
-            let pair_peer = Arc::clone(&pair);
-
-            // Wait for the socket
-            let path = PathBuf::from(&self.socket);
-            std::thread::spawn(move || {
-                let &(ref lock, ref cvar) = &*pair_peer;
-                let mut created = lock.lock();
-
-                while !path.exists() {}
-
-                *created = true;
-                cvar.notify_one();
-            });
-
-            let &(ref lock, ref cvar) = &*pair;
-            let created = lock.lock();
-            if !*created {
-                let result = cvar.wait_for(&mut lock.lock(), timeout);
-                if result.timed_out() {
-                    return Err(RtckError::new(
-                        RtckErrorClass::RemoteError,
-                        "Remote socket set up timeout".to_string(),
-                    ));
-                }
-            }
-
-            Ok(())
+            wait_for_path(Path::new(&self.socket), timeout)
         }
 
         /// Connect to the socket
@@ -88,7 +101,15 @@ pub mod firecracker {
 }
 
 pub mod firecracker_async {
-    use crate::{config::GlobalConfig, local::handle_entry, RtckError, RtckErrorClass, RtckResult};
+    use std::path::Path;
+
+    use crate::{
+        config::{GlobalConfig, ProcessEnv, StdioTypes},
+        local::{handle_entry, wait_for_path_async},
+        RtckResult,
+    };
+
+    use super::resolve_bin;
 
     pub struct FirecrackerAsync {
         // Path to local firecracker bin
@@ -100,6 +121,20 @@ pub mod firecracker_async {
 
         // Path to the config file
         config_path: Option<String>,
+
+        // Maximum size of the MMDS contents
+        mmds_size_limit: Option<usize>,
+
+        // Whether the guest boot time is logged, see `GlobalConfig::boot_timer`
+        boot_timer: bool,
+
+        // Standard streams of the process, stdout carries the guest console
+        stdin: Option<StdioTypes>,
+        stdout: Option<StdioTypes>,
+        stderr: Option<StdioTypes>,
+
+        // Working directory and umask of the process
+        process_env: ProcessEnv,
     }
 
     impl FirecrackerAsync {
@@ -111,9 +146,15 @@ pub mod firecracker_async {
     impl FirecrackerAsync {
         pub fn from_config(config: &GlobalConfig) -> RtckResult<Self> {
             Ok(Self {
-                bin: handle_entry(&config.frck_bin)?,
+                bin: resolve_bin(&handle_entry(&config.frck_bin)?)?,
                 socket: handle_entry(&config.socket_path)?,
                 config_path: config.frck_export_path.clone(),
+                mmds_size_limit: config.mmds_size_limit,
+                boot_timer: config.boot_timer.unwrap_or(false),
+                stdin: config.stdin.clone(),
+                stdout: config.stdout_type(),
+                stderr: config.stderr.clone(),
+                process_env: config.process_env(),
             })
         }
 
@@ -124,23 +165,29 @@ pub mod firecracker_async {
                 Some(config_path) => c = c.arg("--config-file").arg(&config_path),
                 None => (),
             }
+            if let Some(limit) = self.mmds_size_limit {
+                c = c.arg("--mmds-size-limit").arg(limit.to_string());
+            }
+            if self.boot_timer {
+                c = c.arg("--boot-timer");
+            }
+            if let Some(stdin) = &self.stdin {
+                c = c.stdin(stdin.open_io(true)?);
+            }
+            if let Some(stdout) = &self.stdout {
+                c = c.stdout(stdout.open_io(false)?);
+            }
+            if let Some(stderr) = &self.stderr {
+                c = c.stderr(stderr.open_io(false)?);
+            }
+            self.process_env.apply_async(c);
             Ok(c.spawn()?)
         }
 
         /// Waiting for the socket set by firecracker
         #[cfg(feature = "tokio")]
         pub async fn waiting_socket(&self, timeout: tokio::time::Duration) -> RtckResult<()> {
-            // FIXME: better error handling. Give it a class.
-            Ok(tokio::time::timeout(timeout, async {
-                while tokio::fs::try_exists(&self.socket).await.is_err() {}
-            })
-            .await
-            .map_err(|_| {
-                RtckError::new(
-                    RtckErrorClass::RemoteError,
-                    "Remote socket set up timeout".to_string(),
-                )
-            })?)
+            wait_for_path_async(Path::new(&self.socket), timeout).await
         }
 
         /// Connect to the socket
@@ -152,3 +199,237 @@ pub mod firecracker_async {
         }
     }
 }
+
+use crate::{config::resolve_executable, RtckResult};
+
+/// Path of the firecracker binary `bin`, checked before launching it
+fn resolve_bin(bin: &str) -> RtckResult<String> {
+    let resolved = resolve_executable("Firecracker binary", "frck_bin", bin)?;
+    Ok(resolved.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{config::GlobalConfig, local::wait_for_path};
+
+    use super::{firecracker::Firecracker, firecracker_async::FirecrackerAsync};
+
+    fn console_config(tag: &str) -> GlobalConfig {
+        let id = uuid::Uuid::new_v4();
+        GlobalConfig {
+            using_jailer: Some(false),
+            // `echo` prints its arguments to the redirected stdout
+            frck_bin: Some("/bin/echo".into()),
+            socket_path: Some(format!("/tmp/rtck-{tag}-{id}.sock")),
+            console_output: Some(format!("/tmp/rtck-{tag}-{id}.console").into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_console_output() {
+        let config = console_config("console");
+        let frck = Firecracker::from_config(&config).unwrap();
+        let status = frck.launch().unwrap().wait().unwrap();
+        assert!(status.success());
+
+        let console = config.console_output.unwrap();
+        let output = std::fs::read_to_string(&console).unwrap();
+        assert_eq!(output.trim(), format!("--api-sock {}", frck.get_socket()));
+        std::fs::remove_file(console).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_console_output_async() {
+        let config = console_config("console-async");
+        let frck = FirecrackerAsync::from_config(&config).unwrap();
+        let status = frck.launch().await.unwrap().wait().await.unwrap();
+        assert!(status.success());
+
+        let console = config.console_output.unwrap();
+        let output = std::fs::read_to_string(&console).unwrap();
+        assert_eq!(output.trim(), format!("--api-sock {}", frck.get_socket()));
+        std::fs::remove_file(console).unwrap();
+    }
+
+    #[test]
+    fn test_mmds_size_limit_arg() {
+        let config = GlobalConfig {
+            mmds_size_limit: Some(1 << 20),
+            ..console_config("mmds")
+        };
+        let frck = Firecracker::from_config(&config).unwrap();
+        assert!(frck.launch().unwrap().wait().unwrap().success());
+
+        let console = config.console_output.unwrap();
+        let output = std::fs::read_to_string(&console).unwrap();
+        assert_eq!(
+            output.trim(),
+            format!("--api-sock {} --mmds-size-limit 1048576", frck.get_socket())
+        );
+        std::fs::remove_file(console).unwrap();
+    }
+
+    #[test]
+    fn test_boot_timer_arg() {
+        let config = GlobalConfig {
+            boot_timer: Some(true),
+            ..console_config("boot-timer")
+        };
+        let frck = Firecracker::from_config(&config).unwrap();
+        assert!(frck.launch().unwrap().wait().unwrap().success());
+
+        let console = config.console_output.unwrap();
+        let output = std::fs::read_to_string(&console).unwrap();
+        assert_eq!(
+            output.trim(),
+            format!("--api-sock {} --boot-timer", frck.get_socket())
+        );
+        std::fs::remove_file(console).unwrap();
+    }
+
+    #[test]
+    fn test_process_env() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("rtck-process-env-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let script = dir.join("firecracker");
+        std::fs::write(&script, "#!/bin/sh\npwd\numask\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = GlobalConfig {
+            frck_bin: Some(script.to_string_lossy().into_owned()),
+            work_dir: Some(dir.clone()),
+            umask: Some(0o027),
+            ..console_config("process-env")
+        };
+        assert!(config.validate().is_ok());
+        let frck = Firecracker::from_config(&config).unwrap();
+        assert!(frck.launch().unwrap().wait().unwrap().success());
+
+        let console = config.console_output.unwrap();
+        let output = std::fs::read_to_string(&console).unwrap();
+        assert_eq!(output, format!("{}\n0027\n", dir.display()));
+        std::fs::remove_file(console).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_bin() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let missing = GlobalConfig {
+            frck_bin: Some("/nonexistent/rtck/firecracker".into()),
+            ..console_config("missing-bin")
+        };
+        let e = FirecrackerAsync::from_config(&missing).err().unwrap();
+        assert!(e.to_string().contains("Firecracker binary missing"), "{e}");
+
+        let bin = std::env::temp_dir().join(format!("rtck-bin-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&bin, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let not_executable = GlobalConfig {
+            frck_bin: Some(bin.to_string_lossy().into_owned()),
+            ..console_config("not-executable-bin")
+        };
+        let e = FirecrackerAsync::from_config(&not_executable)
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("is not executable"), "{e}");
+        assert!(e.to_string().contains(&*bin.to_string_lossy()), "{e}");
+        std::fs::remove_file(&bin).unwrap();
+
+        // Bare names are looked up in the PATH
+        let bare = GlobalConfig {
+            frck_bin: Some("echo".into()),
+            ..console_config("bare-bin")
+        };
+        let frck = Firecracker::from_config(&bare).unwrap();
+        assert!(frck.launch().unwrap().wait().unwrap().success());
+        let not_found = GlobalConfig {
+            frck_bin: Some("rtck-no-such-firecracker".into()),
+            ..console_config("not-found-bin")
+        };
+        let e = FirecrackerAsync::from_config(&not_found).err().unwrap();
+        assert!(e.to_string().contains("not found in PATH"), "{e}");
+    }
+
+    /// CPU time spent so far by the calling thread
+    fn thread_cpu_time() -> std::time::Duration {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) },
+            0
+        );
+        let micros = |tv: libc::timeval| tv.tv_sec as u64 * 1_000_000 + tv.tv_usec as u64;
+        std::time::Duration::from_micros(micros(usage.ru_utime) + micros(usage.ru_stime))
+    }
+
+    #[test]
+    fn test_waiting_socket() {
+        use std::time::{Duration, Instant};
+
+        let dir = std::env::temp_dir().join(format!("rtck-wait-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let socket = dir.join("firecracker.sock");
+        let frck = Firecracker::from_config(&GlobalConfig {
+            socket_path: Some(socket.to_string_lossy().into_owned()),
+            ..console_config("wait")
+        })
+        .unwrap();
+        let (start, cpu) = (Instant::now(), thread_cpu_time());
+        assert!(frck.waiting_socket(Duration::from_millis(300)).is_err());
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        // Sleeping, not spinning, through the timeout
+        assert!(thread_cpu_time() - cpu < Duration::from_millis(50));
+
+        // Polling a directory not there yet to watch
+        let (start, cpu) = (Instant::now(), thread_cpu_time());
+        let absent = dir.join("jail/firecracker.sock");
+        assert!(wait_for_path(&absent, Duration::from_millis(300)).is_err());
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(thread_cpu_time() - cpu < Duration::from_millis(50));
+
+        let creator = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            std::fs::write(socket, "").unwrap();
+        });
+        let start = Instant::now();
+        frck.waiting_socket(Duration::from_secs(5)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        creator.join().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_waiting_socket_async() {
+        use std::time::{Duration, Instant};
+
+        let dir = std::env::temp_dir().join(format!("rtck-wait-async-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let socket = dir.join("firecracker.sock");
+        let frck = FirecrackerAsync::from_config(&GlobalConfig {
+            socket_path: Some(socket.to_string_lossy().into_owned()),
+            ..console_config("wait-async")
+        })
+        .unwrap();
+        let (start, cpu) = (Instant::now(), thread_cpu_time());
+        assert!(frck
+            .waiting_socket(Duration::from_millis(300))
+            .await
+            .is_err());
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(thread_cpu_time() - cpu < Duration::from_millis(50));
+
+        let creator = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            tokio::fs::write(socket, "").await.unwrap();
+        });
+        let start = Instant::now();
+        frck.waiting_socket(Duration::from_secs(5)).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        creator.await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,14 +1,19 @@
 
 pub mod jailer {
-    use std::{path::PathBuf, sync::Arc};
+    use std::path::{Path, PathBuf};
 
-    use parking_lot::{Condvar, Mutex};
     use serde::{Deserialize, Serialize};
 
     use crate::{
-        config::GlobalConfig, handle_entry_default, handle_entry_ref, local::handle_entry, RtckError, RtckErrorClass, RtckResult
+        config::{GlobalConfig, ProcessEnv, StdioTypes},
+        handle_entry_default, handle_entry_ref,
+        local::{handle_entry, wait_for_path},
+        models::{vsock::Vsock, Validate},
+        RtckError, RtckErrorClass, RtckResult,
     };
 
+    use super::{create_dir_owned, read_pid_file, resolve_bin, PID_FILE_POLL_INTERVAL};
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct Jailer {
         // Path to local jailer bin
@@ -33,12 +38,32 @@ pub mod jailer {
         // Daemonize or not
         daemonize: bool,
 
+        // Fork firecracker into a new PID namespace or not
+        new_pid_ns: bool,
+
+        // Network namespace to join
+        netns: Option<String>,
+
         // Desired path of the socket
         socket: Option<String>,
 
         // Path to the config file
         config_path: Option<String>,
 
+        // Maximum size of the MMDS contents
+        mmds_size_limit: Option<usize>,
+
+        // Whether the guest boot time is logged, see `GlobalConfig::boot_timer`
+        boot_timer: bool,
+
+        // Standard streams of the process, stdout carries the guest console
+        stdin: Option<StdioTypes>,
+        stdout: Option<StdioTypes>,
+        stderr: Option<StdioTypes>,
+
+        // Working directory and umask of the process
+        process_env: ProcessEnv,
+
         // Jailer workspace directory
         jailer_workspace_dir: Option<PathBuf>,
 
@@ -59,6 +84,12 @@ pub mod jailer {
 
         // Metrics path seen by Rtck
         metrics_path_export: Option<PathBuf>,
+
+        // Vsock devices whose sockets are placed in the jail
+        vsocks: Vec<Vsock>,
+
+        // Vsock socket paths seen by Rtck
+        vsock_paths_export: Vec<PathBuf>,
     }
 
     impl Jailer {
@@ -74,6 +105,10 @@ pub mod jailer {
             self.metrics_path_export.as_ref()
         }
 
+        pub fn get_vsock_paths_exported(&self) -> &Vec<PathBuf> {
+            &self.vsock_paths_export
+        }
+
         pub fn get_jailer_workspace_dir(&self) -> RtckResult<&PathBuf> {
             Ok(handle_entry_ref(self.jailer_workspace_dir.as_ref())?)
         }
@@ -88,7 +123,7 @@ pub mod jailer {
 
             const DEFAULT_CHROOT_BASE_DIR: &'static str = "/srv/jailer";
             Ok(Self {
-                bin: handle_entry(&jailer_config.jailer_bin)?,
+                bin: resolve_bin(&handle_entry(&jailer_config.jailer_bin)?)?,
                 id: handle_entry(&jailer_config.id)?,
                 exec_file: handle_entry(&jailer_config.exec_file)?,
                 uid: handle_entry(&jailer_config.uid)?,
@@ -98,8 +133,16 @@ pub mod jailer {
                     DEFAULT_CHROOT_BASE_DIR.into(),
                 ),
                 daemonize: jailer_config.daemonize.unwrap_or(false),
+                new_pid_ns: jailer_config.new_pid_ns.unwrap_or(false),
+                netns: config.frck_config.as_ref().and_then(|c| c.net_ns.clone()),
                 socket: config.socket_path.clone(),
                 config_path: config.frck_export_path.clone(),
+                mmds_size_limit: config.mmds_size_limit,
+                boot_timer: config.boot_timer.unwrap_or(false),
+                stdin: config.stdin.clone(),
+                stdout: config.stdout_type(),
+                stderr: config.stderr.clone(),
+                process_env: config.process_env(),
 
                 jailer_workspace_dir: None,
                 socket_path_export: None,
@@ -120,6 +163,11 @@ pub mod jailer {
                     },
                 },
                 metrics_path_export: None,
+                vsocks: match &config.frck_config {
+                    None => Vec::new(),
+                    Some(frck_config) => frck_config.vsock_devices.clone().unwrap_or_default(),
+                },
+                vsock_paths_export: Vec::new(),
             })
         }
 
@@ -140,11 +188,21 @@ pub mod jailer {
 
             self.jailer_workspace_dir = Some(jailer_workspace_dir.clone());
 
-            const DEFAULT_SOCKET_PATH_UNDER_JAILER: &'static str = "run/firecracker.socket";
-            let socket_path =
-                handle_entry_default(&self.socket, DEFAULT_SOCKET_PATH_UNDER_JAILER.to_string());
+            let socket_path = handle_entry_default(
+                &self.socket,
+                GlobalConfig::DEFAULT_JAILED_SOCKET_PATH.to_string(),
+            );
             self.socket_path_export = Some(jailer_workspace_dir.join(socket_path));
 
+            // The jail itself is left to the jailer, while firecracker binds the API
+            // socket after dropping privileges, as it does for the vsock sockets.
+            std::fs::create_dir_all(&jailer_workspace_dir)?;
+            if let Some(parent) = self.socket_path_export.as_ref().and_then(|p| p.parent()) {
+                if parent != jailer_workspace_dir {
+                    create_dir_owned(parent, self.uid, self.gid)?;
+                }
+            }
+
             match &self.config_path {
                 None => (),
                 Some(config_path) => {
@@ -171,68 +229,126 @@ pub mod jailer {
                 }
             }
 
+            // Firecracker binds vsock sockets inside the jail after dropping privileges,
+            // so the parent directories must exist and belong to the jailed user.
+            self.vsock_paths_export.clear();
+            for vsock in &self.vsocks {
+                vsock.validate()?;
+                let path = vsock.jailed_path(&jailer_workspace_dir)?;
+                if let Some(parent) = path.parent() {
+                    create_dir_owned(parent, self.uid, self.gid)?;
+                }
+                self.vsock_paths_export.push(path);
+            }
+
             Ok(())
         }
 
-        pub fn launch(&self) -> RtckResult<std::process::Child> {
-            let mut cmd = std::process::Command::new(&self.bin);
-            cmd.args(vec!["--id", &self.id]);
-            cmd.args(vec!["--uid", &self.uid.to_string()]);
-            cmd.args(vec!["--gid", &self.gid.to_string()]);
-            cmd.args(vec!["--exec-file", &self.exec_file.to_string()]);
-            cmd.args(vec!["--chroot-base-dir", &self.chroot_base_dir]);
+        /// Command line arguments of the jailer, firecracker ones after `--`
+        pub fn args(&self) -> Vec<String> {
+            let mut args = vec![
+                "--id".to_string(),
+                self.id.clone(),
+                "--uid".to_string(),
+                self.uid.to_string(),
+                "--gid".to_string(),
+                self.gid.to_string(),
+                "--exec-file".to_string(),
+                self.exec_file.clone(),
+                "--chroot-base-dir".to_string(),
+                self.chroot_base_dir.clone(),
+            ];
+            if let Some(netns) = &self.netns {
+                args.extend(["--netns".to_string(), netns.clone()]);
+            }
+            if self.new_pid_ns {
+                args.push("--new-pid-ns".to_string());
+            }
             if self.daemonize {
-                cmd.arg("--daemonize");
+                args.push("--daemonize".to_string());
             }
-            cmd.arg("--");
+            args.push("--".to_string());
 
-            match &self.socket {
-                None => (),
-                Some(path) => {
-                    cmd.args(vec!["--api-sock", path]);
-                }
+            if let Some(path) = &self.socket {
+                args.extend(["--api-sock".to_string(), path.clone()]);
             }
 
-            match &self.config_path {
-                None => (),
-                Some(path) => {
-                    cmd.args(vec!["--config-file", path]);
-                }
+            if let Some(path) = &self.config_path {
+                args.extend(["--config-file".to_string(), path.clone()]);
             }
 
-            Ok(cmd.spawn()?)
+            if let Some(limit) = self.mmds_size_limit {
+                args.extend(["--mmds-size-limit".to_string(), limit.to_string()]);
+            }
+
+            if self.boot_timer {
+                args.push("--boot-timer".to_string());
+            }
+
+            args
         }
 
-        /// Waiting for the socket set by firecracker
-        pub fn waiting_socket(&self, timeout: std::time::Duration) -> RtckResult<()> {
-            let pair = Arc::new((Mutex::new(false), Condvar::new()));
-            let pair_peer = Arc::clone(&pair);
-
-            // Wait for the socket
-            let path = PathBuf::from(handle_entry_ref(self.socket.as_ref())?);
-            std::thread::spawn(move || {
-                let &(ref lock, ref cvar) = &*pair_peer;
-                let mut created = lock.lock();
-
-                while !path.exists() {}
-
-                *created = true;
-                cvar.notify_one();
-            });
-
-            let &(ref lock, ref cvar) = &*pair;
-            let created = lock.lock();
-            if !*created {
-                let result = cvar.wait_for(&mut lock.lock(), timeout);
-                if result.timed_out() {
+        /// Path of the file the jailer reports the pid of firecracker in, if
+        /// forked into a new PID namespace. The pid of the spawned jailer is not
+        /// the one of firecracker then, and firecracker sees itself as pid 1.
+        pub fn pid_file_path(&self) -> RtckResult<Option<PathBuf>> {
+            if !self.new_pid_ns {
+                return Ok(None);
+            }
+            use crate::possible_malformed_entry;
+            let exec_file = PathBuf::from(&self.exec_file);
+            let exec_file_name = possible_malformed_entry(exec_file.file_name())?;
+            let mut file_name = exec_file_name.to_os_string();
+            file_name.push(".pid");
+            Ok(Some(self.get_jailer_workspace_dir()?.join(file_name)))
+        }
+
+        /// Wait for the pid file of firecracker forked into a new PID namespace
+        pub fn read_pid(&self, timeout: std::time::Duration) -> RtckResult<u32> {
+            let path = self.pid_file_path()?.ok_or(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Firecracker not forked into a new PID namespace",
+            ))?;
+            let deadline = std::time::Instant::now() + timeout;
+            loop {
+                if let Some(pid) = read_pid_file(&path)? {
+                    return Ok(pid);
+                }
+                if std::time::Instant::now() >= deadline {
                     return Err(RtckError::new(
                         RtckErrorClass::RemoteError,
-                        "Remote socket set up timeout".to_string(),
+                        format!("Pid file {:?} set up timeout", path),
                     ));
                 }
+                std::thread::sleep(PID_FILE_POLL_INTERVAL);
             }
+        }
 
-            Ok(())
+        pub fn launch(&self) -> RtckResult<std::process::Child> {
+            let mut cmd = std::process::Command::new(&self.bin);
+            cmd.args(self.args());
+
+            if self.daemonize && self.stdout.is_some() {
+                log::warn!("[Jailer::launch stdout discarded since daemonized]");
+            }
+            if let Some(stdin) = &self.stdin {
+                cmd.stdin(stdin.open_io(true)?);
+            }
+            if let Some(stdout) = &self.stdout {
+                cmd.stdout(stdout.open_io(false)?);
+            }
+            if let Some(stderr) = &self.stderr {
+                cmd.stderr(stderr.open_io(false)?);
+            }
+
+            self.process_env.apply(&mut cmd);
+
+            Ok(cmd.spawn()?)
+        }
+
+        /// Waiting for the socket set by firecracker
+        pub fn waiting_socket(&self, timeout: std::time::Duration) -> RtckResult<()> {
+            wait_for_path(Path::new(handle_entry_ref(self.socket.as_ref())?), timeout)
         }
 
         /// Connect to the socket
@@ -250,9 +366,15 @@ pub mod jailer_async {
     use std::path::PathBuf;
 
     use crate::{
-        config::GlobalConfig, handle_entry_default, handle_entry_ref, local::handle_entry, RtckError, RtckErrorClass, RtckResult
+        config::{GlobalConfig, ProcessEnv, StdioTypes},
+        handle_entry_default, handle_entry_ref,
+        local::{handle_entry, wait_for_path_async},
+        models::{vsock::Vsock, Validate},
+        RtckError, RtckErrorClass, RtckResult,
     };
 
+    use super::{create_dir_owned, read_pid_file, resolve_bin, PID_FILE_POLL_INTERVAL};
+
     pub struct JailerAsync {
         // Path to local jailer bin
         // Usually something like `/usr/bin/jailer`
@@ -276,12 +398,32 @@ pub mod jailer_async {
         // Daemonize or not
         daemonize: bool,
 
+        // Fork firecracker into a new PID namespace or not
+        new_pid_ns: bool,
+
+        // Network namespace to join
+        netns: Option<String>,
+
         // Desired path of the socket
         socket: Option<String>,
 
         // Path to the config file
         config_path: Option<String>,
 
+        // Maximum size of the MMDS contents
+        mmds_size_limit: Option<usize>,
+
+        // Whether the guest boot time is logged, see `GlobalConfig::boot_timer`
+        boot_timer: bool,
+
+        // Standard streams of the process, stdout carries the guest console
+        stdin: Option<StdioTypes>,
+        stdout: Option<StdioTypes>,
+        stderr: Option<StdioTypes>,
+
+        // Working directory and umask of the process
+        process_env: ProcessEnv,
+
         // Jailer workspace directory
         jailer_workspace_dir: Option<PathBuf>,
 
@@ -302,6 +444,12 @@ pub mod jailer_async {
 
         // Metrics path seen by Rtck
         metrics_path_export: Option<PathBuf>,
+
+        // Vsock devices whose sockets are placed in the jail
+        vsocks: Vec<Vsock>,
+
+        // Vsock socket paths seen by Rtck
+        vsock_paths_export: Vec<PathBuf>,
     }
 
     impl JailerAsync {
@@ -317,6 +465,10 @@ pub mod jailer_async {
             self.metrics_path_export.as_ref()
         }
 
+        pub fn get_vsock_paths_exported(&self) -> &Vec<PathBuf> {
+            &self.vsock_paths_export
+        }
+
         pub fn get_jailer_workspace_dir(&self) -> RtckResult<&PathBuf> {
             Ok(handle_entry_ref(self.jailer_workspace_dir.as_ref())?)
         }
@@ -333,7 +485,7 @@ pub mod jailer_async {
 
             const DEFAULT_CHROOT_BASE_DIR: &'static str = "/srv/jailer";
             Ok(Self {
-                bin: handle_entry(&jailer_config.jailer_bin)?,
+                bin: resolve_bin(&handle_entry(&jailer_config.jailer_bin)?)?,
                 id: handle_entry(&jailer_config.id)?,
                 exec_file: handle_entry(&jailer_config.exec_file)?,
                 uid: handle_entry(&jailer_config.uid)?,
@@ -343,8 +495,16 @@ pub mod jailer_async {
                     DEFAULT_CHROOT_BASE_DIR.into(),
                 ),
                 daemonize: jailer_config.daemonize.unwrap_or(false),
+                new_pid_ns: jailer_config.new_pid_ns.unwrap_or(false),
+                netns: config.frck_config.as_ref().and_then(|c| c.net_ns.clone()),
                 socket: config.socket_path.clone(),
                 config_path: config.frck_export_path.clone(),
+                mmds_size_limit: config.mmds_size_limit,
+                boot_timer: config.boot_timer.unwrap_or(false),
+                stdin: config.stdin.clone(),
+                stdout: config.stdout_type(),
+                stderr: config.stderr.clone(),
+                process_env: config.process_env(),
 
                 jailer_workspace_dir: None,
                 socket_path_export: None,
@@ -365,6 +525,11 @@ pub mod jailer_async {
                     },
                 },
                 metrics_path_export: None,
+                vsocks: match &config.frck_config {
+                    None => Vec::new(),
+                    Some(frck_config) => frck_config.vsock_devices.clone().unwrap_or_default(),
+                },
+                vsock_paths_export: Vec::new(),
             })
         }
 
@@ -385,11 +550,21 @@ pub mod jailer_async {
 
             self.jailer_workspace_dir = Some(jailer_workspace_dir.clone());
 
-            const DEFAULT_SOCKET_PATH_UNDER_JAILER: &'static str = "run/firecracker.socket";
-            let socket_path =
-                handle_entry_default(&self.socket, DEFAULT_SOCKET_PATH_UNDER_JAILER.to_string());
+            let socket_path = handle_entry_default(
+                &self.socket,
+                GlobalConfig::DEFAULT_JAILED_SOCKET_PATH.to_string(),
+            );
             self.socket_path_export = Some(jailer_workspace_dir.join(socket_path));
 
+            // The jail itself is left to the jailer, while firecracker binds the API
+            // socket after dropping privileges, as it does for the vsock sockets.
+            std::fs::create_dir_all(&jailer_workspace_dir)?;
+            if let Some(parent) = self.socket_path_export.as_ref().and_then(|p| p.parent()) {
+                if parent != jailer_workspace_dir {
+                    create_dir_owned(parent, self.uid, self.gid)?;
+                }
+            }
+
             match &self.config_path {
                 None => (),
                 Some(config_path) => {
@@ -416,54 +591,130 @@ pub mod jailer_async {
                 }
             }
 
+            // Firecracker binds vsock sockets inside the jail after dropping privileges,
+            // so the parent directories must exist and belong to the jailed user.
+            self.vsock_paths_export.clear();
+            for vsock in &self.vsocks {
+                vsock.validate()?;
+                let path = vsock.jailed_path(&jailer_workspace_dir)?;
+                if let Some(parent) = path.parent() {
+                    create_dir_owned(parent, self.uid, self.gid)?;
+                }
+                self.vsock_paths_export.push(path);
+            }
+
             Ok(())
         }
 
-        pub async fn launch(&self) -> RtckResult<tokio::process::Child> {
-            let mut cmd = tokio::process::Command::new(&self.bin);
-            cmd.args(vec!["--id", &self.id]);
-            cmd.args(vec!["--uid", &self.uid.to_string()]);
-            cmd.args(vec!["--gid", &self.gid.to_string()]);
-            cmd.args(vec!["--exec-file", &self.exec_file.to_string()]);
-            cmd.args(vec!["--chroot-base-dir", &self.chroot_base_dir]);
+        /// Command line arguments of the jailer, firecracker ones after `--`
+        pub fn args(&self) -> Vec<String> {
+            let mut args = vec![
+                "--id".to_string(),
+                self.id.clone(),
+                "--uid".to_string(),
+                self.uid.to_string(),
+                "--gid".to_string(),
+                self.gid.to_string(),
+                "--exec-file".to_string(),
+                self.exec_file.clone(),
+                "--chroot-base-dir".to_string(),
+                self.chroot_base_dir.clone(),
+            ];
+            if let Some(netns) = &self.netns {
+                args.extend(["--netns".to_string(), netns.clone()]);
+            }
+            if self.new_pid_ns {
+                args.push("--new-pid-ns".to_string());
+            }
             if self.daemonize {
-                cmd.arg("--daemonize");
+                args.push("--daemonize".to_string());
             }
-            cmd.arg("--");
+            args.push("--".to_string());
 
-            match &self.socket {
-                None => (),
-                Some(path) => {
-                    cmd.args(vec!["--api-sock", path]);
-                }
+            if let Some(path) = &self.socket {
+                args.extend(["--api-sock".to_string(), path.clone()]);
             }
 
-            match &self.config_path {
-                None => (),
-                Some(path) => {
-                    cmd.args(vec!["--config-file", path]);
-                }
+            if let Some(path) = &self.config_path {
+                args.extend(["--config-file".to_string(), path.clone()]);
             }
 
-            Ok(cmd.spawn()?)
+            if let Some(limit) = self.mmds_size_limit {
+                args.extend(["--mmds-size-limit".to_string(), limit.to_string()]);
+            }
+
+            if self.boot_timer {
+                args.push("--boot-timer".to_string());
+            }
+
+            args
         }
 
-        /// Waiting for the socket set by firecracker
+        /// Path of the file the jailer reports the pid of firecracker in, if
+        /// forked into a new PID namespace. The pid of the spawned jailer is not
+        /// the one of firecracker then, and firecracker sees itself as pid 1.
+        pub fn pid_file_path(&self) -> RtckResult<Option<PathBuf>> {
+            if !self.new_pid_ns {
+                return Ok(None);
+            }
+            use crate::possible_malformed_entry;
+            let exec_file = PathBuf::from(&self.exec_file);
+            let exec_file_name = possible_malformed_entry(exec_file.file_name())?;
+            let mut file_name = exec_file_name.to_os_string();
+            file_name.push(".pid");
+            Ok(Some(self.get_jailer_workspace_dir()?.join(file_name)))
+        }
+
+        /// Wait for the pid file of firecracker forked into a new PID namespace
         #[cfg(feature = "tokio")]
-        pub async fn waiting_socket(&self, timeout: tokio::time::Duration) -> RtckResult<()> {
-            let socket_path = handle_entry(&self.socket_path_export)?;
-            let socket_path = socket_path.as_os_str();
-            // FIXME: better error handling. Give it a class.
-            Ok(tokio::time::timeout(timeout, async {
-                while tokio::fs::try_exists(socket_path).await.is_err() {}
+        pub async fn read_pid(&self, timeout: tokio::time::Duration) -> RtckResult<u32> {
+            let path = self.pid_file_path()?.ok_or(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Firecracker not forked into a new PID namespace",
+            ))?;
+            tokio::time::timeout(timeout, async {
+                loop {
+                    if let Some(pid) = read_pid_file(&path)? {
+                        return Ok(pid);
+                    }
+                    tokio::time::sleep(PID_FILE_POLL_INTERVAL).await;
+                }
             })
             .await
             .map_err(|_| {
                 RtckError::new(
                     RtckErrorClass::RemoteError,
-                    "Remote socket set up timeout".to_string(),
+                    format!("Pid file {:?} set up timeout", path),
                 )
-            })?)
+            })?
+        }
+
+        pub async fn launch(&self) -> RtckResult<tokio::process::Child> {
+            let mut cmd = tokio::process::Command::new(&self.bin);
+            cmd.args(self.args());
+
+            if self.daemonize && self.stdout.is_some() {
+                log::warn!("[JailerAsync::launch stdout discarded since daemonized]");
+            }
+            if let Some(stdin) = &self.stdin {
+                cmd.stdin(stdin.open_io(true)?);
+            }
+            if let Some(stdout) = &self.stdout {
+                cmd.stdout(stdout.open_io(false)?);
+            }
+            if let Some(stderr) = &self.stderr {
+                cmd.stderr(stderr.open_io(false)?);
+            }
+
+            self.process_env.apply_async(&mut cmd);
+
+            Ok(cmd.spawn()?)
+        }
+
+        /// Waiting for the socket set by firecracker
+        #[cfg(feature = "tokio")]
+        pub async fn waiting_socket(&self, timeout: tokio::time::Duration) -> RtckResult<()> {
+            wait_for_path_async(&handle_entry(&self.socket_path_export)?, timeout).await
         }
 
         /// Connect to the socket
@@ -474,4 +725,204 @@ pub mod jailer_async {
             ))
         }
     }
-}
\ No newline at end of file
+}
+
+use std::path::Path;
+
+use crate::{config::resolve_executable, RtckError, RtckErrorClass, RtckResult};
+
+/// Create `dir` together with its missing ancestors and hand them over to `uid`:`gid`,
+/// a restrictive umask locking `uid` out of them otherwise. Existing ancestors are left
+/// alone. Directories created here are removed again if an ownership change fails.
+pub(crate) fn create_dir_owned<P: AsRef<Path>>(dir: P, uid: u32, gid: u32) -> RtckResult<()> {
+    let dir = dir.as_ref();
+
+    let created: Vec<&Path> = dir.ancestors().take_while(|p| !p.exists()).collect();
+    std::fs::create_dir_all(dir)?;
+
+    // The directory even if it existed, and the ancestors created along
+    let owned = if created.is_empty() {
+        vec![dir]
+    } else {
+        created.clone()
+    };
+    for path in owned {
+        if let Err(e) = std::os::unix::fs::chown(path, Some(uid), Some(gid)) {
+            log::error!("[create_dir_owned fail to chown {:?}, error = {}]", path, e);
+            if let Some(first_created) = created.last() {
+                let _ = std::fs::remove_dir_all(first_created);
+            }
+            return Err(RtckError::new(
+                RtckErrorClass::IoError,
+                format!("Fail to change owner of {:?} to {uid}:{gid}", path),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Path of the jailer binary `bin`, checked before launching it
+fn resolve_bin(bin: &str) -> RtckResult<String> {
+    let resolved = resolve_executable("Jailer binary", "jailer_bin", bin)?;
+    Ok(resolved.to_string_lossy().into_owned())
+}
+
+/// Interval between two looks for the pid file of firecracker
+const PID_FILE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Pid written in `path`, none while the file is missing or not complete
+fn read_pid_file(path: &Path) -> RtckResult<Option<u32>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content.trim().parse().ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::create_dir_owned;
+
+    #[test]
+    fn test_create_dir_owned() {
+        let base = std::env::temp_dir().join(format!("rtck-jail-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&base).unwrap();
+        // Files we create are owned by our effective uid and gid
+        let probe = base.join("probe");
+        std::fs::write(&probe, b"").unwrap();
+        let meta = std::fs::metadata(&probe).unwrap();
+        let (uid, gid) = (meta.uid(), meta.gid());
+
+        // Handing directories over to someone else takes root
+        let (uid, gid) = match uid {
+            0 => (4242, 4242),
+            _ => (uid, gid),
+        };
+
+        let dir = base.join("root/run/vsock");
+        create_dir_owned(&dir, uid, gid).expect("Fail to create owned directory");
+        // Every directory created along, not only the last one
+        for created in ["root", "root/run", "root/run/vsock"] {
+            let meta = std::fs::metadata(base.join(created)).unwrap();
+            assert_eq!((meta.uid(), meta.gid()), (uid, gid), "{created}");
+        }
+        // Existing ancestors are left alone
+        assert_eq!(
+            std::fs::metadata(&base).unwrap().uid(),
+            std::fs::metadata(&probe).unwrap().uid()
+        );
+
+        // Idempotent on existing directories
+        create_dir_owned(&dir, uid, gid).expect("Fail to create owned directory twice");
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_jail_socket_dir() {
+        use super::jailer::Jailer;
+        use crate::config::{GlobalConfig, JailerConfig};
+
+        let base = std::env::temp_dir().join(format!("rtck-jail-{}", uuid::Uuid::new_v4()));
+        // Handing directories over to someone else takes root
+        let (uid, gid) = match unsafe { (libc::geteuid(), libc::getegid()) } {
+            (0, _) => (4242, 4242),
+            ids => ids,
+        };
+        let config = GlobalConfig {
+            using_jailer: Some(true),
+            jailer_config: Some(JailerConfig {
+                gid: Some(gid),
+                uid: Some(uid),
+                id: Some("vm0".to_string()),
+                exec_file: Some("/bin/true".to_string()),
+                jailer_bin: Some("/bin/true".to_string()),
+                chroot_base_dir: Some(base.to_string_lossy().into_owned()),
+                ..Default::default()
+            }),
+            socket_path: Some("api/v1/firecracker.socket".to_string()),
+            ..Default::default()
+        };
+
+        let mut jailer = Jailer::from_config(&config).unwrap();
+        jailer.jail().unwrap();
+        let root = base.join("true/vm0/root");
+        for dir in ["api", "api/v1"] {
+            let meta = std::fs::metadata(root.join(dir)).unwrap();
+            assert_eq!((meta.uid(), meta.gid()), (uid, gid), "{dir}");
+        }
+        // The jail itself is not handed over
+        let euid = unsafe { libc::geteuid() };
+        assert_eq!(std::fs::metadata(&root).unwrap().uid(), euid);
+
+        // Idempotent once jailed
+        jailer.jail().unwrap();
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_new_pid_ns() {
+        use super::jailer::Jailer;
+        use crate::config::{FirecrackerConfig, GlobalConfig, JailerConfig};
+
+        let base = std::env::temp_dir().join(format!("rtck-jail-{}", uuid::Uuid::new_v4()));
+        let jailer_config = JailerConfig {
+            gid: Some(100),
+            uid: Some(100),
+            id: Some("vm0".to_string()),
+            exec_file: Some("/usr/bin/firecracker".to_string()),
+            jailer_bin: Some("/bin/true".to_string()),
+            chroot_base_dir: Some(base.to_string_lossy().into_owned()),
+            new_pid_ns: Some(true),
+            ..Default::default()
+        };
+        let config = GlobalConfig {
+            using_jailer: Some(true),
+            jailer_config: Some(jailer_config),
+            frck_config: Some(FirecrackerConfig {
+                net_ns: Some("/var/run/netns/vm0".to_string()),
+                ..Default::default()
+            }),
+            boot_timer: Some(true),
+            ..Default::default()
+        };
+
+        let mut jailer = Jailer::from_config(&config).unwrap();
+        jailer.jail().unwrap();
+
+        // Jailer flags go before the separator
+        let args = jailer.args();
+        let separator = args.iter().position(|arg| arg == "--").unwrap();
+        let flag = args.iter().position(|arg| arg == "--new-pid-ns").unwrap();
+        assert!(flag < separator, "{args:?}");
+        let netns = args.iter().position(|arg| arg == "--netns").unwrap();
+        assert_eq!(args[netns + 1], "/var/run/netns/vm0");
+        // Firecracker flags after it
+        let boot_timer = args.iter().position(|arg| arg == "--boot-timer").unwrap();
+        assert!(boot_timer > separator, "{args:?}");
+
+        // The pid of firecracker is not the one of the jailer, read it from
+        // the pid file the jailer leaves at the root of the jail
+        let pid_file = jailer.pid_file_path().unwrap().unwrap();
+        assert_eq!(pid_file, base.join("firecracker/vm0/root/firecracker.pid"));
+        let timeout = std::time::Duration::from_millis(50);
+        assert!(jailer.read_pid(timeout).is_err());
+        std::fs::create_dir_all(pid_file.parent().unwrap()).unwrap();
+        std::fs::write(&pid_file, "4242\n").unwrap();
+        assert_eq!(jailer.read_pid(timeout).unwrap(), 4242);
+
+        // Without the flag the spawned jailer is firecracker
+        let mut config = config;
+        config.jailer_config.as_mut().unwrap().new_pid_ns = None;
+        let mut jailer = Jailer::from_config(&config).unwrap();
+        jailer.jail().unwrap();
+        assert!(!jailer.args().contains(&"--new-pid-ns".to_string()));
+        assert!(jailer.pid_file_path().unwrap().is_none());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -1,80 +1,239 @@
 pub mod command;
 pub mod config;
 pub mod events;
+#[cfg(feature = "prometheus")]
+pub mod exporter;
 pub mod firecracker;
 pub mod jailer;
 pub mod local;
 pub mod machine;
 pub mod micro_http;
 pub mod models;
+pub mod network;
 pub mod ops_res;
+pub mod retry;
 pub mod ser;
+pub mod uffd;
+pub mod utils;
+pub mod vsock_cid;
 pub mod database;
 
-use std::{io, num::ParseIntError, string::FromUtf8Error, sync::PoisonError};
+use std::{
+    io,
+    num::ParseIntError,
+    string::FromUtf8Error,
+    sync::{Arc, PoisonError},
+};
 
 use serde::{Deserialize, Serialize};
 
+use models::error::{InternalError, InternalErrorKind};
+
 mod rtck_conn {
     use std::io::{BufRead, Write};
 
     use crate::{
-        micro_http::{http_io, HttpResponse},
-        RtckResult,
+        micro_http::{http_io, ApiTrace, HttpHead, HttpResponse},
+        RtckError, RtckErrorClass, RtckResult,
     };
 
+    /// Re-establish the connection once the remote closed it
+    pub type Reconnect<S> = Box<dyn FnMut() -> RtckResult<S> + Send>;
+
     pub struct RtckConn<S> {
         // Stream of connection
         pub(crate) stream: S,
+
+        // Whether the remote announced `Connection: close`
+        closed: bool,
+
+        // How to get a new stream after the remote closed the connection
+        reconnect: Option<Reconnect<S>>,
+
+        // Tracing of the exchanges, and whether the last request was an MMDS one
+        trace: Option<ApiTrace>,
+        trace_mmds: bool,
+    }
+
+    impl<S> std::fmt::Debug for RtckConn<S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("RtckConn")
+                .field("closed", &self.closed)
+                .field("reconnect", &self.reconnect.is_some())
+                .finish_non_exhaustive()
+        }
     }
 
     impl<S> RtckConn<S> {
         pub fn from_stream(stream: S) -> RtckConn<S> {
-            RtckConn { stream }
+            RtckConn {
+                stream,
+                closed: false,
+                reconnect: None,
+                trace: None,
+                trace_mmds: false,
+            }
+        }
+
+        pub fn set_reconnect(&mut self, reconnect: Reconnect<S>) {
+            self.reconnect = Some(reconnect);
+        }
+
+        pub fn set_trace(&mut self, trace: Option<ApiTrace>) {
+            self.trace = trace;
+        }
+
+        pub fn stream_mut(&mut self) -> &mut S {
+            &mut self.stream
+        }
+
+        /// Give up the stream, e.g. after an exchange failed midway
+        pub fn mark_closed(&mut self) {
+            self.closed = true;
+        }
+
+        fn ensure_open(&mut self) -> RtckResult<()> {
+            if self.closed {
+                let reconnect = self.reconnect.as_mut().ok_or(RtckError::new(
+                    RtckErrorClass::IoError,
+                    "Connection closed by remote",
+                ))?;
+                log::info!("[RtckConn::ensure_open reconnecting]");
+                self.stream = reconnect()?;
+                self.closed = false;
+            }
+            Ok(())
         }
     }
 
     impl<S: BufRead> RtckConn<S> {
         pub fn read_response(&mut self) -> RtckResult<HttpResponse> {
-            http_io::read_response(&mut self.stream)
+            let res = http_io::read_response(&mut self.stream)?;
+            self.closed = res.is_connection_close();
+            if let Some(trace) = &self.trace {
+                trace.response(&res, self.trace_mmds);
+            }
+            Ok(res)
+        }
+
+        /// Read only the head of a response, leaving the body in the stream
+        pub fn read_head(&mut self) -> RtckResult<HttpHead> {
+            let head = http_io::read_head(&mut self.stream)?;
+            self.closed = head.is_connection_close();
+            if let Some(trace) = &self.trace {
+                trace.head(&head);
+            }
+            Ok(head)
         }
     }
 
     impl<S: Write> RtckConn<S> {
         pub fn write_request<T: AsRef<str>>(&mut self, req: &T) -> RtckResult<()> {
-            Ok(self.stream.write_all(req.as_ref().as_bytes())?)
+            self.ensure_open()?;
+            if let Some(trace) = &self.trace {
+                self.trace_mmds = trace.request(req.as_ref());
+            }
+            self.stream.write_all(req.as_ref().as_bytes())?;
+            Ok(self.stream.flush()?)
         }
     }
 }
 
 mod rtck_conn_async {
+    use std::{future::Future, pin::Pin};
+
     use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt};
 
     use crate::{
-        micro_http::{http_io, HttpResponse},
-        RtckResult,
+        micro_http::{http_io, ApiTrace, HttpResponse},
+        RtckError, RtckErrorClass, RtckResult,
     };
 
+    /// Re-establish the connection once the remote closed it
+    pub type ReconnectAsync<S> =
+        Box<dyn FnMut() -> Pin<Box<dyn Future<Output = RtckResult<S>> + Send>> + Send>;
+
     pub struct RtckConnAsync<S> {
         // Async stream of connection
         pub(crate) stream: S,
+
+        // Whether the remote announced `Connection: close`
+        closed: bool,
+
+        // How to get a new stream after the remote closed the connection
+        reconnect: Option<ReconnectAsync<S>>,
+
+        // Tracing of the exchanges, and whether the last request was an MMDS one
+        trace: Option<ApiTrace>,
+        trace_mmds: bool,
+    }
+
+    impl<S> std::fmt::Debug for RtckConnAsync<S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("RtckConnAsync")
+                .field("closed", &self.closed)
+                .field("reconnect", &self.reconnect.is_some())
+                .finish_non_exhaustive()
+        }
     }
 
     impl<S> RtckConnAsync<S> {
         pub fn from_stream(stream: S) -> RtckConnAsync<S> {
-            RtckConnAsync { stream }
+            RtckConnAsync {
+                stream,
+                closed: false,
+                reconnect: None,
+                trace: None,
+                trace_mmds: false,
+            }
+        }
+
+        pub fn set_reconnect(&mut self, reconnect: ReconnectAsync<S>) {
+            self.reconnect = Some(reconnect);
+        }
+
+        pub fn set_trace(&mut self, trace: Option<ApiTrace>) {
+            self.trace = trace;
+        }
+
+        /// Give up the stream, e.g. after an exchange was interrupted midway
+        pub fn mark_closed(&mut self) {
+            self.closed = true;
+        }
+
+        async fn ensure_open(&mut self) -> RtckResult<()> {
+            if self.closed {
+                let reconnect = self.reconnect.as_mut().ok_or(RtckError::new(
+                    RtckErrorClass::IoError,
+                    "Connection closed by remote",
+                ))?;
+                log::info!("[RtckConnAsync::ensure_open reconnecting]");
+                self.stream = reconnect().await?;
+                self.closed = false;
+            }
+            Ok(())
         }
     }
 
     impl<S: AsyncBufRead + Unpin> RtckConnAsync<S> {
         pub async fn read_response(&mut self) -> RtckResult<HttpResponse> {
-            http_io::read_response_async(&mut self.stream).await
+            let res = http_io::read_response_async(&mut self.stream).await?;
+            self.closed = res.is_connection_close();
+            if let Some(trace) = &self.trace {
+                trace.response(&res, self.trace_mmds);
+            }
+            Ok(res)
         }
     }
 
     impl<S: AsyncWrite + Unpin> RtckConnAsync<S> {
         pub async fn write_request<T: AsRef<str>>(&mut self, req: &T) -> RtckResult<()> {
-            Ok(self.stream.write_all(req.as_ref().as_bytes()).await?)
+            self.ensure_open().await?;
+            if let Some(trace) = &self.trace {
+                self.trace_mmds = trace.request(req.as_ref());
+            }
+            self.stream.write_all(req.as_ref().as_bytes()).await?;
+            Ok(self.stream.flush().await?)
         }
     }
 }
@@ -83,23 +242,63 @@ pub mod rtck {
     use std::io::{BufRead, Write};
 
     use crate::{
+        command::Command,
         events::events::Event,
-        micro_http::Http,
-        ops_res::{Operation, Response},
-        rtck_conn::RtckConn,
+        micro_http::{ApiTrace, BodyReader, Http, HttpMethod},
+        ops_res::{get_mmds::GetMmdsRes, Operation, Response},
+        retry::{is_idempotent, RetryPolicy},
+        rtck_conn::{Reconnect, RtckConn},
         RtckResult,
     };
 
     pub struct Rtck<S> {
         conn: RtckConn<S>,
+        retry: Option<RetryPolicy>,
+    }
+
+    impl<S> std::fmt::Debug for Rtck<S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Rtck")
+                .field("conn", &self.conn)
+                .field("retry", &self.retry)
+                .finish()
+        }
     }
 
     impl<S> Rtck<S> {
         pub fn from_stream(stream: S) -> Self {
             Self {
                 conn: RtckConn::from_stream(stream),
+                retry: None,
             }
         }
+
+        /// Retry idempotent requests failing at the connection level as told
+        /// by `retry`, which takes reconnecting, never retried if `None`
+        pub fn with_retry_policy(mut self, retry: Option<RetryPolicy>) -> Self {
+            self.retry = retry;
+            self
+        }
+
+        /// Reconnect with `reconnect` when firecracker closes the connection
+        pub fn with_reconnect<F>(mut self, reconnect: F) -> Self
+        where
+            F: FnMut() -> RtckResult<S> + Send + 'static,
+        {
+            self.conn.set_reconnect(Box::new(reconnect) as Reconnect<S>);
+            self
+        }
+
+        /// Trace the exchanges with firecracker, not traced if `None`
+        pub fn with_trace(mut self, trace: Option<ApiTrace>) -> Self {
+            self.conn.set_trace(trace);
+            self
+        }
+
+        #[cfg(test)]
+        pub(crate) fn stream_mut(&mut self) -> &mut S {
+            self.conn.stream_mut()
+        }
     }
 
     impl<S: BufRead> Rtck<S> {
@@ -121,9 +320,59 @@ pub mod rtck {
             &mut self,
             event: &mut dyn Event<O, R>,
         ) -> RtckResult<()> {
-            let op = event.get_ops();
-            self.send_request(op)?;
-            let res = self.recv_response::<R>()?;
+            let (req, idempotent) = {
+                let command = event.get_ops().encode();
+                let idempotent = is_idempotent(&command.method, &command.url);
+                (command.encode()?, idempotent)
+            };
+            let retry = self.retry.filter(|_| idempotent);
+            let mut attempt = 1;
+            let res = loop {
+                let exchange = self
+                    .conn
+                    .write_request(&req)
+                    .and_then(|_| self.recv_response::<R>());
+                match exchange {
+                    Err(e) => match retry.and_then(|retry| retry.retry_after(attempt, &e)) {
+                        Some(backoff) => {
+                            log::warn!("[Rtck::execute attempt {attempt} failed, retrying, {e}]");
+                            self.conn.mark_closed();
+                            std::thread::sleep(backoff);
+                            attempt += 1;
+                        }
+                        None => return Err(e),
+                    },
+                    Ok(res) => break res,
+                }
+            };
+            event.set_res(res);
+            Ok(())
+        }
+
+        /// Send an arbitrary request, for endpoints not wrapped by rustcracker.
+        /// Returns the status code and the JSON body (`Null` if empty).
+        pub fn raw_request(
+            &mut self,
+            method: HttpMethod,
+            path: &str,
+            body: Option<serde_json::Value>,
+        ) -> RtckResult<(u16, serde_json::Value)> {
+            let command = Command::raw(method, path, body)?;
+            self.conn.write_request(&command.encode()?)?;
+            let res = self.conn.read_response()?;
+            Ok((res.status().as_u16(), res.json()?))
+        }
+
+        /// Execute GetMmds, decoding the (possibly large) MMDS contents
+        /// directly from the connection instead of buffering them first.
+        pub fn execute_get_mmds_streaming(
+            &mut self,
+            event: &mut crate::events::events::GetMmds,
+        ) -> RtckResult<()> {
+            self.send_request(event.get_ops())?;
+            let head = self.conn.read_head()?;
+            let body = BodyReader::new(self.conn.stream_mut(), &head)?;
+            let res = GetMmdsRes::decode_streaming(&head, body)?;
             event.set_res(res);
             Ok(())
         }
@@ -132,26 +381,90 @@ pub mod rtck {
 
 #[cfg(feature = "tokio")]
 pub mod rtck_async {
+    use std::{future::Future, pin::Pin, time::Duration};
+
     use tokio::io::{AsyncBufRead, AsyncWrite};
 
     use crate::{
+        command::Command,
         events::events_async::EventAsync,
-        micro_http::Http,
+        micro_http::{ApiTrace, Http, HttpMethod},
         ops_res::{Operation, Response},
-        rtck_conn_async::RtckConnAsync,
-        RtckResult,
+        retry::{is_idempotent, RetryPolicy},
+        rtck_conn_async::{ReconnectAsync, RtckConnAsync},
+        RtckError, RtckResult,
     };
 
     pub struct RtckAsync<S> {
         conn: RtckConnAsync<S>,
+        request_timeout: Option<Duration>,
+        retry: Option<RetryPolicy>,
+    }
+
+    impl<S> std::fmt::Debug for RtckAsync<S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("RtckAsync")
+                .field("conn", &self.conn)
+                .field("request_timeout", &self.request_timeout)
+                .field("retry", &self.retry)
+                .finish()
+        }
     }
 
     impl<S> RtckAsync<S> {
         pub fn from_stream(stream: S) -> Self {
             Self {
                 conn: RtckConnAsync::from_stream(stream),
+                request_timeout: None,
+                retry: None,
             }
         }
+
+        /// Retry idempotent requests failing at the connection level as told
+        /// by `retry`, which takes reconnecting, never retried if `None`.
+        /// Timed out requests are not retried.
+        pub fn with_retry_policy(mut self, retry: Option<RetryPolicy>) -> Self {
+            self.retry = retry;
+            self
+        }
+
+        /// Bound every request and its response by `timeout`, unbounded if `None`
+        pub fn with_request_timeout(mut self, timeout: Option<Duration>) -> Self {
+            self.request_timeout = timeout;
+            self
+        }
+
+        /// Reconnect with `reconnect` when firecracker closes the connection
+        pub fn with_reconnect<F, Fut>(mut self, mut reconnect: F) -> Self
+        where
+            F: FnMut() -> Fut + Send + 'static,
+            Fut: Future<Output = RtckResult<S>> + Send + 'static,
+        {
+            self.conn.set_reconnect(Box::new(move || {
+                Box::pin(reconnect()) as Pin<Box<dyn Future<Output = RtckResult<S>> + Send>>
+            }) as ReconnectAsync<S>);
+            self
+        }
+
+        /// Trace the exchanges with firecracker, not traced if `None`
+        pub fn with_trace(mut self, trace: Option<ApiTrace>) -> Self {
+            self.conn.set_trace(trace);
+            self
+        }
+
+        /// The connection is given up on timeout, as the response may be half read
+        fn timed_out(&mut self) -> RtckError {
+            self.conn.mark_closed();
+            RtckError::timeout("Request timeout")
+        }
+    }
+
+    /// `None` if `exchange` did not complete within `timeout`
+    async fn within<T>(timeout: Option<Duration>, exchange: impl Future<Output = T>) -> Option<T> {
+        match timeout {
+            None => Some(exchange.await),
+            Some(timeout) => tokio::time::timeout(timeout, exchange).await.ok(),
+        }
     }
 
     impl<S: AsyncBufRead + Unpin> RtckAsync<S> {
@@ -169,16 +482,89 @@ pub mod rtck_async {
     }
 
     impl<S: AsyncBufRead + AsyncWrite + Unpin> RtckAsync<S> {
+        /// Send an arbitrary request, for endpoints not wrapped by rustcracker.
+        /// Returns the status code and the JSON body (`Null` if empty).
+        pub async fn raw_request(
+            &mut self,
+            method: HttpMethod,
+            path: &str,
+            body: Option<serde_json::Value>,
+        ) -> RtckResult<(u16, serde_json::Value)> {
+            let command = Command::raw(method, path, body)?;
+            let timeout = self.request_timeout;
+            let exchange = async {
+                self.conn.write_request(&command.encode()?).await?;
+                self.conn.read_response().await
+            };
+            let res = match within(timeout, exchange).await {
+                Some(res) => res?,
+                None => return Err(self.timed_out()),
+            };
+            Ok((res.status().as_u16(), res.json()?))
+        }
+
         pub async fn execute<O: Operation + Sync, R: Response>(
             &mut self,
             event: &(dyn EventAsync<O, R> + Sync),
         ) -> RtckResult<()> {
-            let op = event.get_ops();
-            self.send_request(op).await?;
-            let res = self.recv_response::<R>().await?;
-            event.set_res(res);
+            let (req, idempotent) = {
+                let command = event.get_ops().encode();
+                let idempotent = is_idempotent(&command.method, &command.url);
+                (command.encode()?, idempotent)
+            };
+            #[cfg(feature = "tracing")]
+            let res = {
+                use tracing::Instrument;
+                let span = {
+                    let command = event.get_ops().encode();
+                    tracing::info_span!(
+                        "agent.event",
+                        method = command.method.as_str(),
+                        endpoint = %command.url,
+                        latency_us = tracing::field::Empty,
+                    )
+                };
+                let started = std::time::Instant::now();
+                let res = self
+                    .exchange::<R>(&req, idempotent)
+                    .instrument(span.clone())
+                    .await;
+                span.record("latency_us", started.elapsed().as_micros() as u64);
+                res
+            };
+            #[cfg(not(feature = "tracing"))]
+            let res = self.exchange::<R>(&req, idempotent).await;
+            event.set_res(res?);
             Ok(())
         }
+
+        /// Send `req` and receive its response, retrying if `idempotent`
+        async fn exchange<R: Response>(&mut self, req: &str, idempotent: bool) -> RtckResult<R> {
+            let retry = self.retry.filter(|_| idempotent);
+            let timeout = self.request_timeout;
+            let mut attempt = 1;
+            loop {
+                let exchange = async {
+                    self.conn.write_request(&req).await?;
+                    self.recv_response::<R>().await
+                };
+                match within(timeout, exchange).await {
+                    Some(Err(e)) => match retry.and_then(|retry| retry.retry_after(attempt, &e)) {
+                        Some(backoff) => {
+                            log::warn!(
+                                "[RtckAsync::execute attempt {attempt} failed, retrying, {e}]"
+                            );
+                            self.conn.mark_closed();
+                            tokio::time::sleep(backoff).await;
+                            attempt += 1;
+                        }
+                        None => return Err(e),
+                    },
+                    Some(Ok(res)) => return Ok(res),
+                    None => return Err(self.timed_out()),
+                }
+            }
+        }
     }
 }
 
@@ -204,12 +590,31 @@ pub enum RtckErrorClass {
     RemoteError,
     /// Error of machine
     MachineError,
+    /// Error when setting up the network of the machine
+    NetworkError,
+    /// Error when reading a response that was not received yet
+    NotReceived,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RtckError {
     class: RtckErrorClass,
     desc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fault_kind: Option<InternalErrorKind>,
+    #[serde(skip)]
+    cause: Option<ErrorCause>,
+    /// Error this one was made of, e.g. the `io::Error` it was converted from
+    #[serde(skip)]
+    source: Option<Arc<dyn std::error::Error + Send + Sync>>,
+}
+
+/// What the error originates from, as far as it tells whether it is
+/// transient, see `RtckError::is_transient`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorCause {
+    Io(io::ErrorKind),
+    Timeout,
 }
 
 impl RtckError {
@@ -217,6 +622,77 @@ impl RtckError {
         Self {
             class,
             desc: desc.as_ref().to_string(),
+            fault_kind: None,
+            cause: None,
+            source: None,
+        }
+    }
+
+    /// Error of a request firecracker rejected with `fault`, described
+    /// as `context` followed by the fault message
+    pub fn remote<S: AsRef<str>>(context: S, fault: &InternalError) -> Self {
+        Self {
+            class: RtckErrorClass::RemoteError,
+            desc: format!("{}: {}", context.as_ref(), fault),
+            fault_kind: Some(fault.kind()),
+            cause: None,
+            source: None,
+        }
+    }
+
+    pub fn class(&self) -> &RtckErrorClass {
+        &self.class
+    }
+
+    /// Kind of the fault firecracker answered with, if the error is one
+    pub fn fault_kind(&self) -> Option<InternalErrorKind> {
+        self.fault_kind
+    }
+
+    /// Whether retrying the failed operation later may succeed, which is
+    /// only assumed of faults firecracker reports as transient
+    pub fn is_retryable(&self) -> bool {
+        self.fault_kind.is_some_and(|kind| kind.is_retryable())
+    }
+
+    /// Keep `source` as the error this one was made of, see
+    /// `std::error::Error::source`. The description is left as it is.
+    pub fn with_source<E: std::error::Error + Send + Sync + 'static>(mut self, source: E) -> Self {
+        if let Some(e) = (&source as &dyn std::error::Error).downcast_ref::<io::Error>() {
+            self.cause = Some(ErrorCause::Io(e.kind()));
+        }
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    /// Error of a request firecracker did not answer in time
+    pub fn timeout<S: AsRef<str>>(desc: S) -> Self {
+        Self {
+            cause: Some(ErrorCause::Timeout),
+            ..Self::new(RtckErrorClass::RemoteError, desc)
+        }
+    }
+
+    /// Whether the failure is temporary, e.g. a race with firecracker still
+    /// starting, as opposed to a permanent one, e.g. a bad configuration,
+    /// so that attempting the operation again may succeed:
+    ///
+    /// | Error                                                      | Transient |
+    /// |------------------------------------------------------------|-----------|
+    /// | IO `WouldBlock` (`EAGAIN`), `ConnectionRefused`, `ConnectionReset` | yes |
+    /// | Fault of firecracker, busy or internal (its 5xx)           | yes       |
+    /// | Request timeout                                            | yes       |
+    /// | Any other IO error, fault, and every other class           | no        |
+    pub fn is_transient(&self) -> bool {
+        use io::ErrorKind::*;
+        match self.cause {
+            Some(ErrorCause::Io(WouldBlock | ConnectionRefused | ConnectionReset)) => true,
+            Some(ErrorCause::Io(_)) => false,
+            Some(ErrorCause::Timeout) => true,
+            None => matches!(
+                self.fault_kind,
+                Some(InternalErrorKind::Busy | InternalErrorKind::Internal)
+            ),
         }
     }
 }
@@ -225,6 +701,10 @@ impl std::error::Error for RtckError {
     fn description(&self) -> &str {
         &self.desc
     }
+
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_deref().map(|e| e as _)
+    }
 }
 
 impl std::fmt::Display for RtckError {
@@ -235,46 +715,37 @@ impl std::fmt::Display for RtckError {
 
 impl From<io::Error> for RtckError {
     fn from(e: io::Error) -> Self {
-        RtckError {
-            class: RtckErrorClass::IoError,
-            desc: e.to_string(),
-        }
+        RtckError::new(RtckErrorClass::IoError, e.to_string()).with_source(e)
     }
 }
 
 impl From<FromUtf8Error> for RtckError {
     fn from(e: FromUtf8Error) -> Self {
-        RtckError {
-            class: RtckErrorClass::ParseError,
-            desc: e.to_string(),
-        }
+        RtckError::new(RtckErrorClass::ParseError, e.to_string()).with_source(e)
     }
 }
 
 impl From<std::str::Utf8Error> for RtckError {
     fn from(e: std::str::Utf8Error) -> Self {
-        RtckError {
-            class: RtckErrorClass::ParseError,
-            desc: e.to_string(),
-        }
+        RtckError::new(RtckErrorClass::ParseError, e.to_string()).with_source(e)
     }
 }
 
 impl From<ParseIntError> for RtckError {
     fn from(e: ParseIntError) -> Self {
-        RtckError {
-            class: RtckErrorClass::ParseError,
-            desc: e.to_string(),
-        }
+        RtckError::new(RtckErrorClass::ParseError, e.to_string()).with_source(e)
     }
 }
 
 impl From<serde_json::Error> for RtckError {
     fn from(e: serde_json::Error) -> Self {
-        RtckError {
-            class: RtckErrorClass::SerdeError,
-            desc: e.to_string(),
-        }
+        RtckError::new(RtckErrorClass::SerdeError, e.to_string()).with_source(e)
+    }
+}
+
+impl From<toml::de::Error> for RtckError {
+    fn from(e: toml::de::Error) -> Self {
+        RtckError::new(RtckErrorClass::SerdeError, e.to_string()).with_source(e)
     }
 }
 
@@ -283,6 +754,9 @@ impl<T> From<PoisonError<T>> for RtckError {
         RtckError {
             class: RtckErrorClass::SyncError,
             desc: e.to_string(),
+            fault_kind: None,
+            cause: None,
+            source: None,
         }
     }
 }
@@ -349,3 +823,475 @@ pub(crate) fn possible_malformed_entry<T: ?Sized>(option: Option<&T>) -> RtckRes
         "Malformed config entry".to_string(),
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        os::unix::net::{UnixListener, UnixStream},
+    };
+
+    use crate::{
+        events::{
+            events::{self, Event},
+            events_async::{self, EventAsync},
+        },
+        micro_http::{ApiTrace, API_TRACE_TARGET},
+        models::{
+            boot_source::BootSource, instance_action_info::InstanceActionInfo,
+            mmds_config::MmdsContents,
+        },
+        retry::RetryPolicy,
+        rtck::Rtck,
+        rtck_async::RtckAsync,
+        RtckErrorClass,
+    };
+
+    const CLOSE: &str = "HTTP/1.1 413 Payload Too Large\r\nConnection: close\r\nContent-Length: 26\r\n\r\n{\"fault_message\":\"large\"}\n";
+    const VERSION: &str =
+        "HTTP/1.1 200 OK\r\nContent-Length: 33\r\n\r\n{\"firecracker_version\":\"1.7.0\"}\r\n";
+
+    /// Fake firecracker accepting two connections in turn and answering one
+    /// request on each, the first answer announcing `Connection: close`.
+    fn run_server(path: String) -> std::thread::JoinHandle<Vec<String>> {
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).expect("Server bind failed");
+        std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in [CLOSE, VERSION] {
+                let (stream, _addr) = listener.accept().expect("Server accept error");
+                let mut stream = BufReader::new(stream);
+                let mut req = String::new();
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).expect("Server fail to read");
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        len = value.trim().parse().unwrap();
+                    }
+                    req += &line;
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; len];
+                stream.read_exact(&mut body).expect("Server fail to read");
+                requests.push(req);
+                stream
+                    .get_mut()
+                    .write_all(reply.as_bytes())
+                    .expect("Server fail to write");
+            }
+            let _ = std::fs::remove_file(&path);
+            requests
+        })
+    }
+
+    #[test]
+    fn test_reconnect_on_close() {
+        let path = format!("/tmp/rtck-close-{}.sock", uuid::Uuid::new_v4());
+        let server = run_server(path.clone());
+
+        let stream = bufstream::BufStream::new(UnixStream::connect(&path).unwrap());
+        let reconnect_path = path.clone();
+        let mut rtck = Rtck::from_stream(stream).with_reconnect(move || {
+            Ok(bufstream::BufStream::new(UnixStream::connect(
+                &reconnect_path,
+            )?))
+        });
+
+        let mut version = events::GetFirecrackerVersion::new();
+        rtck.execute(&mut version).unwrap();
+        assert!(version.is_err().unwrap());
+
+        let mut version = events::GetFirecrackerVersion::new();
+        rtck.execute(&mut version).unwrap();
+        assert!(version.is_succ().unwrap());
+
+        let requests = server.join().unwrap();
+        assert!(requests
+            .iter()
+            .all(|req| req.contains("Connection: keep-alive\r\n")));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        use std::sync::{Arc, Mutex};
+
+        // The connection is reset, then reconnecting is refused once
+        let (client, server) = UnixStream::pair().unwrap();
+        drop(server);
+        let reconnects = Arc::new(Mutex::new(0));
+        let servers = Arc::new(Mutex::new(Vec::new()));
+        let reconnect = {
+            let (reconnects, servers) = (reconnects.clone(), servers.clone());
+            move || {
+                *reconnects.lock().unwrap() += 1;
+                if *reconnects.lock().unwrap() == 1 {
+                    return Err(std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into());
+                }
+                let (client, mut server) = UnixStream::pair()?;
+                server.write_all(VERSION.as_bytes())?;
+                servers.lock().unwrap().push(server);
+                Ok(bufstream::BufStream::new(client))
+            }
+        };
+        let policy = RetryPolicy::new(3, std::time::Duration::from_millis(1));
+        let mut rtck = Rtck::from_stream(bufstream::BufStream::new(client))
+            .with_reconnect(reconnect.clone())
+            .with_retry_policy(Some(policy));
+
+        let mut version = events::GetFirecrackerVersion::new();
+        rtck.execute(&mut version).unwrap();
+        assert!(version.is_succ().unwrap());
+        assert_eq!(*reconnects.lock().unwrap(), 2);
+
+        // Actions are never sent twice
+        *reconnects.lock().unwrap() = 0;
+        let (client, server) = UnixStream::pair().unwrap();
+        drop(server);
+        let mut rtck = Rtck::from_stream(bufstream::BufStream::new(client))
+            .with_reconnect(reconnect)
+            .with_retry_policy(Some(policy));
+        let mut start = events::CreateSyncAction::new(InstanceActionInfo::instance_start());
+        assert!(rtck.execute(&mut start).is_err());
+        assert_eq!(*reconnects.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_response_not_received() {
+        let mut version = events::GetFirecrackerVersion::new();
+        let err = version.is_succ().unwrap_err();
+        assert_eq!(err.class(), &RtckErrorClass::NotReceived);
+        assert_eq!(
+            version.is_err().unwrap_err().class(),
+            &RtckErrorClass::NotReceived
+        );
+
+        let version = events_async::GetFirecrackerVersion::new();
+        assert_eq!(
+            version.is_succ().unwrap_err().class(),
+            &RtckErrorClass::NotReceived
+        );
+        assert_eq!(
+            version.is_err().unwrap_err().class(),
+            &RtckErrorClass::NotReceived
+        );
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error;
+
+        use crate::RtckError;
+
+        let e = RtckError::from(std::io::Error::new(std::io::ErrorKind::NotFound, "gone"));
+        assert_eq!(e.to_string(), "gone");
+        let source = e
+            .source()
+            .unwrap()
+            .downcast_ref::<std::io::Error>()
+            .unwrap();
+        assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        // Kept by clones
+        assert!(e.clone().source().unwrap().is::<std::io::Error>());
+
+        let json = serde_json::from_str::<u32>("oops").unwrap_err();
+        let desc = json.to_string();
+        let e = RtckError::from(json);
+        assert_eq!(e.class(), &RtckErrorClass::SerdeError);
+        assert_eq!(e.to_string(), desc);
+        assert!(e.source().unwrap().is::<serde_json::Error>());
+
+        // Wrapped with context, an io error still tells it is transient
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        let e = RtckError::new(
+            RtckErrorClass::RemoteError,
+            format!("Fail to connect, {refused}"),
+        )
+        .with_source(refused);
+        assert!(e.to_string().starts_with("Fail to connect, "));
+        assert!(e.source().unwrap().is::<std::io::Error>());
+        assert!(e.is_transient());
+
+        assert!(RtckError::new(RtckErrorClass::GenericError, "plain")
+            .source()
+            .is_none());
+    }
+
+    #[test]
+    fn test_unserializable_request() {
+        use std::collections::BTreeMap;
+
+        use crate::{
+            command::Command, micro_http::HttpMethod, ops_res::Operation, ser::Serde, RtckResult,
+        };
+
+        // JSON object keys must be strings
+        struct Unserializable(BTreeMap<(u8, u8), u8>);
+
+        impl Serde for Unserializable {
+            fn encode(&self) -> RtckResult<String> {
+                Ok(serde_json::to_string(&self.0)?)
+            }
+
+            fn decode<S: AsRef<str>>(_line: &S) -> RtckResult<Self> {
+                unreachable!()
+            }
+        }
+
+        struct PutUnserializable;
+
+        impl Operation for PutUnserializable {
+            fn encode(&self) -> Command {
+                Command {
+                    method: HttpMethod::PUT,
+                    url: "/mmds".into(),
+                    data: Box::new(Unserializable(BTreeMap::from([((0, 0), 0)]))),
+                }
+            }
+        }
+
+        let (client, mut server) = UnixStream::pair().unwrap();
+        let mut rtck = Rtck::from_stream(bufstream::BufStream::new(client));
+        let err = rtck.send_request(&PutUnserializable).unwrap_err();
+        assert_eq!(err.class(), &RtckErrorClass::SerdeError);
+
+        // Nothing was sent
+        drop(rtck);
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).unwrap();
+        assert!(sent.is_empty());
+    }
+
+    #[test]
+    fn test_closed_without_reconnect() {
+        let path = format!("/tmp/rtck-close-{}.sock", uuid::Uuid::new_v4());
+        let _server = run_server(path.clone());
+
+        let stream = bufstream::BufStream::new(UnixStream::connect(&path).unwrap());
+        let mut rtck = Rtck::from_stream(stream);
+
+        let mut version = events::GetFirecrackerVersion::new();
+        rtck.execute(&mut version).unwrap();
+        assert!(version.is_err().unwrap());
+        assert!(rtck
+            .execute(&mut events::GetFirecrackerVersion::new())
+            .is_err());
+    }
+
+    /// Logger keeping the trace of the exchanges with firecracker
+    struct ApiTraceLogger(std::sync::Mutex<Vec<String>>);
+
+    impl log::Log for ApiTraceLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == API_TRACE_TARGET
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static API_TRACE_LOGGER: ApiTraceLogger = ApiTraceLogger(std::sync::Mutex::new(Vec::new()));
+
+    /// Whether a traced line contains `needle`, installing the logger
+    fn traced(needle: &str) -> bool {
+        let _ = log::set_logger(&API_TRACE_LOGGER);
+        log::set_max_level(log::LevelFilter::Debug);
+        API_TRACE_LOGGER
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|line| line.contains(needle))
+    }
+
+    #[test]
+    fn test_trace_api() {
+        traced("");
+
+        let (client, mut server) = UnixStream::pair().unwrap();
+        server
+            .write_all(VERSION.as_bytes())
+            .and_then(|_| server.write_all(b"HTTP/1.1 204 No Content\r\n\r\n"))
+            .unwrap();
+        let mut rtck =
+            Rtck::from_stream(bufstream::BufStream::new(client)).with_trace(Some(ApiTrace {
+                redact_mmds: true,
+                ..Default::default()
+            }));
+
+        rtck.execute(&mut events::GetFirecrackerVersion::new())
+            .unwrap();
+        assert!(traced("--> GET /version"));
+        assert!(traced(r#"<-- 200 {"firecracker_version":"1.7.0"}"#));
+
+        let secret = serde_json::json!({ "token": "rtck-secret-token" });
+        rtck.execute(&mut events::PutMmds::new(MmdsContents::new(secret)))
+            .unwrap();
+        assert!(traced("--> PUT /mmds <redacted>"));
+        assert!(!traced("rtck-secret-token"));
+    }
+
+    #[test]
+    fn test_trace_api_redaction() {
+        traced("");
+
+        let (client, mut server) = UnixStream::pair().unwrap();
+        server
+            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+            .unwrap();
+        let mut rtck = Rtck::from_stream(bufstream::BufStream::new(client))
+            .with_trace(Some(ApiTrace::default()));
+
+        let contents = serde_json::json!({
+            "latest": { "meta-data": { "user": "rtck-user", "password": "rtck-hunter2" } }
+        });
+        rtck.execute(&mut events::PutMmds::new(MmdsContents::new(contents)))
+            .unwrap();
+        assert!(traced(r#""password":"***""#));
+        assert!(traced("rtck-user"));
+        assert!(!traced("rtck-hunter2"));
+
+        // Firecracker gets the password all the same
+        server
+            .set_read_timeout(Some(std::time::Duration::from_millis(100)))
+            .unwrap();
+        let mut sent = Vec::new();
+        let _ = server.read_to_end(&mut sent);
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.contains(r#""password":"rtck-hunter2""#), "{sent}");
+    }
+
+    #[test]
+    fn test_trace_api_kernel_args() {
+        traced("");
+
+        let (client, mut server) = UnixStream::pair().unwrap();
+        server
+            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n".repeat(2).as_slice())
+            .unwrap();
+        let mut rtck = Rtck::from_stream(bufstream::BufStream::new(client))
+            .with_trace(Some(ApiTrace::default()));
+        let boot_source = |token: &str| BootSource {
+            boot_args: Some(format!(
+                "console=ttyS0 ds=nocloud-net;s=http://10.0.0.1/?token={token}"
+            )),
+            initrd_path: None,
+            kernel_image_path: "/vmlinux".to_string(),
+        };
+
+        rtck.execute(&mut events::PutGuestBootSource::new(boot_source(
+            "rtck-kernel-token",
+        )))
+        .unwrap();
+        assert!(traced("console=ttyS0 ds=***"));
+        assert!(!traced("rtck-kernel-token"));
+
+        // Unless logging secrets on purpose
+        let mut rtck = rtck.with_trace(Some(ApiTrace {
+            sensitive: true,
+            ..Default::default()
+        }));
+        rtck.execute(&mut events::PutGuestBootSource::new(boot_source(
+            "rtck-debug-token",
+        )))
+        .unwrap();
+        assert!(traced("token=rtck-debug-token"));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_async() {
+        // The remote never answers
+        let (client, _server) = tokio::io::duplex(1 << 16);
+        let mut rtck = RtckAsync::from_stream(tokio::io::BufStream::new(client))
+            .with_request_timeout(Some(std::time::Duration::from_millis(10)));
+        let version = events_async::GetFirecrackerVersion::new();
+        let err = rtck.execute(&version).await.unwrap_err();
+        assert_eq!(err.class(), &RtckErrorClass::RemoteError);
+        assert!(version.is_succ().is_err());
+
+        // Half read responses are never resumed
+        assert!(rtck
+            .execute(&events_async::GetFirecrackerVersion::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_async() {
+        use std::sync::{Arc, Mutex};
+
+        use tokio::io::AsyncWriteExt;
+
+        // The connection is reset, then reconnecting is refused once
+        let (client, server) = tokio::io::duplex(1 << 16);
+        drop(server);
+        let reconnects = Arc::new(Mutex::new(0));
+        let servers = Arc::new(Mutex::new(Vec::new()));
+        let reconnect = {
+            let (reconnects, servers) = (reconnects.clone(), servers.clone());
+            move || {
+                let (reconnects, servers) = (reconnects.clone(), servers.clone());
+                async move {
+                    *reconnects.lock().unwrap() += 1;
+                    if *reconnects.lock().unwrap() == 1 {
+                        return Err(
+                            std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into()
+                        );
+                    }
+                    let (client, mut server) = tokio::io::duplex(1 << 16);
+                    server.write_all(VERSION.as_bytes()).await?;
+                    servers.lock().unwrap().push(server);
+                    Ok(tokio::io::BufStream::new(client))
+                }
+            }
+        };
+        let mut rtck = RtckAsync::from_stream(tokio::io::BufStream::new(client))
+            .with_reconnect(reconnect)
+            .with_retry_policy(Some(RetryPolicy::new(
+                3,
+                std::time::Duration::from_millis(1),
+            )));
+
+        let version = events_async::GetFirecrackerVersion::new();
+        rtck.execute(&version).await.unwrap();
+        assert!(version.is_succ().unwrap());
+        assert_eq!(*reconnects.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_on_close_async() {
+        let path = format!("/tmp/rtck-close-async-{}.sock", uuid::Uuid::new_v4());
+        let server = run_server(path.clone());
+
+        let stream =
+            tokio::io::BufStream::new(tokio::net::UnixStream::connect(&path).await.unwrap());
+        let mut rtck = RtckAsync::from_stream(stream).with_reconnect(move || {
+            let path = path.clone();
+            async move {
+                Ok(tokio::io::BufStream::new(
+                    tokio::net::UnixStream::connect(path).await?,
+                ))
+            }
+        });
+
+        let version = events_async::GetFirecrackerVersion::new();
+        rtck.execute(&version).await.unwrap();
+        assert!(version.is_err().unwrap());
+
+        let version = events_async::GetFirecrackerVersion::new();
+        rtck.execute(&version).await.unwrap();
+        assert!(version.is_succ().unwrap());
+
+        let requests = tokio::task::spawn_blocking(move || server.join().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(requests.len(), 2);
+    }
+}
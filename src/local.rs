@@ -3,7 +3,7 @@ pub mod local {
 
     use crate::{
         config::GlobalConfig, firecracker::firecracker::Firecracker, jailer::jailer::Jailer,
-        RtckResult,
+        network::NetworkCleanup, RtckResult,
     };
 
     pub struct Local {
@@ -15,6 +15,34 @@ pub mod local {
         machine_log_clear: Option<bool>,
         metrics_clear: Option<bool>,
         network_clear: Option<bool>,
+        network: NetworkCleanup,
+    }
+
+    impl Local {
+        /// Path of the API socket seen by Rtck
+        pub fn get_socket_path(&self) -> &PathBuf {
+            &self.socket_path
+        }
+
+        /// Jail directory removed when cleaning up, if any
+        pub fn get_jail_path(&self) -> Option<&PathBuf> {
+            self.jail_path.as_ref()
+        }
+
+        /// Metrics file seen by Rtck, if configured
+        pub fn get_metrics_path(&self) -> Option<&PathBuf> {
+            self.metrics_path.as_ref()
+        }
+
+        /// Log file of firecracker seen by Rtck, if configured
+        pub fn get_log_path(&self) -> Option<&PathBuf> {
+            self.machine_log_path.as_ref()
+        }
+
+        /// Network resources removed when cleaning up, if `network_clear`
+        pub fn get_network(&self) -> &NetworkCleanup {
+            &self.network
+        }
     }
 
     impl Local {
@@ -44,6 +72,7 @@ pub mod local {
                 machine_log_clear: config.log_clear,
                 metrics_clear: config.metrics_clear,
                 network_clear: config.network_clear,
+                network: NetworkCleanup::new(),
             })
         }
 
@@ -76,6 +105,7 @@ pub mod local {
                 machine_log_clear: config.log_clear,
                 metrics_clear: config.metrics_clear,
                 network_clear: config.network_clear,
+                network: NetworkCleanup::new(),
             })
         }
 
@@ -135,6 +165,11 @@ pub mod local {
                 Ok(_) => (),
                 Err(e) => log::error!("Fail to remove jailer directory, {e}"),
             }
+
+            match self.rm_networks() {
+                Ok(_) => (),
+                Err(e) => log::error!("Fail to remove networks, {e}"),
+            }
         }
 
         /// Remove only the socket
@@ -165,7 +200,7 @@ pub mod local {
         /// Remove the networks
         pub fn rm_networks(&self) -> RtckResult<()> {
             if let Some(true) = self.network_clear {
-                todo!()
+                self.network.run()?;
             }
             Ok(())
         }
@@ -186,7 +221,7 @@ pub mod local_async {
 
     use crate::{
         config::GlobalConfig, firecracker::firecracker_async::FirecrackerAsync,
-        jailer::jailer_async::JailerAsync, RtckResult,
+        jailer::jailer_async::JailerAsync, network::NetworkCleanup, RtckResult,
     };
 
     pub struct LocalAsync {
@@ -198,6 +233,34 @@ pub mod local_async {
         machine_log_clear: Option<bool>,
         metrics_clear: Option<bool>,
         network_clear: Option<bool>,
+        network: NetworkCleanup,
+    }
+
+    impl LocalAsync {
+        /// Path of the API socket seen by Rtck
+        pub fn get_socket_path(&self) -> &PathBuf {
+            &self.socket_path
+        }
+
+        /// Jail directory removed when cleaning up, if any
+        pub fn get_jail_path(&self) -> Option<&PathBuf> {
+            self.jail_path.as_ref()
+        }
+
+        /// Metrics file seen by Rtck, if configured
+        pub fn get_metrics_path(&self) -> Option<&PathBuf> {
+            self.metrics_path.as_ref()
+        }
+
+        /// Log file of firecracker seen by Rtck, if configured
+        pub fn get_log_path(&self) -> Option<&PathBuf> {
+            self.machine_log_path.as_ref()
+        }
+
+        /// Network resources removed when cleaning up, if `network_clear`
+        pub fn get_network(&self) -> &NetworkCleanup {
+            &self.network
+        }
     }
 
     impl LocalAsync {
@@ -227,6 +290,7 @@ pub mod local_async {
                 machine_log_clear: config.log_clear,
                 metrics_clear: config.metrics_clear,
                 network_clear: config.network_clear,
+                network: NetworkCleanup::new(),
             })
         }
 
@@ -259,6 +323,7 @@ pub mod local_async {
                 machine_log_clear: config.log_clear,
                 metrics_clear: config.metrics_clear,
                 network_clear: config.network_clear,
+                network: NetworkCleanup::new(),
             })
         }
 
@@ -321,6 +386,11 @@ pub mod local_async {
                 Ok(_) => (),
                 Err(e) => log::error!("Fail to remove jailer directory, {e}"),
             }
+
+            match self.rm_networks().await {
+                Ok(_) => (),
+                Err(e) => log::error!("Fail to remove networks, {e}"),
+            }
         }
 
         /// Remove the socket
@@ -355,7 +425,7 @@ pub mod local_async {
         #[cfg(feature = "tokio")]
         pub async fn rm_networks(&self) -> RtckResult<()> {
             if let Some(true) = self.network_clear {
-                todo!()
+                self.network.run()?;
             }
             Ok(())
         }
@@ -371,6 +441,17 @@ pub mod local_async {
     }
 }
 
+use std::{
+    ffi::CString,
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+    time::{Duration, Instant},
+};
+
 use crate::{RtckError, RtckErrorClass, RtckResult};
 
 #[doc(hidden)]
@@ -380,3 +461,152 @@ pub(crate) fn handle_entry<T: Clone>(option: &Option<T>) -> RtckResult<T> {
         "Missing config entry".to_string(),
     ))
 }
+
+/// Interval between two looks for a path whose directory inotify cannot
+/// watch, e.g. a jail not built yet or a file system without inotify
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Inotify instance watching a directory for entries created or moved in
+struct DirWatch {
+    fd: OwnedFd,
+}
+
+impl DirWatch {
+    fn new(dir: &Path) -> io::Result<Self> {
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mask = libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF;
+        if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    /// Block until some event is queued, for at most `timeout`
+    fn wait(&self, timeout: Duration) -> io::Result<()> {
+        let mut pollfd = libc::pollfd {
+            fd: self.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // Rounded up, not to spin through the last millisecond
+        let millis = timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+        if unsafe { libc::poll(&mut pollfd, 1, millis) } < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+        self.drain();
+        Ok(())
+    }
+
+    /// Discard the queued events, which only tell to look at the path again
+    fn drain(&self) {
+        let mut buf = [0u8; 4096];
+        let fd = self.as_raw_fd();
+        while unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+    }
+}
+
+impl AsRawFd for DirWatch {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Directory to watch for `path` to show up in
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+fn socket_timeout() -> RtckError {
+    RtckError::new(
+        RtckErrorClass::RemoteError,
+        "Remote socket set up timeout".to_string(),
+    )
+}
+
+/// Wait for `path` to exist, e.g. the socket of firecracker, for at most
+/// `timeout`. Sleeps on inotify events of its directory, or polls every
+/// `WAIT_POLL_INTERVAL` while the directory cannot be watched.
+pub(crate) fn wait_for_path(path: &Path, timeout: Duration) -> RtckResult<()> {
+    let deadline = Instant::now() + timeout;
+    let dir = parent_dir(path);
+    let mut watch = None;
+    loop {
+        // Watching before looking, not to miss the path created in between
+        if watch.is_none() {
+            watch = DirWatch::new(dir).ok();
+        }
+        if path.exists() {
+            return Ok(());
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(socket_timeout());
+        }
+        match &watch {
+            Some(dir_watch) => {
+                if dir_watch.wait(left).is_err() {
+                    watch = None;
+                    std::thread::sleep(left.min(WAIT_POLL_INTERVAL));
+                } else if !dir.is_dir() {
+                    // Removed or moved away, the watch is gone with it
+                    watch = None;
+                }
+            }
+            None => std::thread::sleep(left.min(WAIT_POLL_INTERVAL)),
+        }
+    }
+}
+
+/// Asynchronous `wait_for_path`
+pub(crate) async fn wait_for_path_async(path: &Path, timeout: Duration) -> RtckResult<()> {
+    use tokio::io::unix::AsyncFd;
+
+    let dir = parent_dir(path);
+    tokio::time::timeout(timeout, async {
+        let mut watch: Option<AsyncFd<DirWatch>> = None;
+        loop {
+            // Watching before looking, not to miss the path created in between
+            if watch.is_none() {
+                watch = DirWatch::new(dir)
+                    .and_then(|dir_watch| AsyncFd::new(dir_watch))
+                    .ok();
+            }
+            if tokio::fs::try_exists(path).await.unwrap_or(false) {
+                return;
+            }
+            let watching = match &watch {
+                Some(dir_watch) => match dir_watch.readable().await {
+                    Ok(mut guard) => {
+                        guard.get_inner().drain();
+                        guard.clear_ready();
+                        // Removed or moved away, the watch is gone with it
+                        dir.is_dir()
+                    }
+                    Err(_) => {
+                        tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+                        false
+                    }
+                },
+                None => {
+                    tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+                    false
+                }
+            };
+            if !watching {
+                watch = None;
+            }
+        }
+    })
+    .await
+    .map_err(|_| socket_timeout())
+}
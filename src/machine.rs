@@ -1,5 +1,78 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{FirecrackerConfig, GlobalConfig},
+    models::{
+        balloon_stats::BalloonStatistics,
+        drive::Drive,
+        error::InternalError,
+        firecracker_version::FirecrackerVersion,
+        full_vm_configuration::FullVmConfiguration,
+        instance_action_info::ActionType,
+        logger::LogLevel,
+        machine_configuration::MachineConfiguration,
+        memory_backend::BackendType,
+        network_interface::NetworkInterface,
+        snapshot_create_params::SnapshotType,
+        snapshot_load_params::{NetworkOverride, SnapshotLoadParams},
+    },
+    uffd::UffdHandler,
+    RtckError, RtckErrorClass, RtckResult,
+};
+
+/// What a process must persist to reattach to a machine after restarting,
+/// see `Machine::dump_core` and `Machine::restore_core`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineCore {
+    /// Id of the jailer, if jailed
+    pub id: Option<String>,
+    /// Pid of the firecracker (or jailer) process
+    pub pid: u32,
+    /// Path of the API socket seen by Rtck
+    pub socket_path: PathBuf,
+    /// Jailer workspace directory, if jailed
+    pub jailer_working_dir: Option<PathBuf>,
+    /// Uid and gid firecracker runs as, if jailed
+    pub uid_gid: Option<(u32, u32)>,
+    /// Whether cleaning up removes the jailer workspace directory
+    pub clear_jailer: bool,
+}
+
+impl MachineCore {
+    fn new(
+        config: &GlobalConfig,
+        pid: u32,
+        socket_path: &Path,
+        jailer_working_dir: Option<PathBuf>,
+        jail_path: Option<&PathBuf>,
+    ) -> Self {
+        let jailer_config = config
+            .jailer_config
+            .as_ref()
+            .filter(|_| jailer_working_dir.is_some());
+        Self {
+            id: jailer_config.and_then(|jailer_config| jailer_config.id.clone()),
+            pid,
+            socket_path: socket_path.to_path_buf(),
+            jailer_working_dir,
+            uid_gid: jailer_config
+                .and_then(|jailer_config| Some((jailer_config.uid?, jailer_config.gid?))),
+            clear_jailer: jail_path.is_some(),
+        }
+    }
+}
+
 pub mod machine {
-    use std::io::{BufRead, Write};
+    use std::{
+        io::{BufRead, Write},
+        path::{Path, PathBuf},
+    };
 
     use crate::{
         config::GlobalConfig,
@@ -7,18 +80,105 @@ pub mod machine {
         firecracker::firecracker::Firecracker,
         jailer::jailer::Jailer,
         local::local::Local,
-        models::{instance_action_info, snapshot_create_params, vm},
+        micro_http::HttpMethod,
+        models::{
+            balloon::Balloon,
+            balloon_stats::BalloonStatistics,
+            balloon_update::BalloonUpdate,
+            cpu_template::CPUTemplateString,
+            firecracker_version::{FirecrackerVersion, MIN_SUPPORTED_VERSION},
+            full_vm_configuration::FullVmConfiguration,
+            instance_action_info,
+            instance_info::{self, InstanceInfo},
+            logger::{LogLevel, Logger},
+            partial_drive::PartialDrive,
+            rate_limiter::RateLimiter,
+            snapshot_create_params, snapshot_load_params, vm,
+            vsock::Vsock,
+        },
+        network::NetworkResource,
         rtck::Rtck,
+        uffd::UffdHandler,
+        vsock_cid::{CidClaim, VsockCidAllocator},
         RtckError, RtckErrorClass, RtckResult,
     };
 
+    use super::{
+        adopted_config, boot_time, boot_timer_error, check_balloon_stats, check_clone_config,
+        check_ctrl_alt_del, check_dirty_pages, check_drive_declared, check_drive_exists,
+        check_snapshot_files, check_snapshot_load, check_vsock_ack, clock_resync_message,
+        clone_snapshot_dir, deflate_on_oom_error, fault, host_path, jailed_drive, kill_pid,
+        link_into_jail, payload, pid_exited, resume_error, serve_uffd, snapshot_version_error,
+        CloneOverrides, MachineCore, SnapshotBundle, SnapshotChain, SnapshotFiles,
+    };
+    #[cfg(feature = "prometheus")]
+    use super::{balloon_stats_enabled, machine_vmid, metrics_flushes};
+    #[cfg(feature = "prometheus")]
+    use crate::exporter::MachineMetrics;
+    #[cfg(feature = "cni")]
+    use crate::network::cni::CniSetup;
+
+    /// Firecracker (or jailer) process behind a machine
+    enum Process {
+        /// Spawned by the machine itself
+        Spawned(std::process::Child),
+        /// Started by someone else, see `Machine::from_existing`
+        Adopted(u32),
+        /// Forked by the spawned jailer, already reaped, into a new PID namespace
+        Forked(std::process::Child, u32),
+    }
+
+    impl Process {
+        fn id(&self) -> u32 {
+            match self {
+                Process::Spawned(child) => child.id(),
+                Process::Adopted(pid) | Process::Forked(_, pid) => *pid,
+            }
+        }
+
+        fn kill(&mut self) -> std::io::Result<()> {
+            match self {
+                Process::Spawned(child) => child.kill(),
+                Process::Adopted(pid) | Process::Forked(_, pid) => kill_pid(*pid),
+            }
+        }
+
+        fn child_mut(&mut self) -> Option<&mut std::process::Child> {
+            match self {
+                Process::Spawned(child) | Process::Forked(child, _) => Some(child),
+                Process::Adopted(_) => None,
+            }
+        }
+
+        fn exited(&mut self) -> RtckResult<bool> {
+            match self {
+                Process::Spawned(child) => Ok(child.try_wait()?.is_some()),
+                Process::Adopted(pid) | Process::Forked(_, pid) => pid_exited(*pid),
+            }
+        }
+    }
+
     pub struct Machine<S> {
         rtck: Rtck<S>,
         local: Local,
         jailer: Option<Jailer>,
         frck: Firecracker,
         config: GlobalConfig,
-        child: std::process::Child,
+        child: Process,
+        cids: CidClaim,
+        uffd: Option<UffdHandler>,
+    }
+
+    // The configuration (which may carry metadata for the guest) is elided
+    impl<S> std::fmt::Debug for Machine<S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Machine")
+                .field("rtck", &self.rtck)
+                .field("socket_path", self.local.get_socket_path())
+                .field("jailed", &self.jailer.is_some())
+                .field("pid", &self.child.id())
+                .finish_non_exhaustive()
+        }
     }
 
     impl<S> Machine<S> {
@@ -26,12 +186,140 @@ pub mod machine {
         pub fn get_config(&self) -> GlobalConfig {
             self.config.clone()
         }
+
+        /// Path of the file capturing the guest console, if configured
+        pub fn console_path(&self) -> Option<&PathBuf> {
+            self.config.console_output.as_ref()
+        }
+
+        /// Host side path of the vsock socket, inside the jail if jailed
+        pub fn vsock_uds_path(&self) -> RtckResult<PathBuf> {
+            if let Some(jailer) = &self.jailer {
+                if let Some(path) = jailer.get_vsock_paths_exported().first() {
+                    return Ok(path.clone());
+                }
+            }
+            self.config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.vsock_devices.as_ref())
+                .and_then(|vsocks| vsocks.first())
+                .map(|vsock| PathBuf::from(&vsock.uds_path))
+                .ok_or(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "No vsock device configured",
+                ))
+        }
+
+        /// Connect to vsock `port` of the guest, using the `CONNECT <port>`
+        /// handshake of firecracker
+        fn connect_vsock(
+            &self,
+            port: u32,
+            timeout: Option<std::time::Duration>,
+        ) -> RtckResult<std::os::unix::net::UnixStream> {
+            use std::io::BufReader;
+
+            let path = self.vsock_uds_path()?;
+            let mut stream = std::os::unix::net::UnixStream::connect(&path)?;
+            stream.set_read_timeout(timeout)?;
+            stream.set_write_timeout(timeout)?;
+            stream.write_all(format!("CONNECT {port}\n").as_bytes())?;
+
+            // The guest only speaks once spoken to, nothing is read past the ack
+            let mut stream = BufReader::new(stream);
+            let mut ack = String::new();
+            stream.read_line(&mut ack)?;
+            check_vsock_ack(port, &ack)?;
+            Ok(stream.into_inner())
+        }
+
+        /// Check that the guest accepts connections on vsock `port`,
+        /// using the `CONNECT <port>` handshake of firecracker.
+        pub fn probe_vsock(&self, port: u32, timeout: std::time::Duration) -> RtckResult<()> {
+            self.connect_vsock(port, Some(timeout))?;
+            Ok(())
+        }
+
+        /// Send `data` to the guest listening on vsock `port`, within the
+        /// request timeout, then close the connection
+        pub fn vsock_send(&self, port: u32, data: &[u8]) -> RtckResult<()> {
+            let mut stream = self.connect_vsock(port, self.config.request_timeout()?)?;
+            stream.write_all(data)?;
+            stream.shutdown(std::net::Shutdown::Write)?;
+            Ok(())
+        }
+
+        /// Have the guest resync its clock, stale after a snapshot is restored
+        /// or a long pause, by sending `clock_resync_message` to the agent
+        /// listening on `vsock_control_port`. Nothing is sent without one.
+        pub fn notify_resume_time(&self) -> RtckResult<()> {
+            match self.config.vsock_control_port {
+                Some(port) => self.vsock_send(port, clock_resync_message().as_bytes()),
+                None => Ok(()),
+            }
+        }
+
+        /// Take the stdin handle of firecracker, if configured as piped
+        pub fn take_stdin(&mut self) -> Option<std::process::ChildStdin> {
+            self.child.child_mut()?.stdin.take()
+        }
+
+        /// Take the stdout handle of firecracker, if configured as piped
+        pub fn take_stdout(&mut self) -> Option<std::process::ChildStdout> {
+            self.child.child_mut()?.stdout.take()
+        }
+
+        /// Take the stderr handle of firecracker, if configured as piped
+        pub fn take_stderr(&mut self) -> Option<std::process::ChildStderr> {
+            self.child.child_mut()?.stderr.take()
+        }
+
+        /// Dump what is needed to reattach to the machine with `restore_core`
+        pub fn dump_core(&self) -> MachineCore {
+            MachineCore::new(
+                &self.config,
+                self.child.id(),
+                self.local.get_socket_path(),
+                self.jailer
+                    .as_ref()
+                    .and_then(|jailer| jailer.get_jailer_workspace_dir().ok().cloned()),
+                self.local.get_jail_path(),
+            )
+        }
+
+        /// Wait for the firecracker process to exit. Adopted processes are
+        /// polled every `poll_status_secs`, unless they are children of ours.
+        pub fn wait(&mut self) -> RtckResult<()> {
+            match &mut self.child {
+                Process::Spawned(child) => {
+                    child.wait()?;
+                }
+                Process::Adopted(pid) | Process::Forked(_, pid) => {
+                    while !pid_exited(*pid)? {
+                        std::thread::sleep(self.config.poll_status_interval());
+                    }
+                }
+            }
+            self.cids.release();
+            self.uffd = None;
+            Ok(())
+        }
     }
 
     impl Machine<bufstream::BufStream<std::os::unix::net::UnixStream>> {
         /// Create a machine from scratch, using default stream
         pub fn create(config: &GlobalConfig) -> RtckResult<Self> {
+            let mut config = config.clone();
+            config.assign_instance()?;
+            let cids = VsockCidAllocator::global().claim(&mut config)?;
             config.validate()?;
+            #[cfg(feature = "cni")]
+            let cni = match config.frck_config.as_mut() {
+                Some(frck_config) => CniSetup::run(frck_config)?,
+                None => None,
+            };
+            let config = &config;
 
             let frck = Firecracker::from_config(config)?;
             let mut jailer = Jailer::from_config(config).ok();
@@ -45,50 +333,222 @@ pub mod machine {
                 assert!(jailer.is_some());
                 let jailer = jailer.as_mut().unwrap();
                 jailer.jail()?;
-                let child = jailer.launch()?;
-                jailer.waiting_socket(std::time::Duration::from_secs(3))?;
+                let mut child = jailer.launch()?;
+                jailer.waiting_socket(config.init_timeout()?)?;
+                let child = match jailer.pid_file_path()? {
+                    Some(_) => {
+                        // The jailer exits once firecracker is forked
+                        let pid = jailer.read_pid(config.init_timeout()?)?;
+                        child.wait()?;
+                        Process::Forked(child, pid)
+                    }
+                    None => Process::Spawned(child),
+                };
                 (
-                    jailer.connect()?,
+                    crate::retry::retry(&config.api_retry.unwrap_or_default(), || {
+                        jailer.connect()
+                    })?,
                     child,
                     Local::from_jailer(jailer, config)?,
                 )
             } else {
                 // Firecracker launch and connect
                 let child = frck.launch()?;
-                frck.waiting_socket(std::time::Duration::from_secs(3))?;
-                (frck.connect()?, child, Local::from_frck(&frck, config)?)
+                frck.waiting_socket(config.init_timeout()?)?;
+                (
+                    crate::retry::retry(&config.api_retry.unwrap_or_default(), || frck.connect())?,
+                    Process::Spawned(child),
+                    Local::from_frck(&frck, config)?,
+                )
             };
 
             // Set up local environment
             local.full_clean();
             local.setup()?;
+            #[cfg(feature = "cni")]
+            for resource in cni.into_iter().flat_map(CniSetup::into_resources) {
+                local.get_network().track(resource);
+            }
 
-            let rtck = Rtck::from_stream(stream);
+            let rtck = Self::rtck_from_stream(
+                stream,
+                local.get_socket_path().clone(),
+                config.request_timeout()?,
+            )?
+            .with_trace(config.api_trace())
+            .with_retry_policy(config.api_retry);
 
-            Ok(Self {
+            let mut machine = Self {
                 rtck,
                 local,
                 jailer,
                 frck,
                 config: config.clone(),
                 child,
-            })
+                cids,
+                uffd: None,
+            };
+            if config.check_version.unwrap_or(false) {
+                if let Err(e) = machine.assert_compatible(MIN_SUPPORTED_VERSION) {
+                    let _ = machine.stop_force();
+                    return Err(e);
+                }
+            }
+            Ok(machine)
+        }
+
+        /// Adopt firecracker process `pid`, started by a previous run and
+        /// listening on `socket_path`. The machine must answer to be adopted,
+        /// it is neither configured nor set up again.
+        pub fn from_existing(socket_path: PathBuf, pid: u32) -> RtckResult<Self> {
+            let config = adopted_config(&socket_path, pid)?;
+            let frck = Firecracker::from_config(&config)?;
+            let local = Local::from_frck(&frck, &config)?;
+            let stream =
+                bufstream::BufStream::new(std::os::unix::net::UnixStream::connect(&socket_path)?);
+            let rtck = Self::rtck_from_stream(stream, socket_path, config.request_timeout()?)?;
+
+            let mut machine = Self {
+                rtck,
+                local,
+                jailer: None,
+                frck,
+                config,
+                child: Process::Adopted(pid),
+                cids: CidClaim::empty(),
+                uffd: None,
+            };
+            machine.pint_remote()?;
+            Ok(machine)
+        }
+
+        /// Reattach to the machine described by `core`. Only the socket and
+        /// the process are taken over, so that cleaning up never removes more
+        /// than the socket and dumping the core again drops the jailer fields.
+        pub fn restore_core(core: MachineCore) -> RtckResult<Self> {
+            Self::from_existing(core.socket_path, core.pid)
+        }
+
+        /// Recycle the machine, e.g. once crashed: kill firecracker if still
+        /// running and clean up as `delete_and_clean` does, undoing the host
+        /// side set up of the devices, then create, configure and start the
+        /// machine anew from `config`. Firecracker cannot be configured again
+        /// once started, so a fresh process is launched, on a fresh socket.
+        /// `config` is validated again: a log or metrics file kept from the
+        /// previous run makes it fail.
+        pub fn restart(&mut self, config: &GlobalConfig) -> RtckResult<()> {
+            if !self.child.exited()? {
+                self.stop_force()?;
+            }
+            self.wait()?;
+            self.local.full_clean();
+
+            *self = Self::create(config)?;
+            if let Err(e) = self.configure().and_then(|()| self.start()) {
+                self.abort_start("failed on restart");
+                return Err(e);
+            }
+            Ok(())
+        }
+
+        /// Talk to firecracker through `stream`, reconnecting to `socket_path`
+        /// once it closes the connection. Requests are bounded through the
+        /// socket timeouts.
+        fn rtck_from_stream(
+            stream: bufstream::BufStream<std::os::unix::net::UnixStream>,
+            socket_path: PathBuf,
+            request_timeout: Option<std::time::Duration>,
+        ) -> RtckResult<Rtck<bufstream::BufStream<std::os::unix::net::UnixStream>>> {
+            let set_timeout = move |stream: &std::os::unix::net::UnixStream| {
+                stream.set_read_timeout(request_timeout)?;
+                stream.set_write_timeout(request_timeout)
+            };
+            set_timeout(stream.get_ref())?;
+
+            Ok(Rtck::from_stream(stream).with_reconnect(move || {
+                let stream = std::os::unix::net::UnixStream::connect(&socket_path)?;
+                set_timeout(&stream)?;
+                Ok(bufstream::BufStream::new(stream))
+            }))
         }
     }
 
     impl<S: BufRead + Write> Machine<S> {
         /// Ping firecracker to check its soundness
         pub fn pint_remote(&mut self) -> RtckResult<()> {
+            let policy = self.config.api_retry.unwrap_or_default();
+            Ok(crate::retry::retry(&policy, || {
+                self.rtck.execute(&mut events::GetFirecrackerVersion::new())
+            })
+            .map_err(|e| {
+                RtckError::new(
+                    RtckErrorClass::RemoteError,
+                    format!("Fail to ping remote {}", e.to_string()),
+                )
+                .with_source(e)
+            })?)
+        }
+
+        /// Query the version of firecracker
+        pub fn firecracker_version(&mut self) -> RtckResult<FirecrackerVersion> {
             let mut get_firecracker_version = events::GetFirecrackerVersion::new();
-            Ok(self
-                .rtck
-                .execute(&mut get_firecracker_version)
-                .map_err(|e| {
-                    RtckError::new(
-                        RtckErrorClass::RemoteError,
-                        format!("Fail to ping remote {}", e.to_string()),
-                    )
-                })?)
+            self.rtck.execute(&mut get_firecracker_version)?;
+            if get_firecracker_version.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to get firecracker version",
+                    fault(get_firecracker_version.get_res_mut().as_err()),
+                ));
+            }
+            payload(get_firecracker_version.get_res_mut().as_succ())
+        }
+
+        /// Check that firecracker is version `min`, as (major, minor, patch),
+        /// or later, rather than have an older API fail requests obscurely
+        pub fn assert_compatible(&mut self, min: (u64, u64, u64)) -> RtckResult<()> {
+            self.firecracker_version()?.check_compatible(min)
+        }
+
+        /// Check that the host and firecracker support the huge pages and
+        /// the CPU template the machine configuration asks for
+        fn check_machine_config(&mut self) -> RtckResult<()> {
+            let Some(frck_config) = &self.config.frck_config else {
+                return Ok(());
+            };
+            let Some(machine_config) = &frck_config.machine_config else {
+                return Ok(());
+            };
+            let huge_pages = machine_config.huge_pages.unwrap_or_default();
+            let cpu_template = machine_config
+                .cpu_template
+                .as_ref()
+                .map_or(CPUTemplateString::None, |template| template.0.clone());
+            if frck_config.disable_validation
+                || !machine_config.uses_huge_pages()
+                    && cpu_template.min_firecracker_version().is_none()
+            {
+                return Ok(());
+            }
+            huge_pages.check_host(machine_config.mem_size_mib as u64)?;
+            let version = self.firecracker_version()?;
+            huge_pages.check_version(&version)?;
+            cpu_template.check_version(&version)
+        }
+
+        /// Logger of the configuration, its level named the way the running
+        /// firecracker does, see `LogLevel::for_version`
+        fn versioned_logger(&mut self) -> RtckResult<Option<Logger>> {
+            let Some(mut logger) = self
+                .config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.logger.clone())
+            else {
+                return Ok(None);
+            };
+            if let Some(level) = logger.level.clone().filter(LogLevel::depends_on_version) {
+                logger.level = Some(level.for_version(&self.firecracker_version()?)?);
+            }
+            Ok(Some(logger))
         }
 
         /// Automatically configure the machine.
@@ -99,6 +559,9 @@ pub mod machine {
                 return Ok(());
             }
 
+            self.check_machine_config()?;
+            let logger = self.versioned_logger()?;
+
             use events::*;
 
             // User must guarantee that proper
@@ -106,16 +569,21 @@ pub mod machine {
                 RtckErrorClass::ConfigError,
                 "No proper firecracker configuration passed".to_string(),
             ))?;
+            frck_config.check_device_ids()?;
+            log::debug!(
+                "[Machine::configure configuring {:?}]",
+                self.config.loggable(frck_config)
+            );
 
             // Logger
             {
-                if let Some(logger) = &frck_config.logger {
-                    let mut put_logger = PutLogger::new(logger.clone());
+                if let Some(logger) = logger {
+                    let mut put_logger = PutLogger::new(logger);
                     self.rtck.execute(&mut put_logger)?;
-                    if put_logger.is_err() {
+                    if put_logger.is_err()? {
                         log::error!(
                             "[PutLogger failed, error = {}]",
-                            put_logger.get_res_mut().err()
+                            fault(put_logger.get_res_mut().as_err())
                         );
                     }
                 }
@@ -126,10 +594,10 @@ pub mod machine {
                 if let Some(metrics) = &frck_config.metrics {
                     let mut put_metrics = PutMetrics::new(metrics.clone());
                     self.rtck.execute(&mut put_metrics)?;
-                    if put_metrics.is_err() {
+                    if put_metrics.is_err()? {
                         log::error!(
                             "[PutMetrics failed, error = {}]",
-                            put_metrics.get_res_mut().err()
+                            fault(put_metrics.get_res_mut().as_err())
                         );
                     }
                 }
@@ -137,13 +605,13 @@ pub mod machine {
 
             // Guest boot source
             {
-                if let Some(boot_source) = &frck_config.boot_source {
-                    let mut put_guest_boot_source = PutGuestBootSource::new(boot_source.clone());
+                if let Some(boot_source) = frck_config.effective_boot_source()? {
+                    let mut put_guest_boot_source = PutGuestBootSource::new(boot_source);
                     self.rtck.execute(&mut put_guest_boot_source)?;
-                    if put_guest_boot_source.is_err() {
+                    if put_guest_boot_source.is_err()? {
                         log::error!(
                             "[PutGuestBootSource failed, error = {}]",
-                            put_guest_boot_source.get_res_mut().err()
+                            fault(put_guest_boot_source.get_res_mut().as_err())
                         );
                     }
                 }
@@ -153,12 +621,13 @@ pub mod machine {
             {
                 if let Some(drives) = &frck_config.drives {
                     for drive in drives {
-                        let mut put_guest_drive_by_id = PutGuestDriveById::new(drive.clone());
+                        let drive = jailed_drive(&self.config, self.local.get_jail_path(), drive)?;
+                        let mut put_guest_drive_by_id = PutGuestDriveById::new(drive);
                         self.rtck.execute(&mut put_guest_drive_by_id)?;
-                        if put_guest_drive_by_id.is_err() {
+                        if put_guest_drive_by_id.is_err()? {
                             log::error!(
                                 "[PutGuestDriveById failed, error = {}]",
-                                put_guest_drive_by_id.get_res_mut().err()
+                                fault(put_guest_drive_by_id.get_res_mut().as_err())
                             );
                         }
                     }
@@ -172,26 +641,40 @@ pub mod machine {
                         let mut put_guest_network_interface_by_id =
                             PutGuestNetworkInterfaceById::new(iface.clone());
                         self.rtck.execute(&mut put_guest_network_interface_by_id)?;
-                        if put_guest_network_interface_by_id.is_err() {
+                        if put_guest_network_interface_by_id.is_err()? {
                             log::error!(
                                 "[PutGuestNetworkInterfaceById failed, error = {}]",
-                                put_guest_network_interface_by_id.get_res_mut().err()
+                                fault(put_guest_network_interface_by_id.get_res_mut().as_err())
                             );
                         }
                     }
                 }
             }
 
+            // MMDS configuration, once the interfaces forwarding to it exist
+            {
+                if let Some(mmds_config) = frck_config.mmds_config()? {
+                    let mut put_mmds_config = PutMmdsConfig::new(mmds_config);
+                    self.rtck.execute(&mut put_mmds_config)?;
+                    if put_mmds_config.is_err()? {
+                        log::error!(
+                            "[PutMmdsConfig failed, error = {}]",
+                            fault(put_mmds_config.get_res_mut().as_err())
+                        );
+                    }
+                }
+            }
+
             // Vsocks
             {
                 if let Some(vsocks) = &frck_config.vsock_devices {
                     for vsock in vsocks {
                         let mut put_guest_vsock = PutGuestVsock::new(vsock.clone());
                         self.rtck.execute(&mut put_guest_vsock)?;
-                        if put_guest_vsock.is_err() {
+                        if put_guest_vsock.is_err()? {
                             log::error!(
                                 "[PutGuestVsock failed, error = {}]",
-                                put_guest_vsock.get_res_mut().err()
+                                fault(put_guest_vsock.get_res_mut().as_err())
                             );
                         }
                     }
@@ -203,10 +686,10 @@ pub mod machine {
                 if let Some(cpu_config) = &frck_config.cpu_config {
                     let mut put_cpu_configuration = PutCpuConfiguration::new(cpu_config.clone());
                     self.rtck.execute(&mut put_cpu_configuration)?;
-                    if put_cpu_configuration.is_err() {
+                    if put_cpu_configuration.is_err()? {
                         log::error!(
                             "[PutCpuConfiguration failed, error = {}]",
-                            put_cpu_configuration.get_res_mut().err()
+                            fault(put_cpu_configuration.get_res_mut().as_err())
                         );
                     }
                 }
@@ -215,13 +698,16 @@ pub mod machine {
             // Machine configuration
             {
                 if let Some(machine_config) = &frck_config.machine_config {
+                    if !frck_config.disable_validation {
+                        machine_config.validate()?;
+                    }
                     let mut put_machine_configuration =
                         PutMachineConfiguration::new(machine_config.clone());
                     self.rtck.execute(&mut put_machine_configuration)?;
-                    if put_machine_configuration.is_err() {
+                    if put_machine_configuration.is_err()? {
                         log::error!(
                             "[PutMachineConfiguration failed, error = {}]",
-                            put_machine_configuration.get_res_mut().err()
+                            fault(put_machine_configuration.get_res_mut().as_err())
                         );
                     }
                 }
@@ -232,10 +718,10 @@ pub mod machine {
                 if let Some(balloon) = &frck_config.balloon {
                     let mut put_balloon = PutBalloon::new(balloon.clone());
                     self.rtck.execute(&mut put_balloon)?;
-                    if put_balloon.is_err() {
+                    if put_balloon.is_err()? {
                         log::error!(
                             "[PutBalloon failed, error = {}]",
-                            put_balloon.get_res_mut().err()
+                            fault(put_balloon.get_res_mut().as_err())
                         );
                     }
                 }
@@ -246,10 +732,10 @@ pub mod machine {
                 if let Some(entropy_device) = &frck_config.entropy_device {
                     let mut put_entropy = PutEntropy::new(entropy_device.clone());
                     self.rtck.execute(&mut put_entropy)?;
-                    if put_entropy.is_err() {
+                    if put_entropy.is_err()? {
                         log::error!(
                             "[PutEntropy failed, error = {}]",
-                            put_entropy.get_res_mut().err()
+                            fault(put_entropy.get_res_mut().as_err())
                         );
                     }
                 }
@@ -258,10 +744,14 @@ pub mod machine {
             // Initial mmds content
             {
                 if let Some(content) = &frck_config.init_metadata {
+                    self.config.check_mmds_size(content)?;
                     let mut put_mmds = PutMmds::new(content.clone());
                     self.rtck.execute(&mut put_mmds)?;
-                    if put_mmds.is_err() {
-                        log::error!("[PutMmds failed, error = {}]", put_mmds.get_res_mut().err());
+                    if put_mmds.is_err()? {
+                        log::error!(
+                            "[PutMmds failed, error = {}]",
+                            fault(put_mmds.get_res_mut().as_err())
+                        );
                     }
                 }
             }
@@ -269,43 +759,100 @@ pub mod machine {
             Ok(())
         }
 
-        /// Start the machine by notifying the hypervisor
+        /// Start the machine by notifying the hypervisor, then wait for it
+        /// to run if a launch timeout is configured
         pub fn start(&mut self) -> RtckResult<()> {
-            let mut start_machine =
-                events::CreateSyncAction::new(instance_action_info::InstanceActionInfo {
-                    action_type: instance_action_info::ActionType::InstanceStart,
-                });
+            self.instance_start()?;
+            if let Some(timeout) = self.config.launch_timeout()? {
+                self.wait_until_running(timeout)?;
+            }
+            Ok(())
+        }
+
+        fn instance_start(&mut self) -> RtckResult<()> {
+            let mut start_machine = events::CreateSyncAction::new(
+                instance_action_info::InstanceActionInfo::instance_start(),
+            );
 
             self.rtck.execute(&mut start_machine)?;
             Ok(())
         }
 
-        /// Pause the machine by notifying the hypervisor
-        pub fn pause(&mut self) -> RtckResult<()> {
-            let mut pause_machine = events::PatchVm::new(vm::Vm {
-                state: vm::State::Paused,
-            });
+        /// Configure and start the machine, then wait for it to run, giving
+        /// up once `deadline` passes. The deadline is checked between steps,
+        /// a step blocking on firecracker is bounded by the request timeout.
+        /// A machine through every step is kept, otherwise on expiry it is
+        /// killed and cleaned.
+        pub fn start_with_deadline(&mut self, deadline: std::time::Instant) -> RtckResult<()> {
+            let expired = || std::time::Instant::now() >= deadline;
+            // Whether every step ran, rather than being skipped on expiry
+            let res = (|| {
+                if expired() {
+                    return Ok(false);
+                }
+                self.configure()?;
+                if expired() {
+                    return Ok(false);
+                }
+                self.instance_start()?;
+                if expired() {
+                    return Ok(false);
+                }
+                let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                self.wait_until_running(remaining)?;
+                Ok(true)
+            })();
+            match res {
+                Ok(true) => Ok(()),
+                Err(e) if !expired() => Err(e),
+                _ => Err(self.abort_start("timed out")),
+            }
+        }
 
-            self.rtck.execute(&mut pause_machine)?;
+        /// Kill and clean the machine after an aborted start
+        fn abort_start(&mut self, reason: &str) -> RtckError {
+            log::error!("[Machine::start {reason}, cleaning up]");
+            let _ = self.stop_force();
+            self.cids.release();
+            self.uffd = None;
+            self.local.full_clean();
+            RtckError::new(RtckErrorClass::MachineError, format!("start {reason}"))
+        }
+
+        /// Set the VM to `state`, refusing transitions the machine cannot
+        /// take from its current state
+        pub fn set_vm_state(&mut self, state: vm::State) -> RtckResult<()> {
+            state.check_transition(self.describe_instance()?.state)?;
+
+            let mut patch_vm = events::PatchVm::new(vm::Vm { state });
+            self.rtck.execute(&mut patch_vm)?;
+            if patch_vm.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to set VM state",
+                    fault(patch_vm.get_res_mut().as_err()),
+                ));
+            }
             Ok(())
         }
 
+        /// Pause the machine by notifying the hypervisor
+        pub fn pause(&mut self) -> RtckResult<()> {
+            self.set_vm_state(vm::State::Paused)
+        }
+
         /// Resume the machine by notifying the hypervisor
         pub fn resume(&mut self) -> RtckResult<()> {
-            let mut resume_machine = events::PatchVm::new(vm::Vm {
-                state: vm::State::Resumed,
-            });
-
-            self.rtck.execute(&mut resume_machine)?;
-            Ok(())
+            self.set_vm_state(vm::State::Resumed)
         }
 
-        /// Stop the machine by notifying the hypervisor
+        /// Stop the machine by having firecracker send Ctrl+Alt+Del to the
+        /// guest. Refused on aarch64, where `stop_force` must be used instead.
         pub fn stop(&mut self) -> RtckResult<()> {
+            check_ctrl_alt_del(std::env::consts::ARCH)?;
             let mut stop_machine =
-                events::CreateSyncAction::new(instance_action_info::InstanceActionInfo {
-                    action_type: instance_action_info::ActionType::SendCtrlAtlDel,
-                });
+                events::CreateSyncAction::new(instance_action_info::InstanceActionInfo::new(
+                    instance_action_info::ActionType::SendCtrlAltDel,
+                ));
 
             self.rtck.execute(&mut stop_machine)?;
             Ok(())
@@ -323,15 +870,21 @@ pub mod machine {
         }
 
         pub fn delete(&mut self) -> RtckResult<()> {
-            // Stop the machine first
+            // Stop the machine first, killing it if the guest cannot be asked to
+            if check_ctrl_alt_del(std::env::consts::ARCH).is_err() {
+                self.stop_force()?;
+                self.cids.release();
+                self.uffd = None;
+                return Ok(());
+            }
             self.stop()?;
             let mut query_status = events::DescribeInstance::new();
             self.rtck.execute(&mut query_status)?;
 
-            if query_status.is_err() {
+            if query_status.is_err()? {
                 log::error!(
                     "[Machine::delete query status failed, error = {}]",
-                    query_status.get_res_mut().err()
+                    fault(query_status.get_res_mut().as_err())
                 );
                 return Err(RtckError::new(
                     RtckErrorClass::MachineError,
@@ -339,7 +892,7 @@ pub mod machine {
                 ));
             }
 
-            let state = query_status.get_res_mut().succ().state;
+            let state = payload(query_status.get_res_mut().as_succ())?.state;
 
             use crate::models::instance_info;
             if state == instance_info::State::Running {
@@ -347,9 +900,17 @@ pub mod machine {
                 self.stop_force()?;
             }
 
+            self.cids.release();
+            self.uffd = None;
             Ok(())
         }
 
+        /// Have `resource`, created by the user for this machine, deleted by
+        /// `delete_and_clean` if `network_clear` is set
+        pub fn clear_network_on_delete(&self, resource: NetworkResource) {
+            self.local.get_network().track(resource);
+        }
+
         /// Delete the machine and do cleaning at the same time
         pub fn delete_and_clean(&mut self) -> RtckResult<()> {
             self.delete()?;
@@ -357,415 +918,5155 @@ pub mod machine {
             Ok(())
         }
 
-        /// Create a snapshot
+        /// Create a snapshot. Diff snapshots are refused early unless the
+        /// machine tracks dirty pages.
         pub fn snapshot<P: AsRef<str>, Q: AsRef<str>>(
             &mut self,
             state_path: P,
             mem_path: Q,
             _type: snapshot_create_params::SnapshotType,
         ) -> RtckResult<()> {
+            if _type == snapshot_create_params::SnapshotType::Diff {
+                self.check_diff_snapshot()?;
+            }
+
             let mut create_snapshot =
                 events::CreateSnapshot::new(snapshot_create_params::SnapshotCreateParams {
-                    mem_file_path: state_path.as_ref().to_string(),
-                    snapshot_path: mem_path.as_ref().to_string(),
+                    mem_file_path: mem_path.as_ref().to_string(),
+                    snapshot_path: state_path.as_ref().to_string(),
                     snapshot_type: Some(_type),
                     version: None,
                 });
 
             self.rtck.execute(&mut create_snapshot)?;
+            if create_snapshot.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to create snapshot",
+                    fault(create_snapshot.get_res_mut().as_err()),
+                ));
+            }
             Ok(())
         }
-    }
-}
-
-pub mod machine_async {
-    use parking_lot::Mutex;
 
-    use crate::{
-        config::GlobalConfig,
-        events::events_async::{self, EventAsync},
-        firecracker::firecracker_async::FirecrackerAsync,
-        jailer::jailer_async::JailerAsync,
-        local::local_async::LocalAsync,
-        models::{
-            instance_action_info::{ActionType, InstanceActionInfo},
-            snapshot_create_params::{SnapshotCreateParams, SnapshotType},
-            vm,
-        },
-        rtck_async::RtckAsync,
-        RtckError, RtckErrorClass, RtckResult,
-    };
+        /// Load a snapshot, with its memory in a file or served by a
+        /// userfaultfd handler, e.g. `SnapshotLoadParams::uffd(..).resume(true)`.
+        /// The files of the snapshot are checked first, see `load_snapshot_with`.
+        pub fn load_snapshot(
+            &mut self,
+            params: snapshot_load_params::SnapshotLoadParams,
+        ) -> RtckResult<()> {
+            self.load_snapshot_with(params, false)
+        }
 
-    pub struct Machine<S> {
-        rtck: Mutex<RtckAsync<S>>,
-        local: LocalAsync,
-        jailer: Option<JailerAsync>,
-        frck: FirecrackerAsync,
-        config: GlobalConfig,
-        child: Mutex<tokio::process::Child>,
-    }
+        /// Load a snapshot once its files are checked, unless `force`: they
+        /// must exist, and the memory file must not be truncated. If the
+        /// manifest of `SnapshotBundle` lies next to the state file, the memory
+        /// file must hold the memory of the machine, and the snapshot must
+        /// have been taken by the same minor version of firecracker.
+        pub fn load_snapshot_with(
+            &mut self,
+            params: snapshot_load_params::SnapshotLoadParams,
+            force: bool,
+        ) -> RtckResult<()> {
+            check_snapshot_load(&params, self.local.get_jail_path())?;
+            if !force {
+                if let Some(bundle) = check_snapshot_files(&params, self.local.get_jail_path())? {
+                    bundle
+                        .check_version(&self.firecracker_version()?)
+                        .map_err(snapshot_version_error)?;
+                }
+            }
 
-    impl<S> Machine<S> {
-        /// Dump the global configuration of the machine for future use
-        pub fn get_config(&self) -> GlobalConfig {
-            self.config.clone()
+            let mut load_snapshot = events::LoadSnapshot::new(params);
+            self.rtck.execute(&mut load_snapshot)?;
+            if load_snapshot.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to load snapshot",
+                    fault(load_snapshot.get_res_mut().as_err()),
+                ));
+            }
+            Ok(())
         }
-    }
 
-    #[cfg(feature = "tokio")]
-    impl Machine<tokio::io::BufStream<tokio::net::UnixStream>> {
-        /// Create a machine from scratch, using default stream
-        pub async fn create(config: &GlobalConfig) -> RtckResult<Self> {
-            config.validate()?;
+        /// Load a snapshot with its memory served lazily from `mem_path` by a
+        /// userfaultfd handler, which lives as long as the machine
+        pub fn load_snapshot_uffd<T: AsRef<str>, P: AsRef<Path>>(
+            &mut self,
+            state_path: T,
+            mem_path: P,
+        ) -> RtckResult<()> {
+            let (handler, uds_path) = serve_uffd(
+                &self.config,
+                self.local.get_socket_path(),
+                self.local.get_jail_path(),
+                mem_path.as_ref(),
+            )?;
+            self.load_snapshot(snapshot_load_params::SnapshotLoadParams::uffd(
+                state_path.as_ref(),
+                uds_path,
+            ))?;
+            self.uffd = Some(handler);
+            Ok(())
+        }
 
-            let frck = FirecrackerAsync::from_config(config)?;
-            let mut jailer = JailerAsync::from_config(config).ok();
+        /// Take the next snapshot of the chain kept in `base_dir`, a full one
+        /// first and diffs afterwards, and record it in the chain manifest
+        pub fn snapshot_diff<P: AsRef<Path>>(&mut self, base_dir: P) -> RtckResult<SnapshotFiles> {
+            self.check_diff_snapshot()?;
 
-            if config.frck_export_path.is_some() {
-                config.export_config_async().await?;
-            }
+            let base_dir = base_dir.as_ref();
+            let mut chain = SnapshotChain::load(base_dir)?;
+            let files = chain.next_files(base_dir);
+            self.snapshot(
+                files.snapshot_path.to_string_lossy(),
+                files.mem_file_path.to_string_lossy(),
+                files.snapshot_type,
+            )?;
+            chain.snapshots.push(files.clone());
+            chain.save(base_dir)?;
+            Ok(files)
+        }
 
-            let (stream, child, local) = if config.using_jailer.unwrap() {
-                // Set up for jailing
-                assert!(jailer.is_some());
-                let jailer = jailer.as_mut().unwrap();
-                jailer.jail()?;
-                let child = jailer.launch().await?;
-                jailer
-                    .waiting_socket(tokio::time::Duration::from_secs(3))
-                    .await?;
+        /// Take a full snapshot into `dir`, seen by firecracker, along with
+        /// the manifest of `SnapshotBundle`. The machine is paused meanwhile,
+        /// and a machine failing to resume once the snapshot is written is
+        /// reported with a `MachineError`.
+        pub fn checkpoint<P: AsRef<Path>>(&mut self, dir: P) -> RtckResult<SnapshotBundle> {
+            let dir = dir.as_ref();
+            let bundle = SnapshotBundle {
+                firecracker_version: self.firecracker_version()?,
+                snapshot_type: snapshot_create_params::SnapshotType::Full,
+                vm_config: self.export_vm_config()?,
+            };
+            let (state_path, mem_path) = SnapshotBundle::files(dir);
 
-                (
-                    jailer.connect().await?,
-                    child,
-                    LocalAsync::from_jailer(jailer, config)?,
+            self.pause()?;
+            let written = self
+                .snapshot(
+                    state_path.to_string_lossy(),
+                    mem_path.to_string_lossy(),
+                    bundle.snapshot_type,
                 )
-            } else {
-                // Firecracker launch and connect
-                let child = frck.launch().await?;
-                frck.waiting_socket(tokio::time::Duration::from_secs(3))
-                    .await?;
-                (
-                    frck.connect().await?,
-                    child,
-                    LocalAsync::from_frck(&frck, config)?,
+                .and_then(|_| bundle.save(host_path(self.local.get_jail_path(), dir)));
+            match (written, self.resume()) {
+                (Ok(()), Ok(())) => Ok(bundle),
+                (Ok(()), Err(e)) => Err(resume_error(dir, e)),
+                (Err(e), resumed) => {
+                    if let Err(resume_e) = resumed {
+                        log::error!("[Machine::checkpoint fail to resume, {resume_e}]");
+                    }
+                    Err(e)
+                }
+            }
+        }
+
+        /// Restore the snapshot taken by `checkpoint` into `dir` and resume
+        /// the machine, which must not be configured. The snapshot must have
+        /// been taken by the same minor version of firecracker.
+        pub fn restore_bundle<P: AsRef<Path>>(&mut self, dir: P) -> RtckResult<SnapshotBundle> {
+            let dir = dir.as_ref();
+            let bundle = SnapshotBundle::load(host_path(self.local.get_jail_path(), dir))?;
+            let (state_path, mem_path) = SnapshotBundle::files(dir);
+            self.load_snapshot(
+                snapshot_load_params::SnapshotLoadParams::file(
+                    state_path.to_string_lossy(),
+                    mem_path.to_string_lossy(),
                 )
-            };
+                .resume(true),
+            )?;
+            Ok(bundle)
+        }
 
-            // Set up local environment
-            local.full_clean().await;
-            local.setup().await?;
+        /// Clone the machine into a new one created from `config`, with its
+        /// own socket and vmid. The machine is checkpointed with a full
+        /// snapshot and resumes, and the clone is loaded from the snapshot
+        /// and resumed. `overrides` must give a tap device to every network
+        /// interface, which requires firecracker 1.12 or later.
+        pub fn clone_to(
+            &mut self,
+            config: &GlobalConfig,
+            overrides: &CloneOverrides,
+        ) -> RtckResult<Machine<bufstream::BufStream<std::os::unix::net::UnixStream>>> {
+            check_clone_config(config)?;
+            let network_overrides = overrides.network_overrides(
+                self.export_vm_config()?
+                    .network_interfaces
+                    .as_deref()
+                    .unwrap_or_default(),
+            )?;
+            let (host_dir, dir) =
+                clone_snapshot_dir(&self.config, self.local.get_jail_path(), overrides)?;
+            let bundle = self.checkpoint(&dir)?;
 
-            let rtck = Mutex::new(RtckAsync::from_stream(stream));
+            let mut clone = Machine::create(config)?;
+            let loaded = clone.load_clone(&bundle, &host_dir, network_overrides);
+            if overrides.snapshot_dir.is_none() {
+                let _ = std::fs::remove_dir_all(&host_dir);
+            }
+            if let Err(e) = loaded {
+                let _ = clone.stop_force();
+                clone.local.full_clean();
+                return Err(e);
+            }
+            Ok(clone)
+        }
 
-            Ok(Self {
-                rtck,
-                local,
-                jailer,
-                frck,
-                config: config.clone(),
-                child: Mutex::new(child),
-            })
+        /// Load the snapshot of `bundle` kept in `host_dir` into the machine,
+        /// attaching its network interfaces to other tap devices
+        fn load_clone(
+            &mut self,
+            bundle: &SnapshotBundle,
+            host_dir: &Path,
+            network_overrides: Vec<snapshot_load_params::NetworkOverride>,
+        ) -> RtckResult<()> {
+            bundle
+                .check_version(&self.firecracker_version()?)
+                .map_err(snapshot_version_error)?;
+            let (state_path, mem_path) = SnapshotBundle::files(host_dir);
+            let jail_path = self.local.get_jail_path();
+            let state_path = link_into_jail(&self.config, jail_path, &state_path)?;
+            let mem_path = link_into_jail(&self.config, jail_path, &mem_path)?;
+            let mut params =
+                snapshot_load_params::SnapshotLoadParams::file(state_path, mem_path).resume(true);
+            if !network_overrides.is_empty() {
+                params = params.network_overrides(network_overrides);
+            }
+            self.load_snapshot(params)
         }
-    }
 
-    #[cfg(feature = "tokio")]
-    use tokio::io::{AsyncBufRead, AsyncWrite};
-    impl<S: AsyncBufRead + AsyncWrite + Unpin> Machine<S> {
-        /// Ping firecracker to check its soundness
-        pub async fn ping_remote(&self) -> RtckResult<()> {
-            let get_firecracker_version = events_async::GetFirecrackerVersion::new();
-            Ok(self
-                .rtck
-                .lock()
-                .execute(&get_firecracker_version)
-                .await
-                .map_err(|e| {
-                    RtckError::new(
-                        RtckErrorClass::RemoteError,
-                        format!("Fail to ping remote {}", e.to_string()),
-                    )
-                })?)
+        /// Check the machine tracks dirty pages, with the configuration used
+        /// to create it or the one reported by firecracker
+        fn check_diff_snapshot(&mut self) -> RtckResult<()> {
+            let cached = self
+                .config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.machine_config.clone());
+            let machine_config = match cached {
+                Some(machine_config) => Some(machine_config),
+                None => self.export_vm_config()?.machine_config,
+            };
+            check_dirty_pages(machine_config.as_ref())
         }
 
-        /// Automatically configure the machine.
-        /// User must guarantee that `config` passed to the machine contains
-        /// valid firecracker configuration (`frck_config`).
-        pub async fn configure(&self) -> RtckResult<()> {
-            // If configuration has been exported, then the machine should have been configured.
-            if self.config.frck_export_path.is_some() {
-                return Ok(());
+        /// Throttle a drive after boot. Only the rate limiter is sent, the
+        /// backing file of the drive is left untouched. The drive must be
+        /// one the machine was configured with.
+        pub fn update_guest_drive_rate_limiter<S1: AsRef<str>>(
+            &mut self,
+            drive_id: S1,
+            rate_limiter: RateLimiter,
+        ) -> RtckResult<()> {
+            let partial_drive = PartialDrive::builder()
+                .drive_id(drive_id.as_ref())
+                .rate_limiter(rate_limiter)
+                .build()?;
+            check_drive_exists(&self.config, &partial_drive.drive_id)?;
+            let mut patch_drive = events::PatchGuestDriveById::new(partial_drive);
+            self.rtck.execute(&mut patch_drive)?;
+            if patch_drive.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to update drive rate limiter",
+                    fault(patch_drive.get_res_mut().as_err()),
+                ));
             }
+            Ok(())
+        }
 
-            use events_async::*;
+        /// Set the backing file of a drive after boot, e.g. of a placeholder
+        /// declared in the configuration. Firecracker cannot add drives after
+        /// boot, so `drive_id` must be one the machine was started with.
+        /// Under jailer the file is linked into the jail first.
+        pub fn activate_drive<S1: AsRef<str>, P: AsRef<Path>>(
+            &mut self,
+            drive_id: S1,
+            path_on_host: P,
+        ) -> RtckResult<()> {
+            let drive_id = drive_id.as_ref();
+            let cached = self
+                .config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.drives.clone());
+            let drives = match cached {
+                Some(drives) => drives,
+                None => self.export_vm_config()?.drives.unwrap_or_default(),
+            };
+            check_drive_declared(&drives, drive_id)?;
 
-            // User must guarantee that proper
-            let frck_config = self.config.frck_config.as_ref().ok_or(RtckError::new(
-                RtckErrorClass::ConfigError,
-                "No proper firecracker configuration passed".to_string(),
-            ))?;
+            let path_on_host = link_into_jail(
+                &self.config,
+                self.local.get_jail_path(),
+                path_on_host.as_ref(),
+            )?;
+            let partial_drive = PartialDrive::builder()
+                .drive_id(drive_id)
+                .path_on_host(path_on_host)
+                .build()?;
+            let mut patch_drive = events::PatchGuestDriveById::new(partial_drive);
+            self.rtck.execute(&mut patch_drive)?;
+            if patch_drive.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to activate drive",
+                    fault(patch_drive.get_res_mut().as_err()),
+                ));
+            }
+            Ok(())
+        }
 
-            // Logger
-            {
-                if let Some(logger) = &frck_config.logger {
-                    let put_logger = PutLogger::new(logger.clone());
-                    self.rtck.lock().execute(&put_logger).await?;
-                    if put_logger.is_err() {
-                        log::error!("[PutLogger failed, error = {}]", put_logger.get_res().err());
-                    }
-                }
+        /// Get the full configuration currently applied to the machine
+        pub fn export_vm_config(&mut self) -> RtckResult<FullVmConfiguration> {
+            let mut get_export_vm_config = events::GetExportVmConfig::new();
+            self.rtck.execute(&mut get_export_vm_config)?;
+            if get_export_vm_config.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to export vm config",
+                    fault(get_export_vm_config.get_res_mut().as_err()),
+                ));
             }
+            payload(get_export_vm_config.get_res_mut().as_succ())
+        }
 
-            // Metrics
-            {
-                if let Some(metrics) = &frck_config.metrics {
-                    let put_metrics = PutMetrics::new(metrics.clone());
-                    self.rtck.lock().execute(&put_metrics).await?;
-                    if put_metrics.is_err() {
-                        log::error!(
-                            "[PutMetrics failed, error = {}]",
-                            put_metrics.get_res().err()
-                        );
-                    }
-                }
+        /// Get the vsock device effectively configured on the machine,
+        /// `None` if there is no vsock device.
+        pub fn vsock_config(&mut self) -> RtckResult<Option<Vsock>> {
+            Ok(self.export_vm_config()?.vsock)
+        }
+
+        /// Get the configuration of the balloon device
+        pub fn describe_balloon_config(&mut self) -> RtckResult<Balloon> {
+            let mut describe_balloon_config = events::DescribeBalloonConfig::new();
+            self.rtck.execute(&mut describe_balloon_config)?;
+            if describe_balloon_config.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to describe balloon config",
+                    fault(describe_balloon_config.get_res_mut().as_err()),
+                ));
             }
+            payload(describe_balloon_config.get_res_mut().as_succ())
+        }
 
-            // Guest boot source
-            {
-                if let Some(boot_source) = &frck_config.boot_source {
-                    let put_guest_boot_source = PutGuestBootSource::new(boot_source.clone());
-                    self.rtck.lock().execute(&put_guest_boot_source).await?;
-                    if put_guest_boot_source.is_err() {
-                        log::error!(
-                            "[PutGuestBootSource failed, error = {}]",
-                            put_guest_boot_source.get_res().err()
-                        );
-                    }
-                }
+        /// Change whether the balloon deflates under guest memory pressure,
+        /// keeping its current size. Fails with a `RemoteError` on
+        /// firecracker versions only accepting it before boot.
+        pub fn set_balloon_deflate_on_oom(&mut self, enabled: bool) -> RtckResult<()> {
+            let balloon = self.describe_balloon_config()?;
+            let mut patch_balloon = events::PatchBalloon::new(BalloonUpdate {
+                amount_mib: balloon.amount_mib,
+                deflate_on_oom: Some(enabled),
+            });
+            self.rtck.execute(&mut patch_balloon)?;
+            if patch_balloon.is_err()? {
+                return Err(deflate_on_oom_error(fault(
+                    patch_balloon.get_res_mut().as_err(),
+                )));
             }
+            Ok(())
+        }
 
-            // Guest drives
-            {
-                if let Some(drives) = &frck_config.drives {
-                    for drive in drives {
-                        let put_guest_drive_by_id = PutGuestDriveById::new(drive.clone());
-                        self.rtck.lock().execute(&put_guest_drive_by_id).await?;
-                        if put_guest_drive_by_id.is_err() {
-                            log::error!(
-                                "[PutGuestDriveById failed, error = {}]",
-                                put_guest_drive_by_id.get_res().err()
-                            );
-                        }
-                    }
-                }
+        /// Inflate or deflate the balloon to hold `amount_mib` MiB
+        pub fn resize_balloon(&mut self, amount_mib: i64) -> RtckResult<()> {
+            let mut patch_balloon = events::PatchBalloon::new(BalloonUpdate {
+                amount_mib,
+                deflate_on_oom: None,
+            });
+            self.rtck.execute(&mut patch_balloon)?;
+            if patch_balloon.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to resize balloon",
+                    fault(patch_balloon.get_res_mut().as_err()),
+                ));
             }
+            Ok(())
+        }
 
-            // Guest network interfaces
-            {
-                if let Some(ifaces) = &frck_config.network_interfaces {
-                    for iface in ifaces {
-                        let put_guest_network_interface_by_id =
-                            PutGuestNetworkInterfaceById::new(iface.clone());
-                        self.rtck
-                            .lock()
-                            .execute(&put_guest_network_interface_by_id)
-                            .await?;
-                        if put_guest_network_interface_by_id.is_err() {
-                            log::error!(
-                                "[PutGuestNetworkInterfaceById failed, error = {}]",
-                                put_guest_network_interface_by_id.get_res().err()
-                            );
-                        }
-                    }
-                }
+        /// Get the latest statistics of the balloon device, refreshed by
+        /// firecracker every `stats_polling_interval_s`
+        pub fn describe_balloon_stats(&mut self) -> RtckResult<BalloonStatistics> {
+            check_balloon_stats(&self.config)?;
+            let mut describe_balloon_stats = events::DescribeBalloonStats::new();
+            self.rtck.execute(&mut describe_balloon_stats)?;
+            if describe_balloon_stats.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to describe balloon stats",
+                    fault(describe_balloon_stats.get_res_mut().as_err()),
+                ));
             }
+            payload(describe_balloon_stats.get_res_mut().as_succ())
+        }
 
-            // Vsocks
-            {
-                if let Some(vsocks) = &frck_config.vsock_devices {
-                    for vsock in vsocks {
-                        let put_guest_vsock = PutGuestVsock::new(vsock.clone());
-                        self.rtck.lock().execute(&put_guest_vsock).await?;
-                        if put_guest_vsock.is_err() {
-                            log::error!(
-                                "[PutGuestVsock failed, error = {}]",
-                                put_guest_vsock.get_res().err()
-                            );
-                        }
-                    }
-                }
+        /// Register the metrics of the machine in `registry`, labelled with
+        /// its vmid, see `export_metrics`
+        #[cfg(feature = "prometheus")]
+        pub fn register_metrics(
+            &self,
+            registry: &prometheus::Registry,
+        ) -> RtckResult<MachineMetrics> {
+            MachineMetrics::register(
+                registry,
+                machine_vmid(&self.config, self.local.get_socket_path()),
+            )
+        }
+
+        /// How long the guest took to boot, as logged by firecracker with
+        /// `boot_timer`. `None` until the guest signals it booted, which its
+        /// init has to do, see `parse_boot_time`.
+        pub fn boot_time(&mut self) -> RtckResult<Option<std::time::Duration>> {
+            let boot_time = boot_time(&self.config, self.local.get_log_path())?;
+            if boot_time.is_none() && self.child.exited()? {
+                return Err(boot_timer_error());
             }
+            Ok(boot_time)
+        }
 
-            // CPU configuration
-            {
-                if let Some(cpu_config) = &frck_config.cpu_config {
-                    let put_cpu_configuration = PutCpuConfiguration::new(cpu_config.clone());
-                    self.rtck.lock().execute(&put_cpu_configuration).await?;
-                    if put_cpu_configuration.is_err() {
-                        log::error!(
-                            "[PutCpuConfiguration failed, error = {}]",
-                            put_cpu_configuration.get_res().err()
-                        );
-                    }
-                }
+        /// `boot_time` in milliseconds
+        pub fn boot_time_ms(&mut self) -> RtckResult<Option<u64>> {
+            Ok(self
+                .boot_time()?
+                .map(|boot_time| boot_time.as_millis() as u64))
+        }
+
+        /// Feed `metrics` with the flushes in the metrics file and the
+        /// balloon statistics, if enabled. Firecracker flushes its metrics
+        /// every minute, or when asked with a `FlushMetrics` action.
+        #[cfg(feature = "prometheus")]
+        pub fn export_metrics(&mut self, metrics: &MachineMetrics) -> RtckResult<()> {
+            for flush in metrics_flushes(self.local.get_metrics_path())? {
+                metrics.observe(&flush)?;
             }
+            if balloon_stats_enabled(&self.config) {
+                metrics.observe_balloon(&self.describe_balloon_stats()?);
+            }
+            Ok(())
+        }
 
-            // Machine configuration
-            {
-                if let Some(machine_config) = &frck_config.machine_config {
-                    let put_machine_configuration =
-                        PutMachineConfiguration::new(machine_config.clone());
-                    self.rtck.lock().execute(&put_machine_configuration).await?;
-                    if put_machine_configuration.is_err() {
-                        log::error!(
-                            "[PutMachineConfiguration failed, error = {}]",
-                            put_machine_configuration.get_res().err()
-                        );
-                    }
-                }
+        /// Get information about the machine, including its state
+        pub fn describe_instance(&mut self) -> RtckResult<InstanceInfo> {
+            let mut describe_instance = events::DescribeInstance::new();
+            self.rtck.execute(&mut describe_instance)?;
+            if describe_instance.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to describe instance",
+                    fault(describe_instance.get_res_mut().as_err()),
+                ));
             }
+            payload(describe_instance.get_res_mut().as_succ())
+        }
 
-            // Balloon
-            {
-                if let Some(balloon) = &frck_config.balloon {
-                    let put_balloon = PutBalloon::new(balloon.clone());
-                    self.rtck.lock().execute(&put_balloon).await?;
-                    if put_balloon.is_err() {
-                        log::error!(
-                            "[PutBalloon failed, error = {}]",
-                            put_balloon.get_res().err()
-                        );
-                    }
+        /// Wait until the machine reports `Running`, polling every `poll_status_secs`
+        pub fn wait_until_running(&mut self, timeout: std::time::Duration) -> RtckResult<()> {
+            let deadline = std::time::Instant::now() + timeout;
+            let interval = self.config.poll_status_interval();
+            loop {
+                if self.describe_instance()?.state == instance_info::State::Running {
+                    return Ok(());
+                }
+                let now = std::time::Instant::now();
+                if now >= deadline {
+                    return Err(RtckError::new(
+                        RtckErrorClass::MachineError,
+                        "Timeout waiting for the machine to run",
+                    ));
                 }
+                std::thread::sleep(interval.min(deadline - now));
             }
+        }
 
-            // Entropy device
-            {
-                if let Some(entropy_device) = &frck_config.entropy_device {
-                    let put_entropy = PutEntropy::new(entropy_device.clone());
-                    self.rtck.lock().execute(&put_entropy).await?;
-                    if put_entropy.is_err() {
-                        log::error!(
-                            "[PutEntropy failed, error = {}]",
-                            put_entropy.get_res().err()
-                        );
-                    }
-                }
+        /// Send a request to an endpoint not wrapped by rustcracker.
+        /// `path` must start with `/`, e.g. `/version`.
+        pub fn raw_request(
+            &mut self,
+            method: HttpMethod,
+            path: &str,
+            body: Option<serde_json::Value>,
+        ) -> RtckResult<(u16, serde_json::Value)> {
+            self.rtck.raw_request(method, path, body)
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) mod test {
+        use std::io::{BufRead, Cursor, Read, Write};
+
+        use crate::{
+            config::GlobalConfig, firecracker::firecracker::Firecracker, local::local::Local,
+            rtck::Rtck,
+        };
+
+        use super::{vm, CidClaim, HttpMethod, Machine, MachineCore, Process};
+
+        /// Stream replaying canned responses and recording requests
+        pub(crate) struct MockStream {
+            input: Cursor<Vec<u8>>,
+            pub(crate) output: Vec<u8>,
+        }
+
+        impl Read for MockStream {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.input.read(buf)
             }
+        }
 
-            // Initial mmds content
-            {
-                if let Some(content) = &frck_config.init_metadata {
-                    let put_mmds = PutMmds::new(content.clone());
-                    self.rtck.lock().execute(&put_mmds).await?;
-                    if put_mmds.is_err() {
-                        log::error!("[PutMmds failed, error = {}]", put_mmds.get_res().err());
-                    }
-                }
+        impl BufRead for MockStream {
+            fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+                self.input.fill_buf()
             }
 
-            Ok(())
+            fn consume(&mut self, amt: usize) {
+                self.input.consume(amt)
+            }
         }
 
-        /// Start the machine by notifying the hypervisor
-        pub async fn start(&self) -> RtckResult<()> {
-            let start_machine = events_async::CreateSyncAction::new(InstanceActionInfo {
-                action_type: ActionType::InstanceStart,
-            });
+        impl Write for MockStream {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.output.write(buf)
+            }
 
-            self.rtck.lock().execute(&start_machine).await?;
-            Ok(())
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
         }
 
-        /// Pause the machine by notifying the hypervisor
-        pub async fn pause(&self) -> RtckResult<()> {
-            let pause_machine = events_async::PatchVm::new(vm::VM_STATE_PAUSED);
+        pub(crate) fn response(code: u16, body: &str) -> String {
+            format!(
+                "HTTP/1.1 {} {}\r\nServer: Firecracker API\r\nContent-Length: {}\r\n\r\n{}",
+                code,
+                crate::micro_http::StatusCode::from(code)
+                    .canonical_reason()
+                    .unwrap_or_default(),
+                body.len(),
+                body
+            )
+        }
 
-            self.rtck.lock().execute(&pause_machine).await?;
-            Ok(())
+        /// Build a machine talking to a mocked firecracker which
+        /// answers with `responses` in order.
+        pub(crate) fn mock_machine(responses: &[String]) -> Machine<MockStream> {
+            let config = GlobalConfig {
+                using_jailer: Some(false),
+                frck_bin: Some("/bin/sleep".into()),
+                socket_path: Some(format!("/tmp/rtck-mock-{}.sock", uuid::Uuid::new_v4())),
+                ..Default::default()
+            };
+            let frck = Firecracker::from_config(&config).unwrap();
+            let local = Local::from_frck(&frck, &config).unwrap();
+            let stream = MockStream {
+                input: Cursor::new(responses.concat().into_bytes()),
+                output: Vec::new(),
+            };
+            Machine {
+                rtck: Rtck::from_stream(stream),
+                local,
+                jailer: None,
+                frck,
+                config,
+                child: Process::Spawned(
+                    std::process::Command::new("sleep")
+                        .arg("10")
+                        .spawn()
+                        .unwrap(),
+                ),
+                cids: CidClaim::empty(),
+                uffd: None,
+            }
         }
 
-        /// Resume the machine by notifying the hypervisor
-        pub async fn resume(&self) -> RtckResult<()> {
-            let resume_machine = events_async::PatchVm::new(vm::VM_STATE_RESUMED);
+        #[test]
+        fn test_vsock_config() {
+            let mut machine = mock_machine(&[
+                response(
+                    200,
+                    r#"{"boot-source":{"kernel_image_path":"vmlinux"},"vsock":{"guest_cid":3,"uds_path":"/v.sock"}}"#,
+                ),
+                response(200, r#"{"boot-source":{"kernel_image_path":"vmlinux"}}"#),
+                response(400, r#"{"fault_message":"not configured"}"#),
+            ]);
 
-            self.rtck.lock().execute(&resume_machine).await?;
-            Ok(())
+            let vsock = machine.vsock_config().unwrap().unwrap();
+            assert_eq!(vsock.guest_cid, 3);
+            assert_eq!(vsock.uds_path, "/v.sock");
+            assert!(machine.vsock_config().unwrap().is_none());
+            assert!(machine.vsock_config().is_err());
+
+            let _ = machine.child.kill();
         }
 
-        /// Stop the machine by notifying the hypervisor
-        pub async fn stop(&self) -> RtckResult<()> {
-            let stop_machine = events_async::CreateSyncAction::new(InstanceActionInfo {
-                action_type: ActionType::SendCtrlAtlDel,
-            });
+        #[test]
+        fn test_debug_elides_config() {
+            let mut machine = mock_machine(&[]);
+            let debug = format!("{:?}", machine);
+            assert!(debug.starts_with("Machine {"));
+            assert!(debug.contains("socket_path"));
+            assert!(!debug.contains("frck_bin"));
+            let _ = machine.child.kill();
+        }
 
-            self.rtck.lock().execute(&stop_machine).await?;
-            Ok(())
+        /// Mock of the host side of a firecracker vsock device, where only
+        /// `port` is accepted by the guest.
+        pub(crate) fn run_vsock_listener(
+            path: &std::path::Path,
+            port: u32,
+            connections: usize,
+        ) -> std::thread::JoinHandle<()> {
+            let listener = std::os::unix::net::UnixListener::bind(path).unwrap();
+            std::thread::spawn(move || {
+                for _ in 0..connections {
+                    let (stream, _addr) = listener.accept().unwrap();
+                    let mut stream = std::io::BufReader::new(stream);
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    if line == format!("CONNECT {port}\n") {
+                        stream.get_mut().write_all(b"OK 1073741824\n").unwrap();
+                    }
+                }
+            })
         }
 
-        /// Stop the machine forcefully by killing the firecracker process
-        pub async fn stop_force(&self) -> RtckResult<()> {
-            self.child.lock().kill().await.map_err(|e| {
-                log::error!("[Machine::stop_force killing failed, error = {}]", e);
-                RtckError::new(
-                    RtckErrorClass::MachineError,
-                    "Fail to kill the machine".to_string(),
-                )
+        /// Mock of a guest accepting one connection on vsock `port` and
+        /// echoing what it receives, which is returned once closed
+        pub(crate) fn run_vsock_echo(
+            path: &std::path::Path,
+            port: u32,
+        ) -> std::thread::JoinHandle<Vec<u8>> {
+            use std::io::Read;
+
+            let listener = std::os::unix::net::UnixListener::bind(path).unwrap();
+            std::thread::spawn(move || {
+                let (stream, _addr) = listener.accept().unwrap();
+                let mut stream = std::io::BufReader::new(stream);
+                let mut line = String::new();
+                stream.read_line(&mut line).unwrap();
+                assert_eq!(line, format!("CONNECT {port}\n"));
+                stream.get_mut().write_all(b"OK 1073741824\n").unwrap();
+                let mut payload = Vec::new();
+                stream.read_to_end(&mut payload).unwrap();
+                let _ = stream.get_mut().write_all(&payload);
+                payload
             })
         }
 
-        /// Delete the machine by notifying firecracker
-        pub async fn delete(&self) -> RtckResult<()> {
-            // Stop the machine first
-            self.stop().await?;
-            let query_status = events_async::DescribeInstance::new();
-            self.rtck.lock().execute(&query_status).await?;
+        pub(crate) fn with_vsock(config: &mut GlobalConfig, uds_path: &std::path::Path) {
+            config.frck_config = Some(crate::config::FirecrackerConfig {
+                vsock_devices: Some(vec![crate::models::vsock::Vsock {
+                    guest_cid: 3,
+                    uds_path: uds_path.to_string_lossy().to_string(),
+                    vsock_id: None,
+                }]),
+                ..Default::default()
+            });
+        }
 
-            if query_status.is_err() {
-                log::error!(
-                    "[Machine::delete query status failed, error = {}]",
-                    query_status.get_res().err()
-                );
-                return Err(RtckError::new(
-                    RtckErrorClass::MachineError,
-                    "Fail to query status".to_string(),
-                ));
-            }
+        #[test]
+        fn test_probe_vsock() {
+            let mut machine = mock_machine(&[]);
+            let timeout = std::time::Duration::from_secs(1);
+            assert!(machine.probe_vsock(52, timeout).is_err());
 
-            let state = query_status.get_res().succ().state;
+            let uds_path =
+                std::env::temp_dir().join(format!("rtck-vsock-{}.sock", uuid::Uuid::new_v4()));
+            with_vsock(&mut machine.config, &uds_path);
+            let listener = run_vsock_listener(&uds_path, 52, 2);
+            machine.probe_vsock(52, timeout).unwrap();
+            assert!(machine.probe_vsock(53, timeout).is_err());
+            listener.join().unwrap();
 
-            use crate::models::instance_info;
-            if state == instance_info::State::Running {
-                log::warn!("[Machine::delete cannot stop the machine, killing...]");
-                self.stop_force().await?;
-            }
+            std::fs::remove_file(uds_path).unwrap();
+            let _ = machine.child.kill();
+        }
 
-            Ok(())
+        #[test]
+        fn test_vsock_send() {
+            let mut machine = mock_machine(&[]);
+            let uds_path =
+                std::env::temp_dir().join(format!("rtck-vsock-{}.sock", uuid::Uuid::new_v4()));
+            with_vsock(&mut machine.config, &uds_path);
+
+            let guest = run_vsock_echo(&uds_path, 52);
+            machine.vsock_send(52, b"hello guest").unwrap();
+            assert_eq!(guest.join().unwrap(), b"hello guest");
+            std::fs::remove_file(&uds_path).unwrap();
+
+            // Nothing to notify without a control port
+            machine.notify_resume_time().unwrap();
+            machine.config.vsock_control_port = Some(1024);
+            let guest = run_vsock_echo(&uds_path, 1024);
+            machine.notify_resume_time().unwrap();
+            let message = String::from_utf8(guest.join().unwrap()).unwrap();
+            let (secs, nanos) = message
+                .strip_prefix("CLOCK_RESYNC ")
+                .and_then(|time| time.trim_end().split_once('.'))
+                .unwrap();
+            assert!(secs.parse::<u64>().unwrap() > 0);
+            assert_eq!(nanos.len(), 9);
+
+            std::fs::remove_file(uds_path).unwrap();
+            let _ = machine.child.kill();
         }
 
-        /// Delete the machine and do cleaning at the same time
-        pub async fn delete_and_clean(&self) -> RtckResult<()> {
-            self.delete().await?;
-            self.local.full_clean().await;
-            Ok(())
+        pub(crate) fn instance_info(state: &str) -> String {
+            response(
+                200,
+                &format!(
+                    r#"{{"app_name":"Firecracker","id":"anonymous-instance","state":"{state}","vmm_version":"1.7.0"}}"#
+                ),
+            )
         }
 
-        /// Create a snapshot
-        pub async fn snapshot<P: AsRef<str>, Q: AsRef<str>>(
-            &self,
-            state_path: P,
-            mem_path: Q,
-            _type: SnapshotType,
-        ) -> RtckResult<()> {
-            let create_snapshot = events_async::CreateSnapshot::new(SnapshotCreateParams {
-                mem_file_path: state_path.as_ref().to_string(),
-                snapshot_path: mem_path.as_ref().to_string(),
-                snapshot_type: Some(_type),
-                version: None,
-            });
+        #[test]
+        fn test_wait_until_running() {
+            let mut machine = mock_machine(&[
+                instance_info("Not started"),
+                instance_info("Not started"),
+                instance_info("Running"),
+            ]);
+            machine.config.poll_status_secs = Some(0);
+            machine
+                .wait_until_running(std::time::Duration::from_secs(1))
+                .unwrap();
+            // Exactly three polls were issued
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert_eq!(requests.matches("GET / HTTP/1.1").count(), 3);
+            let _ = machine.child.kill();
 
-            self.rtck.lock().execute(&create_snapshot).await?;
+            // Never running: give up once the deadline passes
+            let mut machine = mock_machine(&[instance_info("Paused"), instance_info("Paused")]);
+            machine.config.poll_status_secs = Some(1);
+            assert!(machine
+                .wait_until_running(std::time::Duration::from_millis(10))
+                .is_err());
+
+            let _ = machine.child.kill();
+        }
+        #[test]
+        fn test_set_vm_state() {
+            let mut machine = mock_machine(&[
+                instance_info("Running"),
+                response(204, ""),
+                instance_info("Paused"),
+                response(204, ""),
+                instance_info("Running"),
+                instance_info("Not started"),
+            ]);
+            machine.pause().unwrap();
+            machine.resume().unwrap();
+            // Refused before patching anything
+            assert!(machine.set_vm_state(vm::State::Resumed).is_err());
+            assert!(machine.set_vm_state(vm::State::Paused).is_err());
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert_eq!(requests.matches("PATCH /vm HTTP/1.1").count(), 2);
+            assert!(requests.contains(r#"{"state":"Paused"}"#));
+            assert!(requests.contains(r#"{"state":"Resumed"}"#));
+            let _ = machine.child.kill();
+
+            // Rejected by firecracker
+            let mut machine = mock_machine(&[
+                instance_info("Running"),
+                response(400, r#"{"fault_message":"busy"}"#),
+            ]);
+            assert!(machine.pause().is_err());
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_firecracker_version() {
+            let mut machine = mock_machine(&[
+                response(200, r#"{"firecracker_version":"1.7.0"}"#),
+                response(400, r#"{"fault_message":"busy"}"#),
+            ]);
+            let version = machine.firecracker_version().unwrap();
+            assert_eq!(version.semver().unwrap(), (1, 7, 0));
+            assert!(machine.firecracker_version().is_err());
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_assert_compatible() {
+            let mut machine = mock_machine(&[
+                response(200, r#"{"firecracker_version":"0.25.2"}"#),
+                response(200, r#"{"firecracker_version":"1.7.0"}"#),
+            ]);
+            let e = machine.assert_compatible((1, 0, 0)).unwrap_err();
+            assert!(e.to_string().contains("0.25.2 is not compatible"), "{e}");
+            machine.assert_compatible((1, 0, 0)).unwrap();
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_start_with_deadline() {
+            use crate::RtckErrorClass;
+
+            let mut machine = mock_machine(&[
+                response(204, ""),
+                instance_info("Not started"),
+                instance_info("Not started"),
+            ]);
+            machine.config.frck_config = Some(Default::default());
+            machine.config.poll_status_secs = Some(1);
+            let socket_path = machine.local.get_socket_path().clone();
+            std::fs::write(&socket_path, "").unwrap();
+
+            // Never running, aborted while waiting
+            let deadline = std::time::Instant::now() + std::time::Duration::from_millis(50);
+            let e = machine.start_with_deadline(deadline).unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::MachineError);
+            assert!(e.to_string().contains("start timed out"));
+            assert!(machine.child.child_mut().unwrap().wait().is_ok());
+            assert!(!socket_path.exists());
+
+            // Expired before the first step
+            let mut machine = mock_machine(&[]);
+            machine.config.frck_config = Some(Default::default());
+            assert!(machine
+                .start_with_deadline(std::time::Instant::now())
+                .is_err());
+            assert!(machine.rtck.stream_mut().output.is_empty());
+            assert!(machine.child.child_mut().unwrap().wait().is_ok());
+
+            // Running in time, left running
+            let mut machine = mock_machine(&[response(204, ""), instance_info("Running")]);
+            machine.config.frck_config = Some(Default::default());
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+            machine.start_with_deadline(deadline).unwrap();
+            assert!(machine
+                .child
+                .child_mut()
+                .unwrap()
+                .try_wait()
+                .unwrap()
+                .is_none());
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_raw_request() {
+            let mut machine = mock_machine(&[response(200, r#"{"firecracker_version":"1.7.0"}"#)]);
+            let (status, body) = machine
+                .raw_request(HttpMethod::GET, "/version", None)
+                .unwrap();
+            assert_eq!(status, 200);
+            assert_eq!(body, serde_json::json!({"firecracker_version": "1.7.0"}));
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.starts_with("GET /version HTTP/1.1\r\n"));
+
+            // Relative or malformed paths never reach the socket
+            assert!(machine
+                .raw_request(HttpMethod::GET, "version", None)
+                .is_err());
+            assert!(machine
+                .raw_request(HttpMethod::GET, "/version HTTP/1.1\r\n", None)
+                .is_err());
+            assert_eq!(machine.rtck.stream_mut().output.len(), requests.len());
+
+            let _ = machine.child.kill();
+        }
+        #[test]
+        fn test_update_guest_drive_rate_limiter() {
+            use crate::{
+                config::FirecrackerConfig,
+                models::{drive::Drive, rate_limiter::RateLimiter, token_bucket::TokenBucket},
+                RtckErrorClass,
+            };
+
+            let mut machine = mock_machine(&[
+                response(204, ""),
+                response(400, r#"{"fault_message":"Drive not found"}"#),
+            ]);
+            let rate_limiter = RateLimiter::new(
+                TokenBucket {
+                    one_time_burst: None,
+                    refill_time: 100,
+                    size: 1 << 20,
+                },
+                TokenBucket {
+                    one_time_burst: Some(10),
+                    refill_time: 100,
+                    size: 100,
+                },
+            );
+            machine
+                .update_guest_drive_rate_limiter("rootfs", rate_limiter.clone())
+                .unwrap();
+            // Both buckets in one partial body, without `path_on_host`
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.starts_with("PATCH /drives/rootfs HTTP/1.1\r\n"));
+            let body = requests.split("\r\n\r\n").nth(1).unwrap();
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(body).unwrap(),
+                serde_json::json!({
+                    "drive_id": "rootfs",
+                    "rate_limiter": {
+                        "bandwidth": {"refill_time": 100, "size": 1048576},
+                        "ops": {"refill_time": 100, "size": 100, "one_time_burst": 10},
+                    },
+                })
+            );
+
+            assert!(machine
+                .update_guest_drive_rate_limiter("scratch", rate_limiter.clone())
+                .is_err());
+            assert!(machine
+                .update_guest_drive_rate_limiter("bad id", rate_limiter.clone())
+                .is_err());
+
+            // Drives missing from the configuration never reach the socket
+            machine.config.frck_config = Some(FirecrackerConfig {
+                drives: Some(vec![Drive {
+                    drive_id: "rootfs".into(),
+                    partuuid: None,
+                    is_root_device: true,
+                    cache_type: None,
+                    is_read_only: false,
+                    path_on_host: "/tmp/rootfs.ext4".into(),
+                    rate_limiter: None,
+                    io_engine: None,
+                    socket: None,
+                }]),
+                ..Default::default()
+            });
+            let sent = machine.rtck.stream_mut().output.len();
+            let e = machine
+                .update_guest_drive_rate_limiter("scratch", rate_limiter)
+                .unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::ConfigError);
+            assert_eq!(machine.rtck.stream_mut().output.len(), sent);
+
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_reapply_exported_config() {
+            use crate::config::FirecrackerConfig;
+
+            let exported = r#"{"balloon":null,"drives":[],"boot-source":{"kernel_image_path":"/vmlinux","initrd_path":null,"boot_args":"console=ttyS0"},"cpu-config":null,"logger":null,"machine-config":{"vcpu_count":2,"mem_size_mib":256,"smt":false,"track_dirty_pages":true},"metrics":null,"mmds-config":null,"network-interfaces":[],"vsock":null,"entropy":null}"#;
+            let mut source = mock_machine(&[response(200, exported)]);
+            let full = source.export_vm_config().unwrap();
+            let _ = source.child.kill();
+
+            let mut machine = mock_machine(&[response(204, ""), response(204, "")]);
+            machine.config.frck_config = Some(FirecrackerConfig::try_from(full).unwrap());
+            machine.configure().unwrap();
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.starts_with("PUT /boot-source HTTP/1.1\r\n"));
+            assert!(requests.contains("PUT /machine-config HTTP/1.1\r\n"));
+            assert_eq!(requests.matches(" HTTP/1.1\r\n").count(), 2);
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_describe_balloon_stats_disabled() {
+            use crate::{config::FirecrackerConfig, models::balloon::Balloon, RtckErrorClass};
+
+            let stats = r#"{"target_pages":16,"actual_pages":8,"target_mib":0,"actual_mib":0}"#;
+            let mut machine = mock_machine(&[response(200, stats)]);
+            let balloon = Balloon {
+                amount_mib: 64,
+                deflate_on_oom: true,
+                stats_polling_interval_s: None,
+            };
+
+            // Disabled statistics are refused before reaching the socket
+            for disabled in [
+                balloon.clone(),
+                balloon.clone().with_stats_interval(0).unwrap(),
+            ] {
+                machine.config.frck_config = Some(FirecrackerConfig {
+                    balloon: Some(disabled),
+                    ..Default::default()
+                });
+                let e = machine.describe_balloon_stats().unwrap_err();
+                assert_eq!(e.class(), &RtckErrorClass::ConfigError);
+                assert!(machine.rtck.stream_mut().output.is_empty());
+            }
+
+            machine.config.frck_config = Some(FirecrackerConfig {
+                balloon: Some(balloon.with_stats_interval(1).unwrap()),
+                ..Default::default()
+            });
+            assert_eq!(machine.describe_balloon_stats().unwrap().actual_pages, 8);
+
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_boot_time() {
+            use std::time::Duration;
+
+            use crate::{
+                config::{FirecrackerConfig, GlobalConfig},
+                machine::{boot_time, parse_boot_time},
+                models::logger::{LogLevel, Logger},
+                RtckErrorClass,
+            };
+
+            let log = "\
+2024-05-07T09:31:11.045307443 [vm0:main] Running Firecracker v1.7.0
+2024-05-07T09:31:11.139421581 [vm0:fc_vcpu 0] Guest-boot-time =  93850 us 93 ms,  93527 CPU us 93 CPU ms
+";
+            assert_eq!(parse_boot_time(log), Some(Duration::from_micros(93850)));
+            assert_eq!(parse_boot_time(log.lines().next().unwrap()), None);
+            // Firecracker 1.0 to 1.3, showing the level and origin
+            let v1_0 = "2022-08-04T12:00:00.123456789 [anonymous-instance:fc_vcpu 0:INFO:src/devices/src/pseudo/boot_timer.rs:48] Guest-boot-time = 125436 us 125 ms, 101286 CPU us 101 CPU ms";
+            assert_eq!(parse_boot_time(v1_0), Some(Duration::from_micros(125436)));
+            // The last boot counts, e.g. after a reboot of the guest
+            let rebooted = format!("{v1_0}\n{log}");
+            assert_eq!(
+                parse_boot_time(&rebooted),
+                Some(Duration::from_micros(93850))
+            );
+            assert_eq!(parse_boot_time("Guest-boot-time = soon"), None);
+
+            let path = std::env::temp_dir().join(format!("rtck-boot-{}.log", uuid::Uuid::new_v4()));
+            std::fs::write(&path, log).unwrap();
+            let logger = Logger::builder()
+                .path(path.to_string_lossy())
+                .level(LogLevel::Info)
+                .build()
+                .unwrap();
+            let mut config = GlobalConfig {
+                frck_config: Some(FirecrackerConfig {
+                    logger: Some(logger),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let e = boot_time(&config, Some(&path)).unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::ConfigError);
+
+            config.boot_timer = Some(true);
+            assert_eq!(
+                boot_time(&config, Some(&path)).unwrap(),
+                Some(Duration::from_micros(93850))
+            );
+            assert!(boot_time(&config, None).is_err());
+
+            // Not logged below the info level
+            let frck_config = config.frck_config.as_mut().unwrap();
+            frck_config.logger.as_mut().unwrap().level = Some(LogLevel::Warning);
+            assert!(boot_time(&config, Some(&path)).is_err());
+
+            std::fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn test_balloon_policy() {
+            use std::time::Duration;
+
+            use crate::{
+                machine::{BalloonDecision, BalloonPolicy},
+                models::balloon_stats::BalloonStatistics,
+                RtckErrorClass,
+            };
+
+            let policy = BalloonPolicy {
+                min_free_mib: 100,
+                max_free_mib: 200,
+                step_mib: 64,
+                interval: Duration::from_secs(1),
+            };
+            policy.validate().unwrap();
+            for invalid in [
+                BalloonPolicy {
+                    step_mib: 0,
+                    ..policy
+                },
+                BalloonPolicy {
+                    max_free_mib: 50,
+                    ..policy
+                },
+                BalloonPolicy {
+                    interval: Duration::ZERO,
+                    ..policy
+                },
+            ] {
+                let e = invalid.validate().unwrap_err();
+                assert_eq!(e.class(), &RtckErrorClass::ConfigError);
+            }
+
+            let stats = |target_mib: u64, free_mib: Option<u64>| {
+                let stats = format!(
+                    r#"{{"target_pages":{},"actual_pages":0,"target_mib":{target_mib},"actual_mib":0}}"#,
+                    target_mib << 8
+                );
+                BalloonStatistics {
+                    free_memory: free_mib.map(|free_mib| free_mib << 20),
+                    ..serde_json::from_str(&stats).unwrap()
+                }
+            };
+            let decide = |target_mib, free_mib| {
+                let decision = policy.decide(&stats(target_mib, Some(free_mib))).unwrap();
+                assert_eq!(decision.free_mib, free_mib);
+                assert_eq!(decision.from_mib, target_mib);
+                decision
+            };
+            // Deflated when short of memory, no further than empty
+            assert_eq!(decide(256, 50).to_mib, 192);
+            assert_eq!(decide(32, 50).to_mib, 0);
+            // Inflated when idle, never below the band
+            assert_eq!(decide(0, 500).to_mib, 64);
+            let coarse = BalloonPolicy {
+                step_mib: 256,
+                ..policy
+            };
+            assert_eq!(coarse.decide(&stats(0, Some(220))).unwrap().to_mib, 120);
+            let kept = decide(128, 150);
+            assert_eq!(
+                kept,
+                BalloonDecision {
+                    free_mib: 150,
+                    from_mib: 128,
+                    to_mib: 128
+                }
+            );
+            assert!(!kept.resizes());
+
+            let e = policy.decide(&stats(0, None)).unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::MachineError);
+        }
+
+        #[cfg(feature = "prometheus")]
+        #[test]
+        fn test_export_metrics() {
+            use crate::{
+                config::FirecrackerConfig,
+                models::{balloon::Balloon, metrics::Metrics},
+            };
+
+            let stats = r#"{"target_pages":16,"actual_pages":8,"target_mib":0,"actual_mib":32}"#;
+            let mut machine = mock_machine(&[response(200, stats)]);
+            let metrics_path =
+                std::env::temp_dir().join(format!("rtck-metrics-{}", uuid::Uuid::new_v4()));
+            std::fs::write(
+                &metrics_path,
+                concat!(
+                    r#"{"utc_timestamp_ms":1000,"block_rootfs":{"read_bytes":4096}}"#,
+                    "\n",
+                    r#"{"utc_timestamp_ms":2000,"block_rootfs":{"read_bytes":1024}}"#,
+                    "\n",
+                ),
+            )
+            .unwrap();
+            machine.config.frck_config = Some(FirecrackerConfig {
+                vmid: Some("vm0".into()),
+                metrics: Some(Metrics {
+                    metrics_path: metrics_path.to_string_lossy().into_owned(),
+                }),
+                balloon: Some(Balloon {
+                    amount_mib: 64,
+                    deflate_on_oom: true,
+                    stats_polling_interval_s: Some(1),
+                }),
+                ..Default::default()
+            });
+            machine.local = Local::from_frck(&machine.frck, &machine.config).unwrap();
+
+            let registry = prometheus::Registry::new();
+            let metrics = machine.register_metrics(&registry).unwrap();
+            assert_eq!(metrics.vmid(), "vm0");
+            machine.export_metrics(&metrics).unwrap();
+
+            let families = registry.gather();
+            let value = |name: &str| {
+                let family = families.iter().find(|f| f.get_name() == name).unwrap();
+                let metric = &family.get_metric()[0];
+                metric.get_counter().get_value() + metric.get_gauge().get_value()
+            };
+            assert_eq!(value("firecracker_block_bytes_total"), 5120.0);
+            assert_eq!(value("firecracker_balloon_actual_mib"), 32.0);
+
+            std::fs::remove_file(&metrics_path).unwrap();
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_set_balloon_deflate_on_oom() {
+            use crate::{models::error::InternalErrorKind, RtckErrorClass};
+
+            let balloon =
+                r#"{"amount_mib":64,"deflate_on_oom":false,"stats_polling_interval_s":1}"#;
+            let mut machine = mock_machine(&[
+                response(200, balloon),
+                response(204, ""),
+                response(200, balloon),
+                response(
+                    400,
+                    r#"{"fault_message":"unknown field `deflate_on_oom`, expected `amount_mib`"}"#,
+                ),
+            ]);
+            machine.set_balloon_deflate_on_oom(true).unwrap();
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            let patch = requests
+                .split("PATCH /balloon HTTP/1.1\r\n")
+                .nth(1)
+                .unwrap();
+            let body = patch.split("\r\n\r\n").nth(1).unwrap();
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(body).unwrap(),
+                serde_json::json!({"amount_mib": 64, "deflate_on_oom": true})
+            );
+
+            // Versions only accepting it before boot
+            let e = machine.set_balloon_deflate_on_oom(false).unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::RemoteError);
+            assert!(e.to_string().contains("not supported at runtime"));
+            assert_eq!(e.fault_kind(), Some(InternalErrorKind::InvalidParameter));
+            assert!(!e.is_retryable());
+
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_activate_drive() {
+            use crate::{config::FirecrackerConfig, machine::link_into_jail, models::drive::Drive};
+
+            let mut machine = mock_machine(&[response(204, "")]);
+            machine.config.frck_config = Some(FirecrackerConfig {
+                drives: Some(vec![Drive {
+                    drive_id: "scratch".into(),
+                    partuuid: None,
+                    is_root_device: false,
+                    cache_type: None,
+                    is_read_only: false,
+                    path_on_host: "/dev/null".into(),
+                    rate_limiter: None,
+                    io_engine: None,
+                    socket: None,
+                }]),
+                ..Default::default()
+            });
+
+            // Firecracker cannot add drives after boot
+            let e = machine
+                .activate_drive("data", "/tmp/data.ext4")
+                .unwrap_err();
+            assert!(e.to_string().contains("not declared"));
+            assert!(machine.rtck.stream_mut().output.is_empty());
+
+            machine
+                .activate_drive("scratch", "/tmp/scratch.ext4")
+                .unwrap();
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.starts_with("PATCH /drives/scratch HTTP/1.1\r\n"));
+            let body = requests.split("\r\n\r\n").nth(1).unwrap();
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(body).unwrap(),
+                serde_json::json!({"drive_id": "scratch", "path_on_host": "/tmp/scratch.ext4"})
+            );
+            let _ = machine.child.kill();
+
+            // Jailed, the file is linked at the root of the jail
+            let jail = std::env::temp_dir().join(format!("rtck-jail-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&jail).unwrap();
+            let file = std::env::temp_dir().join(format!("rtck-drive-{}", uuid::Uuid::new_v4()));
+            std::fs::write(&file, "data").unwrap();
+            let config = GlobalConfig::default();
+            let jailed = link_into_jail(&config, Some(&jail), &file).unwrap();
+            let file_name = file.file_name().unwrap().to_string_lossy();
+            assert_eq!(jailed, format!("/{file_name}"));
+            assert_eq!(std::fs::read(jail.join(&*file_name)).unwrap(), b"data");
+            // Never overwriting a file of the jail
+            assert!(link_into_jail(&config, Some(&jail), &file).is_err());
+            std::fs::remove_dir_all(jail).unwrap();
+            std::fs::remove_file(file).unwrap();
+        }
+
+        #[test]
+        fn test_configure_vhost_user_drive() {
+            use crate::{config::FirecrackerConfig, machine::jailed_drive, models::drive::Drive};
+
+            let mut machine = mock_machine(&[response(204, "")]);
+            let drive = Drive::vhost_user("data", "/run/vhost-user-blk.sock");
+            machine.config.frck_config = Some(FirecrackerConfig {
+                drives: Some(vec![drive.clone()]),
+                ..Default::default()
+            });
+            machine.configure().unwrap();
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.starts_with("PUT /drives/data HTTP/1.1\r\n"));
+            let body = requests.split("\r\n\r\n").nth(1).unwrap();
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(body).unwrap(),
+                serde_json::json!({
+                    "drive_id": "data",
+                    "is_root_device": false,
+                    "socket": "/run/vhost-user-blk.sock",
+                })
+            );
+            // Its media is up to the backend
+            let e = machine
+                .activate_drive("data", "/tmp/data.ext4")
+                .unwrap_err();
+            assert!(e.to_string().contains("vhost-user"));
+            let _ = machine.child.kill();
+
+            // Jailed, the socket is linked at the root of the jail
+            let dir = std::env::temp_dir().join(format!("rtck-vhost-{}", uuid::Uuid::new_v4()));
+            let jail = dir.join("root");
+            std::fs::create_dir_all(&jail).unwrap();
+            let socket = dir.join("blk.sock");
+            let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+            let drive = Drive::vhost_user("data", socket.to_string_lossy());
+            let config = GlobalConfig::default();
+            let jailed = jailed_drive(&config, Some(&jail), &drive).unwrap();
+            assert_eq!(jailed.socket.as_deref(), Some("/blk.sock"));
+            assert!(std::os::unix::net::UnixStream::connect(jail.join("blk.sock")).is_ok());
+            // Nothing to place for other drives
+            assert_eq!(
+                jailed_drive(
+                    &config,
+                    Some(&jail),
+                    &Drive {
+                        socket: None,
+                        path_on_host: "/tmp/rootfs.ext4".into(),
+                        ..drive.clone()
+                    }
+                )
+                .unwrap()
+                .path_on_host,
+                "/tmp/rootfs.ext4"
+            );
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn test_configure_mmds_address() {
+            use crate::{config::FirecrackerConfig, models::network_interface::NetworkInterface};
+
+            let mut machine = mock_machine(&[response(204, ""), response(204, "")]);
+            machine.config.frck_config = Some(FirecrackerConfig {
+                network_interfaces: Some(vec![NetworkInterface {
+                    guest_mac: None,
+                    host_dev_name: "tap0".into(),
+                    iface_id: "eth0".into(),
+                    num_queues: None,
+                    rx_rate_limiter: None,
+                    tx_rate_limiter: None,
+                    static_ip: None,
+                }]),
+                mmds_address: Some("169.254.170.2".parse().unwrap()),
+                ..Default::default()
+            });
+            machine.configure().unwrap();
+            // After the interface forwarding to it
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            let (iface, mmds) = requests
+                .split_once("PUT /mmds/config HTTP/1.1\r\n")
+                .unwrap();
+            assert!(iface.starts_with("PUT /network-interfaces/eth0 HTTP/1.1\r\n"));
+            let body = mmds.split("\r\n\r\n").nth(1).unwrap();
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(body).unwrap(),
+                serde_json::json!({
+                    "ipv4_address": "169.254.170.2",
+                    "network_interfaces": ["eth0"],
+                })
+            );
+            let _ = machine.child.kill();
+        }
+
+        /// Serve a firecracker API socket at `socket_path` answering
+        /// the first request with its version
+        pub(crate) fn serve_version(socket_path: &std::path::Path) -> std::thread::JoinHandle<()> {
+            let listener = std::os::unix::net::UnixListener::bind(socket_path).unwrap();
+            std::thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = std::io::BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                reader
+                    .get_mut()
+                    .write_all(response(200, r#"{"firecracker_version":"1.7.0"}"#).as_bytes())
+                    .unwrap();
+            })
+        }
+
+        #[test]
+        fn test_from_existing() {
+            let socket_path =
+                std::env::temp_dir().join(format!("rtck-adopt-{}.sock", uuid::Uuid::new_v4()));
+            let server = serve_version(&socket_path);
+            let mut process = std::process::Command::new("sleep")
+                .arg("10")
+                .spawn()
+                .unwrap();
+            let pid = process.id();
+
+            let mut machine = Machine::from_existing(socket_path.clone(), pid).unwrap();
+            server.join().unwrap();
+            machine.stop_force().unwrap();
+            machine.wait().unwrap();
+            assert!(!super::super::pid_alive(pid));
+            // Already reaped by the machine
+            assert!(process.try_wait().is_err());
+
+            // Nothing left to adopt
+            assert!(Machine::from_existing(socket_path.clone(), pid).is_err());
+            std::fs::remove_file(socket_path).unwrap();
+        }
+
+        #[test]
+        fn test_dump_restore_core() {
+            let mut machine = mock_machine(&[]);
+            let core = machine.dump_core();
+            assert_eq!(core.pid, machine.child.id());
+            assert_eq!(&core.socket_path, machine.local.get_socket_path());
+            assert_eq!(core.id, None);
+            assert!(!core.clear_jailer);
+            let _ = machine.child.kill();
+
+            let socket_path =
+                std::env::temp_dir().join(format!("rtck-core-{}.sock", uuid::Uuid::new_v4()));
+            let mut process = std::process::Command::new("sleep")
+                .arg("10")
+                .spawn()
+                .unwrap();
+            let core = MachineCore {
+                id: Some("vm0".into()),
+                pid: process.id(),
+                socket_path: socket_path.clone(),
+                jailer_working_dir: Some("/srv/jailer/firecracker/vm0/root".into()),
+                uid_gid: Some((1000, 1000)),
+                clear_jailer: true,
+            };
+            let json = serde_json::to_string(&core).unwrap();
+            assert_eq!(serde_json::from_str::<MachineCore>(&json).unwrap(), core);
+
+            let server = serve_version(&socket_path);
+            let mut machine = Machine::restore_core(core.clone()).unwrap();
+            server.join().unwrap();
+            // Only the socket and the process are taken over
+            assert_eq!(
+                machine.dump_core(),
+                MachineCore {
+                    id: None,
+                    jailer_working_dir: None,
+                    uid_gid: None,
+                    clear_jailer: false,
+                    ..core
+                }
+            );
+
+            machine.stop_force().unwrap();
+            machine.wait().unwrap();
+            assert!(process.try_wait().is_err());
+            std::fs::remove_file(socket_path).unwrap();
+        }
+
+        /// Mock of the firecracker binary, recording the API socket it is
+        /// launched with to `launched` in `dir` before sleeping, and a thread
+        /// serving that socket in its place, answering every request with a 204
+        fn serve_launched(dir: &std::path::Path) -> std::path::PathBuf {
+            use std::os::unix::fs::PermissionsExt;
+
+            let launched = dir.join("launched");
+            let bin = dir.join("firecracker");
+            std::fs::write(
+                &bin,
+                format!(
+                    "#!/bin/sh\necho \"$2\" >> {}\nexec sleep 30\n",
+                    launched.display()
+                ),
+            )
+            .unwrap();
+            std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            let dir = dir.to_path_buf();
+            std::thread::spawn(move || {
+                let mut served = 0;
+                // Until the test removes `dir`
+                while dir.exists() {
+                    let sockets = std::fs::read_to_string(&launched).unwrap_or_default();
+                    for socket in sockets.lines().skip(served) {
+                        let listener = std::os::unix::net::UnixListener::bind(socket).unwrap();
+                        std::thread::spawn(move || {
+                            for stream in listener.incoming() {
+                                let mut reader = std::io::BufReader::new(stream.unwrap());
+                                let mut line = String::new();
+                                let mut length = 0;
+                                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                                    if let Some(len) = line.strip_prefix("Content-Length: ") {
+                                        length = len.trim().parse().unwrap();
+                                    }
+                                    if line == "\r\n" {
+                                        let mut body = vec![0; length];
+                                        reader.read_exact(&mut body).unwrap();
+                                        reader
+                                            .get_mut()
+                                            .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                                            .unwrap();
+                                        length = 0;
+                                    }
+                                    line.clear();
+                                }
+                            }
+                        });
+                        served += 1;
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+                }
+            });
+            bin
+        }
+
+        #[test]
+        fn test_restart() {
+            use crate::config::FirecrackerConfig;
+
+            // Short enough for the socket paths under it
+            let id = uuid::Uuid::new_v4().simple().to_string();
+            let dir = std::env::temp_dir().join(format!("rtck-restart-{}", &id[..8]));
+            std::fs::create_dir(&dir).unwrap();
+            let bin = serve_launched(&dir);
+            let config = GlobalConfig {
+                using_jailer: Some(false),
+                frck_bin: Some(bin.to_string_lossy().into_owned()),
+                run_dir: Some(dir.to_string_lossy().into_owned()),
+                frck_config: Some(FirecrackerConfig {
+                    disable_validation: true,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+
+            let mut machine = Machine::create(&config).unwrap();
+            machine.configure().unwrap();
+            machine.start().unwrap();
+            let pid = machine.child.id();
+            let socket_path = machine.local.get_socket_path().clone();
+
+            machine.restart(&config).unwrap();
+            assert_ne!(machine.child.id(), pid);
+            assert!(!super::super::pid_alive(pid));
+            assert_ne!(machine.local.get_socket_path(), &socket_path);
+            assert!(!socket_path.exists());
+
+            // A crashed machine is restarted all the same
+            let pid = machine.child.id();
+            machine.stop_force().unwrap();
+            machine.restart(&config).unwrap();
+            assert_ne!(machine.child.id(), pid);
+
+            machine.stop_force().unwrap();
+            machine.wait().unwrap();
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn test_checkpoint() {
+            use crate::{machine::SnapshotBundle, RtckErrorClass};
+
+            const VERSION: &str = r#"{"firecracker_version":"1.7.0"}"#;
+            const VM_CONFIG: &str = r#"{"boot-source":{"kernel_image_path":"vmlinux"}}"#;
+            let dir =
+                std::env::temp_dir().join(format!("rtck-checkpoint-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir(&dir).unwrap();
+
+            let mut machine = mock_machine(&[
+                response(200, VERSION),
+                response(200, VM_CONFIG),
+                instance_info("Running"),
+                response(204, ""),
+                response(204, ""),
+                instance_info("Paused"),
+                response(204, ""),
+                // Restored by a later patch release
+                response(200, r#"{"firecracker_version":"1.7.1"}"#),
+                response(204, ""),
+                response(200, r#"{"firecracker_version":"1.8.0"}"#),
+            ]);
+            let bundle = machine.checkpoint(&dir).unwrap();
+            let boot_source = bundle.vm_config.boot_source.as_ref().unwrap();
+            assert_eq!(boot_source.kernel_image_path, "vmlinux");
+            assert_eq!(SnapshotBundle::load(&dir).unwrap(), bundle);
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            let paused = requests.find(r#"{"state":"Paused"}"#).unwrap();
+            let snapshot = requests.find("PUT /snapshot/create").unwrap();
+            let resumed = requests.find(r#"{"state":"Resumed"}"#).unwrap();
+            assert!(paused < snapshot && snapshot < resumed);
+            assert!(requests.contains(&format!(
+                r#""snapshot_path":"{}""#,
+                dir.join("vm.state").display()
+            )));
+
+            // Written by firecracker
+            std::fs::write(dir.join("vm.state"), "").unwrap();
+            std::fs::write(dir.join("vm.mem"), [0u8; 4096]).unwrap();
+            assert_eq!(machine.restore_bundle(&dir).unwrap(), bundle);
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.contains(r#""resume_vm":true"#));
+            // Not before another minor version
+            let e = machine.restore_bundle(&dir).unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::MachineError);
+            let _ = machine.child.kill();
+
+            // Written but not resumed, unlike a failed snapshot
+            let mut machine = mock_machine(&[
+                response(200, VERSION),
+                response(200, VM_CONFIG),
+                instance_info("Running"),
+                response(204, ""),
+                response(204, ""),
+                instance_info("Paused"),
+                response(400, r#"{"fault_message":"oops"}"#),
+                response(200, VERSION),
+                response(200, VM_CONFIG),
+                instance_info("Running"),
+                response(204, ""),
+                response(400, r#"{"fault_message":"Disk full"}"#),
+                instance_info("Paused"),
+                response(204, ""),
+            ]);
+            let e = machine.checkpoint(&dir).unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::MachineError);
+            assert!(e.to_string().contains("Checkpoint written"), "{e}");
+            let source = std::error::Error::source(&e).unwrap();
+            let source = source.downcast_ref::<crate::RtckError>().unwrap();
+            assert_eq!(source.class(), &RtckErrorClass::RemoteError);
+            let e = machine.checkpoint(&dir).unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::RemoteError);
+            let _ = machine.child.kill();
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn test_clone_to() {
+            use crate::{config::GlobalConfig, machine::CloneOverrides, RtckErrorClass};
+
+            let mut machine = mock_machine(&[response(
+                200,
+                r#"{"boot-source":{"kernel_image_path":"vmlinux"},"network-interfaces":[
+                    {"iface_id":"eth0","host_dev_name":"tap0"},
+                    {"iface_id":"eth1","host_dev_name":"tap1"}]}"#,
+            )]);
+            let config = GlobalConfig {
+                socket_path: Some(format!("/tmp/rtck-clone-{}.sock", uuid::Uuid::new_v4())),
+                ..machine.config.clone()
+            };
+            let overrides = CloneOverrides::new().tap("eth0", "tap2");
+
+            // Refused before the machine is paused
+            let e = machine
+                .clone_to(
+                    &GlobalConfig {
+                        frck_export_path: Some("/tmp/rtck-clone.json".into()),
+                        ..config.clone()
+                    },
+                    &overrides,
+                )
+                .unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::ConfigError);
+            let e = machine.clone_to(&config, &overrides).unwrap_err();
+            assert!(
+                e.to_string()
+                    .contains("No tap device given for interfaces eth1"),
+                "{e}"
+            );
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert_eq!(requests.matches("HTTP/1.1\r\n").count(), 1);
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_snapshot_diff() {
+            use crate::{
+                config::FirecrackerConfig,
+                machine::{SnapshotChain, SnapshotFiles},
+                models::{
+                    machine_configuration::MachineConfiguration,
+                    snapshot_create_params::SnapshotType,
+                },
+                RtckErrorClass,
+            };
+
+            // Without dirty page tracking, reported by firecracker
+            let mut machine = mock_machine(&[response(
+                200,
+                r#"{"machine-config":{"vcpu_count":1,"mem_size_mib":128,"track_dirty_pages":false}}"#,
+            )]);
+            let e = machine
+                .snapshot("vm.state", "vm.mem", SnapshotType::Diff)
+                .unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::ConfigError);
+            let _ = machine.child.kill();
+
+            let mut machine = mock_machine(&[
+                response(204, ""),
+                response(204, ""),
+                response(400, r#"{"fault_message":"Disk full"}"#),
+            ]);
+            machine.config.frck_config = Some(FirecrackerConfig {
+                machine_config: Some(
+                    MachineConfiguration::builder()
+                        .vcpu_count(1)
+                        .mem_size_mib(128)
+                        .track_dirty_pages(true)
+                        .build()
+                        .unwrap(),
+                ),
+                ..Default::default()
+            });
+            let dir = std::env::temp_dir().join(format!("rtck-snapshots-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir(&dir).unwrap();
+
+            let base = machine.snapshot_diff(&dir).unwrap();
+            let diff = machine.snapshot_diff(&dir).unwrap();
+            assert_eq!(
+                base,
+                SnapshotFiles {
+                    snapshot_type: SnapshotType::Full,
+                    mem_file_path: dir.join("snapshot_0.mem"),
+                    snapshot_path: dir.join("snapshot_0.state"),
+                }
+            );
+            assert_eq!(diff.snapshot_type, SnapshotType::Diff);
+            assert_eq!(diff.mem_file_path, dir.join("snapshot_1.mem"));
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.contains(&format!(
+                r#""mem_file_path":"{}""#,
+                dir.join("snapshot_1.mem").display()
+            )));
+
+            // Failed snapshots are left out of the chain
+            assert!(machine.snapshot_diff(&dir).is_err());
+            assert_eq!(
+                SnapshotChain::load(&dir).unwrap().snapshots,
+                vec![base, diff]
+            );
+
+            std::fs::remove_dir_all(dir).unwrap();
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        #[cfg(not(target_arch = "aarch64"))]
+        fn test_stop() {
+            use crate::machine::check_ctrl_alt_del;
+
+            assert!(check_ctrl_alt_del("aarch64").is_err());
+            let mut machine = mock_machine(&[response(204, "")]);
+            machine.stop().unwrap();
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.contains(r#"{"action_type":"SendCtrlAltDel"}"#));
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        #[cfg(target_arch = "aarch64")]
+        fn test_stop() {
+            use crate::RtckErrorClass;
+
+            // Refused before anything is sent
+            let mut machine = mock_machine(&[]);
+            let e = machine.stop().unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::MachineError);
+            assert!(e.to_string().contains("stop_force"), "{e}");
+            assert!(machine.rtck.stream_mut().output.is_empty());
+            let _ = machine.child.kill();
+        }
+
+        #[test]
+        fn test_check_snapshot_files() {
+            use crate::{
+                machine::{check_snapshot_files, SnapshotBundle},
+                models::{
+                    full_vm_configuration::FullVmConfiguration,
+                    machine_configuration::MachineConfiguration,
+                    snapshot_create_params::SnapshotType, snapshot_load_params::SnapshotLoadParams,
+                },
+                RtckErrorClass,
+            };
+
+            let dir = std::env::temp_dir().join(format!("rtck-precheck-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir(&dir).unwrap();
+            let (state_path, mem_path) = SnapshotBundle::files(&dir);
+            let params =
+                SnapshotLoadParams::file(state_path.to_string_lossy(), mem_path.to_string_lossy());
+            let check = || check_snapshot_files(&params, None);
+
+            let e = check().unwrap_err();
+            assert!(e.to_string().contains("state file"), "{e}");
+            std::fs::write(&state_path, "").unwrap();
+            let e = check().unwrap_err();
+            assert!(e.to_string().contains("memory file"), "{e}");
+            std::fs::write(&mem_path, [0u8; 4000]).unwrap();
+            let e = check().unwrap_err();
+            assert_eq!(e.class(), &RtckErrorClass::MachineError);
+            assert!(e.to_string().contains("truncated"), "{e}");
+            std::fs::write(&mem_path, [0u8; 8192]).unwrap();
+            assert_eq!(check().unwrap(), None);
+
+            // Smaller than the memory recorded by the manifest
+            let bundle = SnapshotBundle {
+                firecracker_version: serde_json::from_str(r#"{"firecracker_version":"1.7.0"}"#)
+                    .unwrap(),
+                snapshot_type: SnapshotType::Full,
+                vm_config: FullVmConfiguration {
+                    machine_config: Some(
+                        MachineConfiguration::builder()
+                            .vcpu_count(1)
+                            .mem_size_mib(1)
+                            .build()
+                            .unwrap(),
+                    ),
+                    ..Default::default()
+                },
+            };
+            bundle.save(&dir).unwrap();
+            let e = check().unwrap_err();
+            assert!(e.to_string().contains("1 MiB"), "{e}");
+            std::fs::write(&mem_path, vec![0u8; 1 << 20]).unwrap();
+            assert_eq!(check().unwrap(), Some(bundle));
+
+            // Forced through
+            std::fs::write(&mem_path, "").unwrap();
+            let mut machine = mock_machine(&[response(204, "")]);
+            assert!(machine.load_snapshot(params.clone()).is_err());
+            machine.load_snapshot_with(params.clone(), true).unwrap();
+            let _ = machine.child.kill();
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn test_load_snapshot_uffd() {
+            use crate::{
+                machine::check_snapshot_load, models::snapshot_load_params::SnapshotLoadParams,
+            };
+
+            let mut machine = mock_machine(&[response(204, "")]);
+            let uds_path =
+                std::env::temp_dir().join(format!("rtck-uffd-{}.sock", uuid::Uuid::new_v4()));
+            let state_path = uds_path.with_extension("state");
+            std::fs::write(&state_path, "").unwrap();
+            let params =
+                SnapshotLoadParams::uffd(state_path.to_string_lossy(), uds_path.to_string_lossy())
+                    .resume(true);
+            // No handler listening
+            assert!(machine.load_snapshot(params.clone()).is_err());
+
+            let _listener = std::os::unix::net::UnixListener::bind(&uds_path).unwrap();
+            machine.load_snapshot(params).unwrap();
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.starts_with("PUT /snapshot/load HTTP/1.1\r\n"));
+            assert!(requests.contains(r#""backend_type":"Uffd""#));
+            assert!(requests.contains(r#""resume_vm":true"#));
+            std::fs::remove_file(uds_path).unwrap();
+            std::fs::remove_file(state_path).unwrap();
+            let _ = machine.child.kill();
+
+            // Jailed, the socket is resolved inside the jail and must stay there
+            let jail = std::env::temp_dir().join(format!("rtck-jail-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(jail.join("root/run")).unwrap();
+            std::fs::write(jail.join("root/run/uffd.sock"), "").unwrap();
+            std::fs::write(jail.join("uffd.sock"), "").unwrap();
+            let root = jail.join("root");
+            let jailed = |uds_path: &str| {
+                check_snapshot_load(&SnapshotLoadParams::uffd("vmstate", uds_path), Some(&root))
+            };
+            assert!(jailed("/run/uffd.sock").is_ok());
+            assert!(jailed("run/uffd.sock").is_ok());
+            assert!(jailed("/run/missing.sock").is_err());
+            assert!(jailed("/../uffd.sock").is_err());
+            std::fs::remove_dir_all(jail).unwrap();
+        }
+
+        #[test]
+        fn test_load_snapshot_uffd_handler() {
+            let mut machine = mock_machine(&[response(204, ""), response(400, "")]);
+            let mem_file =
+                std::env::temp_dir().join(format!("rtck-uffd-{}.mem", uuid::Uuid::new_v4()));
+            std::fs::write(&mem_file, [0u8; 4096]).unwrap();
+            let state_file = mem_file.with_extension("state");
+            std::fs::write(&state_file, "").unwrap();
+            let vmstate = state_file.to_string_lossy();
+
+            machine.load_snapshot_uffd(&vmstate, &mem_file).unwrap();
+            let uds_path = format!("{}.uffd", machine.local.get_socket_path().display());
+            let requests = String::from_utf8(machine.rtck.stream_mut().output.clone()).unwrap();
+            assert!(requests.contains(&format!(r#""backend_path":"{uds_path}""#)));
+            // Listening as long as the machine lives
+            assert!(std::path::Path::new(&uds_path).exists());
+            machine.uffd.take().unwrap().shutdown().unwrap();
+            assert!(!std::path::Path::new(&uds_path).exists());
+
+            // The handler is dropped if loading fails
+            assert!(machine.load_snapshot_uffd(&vmstate, &mem_file).is_err());
+            assert!(machine.uffd.is_none());
+            assert!(!std::path::Path::new(&uds_path).exists());
+
+            std::fs::remove_file(mem_file).unwrap();
+            std::fs::remove_file(&state_file).unwrap();
+            let _ = machine.child.kill();
+        }
+    }
+}
+
+/// Message asking the guest agent to resync its clock, `CLOCK_RESYNC` and
+/// the time of the host since the epoch as `<seconds>.<nanoseconds>`
+pub fn clock_resync_message() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("CLOCK_RESYNC {}.{:09}\n", now.as_secs(), now.subsec_nanos())
+}
+
+/// Firecracker answers `OK <host port>` once the guest accepted the connection
+/// and closes the connection otherwise.
+fn check_vsock_ack(port: u32, ack: &str) -> RtckResult<()> {
+    if ack.starts_with("OK ") {
+        Ok(())
+    } else {
+        Err(RtckError::new(
+            RtckErrorClass::MachineError,
+            format!("Guest refused vsock connection on port {port}"),
+        ))
+    }
+}
+
+/// Files of one snapshot in a chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFiles {
+    pub snapshot_type: SnapshotType,
+    pub mem_file_path: PathBuf,
+    pub snapshot_path: PathBuf,
+}
+
+/// Snapshots taken by `Machine::snapshot_diff` into a directory, from the
+/// full base to the latest diff. They must be restored in this order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotChain {
+    pub snapshots: Vec<SnapshotFiles>,
+}
+
+impl SnapshotChain {
+    pub const MANIFEST: &str = "manifest.json";
+
+    /// Load the chain of `dir`, which is empty if no snapshot was taken yet
+    pub fn load<P: AsRef<Path>>(dir: P) -> RtckResult<Self> {
+        let path = dir.as_ref().join(Self::MANIFEST);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn load_async<P: AsRef<Path>>(dir: P) -> RtckResult<Self> {
+        let path = dir.as_ref().join(Self::MANIFEST);
+        if !tokio::fs::try_exists(&path).await? {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> RtckResult<()> {
+        let path = dir.as_ref().join(Self::MANIFEST);
+        Ok(std::fs::write(path, serde_json::to_vec_pretty(self)?)?)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn save_async<P: AsRef<Path>>(&self, dir: P) -> RtckResult<()> {
+        let path = dir.as_ref().join(Self::MANIFEST);
+        Ok(tokio::fs::write(path, serde_json::to_vec_pretty(self)?).await?)
+    }
+
+    /// Files of the next snapshot, `snapshot_N.mem` and `snapshot_N.state`
+    /// in `dir`. The first snapshot is a full one the diffs apply to.
+    pub fn next_files<P: AsRef<Path>>(&self, dir: P) -> SnapshotFiles {
+        let n = self.snapshots.len();
+        SnapshotFiles {
+            snapshot_type: if n == 0 {
+                SnapshotType::Full
+            } else {
+                SnapshotType::Diff
+            },
+            mem_file_path: dir.as_ref().join(format!("snapshot_{n}.mem")),
+            snapshot_path: dir.as_ref().join(format!("snapshot_{n}.state")),
+        }
+    }
+}
+
+/// Snapshot taken by `Machine::checkpoint` into a directory, `vm.state` and
+/// `vm.mem` along with this manifest, which tells what restoring it takes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotBundle {
+    /// Version of firecracker which took the snapshot
+    pub firecracker_version: FirecrackerVersion,
+    pub snapshot_type: SnapshotType,
+    /// Configuration of the machine, its vCPUs, memory and devices
+    pub vm_config: FullVmConfiguration,
+}
+
+impl SnapshotBundle {
+    pub const MANIFEST: &str = "manifest.json";
+    pub const STATE_FILE: &str = "vm.state";
+    pub const MEM_FILE: &str = "vm.mem";
+
+    /// State and memory files of the bundle in `dir`
+    pub fn files<P: AsRef<Path>>(dir: P) -> (PathBuf, PathBuf) {
+        let dir = dir.as_ref();
+        (dir.join(Self::STATE_FILE), dir.join(Self::MEM_FILE))
+    }
+
+    pub fn load<P: AsRef<Path>>(dir: P) -> RtckResult<Self> {
+        let path = dir.as_ref().join(Self::MANIFEST);
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn load_async<P: AsRef<Path>>(dir: P) -> RtckResult<Self> {
+        let path = dir.as_ref().join(Self::MANIFEST);
+        Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> RtckResult<()> {
+        let path = dir.as_ref().join(Self::MANIFEST);
+        Ok(std::fs::write(path, serde_json::to_vec_pretty(self)?)?)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub async fn save_async<P: AsRef<Path>>(&self, dir: P) -> RtckResult<()> {
+        let path = dir.as_ref().join(Self::MANIFEST);
+        Ok(tokio::fs::write(path, serde_json::to_vec_pretty(self)?).await?)
+    }
+
+    /// Check firecracker `version` can restore the snapshot. The format of
+    /// snapshots only stays the same within a minor version.
+    pub fn check_version(&self, version: &FirecrackerVersion) -> RtckResult<()> {
+        let (major, minor, _) = self.firecracker_version.semver()?;
+        let (current_major, current_minor, _) = version.semver()?;
+        if (major, minor) != (current_major, current_minor) {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Snapshot taken by firecracker {} cannot be restored by firecracker {}",
+                    self.firecracker_version.firecracker_version, version.firecracker_version
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What a clone changes from the machine it is cloned from, see
+/// `Machine::clone_to`. Clones cannot share the tap devices of the machine,
+/// so each network interface must be given one of its own. Guest MAC
+/// addresses are part of the snapshot and cannot be changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CloneOverrides {
+    /// Tap device of each network interface of the clone, by interface id
+    pub host_dev_names: HashMap<String, String>,
+    /// Directory the snapshot is taken into, seen by the firecracker of the
+    /// machine. Default to a new directory removed once the clone is loaded.
+    pub snapshot_dir: Option<PathBuf>,
+}
+
+impl CloneOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach network interface `iface_id` of the clone to `host_dev_name`
+    pub fn tap<S: Into<String>, T: Into<String>>(mut self, iface_id: S, host_dev_name: T) -> Self {
+        self.host_dev_names
+            .insert(iface_id.into(), host_dev_name.into());
+        self
+    }
+
+    pub fn snapshot_dir<P: Into<PathBuf>>(mut self, snapshot_dir: P) -> Self {
+        self.snapshot_dir = Some(snapshot_dir.into());
+        self
+    }
+
+    /// Overrides of the snapshot load covering every one of `interfaces`
+    fn network_overrides(
+        &self,
+        interfaces: &[NetworkInterface],
+    ) -> RtckResult<Vec<NetworkOverride>> {
+        let missing: Vec<&str> = interfaces
+            .iter()
+            .map(|iface| iface.iface_id.as_str())
+            .filter(|iface_id| !self.host_dev_names.contains_key(*iface_id))
+            .collect();
+        if !missing.is_empty() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("No tap device given for interfaces {}", missing.join(", ")),
+            ));
+        }
+        Ok(interfaces
+            .iter()
+            .map(|iface| NetworkOverride {
+                iface_id: iface.iface_id.clone(),
+                host_dev_name: self.host_dev_names[&iface.iface_id].clone(),
+            })
+            .collect())
+    }
+}
+
+/// Directory to take the snapshot of a clone into, on the host, and as seen
+/// by the firecracker of the machine. Owned by the jailed user if jailed.
+fn clone_snapshot_dir(
+    config: &GlobalConfig,
+    jail_path: Option<&PathBuf>,
+    overrides: &CloneOverrides,
+) -> RtckResult<(PathBuf, PathBuf)> {
+    let dir = overrides.snapshot_dir.clone().unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("rtck-clone-{}", uuid::Uuid::new_v4()))
+    });
+    Ok((create_snapshot_dir(config, jail_path, &dir)?, dir))
+}
+
+/// Create `dir`, seen by firecracker, for snapshots, owned by the jailed
+/// firecracker if any, and return its path on the host
+fn create_snapshot_dir(
+    config: &GlobalConfig,
+    jail_path: Option<&PathBuf>,
+    dir: &Path,
+) -> RtckResult<PathBuf> {
+    let host_dir = host_path(jail_path, dir);
+    std::fs::create_dir_all(&host_dir)?;
+    if let (Some(_), Some(jailer_config)) = (jail_path, &config.jailer_config) {
+        std::os::unix::fs::chown(&host_dir, jailer_config.uid, jailer_config.gid)?;
+    }
+    Ok(host_dir)
+}
+
+/// Check a clone can be created from `config`, which must not boot a
+/// machine of its own
+fn check_clone_config(config: &GlobalConfig) -> RtckResult<()> {
+    if config.frck_export_path.is_some() {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "Clones are loaded from a snapshot and cannot boot from a config file",
+        ));
+    }
+    Ok(())
+}
+
+/// Error of a checkpoint written to `dir` after which the machine failed to
+/// resume, telling it apart from a checkpoint which failed
+fn resume_error(dir: &Path, e: RtckError) -> RtckError {
+    RtckError::new(
+        RtckErrorClass::MachineError,
+        format!("Checkpoint written to {dir:?} but the machine failed to resume, {e}"),
+    )
+    .with_source(e)
+}
+
+/// Path on the host of `path` seen by firecracker, relative to the jail if
+/// `jail_path` is given
+fn host_path(jail_path: Option<&PathBuf>, path: &Path) -> PathBuf {
+    match jail_path {
+        Some(jail_path) => jail_path.join(path.strip_prefix("/").unwrap_or(path)),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Firecracker only tracks the memory dirtied since the previous snapshot
+/// if asked to when the machine is configured, and never with huge pages
+fn check_dirty_pages(machine_config: Option<&MachineConfiguration>) -> RtckResult<()> {
+    if machine_config.is_some_and(MachineConfiguration::uses_huge_pages) {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "Diff snapshots are not supported with huge pages",
+        ));
+    }
+    match machine_config.and_then(|machine_config| machine_config.track_dirty_pages) {
+        Some(true) => Ok(()),
+        _ => Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "Diff snapshots require `track_dirty_pages` in the machine configuration",
+        )),
+    }
+}
+
+/// Check `drive_id` names one of the drives the machine was configured with.
+/// Without drives in the configuration, e.g. for an adopted machine, the
+/// check is left to firecracker.
+fn check_drive_exists(config: &GlobalConfig, drive_id: &str) -> RtckResult<()> {
+    let drives = config
+        .frck_config
+        .as_ref()
+        .and_then(|frck_config| frck_config.drives.as_ref());
+    match drives {
+        Some(drives) if !drives.iter().any(|drive| drive.drive_id == drive_id) => {
+            Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("No drive {drive_id:?} configured"),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Statistics are refused by firecracker if the balloon disabled them,
+/// fail with a clearer error when the configured balloon is known to
+fn check_balloon_stats(config: &GlobalConfig) -> RtckResult<()> {
+    let balloon = config
+        .frck_config
+        .as_ref()
+        .and_then(|frck_config| frck_config.balloon.as_ref());
+    match balloon {
+        Some(balloon) if balloon.stats_polling_interval().is_none() => Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "Balloon statistics disabled, stats_polling_interval_s must be at least 1",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Band of free guest memory the balloon autoscaler keeps the guest in,
+/// see `Machine::start_balloon_autoscaler`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalloonPolicy {
+    pub min_free_mib: u64,
+    pub max_free_mib: u64,
+    /// MiB the balloon is inflated or deflated by at once
+    pub step_mib: u64,
+    /// How often the balloon statistics are polled, no more often than
+    /// firecracker refreshes them
+    pub interval: Duration,
+}
+
+/// Resizing of the balloon decided from its statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalloonDecision {
+    /// Free memory of the guest, in MiB
+    pub free_mib: u64,
+    /// Target size of the balloon before and after the decision, in MiB
+    pub from_mib: u64,
+    pub to_mib: u64,
+}
+
+impl BalloonDecision {
+    /// Whether the balloon is to be resized
+    pub fn resizes(&self) -> bool {
+        self.from_mib != self.to_mib
+    }
+}
+
+impl BalloonPolicy {
+    pub fn validate(&self) -> RtckResult<()> {
+        if self.interval.is_zero() || self.step_mib == 0 || self.min_free_mib > self.max_free_mib {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Balloon policy needs a non-zero interval and step, and min_free_mib no greater than max_free_mib",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Deflate the balloon by `step_mib` when the guest has less free memory
+    /// than the band, inflate it by up to `step_mib` when it has more, never
+    /// leaving it less than `min_free_mib`
+    pub fn decide(&self, stats: &BalloonStatistics) -> RtckResult<BalloonDecision> {
+        let free_mib = stats.free_memory.ok_or(RtckError::new(
+            RtckErrorClass::MachineError,
+            "The guest reports no free memory, is its balloon driver loaded?",
+        ))? >> 20;
+        let from_mib = stats.target_mib;
+        let to_mib = if free_mib < self.min_free_mib {
+            from_mib.saturating_sub(self.step_mib)
+        } else if free_mib > self.max_free_mib {
+            from_mib + self.step_mib.min(free_mib - self.min_free_mib)
+        } else {
+            from_mib
+        };
+        Ok(BalloonDecision {
+            free_mib,
+            from_mib,
+            to_mib,
+        })
+    }
+}
+
+/// Whether the configured balloon reports statistics
+#[cfg(feature = "prometheus")]
+fn balloon_stats_enabled(config: &GlobalConfig) -> bool {
+    config
+        .frck_config
+        .as_ref()
+        .and_then(|frck_config| frck_config.balloon.as_ref())
+        .is_some_and(|balloon| balloon.stats_polling_interval().is_some())
+}
+
+/// Name of the machine in exported metrics and traces, its vmid or else its
+/// jailer id
+#[cfg(any(feature = "prometheus", feature = "tracing"))]
+fn machine_vmid(config: &GlobalConfig, socket_path: &Path) -> String {
+    config
+        .frck_config
+        .as_ref()
+        .and_then(|frck_config| frck_config.vmid.clone())
+        .or_else(|| config.jailer_config.as_ref().and_then(|c| c.id.clone()))
+        .unwrap_or_else(|| socket_path.to_string_lossy().into_owned())
+}
+
+/// Flushes in the metrics file of the machine, which must be configured
+#[cfg(feature = "prometheus")]
+fn metrics_flushes(
+    metrics_path: Option<&PathBuf>,
+) -> RtckResult<Vec<crate::models::metrics::FirecrackerMetricsSnapshot>> {
+    let path = metrics_path.ok_or(RtckError::new(
+        RtckErrorClass::ConfigError,
+        "Metrics not configured",
+    ))?;
+    crate::exporter::read_metrics_file(path)
+}
+
+/// Boot time in the log of firecracker at `log_path`, see `boot_timer`.
+/// Firecracker logs it at the info level, the logger must not be less verbose.
+fn boot_time(config: &GlobalConfig, log_path: Option<&PathBuf>) -> RtckResult<Option<Duration>> {
+    if config.boot_timer != Some(true) {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "Boot timer not enabled, set boot_timer",
+        ));
+    }
+    let logger = config
+        .frck_config
+        .as_ref()
+        .and_then(|frck_config| frck_config.logger.as_ref());
+    if let Some(level @ (LogLevel::Error | LogLevel::Warning | LogLevel::Warn | LogLevel::Off)) =
+        logger.and_then(|logger| logger.level.as_ref())
+    {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Boot time not logged at level {level:?}, Info at least is required"),
+        ));
+    }
+    let path = log_path.ok_or(RtckError::new(
+        RtckErrorClass::ConfigError,
+        "Logger not configured",
+    ))?;
+    Ok(parse_boot_time(&std::fs::read_to_string(path)?))
+}
+
+/// Boot time of the last line of `log` reporting it, once the guest wrote
+/// the magic value to the boot timer device, e.g.
+/// `Guest-boot-time =   1234 us 1 ms,   1100 CPU us 1 CPU ms`, whatever the
+/// prefix the logger puts before
+fn parse_boot_time(log: &str) -> Option<Duration> {
+    log.lines().rev().find_map(|line| {
+        let (_, report) = line.split_once("Guest-boot-time =")?;
+        let mut words = report.split_whitespace();
+        let us = words.next()?.parse().ok()?;
+        (words.next()? == "us").then(|| Duration::from_micros(us))
+    })
+}
+
+/// Error of a firecracker gone without reporting the boot time, such as
+/// one refusing `--boot-timer` at launch
+fn boot_timer_error() -> RtckError {
+    RtckError::new(
+        RtckErrorClass::MachineError,
+        "Firecracker exited without logging the boot time, check it supports --boot-timer",
+    )
+}
+
+/// Fault of a response, once `is_err` told firecracker rejected the request.
+/// Falls back to an empty fault rather than panic on an accepted one
+fn fault(fault: Option<&InternalError>) -> &InternalError {
+    static NO_FAULT: InternalError = InternalError {
+        fault_message: String::new(),
+    };
+    fault.unwrap_or(&NO_FAULT)
+}
+
+/// Payload of a response, once `is_err` told firecracker accepted the request
+fn payload<T: Clone>(succ: Option<&T>) -> RtckResult<T> {
+    succ.cloned().ok_or_else(|| {
+        RtckError::new(
+            RtckErrorClass::RemoteError,
+            "Response carries no payload, firecracker rejected the request",
+        )
+    })
+}
+
+/// Error of a rejected balloon update of `deflate_on_oom`, telling apart
+/// firecracker versions unable to update it after boot
+fn deflate_on_oom_error(fault: &InternalError) -> RtckError {
+    let reason = if fault.fault_message.contains("deflate_on_oom") {
+        "not supported at runtime by this firecracker version"
+    } else {
+        "rejected"
+    };
+    RtckError::remote(
+        format!("Fail to set balloon deflate_on_oom, {reason}"),
+        fault,
+    )
+}
+
+/// Check `drive_id` is among `drives`, declared before boot. Firecracker
+/// can change the backing file of a drive after boot but never add one.
+fn check_drive_declared(drives: &[Drive], drive_id: &str) -> RtckResult<()> {
+    match drives.iter().find(|drive| drive.drive_id == drive_id) {
+        Some(drive) if drive.is_vhost_user() => Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Drive {drive_id:?} is vhost-user, its media is up to the backend"),
+        )),
+        Some(_) => Ok(()),
+        None => Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Drive {drive_id:?} was not declared before boot and cannot be added"),
+        )),
+    }
+}
+
+/// `drive` as firecracker is to see it, the socket of a vhost-user drive
+/// being linked into the jail if jailed. Files of other drives are left
+/// where they are.
+fn jailed_drive(
+    config: &GlobalConfig,
+    jail_path: Option<&PathBuf>,
+    drive: &Drive,
+) -> RtckResult<Drive> {
+    let mut drive = drive.clone();
+    if let Some(socket) = &drive.socket {
+        drive.socket = Some(link_into_jail(config, jail_path, Path::new(socket))?);
+    }
+    Ok(drive)
+}
+
+/// Make `path` reachable by firecracker, returning the path to give it.
+/// Under jailer the file is hard linked at the root of the jail and handed
+/// to the jailed user, copied if it lives on another filesystem.
+fn link_into_jail(
+    config: &GlobalConfig,
+    jail_path: Option<&PathBuf>,
+    path: &Path,
+) -> RtckResult<String> {
+    let Some(jail_path) = jail_path else {
+        return Ok(path.to_string_lossy().into_owned());
+    };
+    let file_name = path.file_name().ok_or(RtckError::new(
+        RtckErrorClass::ConfigError,
+        format!("No file name in {path:?}"),
+    ))?;
+    let jailed_path = jail_path.join(file_name);
+    if jailed_path.exists() {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("{jailed_path:?} already exists in the jail"),
+        ));
+    }
+    if std::fs::hard_link(path, &jailed_path).is_err() {
+        std::fs::copy(path, &jailed_path)?;
+    }
+    if let Some(jailer_config) = &config.jailer_config {
+        std::os::unix::fs::chown(&jailed_path, jailer_config.uid, jailer_config.gid)?;
+    }
+    Ok(format!("/{}", file_name.to_string_lossy()))
+}
+
+/// Check the memory source of `params` is usable. Firecracker resolves paths
+/// inside the jail if `jail_path` is given, from which a userfaultfd socket
+/// must not escape.
+fn check_snapshot_load(params: &SnapshotLoadParams, jail_path: Option<&PathBuf>) -> RtckResult<()> {
+    params.validate()?;
+
+    let uds_path = match &params.mem_backend {
+        Some(backend) if backend.backend_type == BackendType::Uffd => &backend.backend_path,
+        _ => return Ok(()),
+    };
+    let missing = || {
+        RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Userfaultfd socket {uds_path} missing"),
+        )
+    };
+    match jail_path {
+        None => {
+            if !Path::new(uds_path).exists() {
+                return Err(missing());
+            }
+        }
+        Some(jail_path) => {
+            let host_path = jail_path
+                .join(uds_path.trim_start_matches('/'))
+                .canonicalize()
+                .map_err(|_| missing())?;
+            if !host_path.starts_with(jail_path.canonicalize()?) {
+                return Err(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    format!("Userfaultfd socket {uds_path} outside of the jail"),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Guest memory is made of 4 KiB pages, and so is a snapshot memory file
+const PAGE_SIZE: u64 = 4 << 10;
+
+/// Check the files of the snapshot `params` loads look sound, rather than
+/// have firecracker fail with a cryptic KVM error: the state file and the
+/// memory file exist, and the latter is a whole number of pages, no smaller
+/// than the memory recorded in the manifest of `SnapshotBundle` next to the
+/// state file, if any. Returns that manifest, telling which firecracker took
+/// the snapshot. The header of the state file is not looked into, its format
+/// changing between releases of firecracker.
+fn check_snapshot_files(
+    params: &SnapshotLoadParams,
+    jail_path: Option<&PathBuf>,
+) -> RtckResult<Option<SnapshotBundle>> {
+    let missing = |kind: &str, path: &str| {
+        RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Snapshot {kind} file {path} missing"),
+        )
+    };
+    let state_path = host_path(jail_path, Path::new(&params.snapshot_path));
+    if !state_path.is_file() {
+        return Err(missing("state", &params.snapshot_path));
+    }
+    let bundle = state_path
+        .parent()
+        .filter(|dir| dir.join(SnapshotBundle::MANIFEST).is_file())
+        .and_then(|dir| SnapshotBundle::load(dir).ok());
+
+    let mem_path = match (&params.mem_file_path, &params.mem_backend) {
+        (Some(mem_path), _) => mem_path,
+        (None, Some(backend)) if backend.backend_type == BackendType::File => &backend.backend_path,
+        // Served by a userfaultfd handler
+        _ => return Ok(bundle),
+    };
+    let size = match std::fs::metadata(host_path(jail_path, Path::new(mem_path))) {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        _ => return Err(missing("memory", mem_path)),
+    };
+    if size == 0 || size % PAGE_SIZE != 0 {
+        return Err(RtckError::new(
+            RtckErrorClass::MachineError,
+            format!("Snapshot memory file {mem_path} of {size} bytes is truncated, not a whole number of 4 KiB pages"),
+        ));
+    }
+    let mem_size_mib = bundle
+        .as_ref()
+        .and_then(|bundle| bundle.vm_config.machine_config.as_ref())
+        .map(|machine_config| machine_config.mem_size_mib.max(0) as u64);
+    if let Some(mem_size_mib) = mem_size_mib.filter(|mib| size < mib << 20) {
+        return Err(RtckError::new(
+            RtckErrorClass::MachineError,
+            format!("Snapshot memory file {mem_path} of {size} bytes is smaller than the {mem_size_mib} MiB of memory of the machine"),
+        ));
+    }
+    Ok(bundle)
+}
+
+/// Check firecracker can send Ctrl+Alt+Del to the guest on hosts of `arch`,
+/// which it cannot on aarch64, having no keyboard controller there. The
+/// architecture of the host is the one of firecracker and of the guest.
+fn check_ctrl_alt_del(arch: &str) -> RtckResult<()> {
+    if !ActionType::SendCtrlAltDel.supported_on(arch) {
+        return Err(RtckError::new(
+            RtckErrorClass::MachineError,
+            format!("Firecracker cannot send Ctrl+Alt+Del to the guest on {arch}, stop the machine with stop_force or from the guest"),
+        ));
+    }
+    Ok(())
+}
+
+/// Error of a snapshot taken by another minor version of firecracker
+fn snapshot_version_error(e: RtckError) -> RtckError {
+    RtckError::new(RtckErrorClass::MachineError, e.to_string())
+}
+
+/// Name of the userfaultfd socket at the root of the jail
+const UFFD_SOCKET_JAILED: &str = "uffd.sock";
+
+/// Serve `mem_path` with a userfaultfd handler listening next to the API
+/// socket, or at the root of the jail. Returns the handler along with its
+/// socket path seen by firecracker.
+fn serve_uffd(
+    config: &GlobalConfig,
+    socket_path: &Path,
+    jail_path: Option<&PathBuf>,
+    mem_path: &Path,
+) -> RtckResult<(UffdHandler, String)> {
+    match jail_path {
+        None => {
+            let uds_path = PathBuf::from(format!("{}.uffd", socket_path.display()));
+            let handler = UffdHandler::serve(mem_path.to_path_buf(), uds_path.clone())?;
+            Ok((handler, uds_path.to_string_lossy().into_owned()))
+        }
+        Some(jail_path) => {
+            let uds_path = jail_path.join(UFFD_SOCKET_JAILED);
+            let handler = UffdHandler::serve(mem_path.to_path_buf(), uds_path)?;
+            // Firecracker connects as the jailed user
+            if let Some(jailer_config) = &config.jailer_config {
+                std::os::unix::fs::chown(handler.uds_path(), jailer_config.uid, jailer_config.gid)?;
+            }
+            Ok((handler, format!("/{UFFD_SOCKET_JAILED}")))
+        }
+    }
+}
+
+/// Whether process `pid` exists, possibly owned by another user
+fn pid_alive(pid: u32) -> bool {
+    // Signal 0 only checks for existence and permission
+    let ret = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    ret == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Kill process `pid`, which is not necessarily a child of ours
+fn kill_pid(pid: u32) -> std::io::Result<()> {
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Whether process `pid` exited, reaping it with `waitpid` if it is a child
+/// of ours. Processes of other parents can only be polled for existence.
+fn pid_exited(pid: u32) -> RtckResult<bool> {
+    let ret = unsafe { libc::waitpid(pid as libc::pid_t, std::ptr::null_mut(), libc::WNOHANG) };
+    match ret {
+        0 => Ok(false),
+        ret if ret > 0 => Ok(true),
+        _ => {
+            let e = std::io::Error::last_os_error();
+            if e.raw_os_error() == Some(libc::ECHILD) {
+                Ok(!pid_alive(pid))
+            } else {
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// Config of a machine adopted from a previous run. Validation is disabled
+/// since the socket and the files in use already exist.
+fn adopted_config(socket_path: &Path, pid: u32) -> RtckResult<GlobalConfig> {
+    if !pid_alive(pid) {
+        return Err(RtckError::new(
+            RtckErrorClass::MachineError,
+            format!("No process with pid {pid}"),
+        ));
+    }
+    let frck_bin = std::fs::read_link(format!("/proc/{pid}/exe"))
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or("firecracker".to_string());
+    Ok(GlobalConfig {
+        using_jailer: Some(false),
+        socket_path: Some(socket_path.to_string_lossy().into_owned()),
+        frck_bin: Some(frck_bin),
+        frck_config: Some(FirecrackerConfig::default().with_disable_validation(true)),
+        ..Default::default()
+    })
+}
+
+pub mod machine_async {
+    use std::{
+        collections::VecDeque,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use futures_core::Stream;
+    use parking_lot::Mutex;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        config::GlobalConfig,
+        events::events_async::{self, EventAsync},
+        firecracker::firecracker_async::FirecrackerAsync,
+        jailer::jailer_async::JailerAsync,
+        local::local_async::LocalAsync,
+        micro_http::HttpMethod,
+        models::{
+            balloon::Balloon,
+            balloon_stats::BalloonStatistics,
+            balloon_update::BalloonUpdate,
+            cpu_template::CPUTemplateString,
+            firecracker_version::{FirecrackerVersion, MIN_SUPPORTED_VERSION},
+            full_vm_configuration::FullVmConfiguration,
+            instance_action_info::{ActionType, InstanceActionInfo},
+            instance_info::{InstanceInfo, State as InstanceState},
+            logger::{LogLevel, Logger},
+            partial_drive::PartialDrive,
+            rate_limiter::RateLimiter,
+            snapshot_create_params::{SnapshotCreateParams, SnapshotType},
+            snapshot_load_params::{NetworkOverride, SnapshotLoadParams},
+            vm,
+            vsock::Vsock,
+        },
+        network::NetworkResource,
+        rtck_async::RtckAsync,
+        uffd::UffdHandler,
+        vsock_cid::{CidClaim, VsockCidAllocator},
+        RtckError, RtckErrorClass, RtckResult,
+    };
+
+    #[cfg(any(feature = "prometheus", feature = "tracing"))]
+    use super::machine_vmid;
+    use super::{
+        adopted_config, boot_time, boot_timer_error, check_balloon_stats, check_clone_config,
+        check_ctrl_alt_del, check_dirty_pages, check_drive_declared, check_drive_exists,
+        check_snapshot_files, check_snapshot_load, check_vsock_ack, clock_resync_message,
+        clone_snapshot_dir, create_snapshot_dir, deflate_on_oom_error, fault, host_path,
+        jailed_drive, kill_pid, link_into_jail, payload, pid_exited, resume_error, serve_uffd,
+        snapshot_version_error, BalloonDecision, BalloonPolicy, CloneOverrides, MachineCore,
+        SnapshotBundle, SnapshotChain, SnapshotFiles,
+    };
+    #[cfg(feature = "prometheus")]
+    use super::{balloon_stats_enabled, metrics_flushes};
+    #[cfg(feature = "prometheus")]
+    use crate::exporter::MachineMetrics;
+    #[cfg(feature = "cni")]
+    use crate::network::cni::CniSetup;
+
+    /// Firecracker (or jailer) process behind a machine
+    enum Process {
+        /// Spawned by the machine itself
+        Spawned(tokio::process::Child),
+        /// Started by someone else, see `Machine::from_existing`
+        Adopted(u32),
+        /// Forked by the spawned jailer, already reaped, into a new PID namespace
+        Forked(tokio::process::Child, u32),
+    }
+
+    impl Process {
+        fn id(&self) -> Option<u32> {
+            match self {
+                Process::Spawned(child) => child.id(),
+                Process::Adopted(pid) | Process::Forked(_, pid) => Some(*pid),
+            }
+        }
+
+        /// Send SIGKILL, a spawned process being reaped by `reaped`
+        fn start_kill(&mut self) -> std::io::Result<()> {
+            match self {
+                Process::Spawned(child) => child.start_kill(),
+                Process::Adopted(pid) | Process::Forked(_, pid) => kill_pid(*pid),
+            }
+        }
+
+        /// Whether a killed process is gone, only a spawned one being reaped
+        fn reaped(&mut self) -> std::io::Result<bool> {
+            match self {
+                Process::Spawned(child) => Ok(child.try_wait()?.is_some()),
+                Process::Adopted(_) | Process::Forked(..) => Ok(true),
+            }
+        }
+
+        fn child_mut(&mut self) -> Option<&mut tokio::process::Child> {
+            match self {
+                Process::Spawned(child) | Process::Forked(child, _) => Some(child),
+                Process::Adopted(_) => None,
+            }
+        }
+
+        fn exited(&mut self) -> RtckResult<bool> {
+            match self {
+                Process::Spawned(child) => Ok(child.try_wait()?.is_some()),
+                Process::Adopted(pid) | Process::Forked(_, pid) => pid_exited(*pid),
+            }
+        }
+    }
+
+    /// What a snapshot schedule did so far, see `Machine::schedule_snapshots`
+    #[derive(Debug, Clone, Default)]
+    pub struct SnapshotScheduleStatus {
+        /// Snapshots taken
+        pub taken: u64,
+        /// When the last snapshot was taken, and the directory it went into
+        pub last_snapshot: Option<(SystemTime, PathBuf)>,
+        /// Error of the last capture which failed
+        pub last_error: Option<RtckError>,
+    }
+
+    /// Handle of a task attached to a machine, reporting what it did so far
+    /// as `T`. The task is cancelled once the handle is dropped, and by
+    /// `Machine::delete`.
+    #[derive(Debug)]
+    pub struct TaskHandle<T> {
+        cancel: CancellationToken,
+        status: Arc<Mutex<T>>,
+        task: Option<tokio::task::JoinHandle<()>>,
+    }
+
+    impl<T: Clone + Default> TaskHandle<T> {
+        /// Spawn `run`, handing it the token cancelling it and its status
+        fn spawn<F, Fut>(cancel: CancellationToken, run: F) -> Self
+        where
+            F: FnOnce(CancellationToken, Arc<Mutex<T>>) -> Fut,
+            Fut: std::future::Future<Output = ()> + Send + 'static,
+        {
+            let status = Arc::new(Mutex::new(T::default()));
+            let task = tokio::spawn(run(cancel.clone(), status.clone()));
+            Self {
+                cancel,
+                status,
+                task: Some(task),
+            }
+        }
+
+        /// Stop the task, the step under way being completed first
+        pub fn cancel(&self) {
+            self.cancel.cancel();
+        }
+
+        /// Stop the task and wait for the step under way, if any
+        pub async fn stop(mut self) {
+            self.cancel.cancel();
+            if let Some(task) = self.task.take() {
+                let _ = task.await;
+            }
+        }
+
+        /// Whether the task is over, cancelled or the machine gone
+        pub fn is_finished(&self) -> bool {
+            self.task.as_ref().is_none_or(|task| task.is_finished())
+        }
+
+        pub fn status(&self) -> T {
+            self.status.lock().clone()
+        }
+    }
+
+    impl<T> Drop for TaskHandle<T> {
+        fn drop(&mut self) {
+            self.cancel.cancel();
+        }
+    }
+
+    /// Handle of a snapshot schedule, see `Machine::schedule_snapshots`
+    pub type SnapshotScheduleHandle = TaskHandle<SnapshotScheduleStatus>;
+
+    impl SnapshotScheduleHandle {
+        /// When the last snapshot was taken
+        pub fn last_snapshot(&self) -> Option<SystemTime> {
+            self.status.lock().last_snapshot.as_ref().map(|(at, _)| *at)
+        }
+
+        pub fn last_error(&self) -> Option<RtckError> {
+            self.status.lock().last_error.clone()
+        }
+    }
+
+    /// What the balloon autoscaler did so far, see
+    /// `Machine::start_balloon_autoscaler`
+    #[derive(Debug, Clone, Default)]
+    pub struct BalloonAutoscalerStatus {
+        pub last_decision: Option<BalloonDecision>,
+        /// Error of the last poll or resizing which failed
+        pub last_error: Option<RtckError>,
+        /// Resizings of the balloon which failed in a row
+        pub failures: u32,
+    }
+
+    /// Handle of a balloon autoscaler, see `Machine::start_balloon_autoscaler`
+    pub type BalloonAutoscalerHandle = TaskHandle<BalloonAutoscalerStatus>;
+
+    impl BalloonAutoscalerHandle {
+        pub fn last_decision(&self) -> Option<BalloonDecision> {
+            self.status.lock().last_decision
+        }
+
+        pub fn last_error(&self) -> Option<RtckError> {
+            self.status.lock().last_error.clone()
+        }
+    }
+
+    /// Directories, seen by firecracker, of the snapshots a schedule keeps,
+    /// the oldest first
+    #[derive(Debug, Default)]
+    struct SnapshotRotation {
+        dirs: VecDeque<PathBuf>,
+        /// Snapshots in the latest chain of diffs
+        chain_len: usize,
+    }
+
+    impl SnapshotRotation {
+        /// Directory of the next snapshot, a new one unless a diff is added
+        /// to a chain shorter than `keep`
+        fn next_dir(&self, dir: &Path, keep: usize, snapshot_type: SnapshotType) -> PathBuf {
+            match self.dirs.back() {
+                Some(last) if snapshot_type == SnapshotType::Diff && self.chain_len < keep => {
+                    last.clone()
+                }
+                _ => {
+                    let since_epoch = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default();
+                    dir.join(format!("snapshot-{}", since_epoch.as_millis()))
+                }
+            }
+        }
+
+        /// Record a snapshot taken into `dir`, and remove the directories
+        /// rotated out: beyond the `keep` latest ones for full snapshots, the
+        /// previous chain once a new one starts for diffs
+        fn taken(
+            &mut self,
+            dir: PathBuf,
+            keep: usize,
+            snapshot_type: SnapshotType,
+            jail_path: Option<&PathBuf>,
+        ) -> RtckResult<()> {
+            if self.dirs.back() == Some(&dir) {
+                self.chain_len += 1;
+                return Ok(());
+            }
+            self.dirs.push_back(dir);
+            self.chain_len = 1;
+            let keep = match snapshot_type {
+                SnapshotType::Full => keep,
+                SnapshotType::Diff => 1,
+            };
+            while self.dirs.len() > keep {
+                if let Some(old) = self.dirs.pop_front() {
+                    std::fs::remove_dir_all(host_path(jail_path, &old))?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub struct Machine<S> {
+        rtck: tokio::sync::Mutex<RtckAsync<S>>,
+        local: LocalAsync,
+        jailer: Option<JailerAsync>,
+        frck: FirecrackerAsync,
+        config: GlobalConfig,
+        child: Mutex<Process>,
+        cids: Mutex<CidClaim>,
+        uffd: Mutex<Option<UffdHandler>>,
+        /// Cancelled by `delete`, stopping the tasks attached to the machine
+        tasks: CancellationToken,
+    }
+
+    // The configuration (which may carry metadata for the guest) is elided
+    impl<S> std::fmt::Debug for Machine<S> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let mut machine = f.debug_struct("Machine");
+            match self.rtck.try_lock() {
+                Ok(rtck) => machine.field("rtck", &*rtck),
+                Err(_) => machine.field("rtck", &"<locked>"),
+            };
+            machine
+                .field("socket_path", self.local.get_socket_path())
+                .field("jailed", &self.jailer.is_some())
+                .field("pid", &self.child.try_lock().and_then(|child| child.id()))
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl<S> Machine<S> {
+        /// Dump the global configuration of the machine for future use
+        /// Name of the machine in exported metrics and traces
+        #[cfg(any(feature = "prometheus", feature = "tracing"))]
+        fn vmid(&self) -> String {
+            machine_vmid(&self.config, self.local.get_socket_path())
+        }
+
+        pub fn get_config(&self) -> GlobalConfig {
+            self.config.clone()
+        }
+
+        /// Path of the file capturing the guest console, if configured
+        pub fn console_path(&self) -> Option<&PathBuf> {
+            self.config.console_output.as_ref()
+        }
+
+        /// Host side path of the vsock socket, inside the jail if jailed
+        pub fn vsock_uds_path(&self) -> RtckResult<PathBuf> {
+            if let Some(jailer) = &self.jailer {
+                if let Some(path) = jailer.get_vsock_paths_exported().first() {
+                    return Ok(path.clone());
+                }
+            }
+            self.config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.vsock_devices.as_ref())
+                .and_then(|vsocks| vsocks.first())
+                .map(|vsock| PathBuf::from(&vsock.uds_path))
+                .ok_or(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "No vsock device configured",
+                ))
+        }
+
+        /// Check that the guest accepts connections on vsock `port`,
+        /// using the `CONNECT <port>` handshake of firecracker.
+        /// Connect to vsock `port` of the guest, using the `CONNECT <port>`
+        /// handshake of firecracker
+        async fn connect_vsock(&self, port: u32) -> RtckResult<tokio::net::UnixStream> {
+            use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+            let path = self.vsock_uds_path()?;
+            let mut stream = tokio::net::UnixStream::connect(&path).await?;
+            stream
+                .write_all(format!("CONNECT {port}\n").as_bytes())
+                .await?;
+            // The guest only speaks once spoken to, nothing is read past the ack
+            let mut stream = BufReader::new(stream);
+            let mut ack = String::new();
+            stream.read_line(&mut ack).await?;
+            check_vsock_ack(port, &ack)?;
+            Ok(stream.into_inner())
+        }
+
+        /// Check that the guest accepts connections on vsock `port`,
+        /// using the `CONNECT <port>` handshake of firecracker.
+        pub async fn probe_vsock(
+            &self,
+            port: u32,
+            timeout: tokio::time::Duration,
+        ) -> RtckResult<()> {
+            tokio::time::timeout(timeout, self.connect_vsock(port))
+                .await
+                .map_err(|_| {
+                    RtckError::new(RtckErrorClass::MachineError, "Timeout probing vsock")
+                })??;
+            Ok(())
+        }
+
+        /// Send `data` to the guest listening on vsock `port`, within the
+        /// request timeout, then close the connection
+        pub async fn vsock_send(&self, port: u32, data: &[u8]) -> RtckResult<()> {
+            use tokio::io::AsyncWriteExt;
+
+            let send = async {
+                let mut stream = self.connect_vsock(port).await?;
+                stream.write_all(data).await?;
+                stream.shutdown().await?;
+                Ok::<(), RtckError>(())
+            };
+            match self.config.request_timeout()? {
+                Some(timeout) => tokio::time::timeout(timeout, send).await.map_err(|_| {
+                    RtckError::new(RtckErrorClass::MachineError, "Timeout sending to vsock")
+                })?,
+                None => send.await,
+            }
+        }
+
+        /// Have the guest resync its clock, stale after a snapshot is restored
+        /// or a long pause, by sending `clock_resync_message` to the agent
+        /// listening on `vsock_control_port`. Nothing is sent without one.
+        pub async fn notify_resume_time(&self) -> RtckResult<()> {
+            match self.config.vsock_control_port {
+                Some(port) => {
+                    self.vsock_send(port, clock_resync_message().as_bytes())
+                        .await
+                }
+                None => Ok(()),
+            }
+        }
+
+        /// Take the stdin handle of firecracker, if configured as piped
+        pub fn take_stdin(&self) -> Option<tokio::process::ChildStdin> {
+            self.child.lock().child_mut()?.stdin.take()
+        }
+
+        /// Take the stdout handle of firecracker, if configured as piped
+        pub fn take_stdout(&self) -> Option<tokio::process::ChildStdout> {
+            self.child.lock().child_mut()?.stdout.take()
+        }
+
+        /// Take the stderr handle of firecracker, if configured as piped
+        pub fn take_stderr(&self) -> Option<tokio::process::ChildStderr> {
+            self.child.lock().child_mut()?.stderr.take()
+        }
+
+        /// Dump what is needed to reattach to the machine with `restore_core`
+        pub fn dump_core(&self) -> RtckResult<MachineCore> {
+            let pid = self.child.lock().id().ok_or(RtckError::new(
+                RtckErrorClass::MachineError,
+                "Firecracker process already exited",
+            ))?;
+            Ok(MachineCore::new(
+                &self.config,
+                pid,
+                self.local.get_socket_path(),
+                self.jailer
+                    .as_ref()
+                    .and_then(|jailer| jailer.get_jailer_workspace_dir().ok().cloned()),
+                self.local.get_jail_path(),
+            ))
+        }
+
+        /// Wait for the firecracker process to exit, polling it every
+        /// `poll_status_secs` so that it can still be stopped meanwhile
+        pub async fn wait(&self) -> RtckResult<()> {
+            while !self.child.lock().exited()? {
+                tokio::time::sleep(self.config.poll_status_interval()).await;
+            }
+            self.cids.lock().release();
+            self.uffd.lock().take();
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    impl Machine<tokio::io::BufStream<tokio::net::UnixStream>> {
+        /// Create a machine from scratch, using default stream
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.create", skip_all, fields(vmid = tracing::field::Empty))
+        )]
+        pub async fn create(config: &GlobalConfig) -> RtckResult<Self> {
+            let mut config = config.clone();
+            config.assign_instance()?;
+            let cids = VsockCidAllocator::global().claim(&mut config)?;
+            config.validate()?;
+            #[cfg(feature = "cni")]
+            let cni = match config.frck_config.as_mut() {
+                Some(frck_config) => CniSetup::run(frck_config)?,
+                None => None,
+            };
+            let config = &config;
+
+            let frck = FirecrackerAsync::from_config(config)?;
+            let mut jailer = JailerAsync::from_config(config).ok();
+
+            if config.frck_export_path.is_some() {
+                config.export_config_async().await?;
+            }
+
+            let (stream, child, local) = if config.using_jailer.unwrap() {
+                // Set up for jailing
+                assert!(jailer.is_some());
+                let jailer = jailer.as_mut().unwrap();
+                jailer.jail()?;
+                let mut child = jailer.launch().await?;
+                jailer.waiting_socket(config.init_timeout()?).await?;
+                let child = match jailer.pid_file_path()? {
+                    Some(_) => {
+                        // The jailer exits once firecracker is forked
+                        let pid = jailer.read_pid(config.init_timeout()?).await?;
+                        child.wait().await?;
+                        Process::Forked(child, pid)
+                    }
+                    None => Process::Spawned(child),
+                };
+
+                (
+                    crate::retry::retry_async(&config.api_retry.unwrap_or_default(), || {
+                        jailer.connect()
+                    })
+                    .await?,
+                    child,
+                    LocalAsync::from_jailer(jailer, config)?,
+                )
+            } else {
+                // Firecracker launch and connect
+                let child = frck.launch().await?;
+                frck.waiting_socket(config.init_timeout()?).await?;
+                (
+                    crate::retry::retry_async(&config.api_retry.unwrap_or_default(), || {
+                        frck.connect()
+                    })
+                    .await?,
+                    Process::Spawned(child),
+                    LocalAsync::from_frck(&frck, config)?,
+                )
+            };
+
+            // Set up local environment
+            local.full_clean().await;
+            local.setup().await?;
+            #[cfg(feature = "cni")]
+            for resource in cni.into_iter().flat_map(CniSetup::into_resources) {
+                local.get_network().track(resource);
+            }
+
+            let rtck = Self::rtck_from_stream(
+                stream,
+                local.get_socket_path().clone(),
+                config.request_timeout()?,
+            )
+            .with_trace(config.api_trace())
+            .with_retry_policy(config.api_retry);
+
+            let machine = Self {
+                rtck: tokio::sync::Mutex::new(rtck),
+                local,
+                jailer,
+                frck,
+                config: config.clone(),
+                child: Mutex::new(child),
+                cids: Mutex::new(cids),
+                uffd: Mutex::new(None),
+                tasks: CancellationToken::new(),
+            };
+            #[cfg(feature = "tracing")]
+            tracing::Span::current().record("vmid", machine.vmid());
+            if config.check_version.unwrap_or(false) {
+                if let Err(e) = machine.assert_compatible(MIN_SUPPORTED_VERSION).await {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(error = %e, "incompatible firecracker, killing it");
+                    let _ = machine.stop_force().await;
+                    return Err(e);
+                }
+            }
+            Ok(machine)
+        }
+
+        /// Adopt firecracker process `pid`, started by a previous run and
+        /// listening on `socket_path`. The machine must answer to be adopted,
+        /// it is neither configured nor set up again.
+        pub async fn from_existing(socket_path: PathBuf, pid: u32) -> RtckResult<Self> {
+            let config = adopted_config(&socket_path, pid)?;
+            let frck = FirecrackerAsync::from_config(&config)?;
+            let local = LocalAsync::from_frck(&frck, &config)?;
+            let stream =
+                tokio::io::BufStream::new(tokio::net::UnixStream::connect(&socket_path).await?);
+            let rtck = Self::rtck_from_stream(stream, socket_path, config.request_timeout()?);
+
+            let machine = Self {
+                rtck: tokio::sync::Mutex::new(rtck),
+                local,
+                jailer: None,
+                frck,
+                config,
+                child: Mutex::new(Process::Adopted(pid)),
+                cids: Mutex::new(CidClaim::empty()),
+                uffd: Mutex::new(None),
+                tasks: CancellationToken::new(),
+            };
+            machine.ping_remote().await?;
+            Ok(machine)
+        }
+
+        /// Reattach to the machine described by `core`. Only the socket and
+        /// the process are taken over, so that cleaning up never removes more
+        /// than the socket and dumping the core again drops the jailer fields.
+        pub async fn restore_core(core: MachineCore) -> RtckResult<Self> {
+            Self::from_existing(core.socket_path, core.pid).await
+        }
+
+        /// Recycle the machine, e.g. once crashed: stop the tasks attached to
+        /// it, kill firecracker if still running and clean up as
+        /// `delete_and_clean` does, undoing the host side set up of the
+        /// devices, then create, configure and start the machine anew from
+        /// `config`. Firecracker cannot be configured again once started, so a
+        /// fresh process is launched, on a fresh socket. `config` is validated
+        /// again: a log or metrics file kept from the previous run makes it fail.
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.restart", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn restart(&mut self, config: &GlobalConfig) -> RtckResult<()> {
+            self.tasks.cancel();
+            let exited = self.child.lock().exited()?;
+            if !exited {
+                self.stop_force().await?;
+            }
+            self.wait().await?;
+            self.local.full_clean().await;
+
+            *self = Self::create(config).await?;
+            let res = async {
+                self.configure().await?;
+                self.start().await
+            }
+            .await;
+            if let Err(e) = res {
+                self.abort_start("failed on restart").await;
+                return Err(e);
+            }
+            Ok(())
+        }
+
+        /// Talk to firecracker through `stream`, reconnecting to `socket_path`
+        /// once it closes the connection
+        fn rtck_from_stream(
+            stream: tokio::io::BufStream<tokio::net::UnixStream>,
+            socket_path: PathBuf,
+            request_timeout: Option<tokio::time::Duration>,
+        ) -> RtckAsync<tokio::io::BufStream<tokio::net::UnixStream>> {
+            RtckAsync::from_stream(stream)
+                .with_request_timeout(request_timeout)
+                .with_reconnect(move || {
+                    let socket_path = socket_path.clone();
+                    async move {
+                        Ok(tokio::io::BufStream::new(
+                            tokio::net::UnixStream::connect(socket_path).await?,
+                        ))
+                    }
+                })
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    use tokio::io::{AsyncBufRead, AsyncWrite};
+    impl<S: AsyncBufRead + AsyncWrite + Unpin> Machine<S> {
+        /// Ping firecracker to check its soundness
+        pub async fn ping_remote(&self) -> RtckResult<()> {
+            let policy = self.config.api_retry.unwrap_or_default();
+            Ok(crate::retry::retry_async(&policy, || async {
+                self.rtck
+                    .lock()
+                    .await
+                    .execute(&events_async::GetFirecrackerVersion::new())
+                    .await
+            })
+            .await
+            .map_err(|e| {
+                RtckError::new(
+                    RtckErrorClass::RemoteError,
+                    format!("Fail to ping remote {}", e.to_string()),
+                )
+                .with_source(e)
+            })?)
+        }
+
+        /// Query the version of firecracker
+        pub async fn firecracker_version(&self) -> RtckResult<FirecrackerVersion> {
+            let get_firecracker_version = events_async::GetFirecrackerVersion::new();
+            self.rtck
+                .lock()
+                .await
+                .execute(&get_firecracker_version)
+                .await?;
+            if get_firecracker_version.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to get firecracker version",
+                    fault(get_firecracker_version.get_res().as_err()),
+                ));
+            }
+            let version = payload(get_firecracker_version.get_res().as_succ())?;
+            Ok(version)
+        }
+
+        /// Check that firecracker is version `min`, as (major, minor, patch),
+        /// or later, rather than have an older API fail requests obscurely
+        pub async fn assert_compatible(&self, min: (u64, u64, u64)) -> RtckResult<()> {
+            self.firecracker_version().await?.check_compatible(min)
+        }
+
+        /// Check that the host and firecracker support the huge pages and
+        /// the CPU template the machine configuration asks for
+        async fn check_machine_config(&self) -> RtckResult<()> {
+            let Some(frck_config) = &self.config.frck_config else {
+                return Ok(());
+            };
+            let Some(machine_config) = &frck_config.machine_config else {
+                return Ok(());
+            };
+            let huge_pages = machine_config.huge_pages.unwrap_or_default();
+            let cpu_template = machine_config
+                .cpu_template
+                .as_ref()
+                .map_or(CPUTemplateString::None, |template| template.0.clone());
+            if frck_config.disable_validation
+                || !machine_config.uses_huge_pages()
+                    && cpu_template.min_firecracker_version().is_none()
+            {
+                return Ok(());
+            }
+            huge_pages.check_host(machine_config.mem_size_mib as u64)?;
+            let version = self.firecracker_version().await?;
+            huge_pages.check_version(&version)?;
+            cpu_template.check_version(&version)
+        }
+
+        /// Logger of the configuration, its level named the way the running
+        /// firecracker does, see `LogLevel::for_version`
+        async fn versioned_logger(&self) -> RtckResult<Option<Logger>> {
+            let Some(mut logger) = self
+                .config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.logger.clone())
+            else {
+                return Ok(None);
+            };
+            if let Some(level) = logger.level.clone().filter(LogLevel::depends_on_version) {
+                logger.level = Some(level.for_version(&self.firecracker_version().await?)?);
+            }
+            Ok(Some(logger))
+        }
+
+        /// Automatically configure the machine.
+        /// User must guarantee that `config` passed to the machine contains
+        /// valid firecracker configuration (`frck_config`).
+        pub async fn configure(&self) -> RtckResult<()> {
+            // If configuration has been exported, then the machine should have been configured.
+            if self.config.frck_export_path.is_some() {
+                return Ok(());
+            }
+
+            self.check_machine_config().await?;
+            let logger = self.versioned_logger().await?;
+
+            use events_async::*;
+
+            // User must guarantee that proper
+            let frck_config = self.config.frck_config.as_ref().ok_or(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "No proper firecracker configuration passed".to_string(),
+            ))?;
+            frck_config.check_device_ids()?;
+            log::debug!(
+                "[Machine::configure configuring {:?}]",
+                self.config.loggable(frck_config)
+            );
+
+            // Logger
+            {
+                if let Some(logger) = logger {
+                    let put_logger = PutLogger::new(logger);
+                    self.rtck.lock().await.execute(&put_logger).await?;
+                    if put_logger.is_err()? {
+                        log::error!(
+                            "[PutLogger failed, error = {}]",
+                            fault(put_logger.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            // Metrics
+            {
+                if let Some(metrics) = &frck_config.metrics {
+                    let put_metrics = PutMetrics::new(metrics.clone());
+                    self.rtck.lock().await.execute(&put_metrics).await?;
+                    if put_metrics.is_err()? {
+                        log::error!(
+                            "[PutMetrics failed, error = {}]",
+                            fault(put_metrics.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            // Guest boot source
+            {
+                if let Some(boot_source) = frck_config.effective_boot_source()? {
+                    let put_guest_boot_source = PutGuestBootSource::new(boot_source);
+                    self.rtck
+                        .lock()
+                        .await
+                        .execute(&put_guest_boot_source)
+                        .await?;
+                    if put_guest_boot_source.is_err()? {
+                        log::error!(
+                            "[PutGuestBootSource failed, error = {}]",
+                            fault(put_guest_boot_source.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            // Guest drives
+            {
+                if let Some(drives) = &frck_config.drives {
+                    for drive in drives {
+                        let drive = jailed_drive(&self.config, self.local.get_jail_path(), drive)?;
+                        let put_guest_drive_by_id = PutGuestDriveById::new(drive);
+                        self.rtck
+                            .lock()
+                            .await
+                            .execute(&put_guest_drive_by_id)
+                            .await?;
+                        if put_guest_drive_by_id.is_err()? {
+                            log::error!(
+                                "[PutGuestDriveById failed, error = {}]",
+                                fault(put_guest_drive_by_id.get_res().as_err())
+                            );
+                        }
+                    }
+                }
+            }
+
+            // Guest network interfaces
+            {
+                if let Some(ifaces) = &frck_config.network_interfaces {
+                    for iface in ifaces {
+                        let put_guest_network_interface_by_id =
+                            PutGuestNetworkInterfaceById::new(iface.clone());
+                        self.rtck
+                            .lock()
+                            .await
+                            .execute(&put_guest_network_interface_by_id)
+                            .await?;
+                        if put_guest_network_interface_by_id.is_err()? {
+                            log::error!(
+                                "[PutGuestNetworkInterfaceById failed, error = {}]",
+                                fault(put_guest_network_interface_by_id.get_res().as_err())
+                            );
+                        }
+                    }
+                }
+            }
+
+            // MMDS configuration, once the interfaces forwarding to it exist
+            {
+                if let Some(mmds_config) = frck_config.mmds_config()? {
+                    let put_mmds_config = PutMmdsConfig::new(mmds_config);
+                    self.rtck.lock().await.execute(&put_mmds_config).await?;
+                    if put_mmds_config.is_err()? {
+                        log::error!(
+                            "[PutMmdsConfig failed, error = {}]",
+                            fault(put_mmds_config.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            // Vsocks
+            {
+                if let Some(vsocks) = &frck_config.vsock_devices {
+                    for vsock in vsocks {
+                        let put_guest_vsock = PutGuestVsock::new(vsock.clone());
+                        self.rtck.lock().await.execute(&put_guest_vsock).await?;
+                        if put_guest_vsock.is_err()? {
+                            log::error!(
+                                "[PutGuestVsock failed, error = {}]",
+                                fault(put_guest_vsock.get_res().as_err())
+                            );
+                        }
+                    }
+                }
+            }
+
+            // CPU configuration
+            {
+                if let Some(cpu_config) = &frck_config.cpu_config {
+                    let put_cpu_configuration = PutCpuConfiguration::new(cpu_config.clone());
+                    self.rtck
+                        .lock()
+                        .await
+                        .execute(&put_cpu_configuration)
+                        .await?;
+                    if put_cpu_configuration.is_err()? {
+                        log::error!(
+                            "[PutCpuConfiguration failed, error = {}]",
+                            fault(put_cpu_configuration.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            // Machine configuration
+            {
+                if let Some(machine_config) = &frck_config.machine_config {
+                    if !frck_config.disable_validation {
+                        machine_config.validate()?;
+                    }
+                    let put_machine_configuration =
+                        PutMachineConfiguration::new(machine_config.clone());
+                    self.rtck
+                        .lock()
+                        .await
+                        .execute(&put_machine_configuration)
+                        .await?;
+                    if put_machine_configuration.is_err()? {
+                        log::error!(
+                            "[PutMachineConfiguration failed, error = {}]",
+                            fault(put_machine_configuration.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            // Balloon
+            {
+                if let Some(balloon) = &frck_config.balloon {
+                    let put_balloon = PutBalloon::new(balloon.clone());
+                    self.rtck.lock().await.execute(&put_balloon).await?;
+                    if put_balloon.is_err()? {
+                        log::error!(
+                            "[PutBalloon failed, error = {}]",
+                            fault(put_balloon.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            // Entropy device
+            {
+                if let Some(entropy_device) = &frck_config.entropy_device {
+                    let put_entropy = PutEntropy::new(entropy_device.clone());
+                    self.rtck.lock().await.execute(&put_entropy).await?;
+                    if put_entropy.is_err()? {
+                        log::error!(
+                            "[PutEntropy failed, error = {}]",
+                            fault(put_entropy.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            // Initial mmds content
+            {
+                if let Some(content) = &frck_config.init_metadata {
+                    self.config.check_mmds_size(content)?;
+                    let put_mmds = PutMmds::new(content.clone());
+                    self.rtck.lock().await.execute(&put_mmds).await?;
+                    if put_mmds.is_err()? {
+                        log::error!(
+                            "[PutMmds failed, error = {}]",
+                            fault(put_mmds.get_res().as_err())
+                        );
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Start the machine by notifying the hypervisor, then wait for it
+        /// to run if a launch timeout is configured
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.start", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn start(&self) -> RtckResult<()> {
+            self.instance_start().await?;
+            if let Some(timeout) = self.config.launch_timeout()? {
+                self.wait_until_running(timeout).await?;
+            }
+            Ok(())
+        }
+
+        async fn instance_start(&self) -> RtckResult<()> {
+            let start_machine =
+                events_async::CreateSyncAction::new(InstanceActionInfo::instance_start());
+
+            self.rtck.lock().await.execute(&start_machine).await?;
+            Ok(())
+        }
+
+        /// Configure and start the machine, then wait for it to run, giving
+        /// up once `deadline` passes or `cancel` is cancelled, even in the
+        /// middle of a step. An aborted machine is killed and cleaned.
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.start", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn start_with_deadline(
+            &self,
+            deadline: tokio::time::Instant,
+            cancel: Option<&CancellationToken>,
+        ) -> RtckResult<()> {
+            let steps = async {
+                self.configure().await?;
+                self.instance_start().await?;
+                let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+                self.wait_until_running(remaining).await
+            };
+            let cancelled = async {
+                match cancel {
+                    Some(cancel) => cancel.cancelled().await,
+                    None => std::future::pending().await,
+                }
+            };
+            let reason = tokio::select! {
+                res = steps => match res {
+                    Err(_) if tokio::time::Instant::now() >= deadline => "timed out",
+                    res => return res,
+                },
+                _ = tokio::time::sleep_until(deadline) => "timed out",
+                _ = cancelled => "cancelled",
+            };
+            Err(self.abort_start(reason).await)
+        }
+
+        /// Kill and clean the machine after an aborted start
+        async fn abort_start(&self, reason: &str) -> RtckError {
+            log::error!("[Machine::start {reason}, cleaning up]");
+            #[cfg(feature = "tracing")]
+            tracing::warn!(reason, "start aborted, killing and cleaning the machine");
+            let _ = self.stop_force().await;
+            self.cids.lock().release();
+            self.uffd.lock().take();
+            self.local.full_clean().await;
+            RtckError::new(RtckErrorClass::MachineError, format!("start {reason}"))
+        }
+
+        /// Set the VM to `state`, refusing transitions the machine cannot
+        /// take from its current state
+        pub async fn set_vm_state(&self, state: vm::State) -> RtckResult<()> {
+            state.check_transition(self.describe_instance().await?.state)?;
+
+            let patch_vm = events_async::PatchVm::new(vm::Vm { state });
+            self.rtck.lock().await.execute(&patch_vm).await?;
+            if patch_vm.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to set VM state",
+                    fault(patch_vm.get_res().as_err()),
+                ));
+            }
+            Ok(())
+        }
+
+        /// Pause the machine by notifying the hypervisor
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.pause", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn pause(&self) -> RtckResult<()> {
+            self.set_vm_state(vm::State::Paused).await
+        }
+
+        /// Resume the machine by notifying the hypervisor
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.resume", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn resume(&self) -> RtckResult<()> {
+            self.set_vm_state(vm::State::Resumed).await
+        }
+
+        /// Stop the machine by having firecracker send Ctrl+Alt+Del to the
+        /// guest. Refused on aarch64, where `stop_force` must be used instead.
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.stop", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn stop(&self) -> RtckResult<()> {
+            check_ctrl_alt_del(std::env::consts::ARCH)?;
+            let stop_machine = events_async::CreateSyncAction::new(InstanceActionInfo::new(
+                ActionType::SendCtrlAltDel,
+            ));
+
+            self.rtck.lock().await.execute(&stop_machine).await?;
+            Ok(())
+        }
+
+        /// Stop the machine forcefully by killing the firecracker process
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.stop_force", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn stop_force(&self) -> RtckResult<()> {
+            let kill_failed = |e: std::io::Error| {
+                log::error!("[Machine::stop_force killing failed, error = {}]", e);
+                RtckError::new(
+                    RtckErrorClass::MachineError,
+                    "Fail to kill the machine".to_string(),
+                )
+            };
+            let killed = self.child.lock().start_kill();
+            killed.map_err(kill_failed)?;
+            // The lock is not held across awaits, keeping the machine `Send`
+            loop {
+                let reaped = self.child.lock().reaped();
+                if reaped.map_err(kill_failed)? {
+                    return Ok(());
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        }
+
+        /// Delete the machine by notifying firecracker
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.delete", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn delete(&self) -> RtckResult<()> {
+            self.tasks.cancel();
+            // Stop the machine first, killing it if the guest cannot be asked to
+            if check_ctrl_alt_del(std::env::consts::ARCH).is_err() {
+                self.stop_force().await?;
+                self.cids.lock().release();
+                self.uffd.lock().take();
+                return Ok(());
+            }
+            self.stop().await?;
+            let query_status = events_async::DescribeInstance::new();
+            self.rtck.lock().await.execute(&query_status).await?;
+
+            if query_status.is_err()? {
+                log::error!(
+                    "[Machine::delete query status failed, error = {}]",
+                    fault(query_status.get_res().as_err())
+                );
+                return Err(RtckError::new(
+                    RtckErrorClass::MachineError,
+                    "Fail to query status".to_string(),
+                ));
+            }
+
+            let state = payload(query_status.get_res().as_succ())?.state;
+
+            use crate::models::instance_info;
+            if state == instance_info::State::Running {
+                log::warn!("[Machine::delete cannot stop the machine, killing...]");
+                #[cfg(feature = "tracing")]
+                tracing::warn!("machine still running, killing it");
+                self.stop_force().await?;
+            }
+
+            self.cids.lock().release();
+            self.uffd.lock().take();
+            Ok(())
+        }
+
+        /// Have `resource`, created by the user for this machine, deleted by
+        /// `delete_and_clean` if `network_clear` is set
+        pub fn clear_network_on_delete(&self, resource: NetworkResource) {
+            self.local.get_network().track(resource);
+        }
+
+        /// Delete the machine and do cleaning at the same time
+        pub async fn delete_and_clean(&self) -> RtckResult<()> {
+            self.delete().await?;
+            self.local.full_clean().await;
+            Ok(())
+        }
+
+        /// Create a snapshot. Diff snapshots are refused early unless the
+        /// machine tracks dirty pages.
+        #[cfg_attr(
+            feature = "tracing",
+            tracing::instrument(name = "machine.snapshot", skip_all, fields(vmid = %self.vmid()))
+        )]
+        pub async fn snapshot<P: AsRef<str>, Q: AsRef<str>>(
+            &self,
+            state_path: P,
+            mem_path: Q,
+            _type: SnapshotType,
+        ) -> RtckResult<()> {
+            if _type == SnapshotType::Diff {
+                self.check_diff_snapshot().await?;
+            }
+
+            let create_snapshot = events_async::CreateSnapshot::new(SnapshotCreateParams {
+                mem_file_path: mem_path.as_ref().to_string(),
+                snapshot_path: state_path.as_ref().to_string(),
+                snapshot_type: Some(_type),
+                version: None,
+            });
+
+            self.rtck.lock().await.execute(&create_snapshot).await?;
+            if create_snapshot.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to create snapshot",
+                    fault(create_snapshot.get_res().as_err()),
+                ));
+            }
+            Ok(())
+        }
+
+        /// Load a snapshot, with its memory in a file or served by a
+        /// userfaultfd handler, e.g. `SnapshotLoadParams::uffd(..).resume(true)`.
+        /// The files of the snapshot are checked first, see `load_snapshot_with`.
+        pub async fn load_snapshot(&self, params: SnapshotLoadParams) -> RtckResult<()> {
+            self.load_snapshot_with(params, false).await
+        }
+
+        /// Load a snapshot once its files are checked, unless `force`: they
+        /// must exist, and the memory file must not be truncated. If the
+        /// manifest of `SnapshotBundle` lies next to the state file, the memory
+        /// file must hold the memory of the machine, and the snapshot must
+        /// have been taken by the same minor version of firecracker.
+        pub async fn load_snapshot_with(
+            &self,
+            params: SnapshotLoadParams,
+            force: bool,
+        ) -> RtckResult<()> {
+            check_snapshot_load(&params, self.local.get_jail_path())?;
+            if !force {
+                if let Some(bundle) = check_snapshot_files(&params, self.local.get_jail_path())? {
+                    bundle
+                        .check_version(&self.firecracker_version().await?)
+                        .map_err(snapshot_version_error)?;
+                }
+            }
+
+            let load_snapshot = events_async::LoadSnapshot::new(params);
+            self.rtck.lock().await.execute(&load_snapshot).await?;
+            if load_snapshot.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to load snapshot",
+                    fault(load_snapshot.get_res().as_err()),
+                ));
+            }
+            Ok(())
+        }
+
+        /// Load a snapshot with its memory served lazily from `mem_path` by a
+        /// userfaultfd handler, which lives as long as the machine
+        pub async fn load_snapshot_uffd<T: AsRef<str>, P: AsRef<Path>>(
+            &self,
+            state_path: T,
+            mem_path: P,
+        ) -> RtckResult<()> {
+            let (handler, uds_path) = serve_uffd(
+                &self.config,
+                self.local.get_socket_path(),
+                self.local.get_jail_path(),
+                mem_path.as_ref(),
+            )?;
+            self.load_snapshot(SnapshotLoadParams::uffd(state_path.as_ref(), uds_path))
+                .await?;
+            *self.uffd.lock() = Some(handler);
+            Ok(())
+        }
+
+        /// Take the next snapshot of the chain kept in `base_dir`, a full one
+        /// first and diffs afterwards, and record it in the chain manifest
+        pub async fn snapshot_diff<P: AsRef<Path>>(
+            &self,
+            base_dir: P,
+        ) -> RtckResult<SnapshotFiles> {
+            self.check_diff_snapshot().await?;
+
+            let base_dir = base_dir.as_ref();
+            let mut chain = SnapshotChain::load_async(base_dir).await?;
+            let files = chain.next_files(base_dir);
+            self.snapshot(
+                files.snapshot_path.to_string_lossy(),
+                files.mem_file_path.to_string_lossy(),
+                files.snapshot_type,
+            )
+            .await?;
+            chain.snapshots.push(files.clone());
+            chain.save_async(base_dir).await?;
+            Ok(files)
+        }
+
+        /// Take a full snapshot into `dir`, seen by firecracker, along with
+        /// the manifest of `SnapshotBundle`. The machine is paused meanwhile,
+        /// and a machine failing to resume once the snapshot is written is
+        /// reported with a `MachineError`.
+        pub async fn checkpoint<P: AsRef<Path>>(&self, dir: P) -> RtckResult<SnapshotBundle> {
+            let dir = dir.as_ref();
+            let bundle = SnapshotBundle {
+                firecracker_version: self.firecracker_version().await?,
+                snapshot_type: SnapshotType::Full,
+                vm_config: self.export_vm_config().await?,
+            };
+            let (state_path, mem_path) = SnapshotBundle::files(dir);
+
+            self.pause().await?;
+            let mut written = self
+                .snapshot(
+                    state_path.to_string_lossy(),
+                    mem_path.to_string_lossy(),
+                    bundle.snapshot_type,
+                )
+                .await;
+            if written.is_ok() {
+                written = bundle
+                    .save_async(host_path(self.local.get_jail_path(), dir))
+                    .await;
+            }
+            match (written, self.resume().await) {
+                (Ok(()), Ok(())) => Ok(bundle),
+                (Ok(()), Err(e)) => Err(resume_error(dir, e)),
+                (Err(e), resumed) => {
+                    if let Err(resume_e) = resumed {
+                        log::error!("[Machine::checkpoint fail to resume, {resume_e}]");
+                    }
+                    Err(e)
+                }
+            }
+        }
+
+        /// Take a snapshot of `snapshot_type` into `dir` with the machine
+        /// paused, a full one along with the manifest of `SnapshotBundle`,
+        /// a diff added to the chain of `dir`
+        async fn capture(&self, dir: &Path, snapshot_type: SnapshotType) -> RtckResult<()> {
+            create_snapshot_dir(&self.config, self.local.get_jail_path(), dir)?;
+            if snapshot_type == SnapshotType::Full {
+                return self.checkpoint(dir).await.map(|_| ());
+            }
+            self.pause().await?;
+            let written = self.snapshot_diff(dir).await;
+            match (written, self.resume().await) {
+                (Ok(_), Ok(())) => Ok(()),
+                (Ok(_), Err(e)) => Err(resume_error(dir, e)),
+                (Err(e), resumed) => {
+                    if let Err(resume_e) = resumed {
+                        log::error!("[Machine::capture fail to resume, {resume_e}]");
+                    }
+                    Err(e)
+                }
+            }
+        }
+
+        /// Restore the snapshot taken by `checkpoint` into `dir` and resume
+        /// the machine, which must not be configured. The snapshot must have
+        /// been taken by the same minor version of firecracker.
+        pub async fn restore_bundle<P: AsRef<Path>>(&self, dir: P) -> RtckResult<SnapshotBundle> {
+            let dir = dir.as_ref();
+            let bundle =
+                SnapshotBundle::load_async(host_path(self.local.get_jail_path(), dir)).await?;
+            let (state_path, mem_path) = SnapshotBundle::files(dir);
+            self.load_snapshot(
+                SnapshotLoadParams::file(state_path.to_string_lossy(), mem_path.to_string_lossy())
+                    .resume(true),
+            )
+            .await?;
+            Ok(bundle)
+        }
+
+        /// Clone the machine into a new one created from `config`, with its
+        /// own socket and vmid. The machine is checkpointed with a full
+        /// snapshot and resumes, and the clone is loaded from the snapshot
+        /// and resumed. `overrides` must give a tap device to every network
+        /// interface, which requires firecracker 1.12 or later.
+        pub async fn clone_to(
+            &self,
+            config: &GlobalConfig,
+            overrides: &CloneOverrides,
+        ) -> RtckResult<Machine<tokio::io::BufStream<tokio::net::UnixStream>>> {
+            check_clone_config(config)?;
+            let network_overrides = overrides.network_overrides(
+                self.export_vm_config()
+                    .await?
+                    .network_interfaces
+                    .as_deref()
+                    .unwrap_or_default(),
+            )?;
+            let (host_dir, dir) =
+                clone_snapshot_dir(&self.config, self.local.get_jail_path(), overrides)?;
+            let bundle = self.checkpoint(&dir).await?;
+
+            let clone = Machine::create(config).await?;
+            let loaded = clone
+                .load_clone(&bundle, &host_dir, network_overrides)
+                .await;
+            if overrides.snapshot_dir.is_none() {
+                let _ = tokio::fs::remove_dir_all(&host_dir).await;
+            }
+            if let Err(e) = loaded {
+                let _ = clone.stop_force().await;
+                clone.local.full_clean().await;
+                return Err(e);
+            }
+            Ok(clone)
+        }
+
+        /// Load the snapshot of `bundle` kept in `host_dir` into the machine,
+        /// attaching its network interfaces to other tap devices
+        async fn load_clone(
+            &self,
+            bundle: &SnapshotBundle,
+            host_dir: &Path,
+            network_overrides: Vec<NetworkOverride>,
+        ) -> RtckResult<()> {
+            bundle
+                .check_version(&self.firecracker_version().await?)
+                .map_err(snapshot_version_error)?;
+            let (state_path, mem_path) = SnapshotBundle::files(host_dir);
+            let jail_path = self.local.get_jail_path();
+            let state_path = link_into_jail(&self.config, jail_path, &state_path)?;
+            let mem_path = link_into_jail(&self.config, jail_path, &mem_path)?;
+            let mut params = SnapshotLoadParams::file(state_path, mem_path).resume(true);
+            if !network_overrides.is_empty() {
+                params = params.network_overrides(network_overrides);
+            }
+            self.load_snapshot(params).await
+        }
+
+        /// Check the machine tracks dirty pages, with the configuration used
+        /// to create it or the one reported by firecracker
+        async fn check_diff_snapshot(&self) -> RtckResult<()> {
+            let cached = self
+                .config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.machine_config.clone());
+            let machine_config = match cached {
+                Some(machine_config) => Some(machine_config),
+                None => self.export_vm_config().await?.machine_config,
+            };
+            check_dirty_pages(machine_config.as_ref())
+        }
+
+        /// Throttle a drive after boot. Only the rate limiter is sent, the
+        /// backing file of the drive is left untouched. The drive must be
+        /// one the machine was configured with.
+        pub async fn update_guest_drive_rate_limiter<S1: AsRef<str>>(
+            &self,
+            drive_id: S1,
+            rate_limiter: RateLimiter,
+        ) -> RtckResult<()> {
+            let partial_drive = PartialDrive::builder()
+                .drive_id(drive_id.as_ref())
+                .rate_limiter(rate_limiter)
+                .build()?;
+            check_drive_exists(&self.config, &partial_drive.drive_id)?;
+            let patch_drive = events_async::PatchGuestDriveById::new(partial_drive);
+            self.rtck.lock().await.execute(&patch_drive).await?;
+            if patch_drive.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to update drive rate limiter",
+                    fault(patch_drive.get_res().as_err()),
+                ));
+            }
+            Ok(())
+        }
+
+        /// Set the backing file of a drive after boot, e.g. of a placeholder
+        /// declared in the configuration. Firecracker cannot add drives after
+        /// boot, so `drive_id` must be one the machine was started with.
+        /// Under jailer the file is linked into the jail first.
+        pub async fn activate_drive<S1: AsRef<str>, P: AsRef<Path>>(
+            &self,
+            drive_id: S1,
+            path_on_host: P,
+        ) -> RtckResult<()> {
+            let drive_id = drive_id.as_ref();
+            let cached = self
+                .config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.drives.clone());
+            let drives = match cached {
+                Some(drives) => drives,
+                None => self.export_vm_config().await?.drives.unwrap_or_default(),
+            };
+            check_drive_declared(&drives, drive_id)?;
+
+            let path_on_host = link_into_jail(
+                &self.config,
+                self.local.get_jail_path(),
+                path_on_host.as_ref(),
+            )?;
+            let partial_drive = PartialDrive::builder()
+                .drive_id(drive_id)
+                .path_on_host(path_on_host)
+                .build()?;
+            let patch_drive = events_async::PatchGuestDriveById::new(partial_drive);
+            self.rtck.lock().await.execute(&patch_drive).await?;
+            if patch_drive.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to activate drive",
+                    fault(patch_drive.get_res().as_err()),
+                ));
+            }
+            Ok(())
+        }
+
+        /// Get the full configuration currently applied to the machine
+        pub async fn export_vm_config(&self) -> RtckResult<FullVmConfiguration> {
+            let get_export_vm_config = events_async::GetExportVmConfig::new();
+            self.rtck
+                .lock()
+                .await
+                .execute(&get_export_vm_config)
+                .await?;
+            if get_export_vm_config.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to export vm config",
+                    fault(get_export_vm_config.get_res().as_err()),
+                ));
+            }
+            let config = payload(get_export_vm_config.get_res().as_succ())?;
+            Ok(config)
+        }
+
+        /// Get the vsock device effectively configured on the machine,
+        /// `None` if there is no vsock device.
+        pub async fn vsock_config(&self) -> RtckResult<Option<Vsock>> {
+            Ok(self.export_vm_config().await?.vsock)
+        }
+
+        /// Get the configuration of the balloon device
+        pub async fn describe_balloon_config(&self) -> RtckResult<Balloon> {
+            let describe_balloon_config = events_async::DescribeBalloonConfig::new();
+            self.rtck
+                .lock()
+                .await
+                .execute(&describe_balloon_config)
+                .await?;
+            if describe_balloon_config.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to describe balloon config",
+                    fault(describe_balloon_config.get_res().as_err()),
+                ));
+            }
+            let balloon = payload(describe_balloon_config.get_res().as_succ())?;
+            Ok(balloon)
+        }
+
+        /// Change whether the balloon deflates under guest memory pressure,
+        /// keeping its current size. Fails with a `RemoteError` on
+        /// firecracker versions only accepting it before boot.
+        pub async fn set_balloon_deflate_on_oom(&self, enabled: bool) -> RtckResult<()> {
+            let balloon = self.describe_balloon_config().await?;
+            let patch_balloon = events_async::PatchBalloon::new(BalloonUpdate {
+                amount_mib: balloon.amount_mib,
+                deflate_on_oom: Some(enabled),
+            });
+            self.rtck.lock().await.execute(&patch_balloon).await?;
+            if patch_balloon.is_err()? {
+                return Err(deflate_on_oom_error(fault(
+                    patch_balloon.get_res().as_err(),
+                )));
+            }
+            Ok(())
+        }
+
+        /// Inflate or deflate the balloon to hold `amount_mib` MiB
+        pub async fn resize_balloon(&self, amount_mib: i64) -> RtckResult<()> {
+            let patch_balloon = events_async::PatchBalloon::new(BalloonUpdate {
+                amount_mib,
+                deflate_on_oom: None,
+            });
+            self.rtck.lock().await.execute(&patch_balloon).await?;
+            if patch_balloon.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to resize balloon",
+                    fault(patch_balloon.get_res().as_err()),
+                ));
+            }
+            Ok(())
+        }
+
+        /// Get the latest statistics of the balloon device, refreshed by
+        /// firecracker every `stats_polling_interval_s`
+        pub async fn describe_balloon_stats(&self) -> RtckResult<BalloonStatistics> {
+            check_balloon_stats(&self.config)?;
+            let describe_balloon_stats = events_async::DescribeBalloonStats::new();
+            self.rtck
+                .lock()
+                .await
+                .execute(&describe_balloon_stats)
+                .await?;
+            if describe_balloon_stats.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to describe balloon stats",
+                    fault(describe_balloon_stats.get_res().as_err()),
+                ));
+            }
+            let stats = payload(describe_balloon_stats.get_res().as_succ())?;
+            Ok(stats)
+        }
+
+        /// Register the metrics of the machine in `registry`, labelled with
+        /// its vmid, see `export_metrics`
+        #[cfg(feature = "prometheus")]
+        pub fn register_metrics(
+            &self,
+            registry: &prometheus::Registry,
+        ) -> RtckResult<MachineMetrics> {
+            MachineMetrics::register(registry, self.vmid())
+        }
+
+        /// How long the guest took to boot, as logged by firecracker with
+        /// `boot_timer`. `None` until the guest signals it booted, which its
+        /// init has to do, see `parse_boot_time`.
+        pub fn boot_time(&self) -> RtckResult<Option<Duration>> {
+            let boot_time = boot_time(&self.config, self.local.get_log_path())?;
+            if boot_time.is_none() && self.child.lock().exited()? {
+                return Err(boot_timer_error());
+            }
+            Ok(boot_time)
+        }
+
+        /// `boot_time` in milliseconds
+        pub fn boot_time_ms(&self) -> RtckResult<Option<u64>> {
+            Ok(self
+                .boot_time()?
+                .map(|boot_time| boot_time.as_millis() as u64))
+        }
+
+        /// Feed `metrics` with the flushes in the metrics file and the
+        /// balloon statistics, if enabled. Firecracker flushes its metrics
+        /// every minute, or when asked with a `FlushMetrics` action.
+        #[cfg(feature = "prometheus")]
+        pub async fn export_metrics(&self, metrics: &MachineMetrics) -> RtckResult<()> {
+            for flush in metrics_flushes(self.local.get_metrics_path())? {
+                metrics.observe(&flush)?;
+            }
+            if balloon_stats_enabled(&self.config) {
+                metrics.observe_balloon(&self.describe_balloon_stats().await?);
+            }
             Ok(())
         }
+
+        /// Poll the balloon statistics every `interval`, never more often
+        /// than firecracker refreshes them with the configured balloon.
+        /// The stream ends once the machine exited or after the first error.
+        pub fn balloon_stats_stream(
+            &self,
+            interval: tokio::time::Duration,
+        ) -> impl Stream<Item = RtckResult<BalloonStatistics>> + '_ {
+            let refresh = self
+                .config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.balloon.as_ref())
+                .and_then(Balloon::stats_polling_interval)
+                .unwrap_or_default();
+            let interval = interval.max(refresh);
+            async_stream::stream! {
+                if interval.is_zero() {
+                    yield Err(RtckError::new(
+                        RtckErrorClass::ConfigError,
+                        "Balloon stats interval must be non-zero",
+                    ));
+                    return;
+                }
+                let mut ticker = tokio::time::interval(interval);
+                ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    ticker.tick().await;
+                    let exited = self.child.lock().exited();
+                    match exited {
+                        Ok(false) => (),
+                        Ok(true) => break,
+                        Err(e) => {
+                            yield Err(e);
+                            break;
+                        }
+                    }
+                    let stats = self.describe_balloon_stats().await;
+                    let failed = stats.is_err();
+                    yield stats;
+                    if failed {
+                        break;
+                    }
+                }
+            }
+        }
+
+        /// Get information about the machine, including its state
+        pub async fn describe_instance(&self) -> RtckResult<InstanceInfo> {
+            let describe_instance = events_async::DescribeInstance::new();
+            self.rtck.lock().await.execute(&describe_instance).await?;
+            if describe_instance.is_err()? {
+                return Err(RtckError::remote(
+                    "Fail to describe instance",
+                    fault(describe_instance.get_res().as_err()),
+                ));
+            }
+            let info = payload(describe_instance.get_res().as_succ())?;
+            Ok(info)
+        }
+
+        /// Wait until the machine reports `Running`, polling every `poll_status_secs`
+        pub async fn wait_until_running(&self, timeout: tokio::time::Duration) -> RtckResult<()> {
+            let deadline = tokio::time::Instant::now() + timeout;
+            let interval = self.config.poll_status_interval();
+            loop {
+                if self.describe_instance().await?.state == InstanceState::Running {
+                    return Ok(());
+                }
+                let now = tokio::time::Instant::now();
+                if now >= deadline {
+                    return Err(RtckError::new(
+                        RtckErrorClass::MachineError,
+                        "Timeout waiting for the machine to run",
+                    ));
+                }
+                tokio::time::sleep(interval.min(deadline - now)).await;
+            }
+        }
+
+        /// Send a request to an endpoint not wrapped by rustcracker.
+        /// `path` must start with `/`, e.g. `/version`.
+        pub async fn raw_request(
+            &self,
+            method: HttpMethod,
+            path: &str,
+            body: Option<serde_json::Value>,
+        ) -> RtckResult<(u16, serde_json::Value)> {
+            self.rtck.lock().await.raw_request(method, path, body).await
+        }
+    }
+
+    impl<S: AsyncBufRead + AsyncWrite + Unpin + Send + Sync + 'static> Machine<S> {
+        /// Spawn a task taking a snapshot of `snapshot_type` every `interval`
+        /// into a directory of its own under `dir`, seen by firecracker. The
+        /// `keep` latest full snapshots are kept, diffs are chained by `keep`
+        /// starting from a full snapshot, the previous chain removed once the
+        /// next one starts. Captures are skipped while the machine is not
+        /// running, e.g. paused by the user, and failures are reported by the
+        /// handle without ending the schedule. The schedule ends once
+        /// cancelled, by the handle or by `delete`, or once the machine exits.
+        pub fn schedule_snapshots(
+            self: &Arc<Self>,
+            interval: Duration,
+            dir: PathBuf,
+            keep: usize,
+            snapshot_type: SnapshotType,
+        ) -> RtckResult<SnapshotScheduleHandle> {
+            if interval.is_zero() || keep == 0 {
+                return Err(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "Snapshot schedule needs a non-zero interval and keep",
+                ));
+            }
+            let machine = self.clone();
+            Ok(TaskHandle::spawn(
+                self.tasks.child_token(),
+                |cancel, status| async move {
+                    machine
+                        .run_schedule(interval, dir, keep, snapshot_type, cancel, status)
+                        .await
+                },
+            ))
+        }
+
+        /// Spawn a task keeping the free memory of the guest within the band
+        /// of `policy`, resizing the balloon after its statistics every
+        /// `policy.interval`, see `BalloonPolicy::decide`. Failing resizings
+        /// are retried less and less often. The task ends once cancelled, by
+        /// the handle or by `delete`, or once the machine stops running, e.g.
+        /// paused or exited.
+        pub fn start_balloon_autoscaler(
+            self: &Arc<Self>,
+            policy: BalloonPolicy,
+        ) -> RtckResult<BalloonAutoscalerHandle> {
+            policy.validate()?;
+            check_balloon_stats(&self.config)?;
+            let machine = self.clone();
+            Ok(TaskHandle::spawn(
+                self.tasks.child_token(),
+                |cancel, status| async move { machine.run_autoscaler(policy, cancel, status).await },
+            ))
+        }
+
+        async fn run_autoscaler(
+            &self,
+            policy: BalloonPolicy,
+            cancel: CancellationToken,
+            status: Arc<Mutex<BalloonAutoscalerStatus>>,
+        ) {
+            let failed = |e: RtckError| {
+                log::error!("[Machine::start_balloon_autoscaler failed, {e}]");
+                status.lock().last_error = Some(e);
+            };
+            let refresh = self
+                .config
+                .frck_config
+                .as_ref()
+                .and_then(|frck_config| frck_config.balloon.as_ref())
+                .and_then(Balloon::stats_polling_interval)
+                .unwrap_or_default();
+            let interval = policy.interval.max(refresh);
+            let mut ticker =
+                tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            // Ticks skipped to back off after failed resizings
+            let mut skipped = 0;
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => return,
+                    _ = ticker.tick() => (),
+                }
+                if skipped > 0 {
+                    skipped -= 1;
+                    continue;
+                }
+                let exited = self.child.lock().exited();
+                match exited {
+                    Ok(false) => (),
+                    Ok(true) => return,
+                    Err(e) => return failed(e),
+                }
+                match self.describe_instance().await {
+                    Ok(info) if info.state == InstanceState::Running => (),
+                    Ok(_) => return,
+                    Err(e) => {
+                        failed(e);
+                        continue;
+                    }
+                }
+
+                let decision = match self.describe_balloon_stats().await {
+                    Ok(stats) => policy.decide(&stats),
+                    Err(e) => Err(e),
+                };
+                let decision = match decision {
+                    Ok(decision) => decision,
+                    Err(e) => {
+                        failed(e);
+                        continue;
+                    }
+                };
+                status.lock().last_decision = Some(decision);
+                if !decision.resizes() {
+                    continue;
+                }
+                let amount_mib = decision.to_mib.try_into().unwrap_or(i64::MAX);
+                match self.resize_balloon(amount_mib).await {
+                    Ok(()) => status.lock().failures = 0,
+                    Err(e) => {
+                        let failures = {
+                            let mut status = status.lock();
+                            status.failures += 1;
+                            status.failures
+                        };
+                        skipped = (1 << failures.min(5)) - 1;
+                        failed(e);
+                    }
+                }
+            }
+        }
+
+        async fn run_schedule(
+            &self,
+            interval: Duration,
+            dir: PathBuf,
+            keep: usize,
+            snapshot_type: SnapshotType,
+            cancel: CancellationToken,
+            status: Arc<Mutex<SnapshotScheduleStatus>>,
+        ) {
+            let failed = |e: RtckError| {
+                log::error!("[Machine::schedule_snapshots capture failed, {e}]");
+                status.lock().last_error = Some(e);
+            };
+            let mut ticker =
+                tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            let mut rotation = SnapshotRotation::default();
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => return,
+                    _ = ticker.tick() => (),
+                }
+                let exited = self.child.lock().exited();
+                match exited {
+                    Ok(false) => (),
+                    Ok(true) => return,
+                    Err(e) => return failed(e),
+                }
+                match self.describe_instance().await {
+                    Ok(info) if info.state == InstanceState::Running => (),
+                    // Paused by the user, or not started yet
+                    Ok(_) => continue,
+                    Err(e) => {
+                        failed(e);
+                        continue;
+                    }
+                }
+
+                let next_dir = rotation.next_dir(&dir, keep, snapshot_type);
+                if let Err(e) = self.capture(&next_dir, snapshot_type).await {
+                    failed(e);
+                    continue;
+                }
+                {
+                    let mut status = status.lock();
+                    status.taken += 1;
+                    status.last_snapshot = Some((SystemTime::now(), next_dir.clone()));
+                }
+                let jail_path = self.local.get_jail_path();
+                if let Err(e) = rotation.taken(next_dir, keep, snapshot_type, jail_path) {
+                    failed(e);
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use tokio::io::{AsyncWriteExt, BufStream, DuplexStream};
+
+        use crate::{
+            config::GlobalConfig, firecracker::firecracker_async::FirecrackerAsync,
+            local::local_async::LocalAsync, rtck_async::RtckAsync,
+        };
+
+        use super::{
+            vm, CancellationToken, CidClaim, HttpMethod, Machine, MachineCore, Mutex, Process,
+        };
+
+        /// Build a machine talking to a mocked firecracker which
+        /// answers with `responses` in order.
+        pub(crate) async fn mock_machine(responses: &[String]) -> Machine<BufStream<DuplexStream>> {
+            let config = GlobalConfig {
+                using_jailer: Some(false),
+                frck_bin: Some("/bin/sleep".into()),
+                socket_path: Some(format!("/tmp/rtck-mock-{}.sock", uuid::Uuid::new_v4())),
+                ..Default::default()
+            };
+            let frck = FirecrackerAsync::from_config(&config).unwrap();
+            let local = LocalAsync::from_frck(&frck, &config).unwrap();
+            let (client, mut server) = tokio::io::duplex(1 << 16);
+            server
+                .write_all(responses.concat().as_bytes())
+                .await
+                .unwrap();
+            // Keep the remote end open for as long as the machine lives
+            tokio::spawn(async move {
+                let mut sink = tokio::io::sink();
+                let _ = tokio::io::copy(&mut server, &mut sink).await;
+            });
+            Machine {
+                rtck: tokio::sync::Mutex::new(RtckAsync::from_stream(BufStream::new(client))),
+                local,
+                jailer: None,
+                frck,
+                config,
+                child: Mutex::new(Process::Spawned(
+                    tokio::process::Command::new("sleep")
+                        .arg("10")
+                        .kill_on_drop(true)
+                        .spawn()
+                        .unwrap(),
+                )),
+                cids: Mutex::new(CidClaim::empty()),
+                uffd: Mutex::new(None),
+                tasks: CancellationToken::new(),
+            }
+        }
+
+        #[tokio::test]
+        async fn test_wait_until_running() {
+            use crate::machine::machine::test::instance_info;
+
+            let mut machine = mock_machine(&[
+                instance_info("Not started"),
+                instance_info("Not started"),
+                instance_info("Running"),
+            ])
+            .await;
+            machine.config.poll_status_secs = Some(0);
+            machine
+                .wait_until_running(tokio::time::Duration::from_secs(1))
+                .await
+                .unwrap();
+
+            let mut machine =
+                mock_machine(&[instance_info("Paused"), instance_info("Paused")]).await;
+            machine.config.poll_status_secs = Some(1);
+            assert!(machine
+                .wait_until_running(tokio::time::Duration::from_millis(10))
+                .await
+                .is_err());
+        }
+
+        #[tokio::test]
+        async fn test_set_vm_state() {
+            use crate::machine::machine::test::{instance_info, response};
+
+            let machine = mock_machine(&[
+                instance_info("Running"),
+                response(204, ""),
+                instance_info("Paused"),
+                response(204, ""),
+                instance_info("Paused"),
+            ])
+            .await;
+            machine.pause().await.unwrap();
+            machine.resume().await.unwrap();
+            // Already paused
+            assert!(machine.set_vm_state(vm::State::Paused).await.is_err());
+        }
+
+        #[tokio::test]
+        async fn test_balloon_stats_stream() {
+            use std::pin::Pin;
+
+            use futures_core::Stream;
+
+            use crate::machine::machine::test::response;
+
+            async fn next<S: Stream>(stream: &mut Pin<&mut S>) -> Option<S::Item> {
+                std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await
+            }
+
+            let stats = |pages: u64| {
+                response(
+                    200,
+                    &format!(
+                        r#"{{"target_pages":16,"actual_pages":{pages},"target_mib":0,"actual_mib":0}}"#
+                    ),
+                )
+            };
+            let machine = mock_machine(&[
+                stats(1),
+                stats(2),
+                stats(3),
+                response(400, r#"{"fault_message":"Statistics are not enabled"}"#),
+            ])
+            .await;
+            let stream = machine.balloon_stats_stream(tokio::time::Duration::from_millis(10));
+            tokio::pin!(stream);
+            for pages in 1..=3 {
+                assert_eq!(
+                    next(&mut stream).await.unwrap().unwrap().actual_pages,
+                    pages
+                );
+            }
+            // Ends after the first error
+            assert!(next(&mut stream).await.unwrap().is_err());
+            assert!(next(&mut stream).await.is_none());
+
+            // Ends once the machine exited
+            let machine = mock_machine(&[stats(1)]).await;
+            machine
+                .child
+                .lock()
+                .child_mut()
+                .unwrap()
+                .start_kill()
+                .unwrap();
+            while !machine.child.lock().exited().unwrap() {
+                tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
+            }
+            let stream = machine.balloon_stats_stream(tokio::time::Duration::from_millis(10));
+            tokio::pin!(stream);
+            assert!(next(&mut stream).await.is_none());
+        }
+
+        #[tokio::test]
+        async fn test_start_with_deadline() {
+            use crate::machine::machine::test::response;
+
+            // Firecracker never answers the status query
+            let mut machine = mock_machine(&[response(204, "")]).await;
+            machine.config.frck_config = Some(Default::default());
+            let socket_path = machine.local.get_socket_path().clone();
+            std::fs::write(&socket_path, "").unwrap();
+            let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_millis(50);
+            let e = machine
+                .start_with_deadline(deadline, None)
+                .await
+                .unwrap_err();
+            assert!(e.to_string().contains("start timed out"));
+            assert!(machine
+                .child
+                .lock()
+                .child_mut()
+                .unwrap()
+                .try_wait()
+                .unwrap()
+                .is_some());
+            assert!(!socket_path.exists());
+
+            let mut machine = mock_machine(&[response(204, "")]).await;
+            machine.config.frck_config = Some(Default::default());
+            let cancel = CancellationToken::new();
+            let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(10);
+            let (res, _) = tokio::join!(
+                machine.start_with_deadline(deadline, Some(&cancel)),
+                async {
+                    tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+                    cancel.cancel();
+                }
+            );
+            assert!(res.unwrap_err().to_string().contains("start cancelled"));
+        }
+
+        #[tokio::test]
+        async fn test_vsock_send() {
+            use crate::machine::machine::test::{run_vsock_echo, with_vsock};
+
+            let mut machine = mock_machine(&[]).await;
+            let uds_path =
+                std::env::temp_dir().join(format!("rtck-vsock-{}.sock", uuid::Uuid::new_v4()));
+            with_vsock(&mut machine.config, &uds_path);
+            machine.config.vsock_control_port = Some(1024);
+
+            let guest = run_vsock_echo(&uds_path, 1024);
+            machine.notify_resume_time().await.unwrap();
+            let message = tokio::task::spawn_blocking(move || guest.join().unwrap())
+                .await
+                .unwrap();
+            assert!(message.starts_with(b"CLOCK_RESYNC "));
+            std::fs::remove_file(uds_path).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_probe_vsock() {
+            use crate::machine::machine::test::{run_vsock_listener, with_vsock};
+
+            let mut machine = mock_machine(&[]).await;
+            let uds_path =
+                std::env::temp_dir().join(format!("rtck-vsock-{}.sock", uuid::Uuid::new_v4()));
+            with_vsock(&mut machine.config, &uds_path);
+            let listener = run_vsock_listener(&uds_path, 52, 2);
+            let timeout = tokio::time::Duration::from_secs(1);
+            machine.probe_vsock(52, timeout).await.unwrap();
+            assert!(machine.probe_vsock(53, timeout).await.is_err());
+            tokio::task::spawn_blocking(move || listener.join().unwrap())
+                .await
+                .unwrap();
+            std::fs::remove_file(uds_path).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_raw_request() {
+            use crate::machine::machine::test::response;
+
+            let machine =
+                mock_machine(&[response(200, r#"{"firecracker_version":"1.7.0"}"#)]).await;
+            let (status, body) = machine
+                .raw_request(HttpMethod::GET, "/version", None)
+                .await
+                .unwrap();
+            assert_eq!(status, 200);
+            assert_eq!(body, serde_json::json!({"firecracker_version": "1.7.0"}));
+            assert!(machine
+                .raw_request(HttpMethod::GET, "version", None)
+                .await
+                .is_err());
+        }
+
+        #[tokio::test]
+        async fn test_update_guest_drive_rate_limiter() {
+            use crate::machine::machine::test::response;
+            use crate::models::{rate_limiter::RateLimiter, token_bucket::TokenBucket};
+
+            let machine = mock_machine(&[
+                response(204, ""),
+                response(400, r#"{"fault_message":"Drive not found"}"#),
+            ])
+            .await;
+            let bucket = TokenBucket {
+                one_time_burst: None,
+                refill_time: 100,
+                size: 100,
+            };
+            let rate_limiter = RateLimiter::new(bucket.clone(), bucket);
+            machine
+                .update_guest_drive_rate_limiter("rootfs", rate_limiter.clone())
+                .await
+                .unwrap();
+            assert!(machine
+                .update_guest_drive_rate_limiter("scratch", rate_limiter)
+                .await
+                .is_err());
+        }
+
+        #[tokio::test]
+        async fn test_from_existing() {
+            use crate::machine::machine::test::serve_version;
+
+            let socket_path =
+                std::env::temp_dir().join(format!("rtck-adopt-{}.sock", uuid::Uuid::new_v4()));
+            let server = serve_version(&socket_path);
+            let mut process = std::process::Command::new("sleep")
+                .arg("10")
+                .spawn()
+                .unwrap();
+            let pid = process.id();
+
+            let machine = Machine::from_existing(socket_path.clone(), pid)
+                .await
+                .unwrap();
+            server.join().unwrap();
+            machine.stop_force().await.unwrap();
+            machine.wait().await.unwrap();
+            assert!(!super::super::pid_alive(pid));
+            // Already reaped by the machine
+            assert!(process.try_wait().is_err());
+
+            assert!(Machine::from_existing(socket_path.clone(), pid)
+                .await
+                .is_err());
+            std::fs::remove_file(socket_path).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_dump_restore_core() {
+            use crate::machine::machine::test::serve_version;
+
+            let socket_path =
+                std::env::temp_dir().join(format!("rtck-core-{}.sock", uuid::Uuid::new_v4()));
+            let server = serve_version(&socket_path);
+            let mut process = std::process::Command::new("sleep")
+                .arg("10")
+                .spawn()
+                .unwrap();
+            let core = MachineCore {
+                id: None,
+                pid: process.id(),
+                socket_path: socket_path.clone(),
+                jailer_working_dir: None,
+                uid_gid: None,
+                clear_jailer: false,
+            };
+
+            let machine = Machine::restore_core(core.clone()).await.unwrap();
+            server.join().unwrap();
+            assert_eq!(machine.dump_core().unwrap(), core);
+
+            machine.stop_force().await.unwrap();
+            machine.wait().await.unwrap();
+            assert!(process.try_wait().is_err());
+            std::fs::remove_file(socket_path).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_checkpoint() {
+            use crate::{
+                machine::machine::test::{instance_info, response},
+                machine::SnapshotBundle,
+            };
+
+            let dir =
+                std::env::temp_dir().join(format!("rtck-checkpoint-{}", uuid::Uuid::new_v4()));
+            tokio::fs::create_dir(&dir).await.unwrap();
+            let machine = mock_machine(&[
+                response(200, r#"{"firecracker_version":"1.7.0"}"#),
+                response(200, r#"{"boot-source":{"kernel_image_path":"vmlinux"}}"#),
+                instance_info("Running"),
+                response(204, ""),
+                response(204, ""),
+                instance_info("Paused"),
+                response(204, ""),
+            ])
+            .await;
+            let bundle = machine.checkpoint(&dir).await.unwrap();
+            assert_eq!(SnapshotBundle::load_async(&dir).await.unwrap(), bundle);
+
+            tokio::fs::remove_dir_all(dir).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_schedule_snapshots() {
+            use std::{sync::Arc, time::Duration};
+
+            use crate::{
+                machine::machine::test::{instance_info, response},
+                machine::SnapshotBundle,
+                models::snapshot_create_params::SnapshotType,
+            };
+
+            let checkpoint = [
+                instance_info("Running"),
+                response(200, r#"{"firecracker_version":"1.7.0"}"#),
+                response(200, r#"{"boot-source":{"kernel_image_path":"vmlinux"}}"#),
+                instance_info("Running"),
+                response(204, ""),
+                response(204, ""),
+                instance_info("Paused"),
+                response(204, ""),
+            ];
+            let mut responses = checkpoint.to_vec();
+            // Paused by the user meanwhile
+            responses.push(instance_info("Paused"));
+            responses.extend(checkpoint);
+            // Answers to `delete`
+            responses.extend([response(204, ""), instance_info("Paused")]);
+            let machine = Arc::new(mock_machine(&responses).await);
+            let dir = std::env::temp_dir().join(format!("rtck-schedule-{}", uuid::Uuid::new_v4()));
+
+            let interval = Duration::from_millis(20);
+            assert!(machine
+                .schedule_snapshots(interval, dir.clone(), 0, SnapshotType::Full)
+                .is_err());
+            let schedule = machine
+                .schedule_snapshots(interval, dir.clone(), 1, SnapshotType::Full)
+                .unwrap();
+            while schedule.status().taken < 2 {
+                tokio::time::sleep(interval).await;
+            }
+            let status = schedule.status();
+            assert!(status.last_error.is_none(), "{:?}", status.last_error);
+            schedule.stop().await;
+
+            // Rotated, only the last snapshot is kept
+            let (_, last_dir) = status.last_snapshot.unwrap();
+            let kept: Vec<_> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            assert_eq!(kept, vec![last_dir.clone()]);
+            assert!(last_dir.join(SnapshotBundle::MANIFEST).exists());
+
+            // Cancelled along with the machine
+            let schedule = machine
+                .schedule_snapshots(Duration::from_secs(60), dir.clone(), 1, SnapshotType::Full)
+                .unwrap();
+            machine.delete().await.unwrap();
+            tokio::time::timeout(Duration::from_secs(1), async {
+                while !schedule.is_finished() {
+                    tokio::task::yield_now().await;
+                }
+            })
+            .await
+            .unwrap();
+
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[cfg(feature = "tracing")]
+        #[tokio::test]
+        async fn test_tracing_spans() {
+            use std::{collections::BTreeMap, sync::Arc};
+
+            use tracing::{
+                field::{Field, Visit},
+                span, Event, Metadata, Subscriber,
+            };
+
+            use crate::{
+                machine::machine::test::{instance_info, response},
+                models::snapshot_create_params::SnapshotType,
+            };
+
+            type Spans = Arc<Mutex<Vec<(&'static str, BTreeMap<String, String>)>>>;
+
+            /// Subscriber keeping the spans created, with their fields
+            struct Recorder(Spans);
+
+            struct Fields<'a>(&'a mut BTreeMap<String, String>);
+
+            impl Visit for Fields<'_> {
+                fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                    self.0
+                        .insert(field.name().to_string(), format!("{value:?}"));
+                }
+
+                fn record_str(&mut self, field: &Field, value: &str) {
+                    self.0.insert(field.name().to_string(), value.to_string());
+                }
+            }
+
+            impl Subscriber for Recorder {
+                fn enabled(&self, _: &Metadata<'_>) -> bool {
+                    true
+                }
+                fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+                    let mut fields = BTreeMap::new();
+                    span.record(&mut Fields(&mut fields));
+                    let mut spans = self.0.lock();
+                    spans.push((span.metadata().name(), fields));
+                    span::Id::from_u64(spans.len() as u64)
+                }
+                fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+                    let mut spans = self.0.lock();
+                    values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+                }
+                fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+                fn event(&self, _: &Event<'_>) {}
+                fn enter(&self, _: &span::Id) {}
+                fn exit(&self, _: &span::Id) {}
+            }
+
+            let spans = Spans::default();
+            let _guard = tracing::subscriber::set_default(Recorder(spans.clone()));
+
+            let machine = mock_machine(&[
+                instance_info("Running"),
+                response(204, ""),
+                response(204, ""),
+            ])
+            .await;
+            machine.pause().await.unwrap();
+            machine
+                .snapshot("vm.state", "vm.mem", SnapshotType::Full)
+                .await
+                .unwrap();
+
+            let spans = spans.lock().clone();
+            let names: Vec<_> = spans.iter().map(|(name, _)| *name).collect();
+            assert_eq!(
+                names,
+                [
+                    "machine.pause",
+                    "agent.event",
+                    "agent.event",
+                    "machine.snapshot",
+                    "agent.event"
+                ]
+            );
+            assert!(spans[0].1.contains_key("vmid"));
+            let (_, request) = &spans[2];
+            assert_eq!(request["method"], "PATCH");
+            assert_eq!(request["endpoint"], "/vm");
+            assert!(request.contains_key("latency_us"));
+
+            machine.stop_force().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_balloon_autoscaler() {
+            use std::{sync::Arc, time::Duration};
+
+            use crate::{
+                machine::machine::test::{instance_info, response},
+                machine::BalloonPolicy,
+            };
+
+            let stats = |target_mib: u64, free_mib: u64| {
+                response(
+                    200,
+                    &format!(
+                        r#"{{"target_pages":{},"actual_pages":0,"target_mib":{target_mib},"actual_mib":0,"free_memory":{}}}"#,
+                        target_mib << 8,
+                        free_mib << 20
+                    ),
+                )
+            };
+            let machine = Arc::new(
+                mock_machine(&[
+                    instance_info("Running"),
+                    stats(64, 50),
+                    response(204, ""),
+                    instance_info("Running"),
+                    stats(32, 500),
+                    response(400, r#"{"fault_message":"busy"}"#),
+                    // One tick skipped after the failure, then paused
+                    instance_info("Paused"),
+                ])
+                .await,
+            );
+            let policy = BalloonPolicy {
+                min_free_mib: 100,
+                max_free_mib: 200,
+                step_mib: 32,
+                interval: Duration::from_millis(10),
+            };
+            assert!(machine
+                .start_balloon_autoscaler(BalloonPolicy {
+                    step_mib: 0,
+                    ..policy
+                })
+                .is_err());
+
+            let autoscaler = machine.start_balloon_autoscaler(policy).unwrap();
+            tokio::time::timeout(Duration::from_secs(1), async {
+                while !autoscaler.is_finished() {
+                    tokio::time::sleep(policy.interval).await;
+                }
+            })
+            .await
+            .unwrap();
+
+            let decision = autoscaler.last_decision().unwrap();
+            assert_eq!((decision.from_mib, decision.to_mib), (32, 64));
+            assert!(autoscaler.last_error().is_some());
+            assert_eq!(autoscaler.status().failures, 1);
+
+            machine.stop_force().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_snapshot_diff() {
+            use crate::{
+                config::FirecrackerConfig, machine::machine::test::response,
+                machine::SnapshotChain, models::machine_configuration::MachineConfiguration,
+            };
+
+            let mut machine = mock_machine(&[response(204, "")]).await;
+            let mut machine_config = MachineConfiguration::builder()
+                .vcpu_count(1)
+                .mem_size_mib(128)
+                .build()
+                .unwrap();
+            machine.config.frck_config = Some(FirecrackerConfig {
+                machine_config: Some(machine_config.clone()),
+                ..Default::default()
+            });
+            let dir = std::env::temp_dir().join(format!("rtck-snapshots-{}", uuid::Uuid::new_v4()));
+            tokio::fs::create_dir(&dir).await.unwrap();
+
+            // Refused before anything is sent
+            assert!(machine.snapshot_diff(&dir).await.is_err());
+
+            machine_config.track_dirty_pages = Some(true);
+            machine.config.frck_config.as_mut().unwrap().machine_config = Some(machine_config);
+            let base = machine.snapshot_diff(&dir).await.unwrap();
+            assert_eq!(
+                SnapshotChain::load_async(&dir).await.unwrap().snapshots,
+                vec![base]
+            );
+
+            tokio::fs::remove_dir_all(dir).await.unwrap();
+        }
     }
 }
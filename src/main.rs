@@ -1,6 +1,7 @@
 use std::path::Path;
 
 use rustcracker::{
+    config::DeploymentConfig,
     events::{
         events::{self, Event},
         events_async::{self, EventAsync},
@@ -23,7 +24,7 @@ fn main() {
     let mode = std::env::args()
         .skip(1)
         .next()
-        .expect("Need to specify sync/async/test");
+        .expect("Need to specify sync/async/test/--config");
     let socket = std::env::args()
         .skip(2)
         .next()
@@ -43,6 +44,16 @@ fn main() {
         "test" => {
             pressure_test(socket.parse::<usize>().expect("Fail to get write times"));
         }
+        "--config" => {
+            // The second argument is the deployment config file here
+            let config = DeploymentConfig::from_path(&socket)
+                .unwrap_or_else(|e| panic!("config error: {e}"))
+                .into_global_config();
+            match config.validate() {
+                Ok(()) => println!("Config {socket} is valid"),
+                Err(e) => eprintln!("Config {socket} is invalid: {e}"),
+            }
+        }
         _ => panic!("Need to specify sync/async"),
     }
 }
@@ -54,6 +65,7 @@ fn sync_main<P: AsRef<Path>>(socket: P) -> RtckResult<()> {
 
     let mut put_machine_config = events::PutMachineConfiguration::new(MachineConfiguration {
         cpu_template: None,
+        huge_pages: None,
         ht_enabled: None,
         mem_size_mib: 256,
         track_dirty_pages: None,
@@ -62,7 +74,7 @@ fn sync_main<P: AsRef<Path>>(socket: P) -> RtckResult<()> {
 
     rtck.execute(&mut put_machine_config)?;
 
-    if put_machine_config.is_succ() {
+    if put_machine_config.is_succ()? {
         println!("Put machine configuration succeeded");
     } else {
         eprintln!("Put machine configuration error")
@@ -72,7 +84,7 @@ fn sync_main<P: AsRef<Path>>(socket: P) -> RtckResult<()> {
 
     rtck.execute(&mut get_machine_config)?;
 
-    if get_machine_config.is_succ() {
+    if get_machine_config.is_succ()? {
         println!("Get machine configuration succeeded");
     } else {
         eprintln!("Get machine configuration error")
@@ -89,13 +101,14 @@ async fn async_main<P: AsRef<Path>>(socket: P) -> RtckResult<()> {
     // Create an asynchronous Rtck with this stream
     let mut rtck = RtckAsync::from_stream(stream);
 
-    let put_logger = events_async::PutLogger::new(Logger {
-        level: Some(LogLevel::Debug),
-        log_path: "~/test_fire/firecracker.log".into(),
-        show_level: Some(true),
-        show_log_origin: Some(true),
-        module: None,
-    });
+    let put_logger = events_async::PutLogger::new(
+        Logger::builder()
+            .path("~/test_fire/firecracker.log")
+            .level(LogLevel::Debug)
+            .show_level(true)
+            .show_log_origin(true)
+            .build()?,
+    );
 
     rtck.execute(&put_logger).await?;
 
@@ -126,8 +139,10 @@ async fn async_main<P: AsRef<Path>>(socket: P) -> RtckResult<()> {
             guest_mac: Some("06:00:AC:10:00:02".to_string()),
             host_dev_name: "tap0".to_string(),
             iface_id: "net1".to_string(),
+            num_queues: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            static_ip: None,
         });
 
     rtck.execute(&put_guest_network_interface_by_id).await?;
@@ -135,6 +150,7 @@ async fn async_main<P: AsRef<Path>>(socket: P) -> RtckResult<()> {
     // Create an asynchronous event
     let put_machine_config = events_async::PutMachineConfiguration::new(MachineConfiguration {
         cpu_template: None,
+        huge_pages: None,
         ht_enabled: None,
         mem_size_mib: 256,
         track_dirty_pages: None,
@@ -145,7 +161,7 @@ async fn async_main<P: AsRef<Path>>(socket: P) -> RtckResult<()> {
     rtck.execute(&put_machine_config).await?;
 
     // Inspect the status of the event
-    if put_machine_config.is_succ() {
+    if put_machine_config.is_succ()? {
         println!("Put machine configuration succeeded");
     } else {
         eprintln!("Put machine configuration error")
@@ -155,7 +171,7 @@ async fn async_main<P: AsRef<Path>>(socket: P) -> RtckResult<()> {
 
     rtck.execute(&get_machine_config).await?;
 
-    if get_machine_config.is_succ() {
+    if get_machine_config.is_succ()? {
         println!("Get machine configuration succeeded");
     } else {
         eprintln!("Get machine configuration error")
@@ -1,9 +1,15 @@
-use crate::RtckResult;
+use std::borrow::Cow;
+
+use crate::{models::kernel_args::KernelArgs, RtckResult};
 
 pub enum HttpMethod {
     GET,
     PUT,
     PATCH,
+    POST,
+    DELETE,
+    HEAD,
+    OPTIONS,
 }
 
 impl HttpMethod {
@@ -12,6 +18,10 @@ impl HttpMethod {
             HttpMethod::GET => "GET",
             HttpMethod::PATCH => "PATCH",
             HttpMethod::PUT => "PUT",
+            HttpMethod::POST => "POST",
+            HttpMethod::DELETE => "DELETE",
+            HttpMethod::HEAD => "HEAD",
+            HttpMethod::OPTIONS => "OPTIONS",
         }
     }
 
@@ -20,27 +30,126 @@ impl HttpMethod {
             "GET" | "Get" | "get" => Ok(HttpMethod::GET),
             "PUT" | "Put" | "put" => Ok(HttpMethod::PUT),
             "PATCH" | "Patch" | "patch" => Ok(HttpMethod::PATCH),
+            "POST" | "Post" | "post" => Ok(HttpMethod::POST),
+            "DELETE" | "Delete" | "delete" => Ok(HttpMethod::DELETE),
+            "HEAD" | "Head" | "head" => Ok(HttpMethod::HEAD),
+            "OPTIONS" | "Options" | "options" => Ok(HttpMethod::OPTIONS),
+            _ => Err(crate::RtckError {
+                class: crate::RtckErrorClass::ParseError,
+                desc: "Error HTTP method".to_string(),
+                fault_kind: None,
+                cause: None,
+                source: None,
+            }),
+        }
+    }
+
+    /// Parse the method token of a request line, which is case-sensitive.
+    pub fn from_bytes(b: &[u8]) -> RtckResult<Self> {
+        match b {
+            b"GET" => Ok(HttpMethod::GET),
+            b"PUT" => Ok(HttpMethod::PUT),
+            b"PATCH" => Ok(HttpMethod::PATCH),
+            b"POST" => Ok(HttpMethod::POST),
+            b"DELETE" => Ok(HttpMethod::DELETE),
+            b"HEAD" => Ok(HttpMethod::HEAD),
+            b"OPTIONS" => Ok(HttpMethod::OPTIONS),
             _ => Err(crate::RtckError {
                 class: crate::RtckErrorClass::ParseError,
                 desc: "Error HTTP method".to_string(),
+                fault_kind: None,
+                cause: None,
+                source: None,
             }),
         }
     }
 }
 
+/// Status codes that firecracker API server could respond with.
+/// Codes not listed here are kept in `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StatusCode {
+    Ok,
+    NoContent,
+    BadRequest,
+    NotFound,
+    MethodNotAllowed,
+    PayloadTooLarge,
+    InternalServerError,
+    Unknown(u16),
+}
+
+impl StatusCode {
+    pub fn as_u16(&self) -> u16 {
+        match self {
+            StatusCode::Ok => 200,
+            StatusCode::NoContent => 204,
+            StatusCode::BadRequest => 400,
+            StatusCode::NotFound => 404,
+            StatusCode::MethodNotAllowed => 405,
+            StatusCode::PayloadTooLarge => 413,
+            StatusCode::InternalServerError => 500,
+            StatusCode::Unknown(code) => *code,
+        }
+    }
+
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.as_u16())
+    }
+
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.as_u16())
+    }
+
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.as_u16())
+    }
+
+    pub fn canonical_reason(&self) -> Option<&'static str> {
+        match self {
+            StatusCode::Ok => Some("OK"),
+            StatusCode::NoContent => Some("No Content"),
+            StatusCode::BadRequest => Some("Bad Request"),
+            StatusCode::NotFound => Some("Not Found"),
+            StatusCode::MethodNotAllowed => Some("Method Not Allowed"),
+            StatusCode::PayloadTooLarge => Some("Payload Too Large"),
+            StatusCode::InternalServerError => Some("Internal Server Error"),
+            StatusCode::Unknown(_) => None,
+        }
+    }
+}
+
+impl From<u16> for StatusCode {
+    fn from(code: u16) -> Self {
+        match code {
+            200 => StatusCode::Ok,
+            204 => StatusCode::NoContent,
+            400 => StatusCode::BadRequest,
+            404 => StatusCode::NotFound,
+            405 => StatusCode::MethodNotAllowed,
+            413 => StatusCode::PayloadTooLarge,
+            500 => StatusCode::InternalServerError,
+            code => StatusCode::Unknown(code),
+        }
+    }
+}
+
 pub struct HttpResponse {
     code: usize,
     headers: String,
     body: String,
+    connection_close: bool,
 }
 
 impl HttpResponse {
     pub fn is_fine(&self) -> bool {
-        if self.code == 200 || self.code == 204 {
-            true
-        } else {
-            false
-        }
+        self.status().is_success()
+    }
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from(self.code as u16)
     }
     pub fn headers(&self) -> &String {
         &self.headers
@@ -48,154 +157,591 @@ impl HttpResponse {
     pub fn body(&self) -> &String {
         &self.body
     }
+    /// Whether the remote will close the connection after this response
+    pub fn is_connection_close(&self) -> bool {
+        self.connection_close
+    }
+    /// Body parsed as JSON, `Null` if empty
+    pub fn json(&self) -> RtckResult<serde_json::Value> {
+        if self.body.trim().is_empty() {
+            Ok(serde_json::Value::Null)
+        } else {
+            Ok(serde_json::from_str(&self.body)?)
+        }
+    }
 }
 
-pub mod http_io {
-    use std::io::BufRead;
+/// Log target of the exchanges with firecracker traced by `ApiTrace`
+pub const API_TRACE_TARGET: &str = "rustcracker::api";
 
-    use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+/// Keys whose values are masked in what is logged: those of the MMDS
+/// bodies, at any depth, e.g. the credentials handed over to cloud-init, and
+/// the kernel parameters. A key matches when it is one of `keys`, or, if
+/// `substring`, when it contains one of them ignoring case, so that `token`
+/// masks `api_token` as well. Only the default policy matches substrings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedactionPolicy {
+    pub keys: Vec<String>,
+    pub substring: bool,
+}
 
-    use crate::{RtckError, RtckResult};
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_KEYS.iter().copied()).with_substring(true)
+    }
+}
 
-    use super::HttpResponse;
+impl RedactionPolicy {
+    pub const DEFAULT_KEYS: &'static [&'static str] = &["password", "token", "secret", "ssh-keys"];
 
-    pub fn read_response<S: BufRead>(stream: &mut S) -> RtckResult<HttpResponse> {
-        let mut res = String::new();
-        let mut buf = String::new();
+    /// Replacement of the masked values
+    pub const MASK: &'static str = "***";
 
-        let code: usize;
-        const HTTP_PATTERN: &'static str = "HTTP/";
-        const PATTERN_LEN: usize = HTTP_PATTERN.len();
-        loop {
-            stream.read_line(&mut buf)?;
-
-            if buf.len() >= PATTERN_LEN {
-                match buf.find("HTTP/") {
-                    None => (),
-                    Some(pos) => {
-                        let t = buf.split_at(pos).1;
-                        res += t;
-                        code = t
-                            .split_ascii_whitespace()
-                            .skip(1)
-                            .next()
-                            .ok_or(RtckError::new(
-                                crate::RtckErrorClass::ParseError,
-                                "Malformed HTTP response",
-                            ))?
-                            .parse::<usize>()?;
-                        break;
+    /// Policy matching exactly `keys`
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(keys: I) -> Self {
+        Self {
+            keys: keys.into_iter().map(Into::into).collect(),
+            substring: false,
+        }
+    }
+
+    pub fn with_substring(mut self, substring: bool) -> Self {
+        self.substring = substring;
+        self
+    }
+
+    /// Whether `text` is one of the keys, or mentions one if `substring`
+    pub fn matches(&self, text: &str) -> bool {
+        if !self.substring {
+            return self.keys.iter().any(|key| key == text);
+        }
+        let text = text.to_lowercase();
+        self.keys
+            .iter()
+            .any(|key| text.contains(&key.to_lowercase()))
+    }
+
+    /// `body` with the values of the keys masked. A body which is not JSON
+    /// can't be looked into and is hidden altogether.
+    pub fn redact<'a>(&self, body: &'a str) -> Cow<'a, str> {
+        if self.keys.is_empty() || body.trim().is_empty() {
+            return Cow::Borrowed(body);
+        }
+        let Ok(mut value) = serde_json::from_str::<serde_json::Value>(body) else {
+            return Cow::Borrowed(ApiTrace::REDACTED);
+        };
+        if self.mask(&mut value) {
+            Cow::Owned(value.to_string())
+        } else {
+            Cow::Borrowed(body)
+        }
+    }
+
+    /// Mask the keys in `value`, returning whether any was found
+    pub fn mask(&self, value: &mut serde_json::Value) -> bool {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut masked = false;
+                for (key, value) in map.iter_mut() {
+                    if self.matches(key) {
+                        *value = serde_json::Value::String(Self::MASK.to_string());
+                        masked = true;
+                    } else if let ("boot_args", serde_json::Value::String(args)) =
+                        (key.as_str(), &mut *value)
+                    {
+                        if let Some(args_masked) = self.mask_kernel_args(args) {
+                            *args = args_masked;
+                            masked = true;
+                        }
+                    } else {
+                        masked |= self.mask(value);
                     }
                 }
+                masked
             }
-            buf.clear()
+            serde_json::Value::Array(values) => {
+                let mut masked = false;
+                for value in values {
+                    masked |= self.mask(value);
+                }
+                masked
+            }
+            _ => false,
         }
+    }
 
-        const PATTERN: &'static str = "Content-Length: ";
-        let mut len = None::<usize>;
-        loop {
-            buf.clear();
+    /// `args` with the values of the kernel parameters named after a key
+    /// masked. If `substring`, a key mentioned in the value masks it as well,
+    /// as in `ds=nocloud-net;s=http://host/?token=...`. `None` if there is
+    /// nothing to mask.
+    pub fn mask_kernel_args(&self, args: &str) -> Option<String> {
+        let mut args = KernelArgs::from(args);
+        let mut masked = false;
+        for (key, value) in args.0.iter_mut() {
+            match value {
+                Some(value) if self.matches(key) || (self.substring && self.matches(value)) => {
+                    *value = Self::MASK.to_string();
+                    masked = true;
+                }
+                None if self.matches(key) => {
+                    *key = Self::MASK.to_string();
+                    masked = true;
+                }
+                _ => (),
+            }
+        }
+        masked.then(|| args.to_string())
+    }
+}
 
-            stream.read_line(&mut buf)?;
-            res += &buf;
+/// Copy of a value fit to be logged, with the secrets it holds masked
+/// according to a `RedactionPolicy`
+pub trait Redact {
+    fn redacted(&self, policy: &RedactionPolicy) -> Self;
+}
 
-            if &buf[0..2] == "\r\n" {
-                break;
+/// Tracing of the requests sent to firecracker and of its responses, logged
+/// at debug level under `API_TRACE_TARGET`, see `GlobalConfig::trace_api`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApiTrace {
+    /// Hide the bodies of MMDS requests and responses, which may hold secrets
+    pub redact_mmds: bool,
+    /// Keys masked in the MMDS bodies otherwise, and in the kernel arguments
+    pub redaction: RedactionPolicy,
+    /// Log the bodies as they are, secrets included, see
+    /// `GlobalConfig::log_sensitive`
+    pub sensitive: bool,
+}
+
+impl ApiTrace {
+    const REDACTED: &'static str = "<redacted>";
+
+    /// Whether anything is logged, so that bodies are only redacted if so
+    fn enabled() -> bool {
+        log::log_enabled!(target: API_TRACE_TARGET, log::Level::Debug)
+    }
+
+    /// `body` as traced, exchanged with an MMDS endpoint if `mmds`
+    fn body<'a>(&self, body: &'a str, mmds: bool) -> Cow<'a, str> {
+        if self.sensitive {
+            return Cow::Borrowed(body);
+        }
+        match (mmds, self.redact_mmds) {
+            // The boot source, or the configuration exported from `/vm/config`
+            (false, _) if body.contains("\"boot_args\"") => self.redaction.redact(body),
+            (false, _) => Cow::Borrowed(body),
+            (true, true) => Cow::Borrowed(Self::REDACTED),
+            (true, false) => self.redaction.redact(body),
+        }
+    }
+
+    /// Log the request line and the body of the encoded request `req`,
+    /// returning whether it is an MMDS one
+    pub(crate) fn request(&self, req: &str) -> bool {
+        let (head, body) = req.split_once("\r\n\r\n").unwrap_or((req, ""));
+        // Method and path, without the protocol version
+        let line = head.lines().next().unwrap_or_default();
+        let line = line.rsplit_once(' ').map_or(line, |(line, _version)| line);
+        let mmds = line
+            .split(' ')
+            .nth(1)
+            .is_some_and(|path| path.starts_with("/mmds"));
+        if Self::enabled() {
+            let body = self.body(body, mmds);
+            log::debug!(target: API_TRACE_TARGET, "--> {line} {body}");
+        }
+        mmds
+    }
+
+    /// Log the status and the body of `res`, answering an MMDS request if `mmds`
+    pub(crate) fn response(&self, res: &HttpResponse, mmds: bool) {
+        if Self::enabled() {
+            let body = self.body(res.body.trim_end(), mmds);
+            log::debug!(target: API_TRACE_TARGET, "<-- {} {body}", res.code);
+        }
+    }
+
+    /// Log the status of `head`, whose body is streamed
+    pub(crate) fn head(&self, head: &HttpHead) {
+        log::debug!(target: API_TRACE_TARGET, "<-- {} <streamed>", head.code);
+    }
+}
+
+/// Percent-encode everything but unreserved characters (RFC 3986) of a
+/// single path segment, so that user supplied ids can't escape it. The dot
+/// segments `.` and `..` are encoded too, not to be taken as relative.
+pub fn encode_path_segment(segment: &str) -> String {
+    if segment == "." || segment == ".." {
+        return "%2E".repeat(segment.len());
+    }
+    let mut encoded = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
             }
+            _ => encoded += &format!("%{:02X}", b),
+        }
+    }
+    encoded
+}
+
+/// Status line and headers of a response, read before its body.
+pub struct HttpHead {
+    code: usize,
+    headers: String,
+    content_length: Option<usize>,
+    chunked: bool,
+    connection_close: bool,
+}
+
+impl HttpHead {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from(self.code as u16)
+    }
+    pub fn headers(&self) -> &String {
+        &self.headers
+    }
+    pub fn content_length(&self) -> Option<usize> {
+        self.content_length
+    }
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+    /// Whether the remote will close the connection after this response
+    pub fn is_connection_close(&self) -> bool {
+        self.connection_close
+    }
+
+    fn parse_status_line(&mut self, line: &str) -> RtckResult<()> {
+        self.code = line
+            .split_ascii_whitespace()
+            .nth(1)
+            .ok_or(crate::RtckError::new(
+                crate::RtckErrorClass::ParseError,
+                "Malformed HTTP response",
+            ))?
+            .parse::<usize>()?;
+        self.headers += line;
+        Ok(())
+    }
 
-            len = buf
-                .trim_end()
-                .trim_start_matches(PATTERN)
-                .parse::<usize>()
-                .ok();
-        }
-
-        match len {
-            Some(len) => {
-                let mut buf: Vec<u8> = vec![0; len];
-                stream.read_exact(&mut buf)?;
-                let body = String::from_utf8(buf)?;
-                Ok(HttpResponse {
-                    code,
-                    headers: res,
-                    body,
-                })
+    fn parse_header_line(&mut self, line: &str) {
+        self.headers += line;
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("Content-Length") {
+                self.content_length = value.parse::<usize>().ok();
+            } else if name.eq_ignore_ascii_case("Transfer-Encoding") {
+                self.chunked = value.eq_ignore_ascii_case("chunked");
+            } else if name.eq_ignore_ascii_case("Connection") {
+                self.connection_close = value
+                    .split(',')
+                    .any(|token| token.trim().eq_ignore_ascii_case("close"));
             }
-            None => Err(RtckError::new(
-                crate::RtckErrorClass::IoError,
-                "Fail to read response due to no content length specification",
-            )),
         }
     }
 
-    pub async fn read_response_async<S: AsyncBufRead + Unpin>(
-        stream: &mut S,
-    ) -> RtckResult<HttpResponse> {
-        let mut res = String::new();
+    /// Whether the head promises no body at all.
+    fn is_bodyless(&self) -> bool {
+        !self.chunked && self.content_length.is_none() && self.status() == StatusCode::NoContent
+    }
+}
+
+enum BodyState {
+    // Bytes left in a body of known length
+    Length(usize),
+    // Reading the hex size line of a chunk
+    ChunkSize(Vec<u8>),
+    // Bytes left in the current chunk
+    ChunkData(usize),
+    // Skipping the CRLF that ends a chunk
+    ChunkEnd,
+    // Skipping trailer lines after the last chunk
+    Trailer(Vec<u8>),
+    Done,
+}
+
+/// Reader over the body of a response whose head has been consumed,
+/// yielding at most the bytes of this response from the underlying stream.
+/// Memory usage is bounded by the buffer of the stream.
+pub struct BodyReader<'a, S> {
+    stream: &'a mut S,
+    state: BodyState,
+}
+
+impl<'a, S> BodyReader<'a, S> {
+    pub fn new(stream: &'a mut S, head: &HttpHead) -> RtckResult<Self> {
+        let state = if head.chunked {
+            BodyState::ChunkSize(Vec::new())
+        } else if head.is_bodyless() {
+            BodyState::Done
+        } else {
+            match head.content_length {
+                Some(0) => BodyState::Done,
+                Some(len) => BodyState::Length(len),
+                None => {
+                    return Err(crate::RtckError::new(
+                        crate::RtckErrorClass::IoError,
+                        "Fail to read response due to no content length specification",
+                    ))
+                }
+            }
+        };
+        Ok(Self { stream, state })
+    }
+}
+
+/// Feed buffered bytes `input` of the stream through the state machine,
+/// copying body bytes into `out`. Returns (consumed, produced).
+fn advance(state: &mut BodyState, input: &[u8], out: &mut [u8]) -> std::io::Result<(usize, usize)> {
+    fn take_line(line: &mut Vec<u8>, input: &[u8]) -> (usize, bool) {
+        match input.iter().position(|b| *b == b'\n') {
+            Some(pos) => {
+                line.extend_from_slice(&input[..=pos]);
+                (pos + 1, true)
+            }
+            None => {
+                line.extend_from_slice(input);
+                (input.len(), false)
+            }
+        }
+    }
+
+    match state {
+        BodyState::Length(left) | BodyState::ChunkData(left) => {
+            let n = (*left).min(input.len()).min(out.len());
+            out[..n].copy_from_slice(&input[..n]);
+            *left -= n;
+            if *left == 0 {
+                *state = match state {
+                    BodyState::ChunkData(_) => BodyState::ChunkEnd,
+                    _ => BodyState::Done,
+                };
+            }
+            Ok((n, n))
+        }
+        BodyState::ChunkSize(line) => {
+            let (consumed, complete) = take_line(line, input);
+            if complete {
+                let text = String::from_utf8_lossy(line);
+                let size = text.split(';').next().unwrap_or_default().trim();
+                let size = usize::from_str_radix(size, 16).map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "Malformed chunk size")
+                })?;
+                *state = if size == 0 {
+                    BodyState::Trailer(Vec::new())
+                } else {
+                    BodyState::ChunkData(size)
+                };
+            }
+            Ok((consumed, 0))
+        }
+        BodyState::ChunkEnd => match input.iter().position(|b| *b == b'\n') {
+            Some(pos) => {
+                *state = BodyState::ChunkSize(Vec::new());
+                Ok((pos + 1, 0))
+            }
+            None => Ok((input.len(), 0)),
+        },
+        BodyState::Trailer(line) => {
+            let (consumed, complete) = take_line(line, input);
+            if complete {
+                if line.as_slice() == b"\r\n" || line.as_slice() == b"\n" {
+                    *state = BodyState::Done;
+                } else {
+                    line.clear();
+                }
+            }
+            Ok((consumed, 0))
+        }
+        BodyState::Done => Ok((0, 0)),
+    }
+}
+
+fn unexpected_eof() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        "Connection closed before the end of response body",
+    )
+}
+
+impl<'a, S: std::io::BufRead> std::io::Read for BodyReader<'a, S> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let BodyState::Done = self.state {
+                return Ok(0);
+            }
+            let input = self.stream.fill_buf()?;
+            if input.is_empty() {
+                return Err(unexpected_eof());
+            }
+            let (consumed, produced) = advance(&mut self.state, input, out)?;
+            self.stream.consume(consumed);
+            if produced > 0 {
+                return Ok(produced);
+            }
+        }
+    }
+}
+
+impl<'a, S: tokio::io::AsyncBufRead + Unpin> tokio::io::AsyncRead for BodyReader<'a, S> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        use std::task::Poll;
+
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            if let BodyState::Done = this.state {
+                return Poll::Ready(Ok(()));
+            }
+            let input = match std::pin::Pin::new(&mut *this.stream).poll_fill_buf(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Ready(Ok(input)) => input,
+            };
+            if input.is_empty() {
+                return Poll::Ready(Err(unexpected_eof()));
+            }
+            let (consumed, produced) = advance(&mut this.state, input, buf.initialize_unfilled())?;
+            std::pin::Pin::new(&mut *this.stream).consume(consumed);
+            if produced > 0 {
+                buf.advance(produced);
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+pub mod http_io {
+    use std::io::{BufRead, Read};
+
+    use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+    use crate::{RtckError, RtckResult};
+
+    use super::{BodyReader, HttpHead, HttpResponse};
+
+    const HTTP_PATTERN: &str = "HTTP/";
+
+    fn closed() -> RtckError {
+        RtckError::new(
+            crate::RtckErrorClass::IoError,
+            "Connection closed while reading response",
+        )
+    }
+
+    /// Read the status line and headers of a response,
+    /// skipping garbage in front of the status line.
+    pub fn read_head<S: BufRead>(stream: &mut S) -> RtckResult<HttpHead> {
+        let mut head = HttpHead {
+            code: 0,
+            headers: String::new(),
+            content_length: None,
+            chunked: false,
+            connection_close: false,
+        };
         let mut buf = String::new();
 
-        let code: usize;
-        const HTTP_PATTERN: &'static str = "HTTP/";
-        const PATTERN_LEN: usize = HTTP_PATTERN.len();
         loop {
-            stream.read_line(&mut buf).await?;
-            if buf.len() >= PATTERN_LEN {
-                match buf.find("HTTP/") {
-                    None => (),
-                    Some(pos) => {
-                        let t = buf.split_at(pos).1;
-                        res += t;
-                        code = t
-                            .split_ascii_whitespace()
-                            .skip(1)
-                            .next()
-                            .ok_or(RtckError::new(
-                                crate::RtckErrorClass::ParseError,
-                                "Malformed HTTP response",
-                            ))?
-                            .parse::<usize>()?;
-                        break;
-                    }
-                }
+            buf.clear();
+            if stream.read_line(&mut buf)? == 0 {
+                return Err(closed());
+            }
+            if let Some(pos) = buf.find(HTTP_PATTERN) {
+                head.parse_status_line(buf.split_at(pos).1)?;
+                break;
             }
-            buf.clear()
         }
 
-        const PATTERN: &'static str = "Content-Length: ";
-        let mut len = None::<usize>;
         loop {
             buf.clear();
+            if stream.read_line(&mut buf)? == 0 {
+                return Err(closed());
+            }
+            if buf == "\r\n" || buf == "\n" {
+                head.headers += &buf;
+                break;
+            }
+            head.parse_header_line(&buf);
+        }
+
+        Ok(head)
+    }
 
-            stream.read_line(&mut buf).await?;
-            res += &buf;
+    pub fn read_response<S: BufRead>(stream: &mut S) -> RtckResult<HttpResponse> {
+        let head = read_head(stream)?;
+        let mut body = Vec::with_capacity(head.content_length.unwrap_or_default());
+        BodyReader::new(stream, &head)?.read_to_end(&mut body)?;
+        Ok(HttpResponse {
+            code: head.code,
+            headers: head.headers,
+            body: String::from_utf8(body)?,
+            connection_close: head.connection_close,
+        })
+    }
+
+    /// Read the status line and headers of a response,
+    /// skipping garbage in front of the status line.
+    pub async fn read_head_async<S: AsyncBufRead + Unpin>(stream: &mut S) -> RtckResult<HttpHead> {
+        let mut head = HttpHead {
+            code: 0,
+            headers: String::new(),
+            content_length: None,
+            chunked: false,
+            connection_close: false,
+        };
+        let mut buf = String::new();
 
-            if &buf[0..2] == "\r\n" {
+        loop {
+            buf.clear();
+            if stream.read_line(&mut buf).await? == 0 {
+                return Err(closed());
+            }
+            if let Some(pos) = buf.find(HTTP_PATTERN) {
+                head.parse_status_line(buf.split_at(pos).1)?;
                 break;
             }
+        }
 
-            len = buf
-                .trim_end()
-                .trim_start_matches(PATTERN)
-                .parse::<usize>()
-                .ok();
-        }
-
-        match len {
-            Some(len) => {
-                let mut buf: Vec<u8> = vec![0; len];
-                stream.read_exact(&mut buf).await?;
-                let body = String::from_utf8(buf)?;
-                Ok(HttpResponse {
-                    code,
-                    headers: res,
-                    body,
-                })
+        loop {
+            buf.clear();
+            if stream.read_line(&mut buf).await? == 0 {
+                return Err(closed());
+            }
+            if buf == "\r\n" || buf == "\n" {
+                head.headers += &buf;
+                break;
             }
-            None => Err(RtckError::new(
-                crate::RtckErrorClass::IoError,
-                "Fail to read response due to no content length specification",
-            )),
+            head.parse_header_line(&buf);
         }
+
+        Ok(head)
+    }
+
+    pub async fn read_response_async<S: AsyncBufRead + Unpin>(
+        stream: &mut S,
+    ) -> RtckResult<HttpResponse> {
+        let head = read_head_async(stream).await?;
+        let mut body = Vec::with_capacity(head.content_length.unwrap_or_default());
+        BodyReader::new(stream, &head)?
+            .read_to_end(&mut body)
+            .await?;
+        Ok(HttpResponse {
+            code: head.code,
+            headers: head.headers,
+            body: String::from_utf8(body)?,
+            connection_close: head.connection_close,
+        })
     }
 }
 
@@ -262,6 +808,160 @@ mod test {
         assert_eq!(res.code, 200);
         assert_eq!(res.body, "This is message body".to_string());
     }
+
+    #[test]
+    fn test_method_and_status_code() {
+        use super::{HttpMethod, StatusCode};
+
+        for m in ["GET", "PUT", "PATCH", "POST", "DELETE", "HEAD", "OPTIONS"] {
+            assert_eq!(HttpMethod::from_bytes(m.as_bytes()).unwrap().as_str(), m);
+        }
+        assert!(HttpMethod::from_bytes(b"get").is_err());
+        assert!(HttpMethod::from_bytes(b"CONNECT").is_err());
+
+        for code in [200u16, 204, 400, 404, 405, 413, 500] {
+            let status = StatusCode::from(code);
+            assert_eq!(status.as_u16(), code);
+            assert!(status.canonical_reason().is_some());
+        }
+        assert!(StatusCode::from(204).is_success());
+        assert!(StatusCode::from(413).is_client_error());
+        assert!(StatusCode::from(503).is_server_error());
+        assert_eq!(StatusCode::from(418), StatusCode::Unknown(418));
+        assert_eq!(StatusCode::from(418).canonical_reason(), None);
+    }
+
+    #[test]
+    fn test_encode_path_segment() {
+        use super::encode_path_segment;
+
+        assert_eq!(encode_path_segment("rootfs_1"), "rootfs_1");
+        assert_eq!(encode_path_segment("root fs"), "root%20fs");
+        assert_eq!(encode_path_segment("../vm/config"), "..%2Fvm%2Fconfig");
+        assert_eq!(encode_path_segment("é"), "%C3%A9");
+        assert_eq!(encode_path_segment("a\r\nb"), "a%0D%0Ab");
+        // Dot segments, dots elsewhere are left alone
+        assert_eq!(encode_path_segment("."), "%2E");
+        assert_eq!(encode_path_segment(".."), "%2E%2E");
+        assert_eq!(encode_path_segment("..."), "...");
+        assert_eq!(encode_path_segment("rootfs.1"), "rootfs.1");
+    }
+
+    #[test]
+    fn test_body_reader() {
+        use std::io::{BufReader, Cursor};
+
+        use super::BodyReader;
+
+        // Chunked body followed by the next response on the same stream,
+        // read through a tiny buffer so chunks straddle refills.
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4\r\n{\"a\"\r\nb;ext=1\r\n:\"bcdefghij\r\n2\r\n\"}\r\n0\r\n\r\n\
+            HTTP/1.1 204 No Content\r\nServer: Firecracker API\r\n\r\n\
+            HTTP/1.1 400 Bad Request\r\nContent-Length: 2\r\nServer: Firecracker API\r\n\r\n{}";
+        let mut stream = BufReader::with_capacity(3, Cursor::new(raw.to_vec()));
+
+        let head = http_io::read_head(&mut stream).unwrap();
+        assert!(head.is_chunked());
+        let mut body = String::new();
+        BodyReader::new(&mut stream, &head)
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, r#"{"a":"bcdefghij"}"#);
+
+        let res = http_io::read_response(&mut stream).unwrap();
+        assert_eq!(res.code, 204);
+        assert!(res.body.is_empty());
+
+        // Content-Length must not be overridden by the headers after it
+        let res = http_io::read_response(&mut stream).unwrap();
+        assert_eq!(res.code, 400);
+        assert_eq!(res.body, "{}");
+
+        assert!(http_io::read_response(&mut stream).is_err());
+    }
+
+    #[test]
+    fn test_get_mmds_decode_streaming() {
+        use std::io::Cursor;
+
+        use super::BodyReader;
+        use crate::ops_res::get_mmds::GetMmdsRes;
+
+        fn decode(raw: &[u8]) -> crate::RtckResult<GetMmdsRes> {
+            let mut stream = Cursor::new(raw.to_vec());
+            let head = http_io::read_head(&mut stream)?;
+            GetMmdsRes::decode_streaming(&head, BodyReader::new(&mut stream, &head)?)
+        }
+
+        let res = decode(b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\n\"hello\"").unwrap();
+        assert_eq!(res.as_succ().unwrap().0, "hello");
+
+        let res = decode(
+            b"HTTP/1.1 400 Bad Request\r\nContent-Length: 24\r\n\r\n{\"fault_message\":\"oops\"}",
+        )
+        .unwrap();
+        assert_eq!(res.as_err().unwrap().fault_message, "oops");
+
+        // Truncated body is reported rather than hanging
+        assert!(decode(b"HTTP/1.1 200 OK\r\nContent-Length: 70\r\n\r\n\"hello\"").is_err());
+    }
+
+    #[test]
+    fn test_redaction_policy() {
+        use super::RedactionPolicy;
+
+        let policy = RedactionPolicy::default();
+        let body = r#"{"users":[{"name":"rtck","ssh-keys":["ssh-ed25519 AAAA"]}],"token":"t0k3n"}"#;
+        assert_eq!(
+            policy.redact(body),
+            r#"{"token":"***","users":[{"name":"rtck","ssh-keys":"***"}]}"#
+        );
+        // Left alone when nothing is masked
+        assert_eq!(
+            policy.redact(r#"{ "user": "rtck" }"#),
+            r#"{ "user": "rtck" }"#
+        );
+        assert_eq!(policy.redact(""), "");
+        assert_eq!(policy.redact("password=hunter2"), "<redacted>");
+        assert_eq!(
+            RedactionPolicy::new(Vec::<String>::new()).redact("password=hunter2"),
+            "password=hunter2"
+        );
+        assert!(policy.matches("X-Api-Token"));
+
+        // Keys given by the user match exactly
+        let policy = RedactionPolicy::new(["token", "db_pass"]);
+        assert_eq!(
+            policy.redact(r#"{"token":"t0k3n","api_token":"t0k3n","Token":"t0k3n"}"#),
+            r#"{"Token":"t0k3n","api_token":"t0k3n","token":"***"}"#
+        );
+        assert_eq!(
+            policy
+                .mask_kernel_args("ds=nocloud-net;s=http://10.0.0.1/?token=t0k3n db_pass=s3cr3t")
+                .as_deref(),
+            Some("ds=nocloud-net;s=http://10.0.0.1/?token=t0k3n db_pass=***")
+        );
+        let policy = policy.with_substring(true);
+        assert!(policy.matches("X-Api-Token"));
+        let policy = RedactionPolicy::default();
+
+        // Kernel parameters, by name or by value
+        assert_eq!(
+            policy
+                .mask_kernel_args(
+                    "console=ttyS0 ds=nocloud-net;s=http://10.0.0.1/?token=t0k3n db_secret=s3cr3t"
+                )
+                .as_deref(),
+            Some("console=ttyS0 ds=*** db_secret=***")
+        );
+        assert_eq!(policy.mask_kernel_args("console=ttyS0 quiet"), None);
+        assert_eq!(
+            policy.redact(r#"{"kernel_image_path":"/vmlinux","boot_args":"quiet token=t0k3n"}"#),
+            r#"{"boot_args":"quiet token=***","kernel_image_path":"/vmlinux"}"#
+        );
+    }
 }
 
 #[cfg(test)]
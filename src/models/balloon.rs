@@ -1,5 +1,11 @@
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::Validate;
+
 /// Balloon device descriptor.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Balloon {
@@ -15,10 +21,91 @@ pub struct Balloon {
 
     /// Interval in seconds between refreshing statistics
     /// non-zero value will enable statistics
-    /// Defaults to 0
+    /// Defaults to 0, which disables statistics: firecracker then rejects
+    /// every request for them, and they cannot be enabled after boot
     #[serde(
         rename = "stats_polling_interval_s",
         skip_serializing_if = "Option::is_none"
     )]
     pub stats_polling_interval_s: Option<i64>,
 }
+
+impl Balloon {
+    /// Refresh the statistics every `secs` seconds, 0 disabling them
+    pub fn with_stats_interval(mut self, secs: i64) -> RtckResult<Self> {
+        if secs < 0 {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Negative balloon stats_polling_interval_s {secs}"),
+            ));
+        }
+        self.stats_polling_interval_s = Some(secs);
+        Ok(self)
+    }
+
+    /// Interval between refreshes of the statistics, `None` if disabled
+    pub fn stats_polling_interval(&self) -> Option<Duration> {
+        match self.stats_polling_interval_s {
+            Some(secs) if secs > 0 => Some(Duration::from_secs(secs as u64)),
+            _ => None,
+        }
+    }
+}
+
+impl Validate for Balloon {
+    fn validate(&self) -> RtckResult<()> {
+        if self.amount_mib < 0 {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Negative balloon amount_mib {}", self.amount_mib),
+            ));
+        }
+        match self.stats_polling_interval_s {
+            Some(secs) if secs < 0 => Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Negative balloon stats_polling_interval_s {secs}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Balloon;
+    use crate::models::Validate;
+
+    #[test]
+    fn test_stats_interval() {
+        let balloon = Balloon {
+            amount_mib: 64,
+            deflate_on_oom: true,
+            stats_polling_interval_s: None,
+        };
+        assert_eq!(balloon.stats_polling_interval(), None);
+        assert!(balloon.validate().is_ok());
+
+        let enabled = balloon.clone().with_stats_interval(5).unwrap();
+        assert_eq!(
+            enabled.stats_polling_interval(),
+            Some(Duration::from_secs(5))
+        );
+        let disabled = balloon.clone().with_stats_interval(0).unwrap();
+        assert_eq!(disabled.stats_polling_interval_s, Some(0));
+        assert_eq!(disabled.stats_polling_interval(), None);
+        assert!(balloon.clone().with_stats_interval(-1).is_err());
+
+        let negative = Balloon {
+            stats_polling_interval_s: Some(-1),
+            ..balloon.clone()
+        };
+        assert!(negative.validate().is_err());
+        let negative = Balloon {
+            amount_mib: -1,
+            ..balloon
+        };
+        assert!(negative.validate().is_err());
+    }
+}
@@ -6,13 +6,14 @@ use serde::{Deserialize, Serialize};
 /// by `GET /balloon/statistics`, which describes detailed
 /// information of the balloon device.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
 pub struct BalloonStatistics {
-    /// Target number of pages the device aims to hold.
+    /// Target number of 4 KiB pages the device aims to hold.
     /// Required: true
     #[serde(rename = "target_pages")]
     pub target_pages: u64,
 
-    /// Actual number of pages the device is holding.
+    /// Actual number of 4 KiB pages the device is holding.
     /// Required: true
     #[serde(rename = "actual_pages")]
     pub actual_pages: u64,
@@ -72,3 +73,83 @@ pub struct BalloonStatistics {
     #[serde(rename = "hugetlb_failures", skip_serializing_if = "Option::is_none")]
     pub hugetlb_failures: Option<u64>,
 }
+
+impl BalloonStatistics {
+    /// Size of the pages counted by the balloon, whatever the guest page size
+    pub const PAGE_SIZE: u64 = 4096;
+
+    /// Memory the device aims to hold, in bytes
+    pub fn target_bytes(&self) -> u64 {
+        self.target_pages.saturating_mul(Self::PAGE_SIZE)
+    }
+
+    /// Memory the device is holding, in bytes
+    pub fn actual_bytes(&self) -> u64 {
+        self.actual_pages.saturating_mul(Self::PAGE_SIZE)
+    }
+
+    /// Memory the device still has to inflate (positive) or deflate
+    /// (negative) by to reach its target, in bytes
+    pub fn pending_bytes(&self) -> i128 {
+        self.target_bytes() as i128 - self.actual_bytes() as i128
+    }
+
+    /// Memory of the guest in use, i.e. neither free nor reclaimable disk
+    /// caches, in bytes. `None` unless the guest reported both.
+    pub fn used_memory(&self) -> Option<u64> {
+        let total = self.total_memory?;
+        let free = self.free_memory?;
+        Some(
+            total
+                .saturating_sub(free)
+                .saturating_sub(self.disk_caches.unwrap_or(0)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BalloonStatistics;
+
+    fn stats(target_pages: u64, actual_pages: u64) -> BalloonStatistics {
+        BalloonStatistics {
+            target_pages,
+            actual_pages,
+            target_mib: target_pages / 256,
+            actual_mib: actual_pages / 256,
+            swap_in: None,
+            swap_out: None,
+            major_faults: None,
+            minor_faults: None,
+            free_memory: None,
+            total_memory: None,
+            available_memory: None,
+            disk_caches: None,
+            hugetlb_allocations: None,
+            hugetlb_failures: None,
+        }
+    }
+
+    #[test]
+    fn test_balloon_stats_bytes() {
+        // 16384 pages of 4 KiB are 64 MiB
+        let inflating = stats(16384, 8192);
+        assert_eq!(inflating.target_bytes(), 64 << 20);
+        assert_eq!(inflating.target_bytes() >> 20, inflating.target_mib);
+        assert_eq!(inflating.actual_bytes(), 32 << 20);
+        assert_eq!(inflating.pending_bytes(), 32 << 20);
+        assert_eq!(stats(0, 1).pending_bytes(), -4096);
+        assert_eq!(stats(u64::MAX, 0).target_bytes(), u64::MAX);
+    }
+
+    #[test]
+    fn test_balloon_stats_used_memory() {
+        let mut stats = stats(0, 0);
+        assert_eq!(stats.used_memory(), None);
+        stats.total_memory = Some(256 << 20);
+        stats.free_memory = Some(64 << 20);
+        assert_eq!(stats.used_memory(), Some(192 << 20));
+        stats.disk_caches = Some(32 << 20);
+        assert_eq!(stats.used_memory(), Some(160 << 20));
+    }
+}
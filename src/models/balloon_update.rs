@@ -6,4 +6,9 @@ pub struct BalloonUpdate {
     /// Target balloon size in MiB.
     #[serde(rename = "amount_mib")]
     pub amount_mib: i64,
+
+    /// Whether the balloon should deflate when the guest has memory pressure.
+    /// Only accepted by firecracker versions updating it at runtime.
+    #[serde(rename = "deflate_on_oom", skip_serializing_if = "Option::is_none")]
+    pub deflate_on_oom: Option<bool>,
 }
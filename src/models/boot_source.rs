@@ -1,13 +1,17 @@
 use serde::{Deserialize, Serialize};
 
+use crate::micro_http::{Redact, RedactionPolicy};
+
 /// Boot source descriptor.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BootSource {
     /// Kernel boot arguments defines the command-line arguments
     /// that should be passed to the kernel.
+    #[serde(rename = "boot_args", skip_serializing_if = "Option::is_none")]
     pub boot_args: Option<String>,
 
     /// Host level path to the initrd image used to boot the guest
+    #[serde(rename = "initrd_path", skip_serializing_if = "Option::is_none")]
     pub initrd_path: Option<String>,
 
     /// Host level path to the kernel image used to boot the guest
@@ -15,3 +19,17 @@ pub struct BootSource {
     /// Required: true
     pub kernel_image_path: String,
 }
+
+impl Redact for BootSource {
+    /// Boot source with the secrets passed in the kernel arguments masked
+    fn redacted(&self, policy: &RedactionPolicy) -> Self {
+        Self {
+            boot_args: self.boot_args.as_ref().map(|args| {
+                policy
+                    .mask_kernel_args(args)
+                    .unwrap_or_else(|| args.clone())
+            }),
+            ..self.clone()
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::firecracker_version::FirecrackerVersion;
+
 /// The CPU Template defines a set of flags to be disabled from the microvm so that
 /// the features exposed to the guest are the same as in the selected instance type.
 /// This parameter has been deprecated and it will be removed in future Firecracker
@@ -28,17 +32,64 @@ pub enum CPUTemplateString {
     None,
 }
 
+impl CPUTemplateString {
+    /// Whether the template applies to hosts of `arch`, as in
+    /// `std::env::consts::ARCH`
+    pub fn supported_on(&self, arch: &str) -> bool {
+        match self {
+            CPUTemplateString::C3
+            | CPUTemplateString::T2
+            | CPUTemplateString::T2S
+            | CPUTemplateString::T2CL
+            | CPUTemplateString::T2A => arch == "x86_64",
+            CPUTemplateString::V1N1 => arch == "aarch64",
+            CPUTemplateString::None => true,
+        }
+    }
+
+    /// First firecracker release (major, minor) shipping the template,
+    /// `None` if every release does
+    pub fn min_firecracker_version(&self) -> Option<(u64, u64)> {
+        match self {
+            CPUTemplateString::T2S => Some((1, 2)),
+            CPUTemplateString::T2CL | CPUTemplateString::T2A => Some((1, 3)),
+            CPUTemplateString::V1N1 => Some((1, 4)),
+            CPUTemplateString::C3 | CPUTemplateString::T2 | CPUTemplateString::None => None,
+        }
+    }
+
+    /// Check that firecracker `version` ships the template
+    pub fn check_version(&self, version: &FirecrackerVersion) -> RtckResult<()> {
+        let Some((major, minor)) = self.min_firecracker_version() else {
+            return Ok(());
+        };
+        if !version.at_least(major, minor)? {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "CPU template {self:?} requires firecracker v{major}.{minor} or later, running {}",
+                    version.firecracker_version
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
 /// The CPU configuration template defines a set of bit maps as modifiers
 /// of flags accessed by register to be disabled/enabled for the microvm.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct CPUConfig {
     // A collection of CPUIDs to be modified. (x86_64)
+    #[serde(default)]
     pub cpuid_modifiers: Vec<CpuIdModifier>,
 
     // A collection of model specific registers to be modified. (x86_64)
+    #[serde(default)]
     pub msr_modifiers: Vec<MsrModifier>,
 
     // A collection of registers to be modified. (aarch64)
+    #[serde(default)]
     pub reg_modifiers: Vec<RegModifier>,
 }
 
@@ -110,3 +161,57 @@ pub struct RegModifier {
     /// Example: ["0bxxxxxxxxxxxx_0000_xxxx_xxxx_xxxx_0000_xxxx_xxxx_xxxx_xxxx_xxxx_xxxx_xxxx_xxxx"]
     pub bitmap: String,
 }
+
+#[cfg(test)]
+mod test {
+    use super::{CPUTemplate, CPUTemplateString};
+    use crate::models::firecracker_version::FirecrackerVersion;
+
+    #[test]
+    fn test_cpu_template_wire_names() {
+        for (template, name) in [
+            (CPUTemplateString::C3, "C3"),
+            (CPUTemplateString::T2, "T2"),
+            (CPUTemplateString::T2S, "T2S"),
+            (CPUTemplateString::T2CL, "T2CL"),
+            (CPUTemplateString::T2A, "T2A"),
+            (CPUTemplateString::V1N1, "V1N1"),
+            (CPUTemplateString::None, "None"),
+        ] {
+            let json = serde_json::to_string(&CPUTemplate(template.clone())).unwrap();
+            assert_eq!(json, format!(r#""{name}""#));
+            assert_eq!(
+                serde_json::from_str::<CPUTemplate>(&json).unwrap(),
+                CPUTemplate(template)
+            );
+        }
+    }
+
+    #[test]
+    fn test_cpu_template_support() {
+        assert!(CPUTemplateString::T2S.supported_on("x86_64"));
+        assert!(!CPUTemplateString::T2S.supported_on("aarch64"));
+        assert!(CPUTemplateString::V1N1.supported_on("aarch64"));
+        assert!(!CPUTemplateString::V1N1.supported_on("x86_64"));
+        assert!(CPUTemplateString::None.supported_on("aarch64"));
+
+        let version = |v: &str| FirecrackerVersion {
+            firecracker_version: v.into(),
+        };
+        assert!(CPUTemplateString::T2S
+            .check_version(&version("1.2.0"))
+            .is_ok());
+        assert!(CPUTemplateString::T2S
+            .check_version(&version("1.1.4"))
+            .is_err());
+        assert!(CPUTemplateString::T2CL
+            .check_version(&version("1.2.0"))
+            .is_err());
+        assert!(CPUTemplateString::V1N1
+            .check_version(&version("1.4.1"))
+            .is_ok());
+        assert!(CPUTemplateString::C3
+            .check_version(&version("0.25.0"))
+            .is_ok());
+    }
+}
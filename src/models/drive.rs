@@ -1,8 +1,13 @@
-use serde::{Deserialize, Serialize};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 
-use super::rate_limiter::RateLimiter;
+use crate::{RtckError, RtckErrorClass, RtckResult};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+use super::{rate_limiter::RateLimiter, resource_id};
+
+/// Block device of the guest, either a virtio-block drive backed by the file
+/// `path_on_host` or a vhost-user-block drive backed by the `socket` of a
+/// backend. The fields of the other kind are left out on the wire.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Drive {
     /// drive id
     /// Required: true
@@ -29,15 +34,17 @@ pub struct Drive {
     /// VirtioBlock specific parameters:
     /// Is block read only.
     /// This field is required for virtio-block config and should be omitted for vhost-user-block configuration.
+    /// Must be false for vhost-user-block, where the backend decides.
     /// Required: true
-    #[serde(rename = "is_read_only")]
+    #[serde(rename = "is_read_only", default)]
     pub is_read_only: bool,
 
     /// VirtioBlock specific parameters:
     /// Host level path for the guest drive.
     /// This field is required for virtio-block config and should be omitted for vhost-user-block configuration.
+    /// Empty for vhost-user-block.
     /// Required: true
-    #[serde(rename = "path_on_host")]
+    #[serde(rename = "path_on_host", default)]
     pub path_on_host: String,
 
     /// VirtioBlock specific parameters:
@@ -59,11 +66,88 @@ pub struct Drive {
     pub socket: Option<String>,
 }
 
+impl Drive {
+    /// vhost-user-block drive served by the backend listening on `socket_path`
+    pub fn vhost_user<S: Into<String>, P: Into<String>>(drive_id: S, socket_path: P) -> Self {
+        Self {
+            drive_id: drive_id.into(),
+            partuuid: None,
+            is_root_device: false,
+            cache_type: None,
+            is_read_only: false,
+            path_on_host: String::new(),
+            rate_limiter: None,
+            io_engine: None,
+            socket: Some(socket_path.into()),
+        }
+    }
+
+    pub fn is_vhost_user(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Check `drive_id` so that it is accepted by firecracker, and that the
+    /// drive is backed by exactly one of a file or a vhost-user socket
+    pub fn validate(&self) -> RtckResult<()> {
+        resource_id::validate_id(&self.drive_id)?;
+        let invalid = |reason: &str| {
+            Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Drive {:?} {reason}", self.drive_id),
+            ))
+        };
+        match &self.socket {
+            None if self.path_on_host.is_empty() => invalid("has neither path_on_host nor socket"),
+            None => Ok(()),
+            Some(_) if !self.path_on_host.is_empty() => invalid("has both path_on_host and socket"),
+            Some(socket) if socket.is_empty() => invalid("has an empty socket"),
+            Some(_)
+                if self.is_read_only || self.rate_limiter.is_some() || self.io_engine.is_some() =>
+            {
+                invalid("is vhost-user, is_read_only, rate_limiter and io_engine are not supported")
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+impl Serialize for Drive {
+    /// Like a derived implementation, leaving out the virtio-block fields
+    /// of vhost-user-block drives which firecracker refuses for them
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut drive = serializer.serialize_struct("Drive", 9)?;
+        drive.serialize_field("drive_id", &self.drive_id)?;
+        if let Some(partuuid) = &self.partuuid {
+            drive.serialize_field("partuuid", partuuid)?;
+        }
+        drive.serialize_field("is_root_device", &self.is_root_device)?;
+        if let Some(cache_type) = &self.cache_type {
+            drive.serialize_field("cache_type", cache_type)?;
+        }
+        if !self.is_vhost_user() {
+            drive.serialize_field("is_read_only", &self.is_read_only)?;
+            drive.serialize_field("path_on_host", &self.path_on_host)?;
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            drive.serialize_field("rate_limiter", rate_limiter)?;
+        }
+        if let Some(io_engine) = &self.io_engine {
+            drive.serialize_field("io_engine", io_engine)?;
+        }
+        if let Some(socket) = &self.socket {
+            drive.serialize_field("socket", socket)?;
+        }
+        drive.end()
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum CacheType {
     #[serde(rename = "Unsafe")]
     Unsafe,
-    #[serde(rename = "WriteBack")]
+    /// Spelled `WriteBack` by earlier releases of rustcracker, which
+    /// firecracker refuses, still accepted when decoding
+    #[serde(rename = "Writeback", alias = "WriteBack")]
     WriteBack,
 }
 
@@ -74,3 +158,48 @@ pub enum IoEngine {
     #[serde(rename = "Async")]
     Async,
 }
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{Drive, IoEngine};
+
+    #[test]
+    fn test_vhost_user_drive() {
+        let drive = Drive::vhost_user("data", "/run/vhost-user-blk.sock");
+        assert!(drive.validate().is_ok());
+        assert_eq!(
+            serde_json::to_value(&drive).unwrap(),
+            json!({
+                "drive_id": "data",
+                "is_root_device": false,
+                "socket": "/run/vhost-user-blk.sock",
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<Drive>(serde_json::to_value(&drive).unwrap()).unwrap(),
+            drive
+        );
+
+        // Backed by exactly one of a file and a socket
+        let mut both = drive.clone();
+        both.path_on_host = "/tmp/data.ext4".into();
+        assert!(both.validate().is_err());
+        let mut neither = both.clone();
+        neither.path_on_host.clear();
+        neither.socket = None;
+        assert!(neither.validate().is_err());
+        let mut file = neither;
+        file.path_on_host = "/tmp/data.ext4".into();
+        assert!(file.validate().is_ok());
+
+        // Virtio-block only parameters
+        let mut read_only = drive.clone();
+        read_only.is_read_only = true;
+        assert!(read_only.validate().is_err());
+        let mut io_engine = drive;
+        io_engine.io_engine = Some(IoEngine::Async);
+        assert!(io_engine.validate().is_err());
+    }
+}
@@ -1,10 +1,62 @@
 use serde::{Deserialize, Serialize};
 
-use super::rate_limiter::RateLimiter;
+use crate::RtckResult;
+
+use super::{rate_limiter::RateLimiter, Validate};
 
 /// Defines an entropy device.
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct EntropyDevice {
-    #[serde(rename = "rate_limiter")]
+    #[serde(rename = "rate_limiter", skip_serializing_if = "Option::is_none")]
     pub rate_limiter: Option<RateLimiter>,
 }
+
+impl EntropyDevice {
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+}
+
+impl Validate for EntropyDevice {
+    fn validate(&self) -> RtckResult<()> {
+        match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{EntropyDevice, Validate};
+    use crate::models::{rate_limiter::RateLimiter, token_bucket::TokenBucket};
+
+    #[test]
+    fn test_entropy_device() {
+        // Optional fields are omitted
+        let entropy_device = EntropyDevice::default();
+        assert_eq!(serde_json::to_value(&entropy_device).unwrap(), json!({}));
+        assert!(entropy_device.validate().is_ok());
+
+        let entropy_device =
+            entropy_device.with_rate_limiter(RateLimiter::ops_per_sec(100).unwrap());
+        assert_eq!(
+            serde_json::to_value(&entropy_device).unwrap()["rate_limiter"]["ops"]["size"],
+            100
+        );
+        assert!(entropy_device.validate().is_ok());
+
+        let entropy_device = EntropyDevice::default().with_rate_limiter(RateLimiter {
+            bandwidth: Some(TokenBucket {
+                one_time_burst: None,
+                refill_time: 0,
+                size: 1024,
+            }),
+            ops: None,
+        });
+        assert!(entropy_device.validate().is_err());
+    }
+}
@@ -14,4 +14,223 @@ impl Display for InternalError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.fault_message)
     }
-}
\ No newline at end of file
+}
+
+impl InternalError {
+    /// Classify the fault against the messages firecracker is known to send
+    pub fn kind(&self) -> InternalErrorKind {
+        InternalErrorKind::classify(&self.fault_message)
+    }
+}
+
+/// What went wrong according to a firecracker fault message
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InternalErrorKind {
+    /// The device, resource or host file referred to does not exist
+    NotFound,
+    /// The request body or one of its values is malformed
+    InvalidParameter,
+    /// The operation is not allowed in the current state of the machine,
+    /// e.g. after boot or before the resource is configured
+    InvalidState,
+    /// A host resource is temporarily in use, the request may succeed later
+    Busy,
+    /// A host resource could not be accessed for lack of permission
+    PermissionDenied,
+    /// Firecracker failed internally
+    Internal,
+    /// Unknown message
+    Other,
+}
+
+/// Known fault messages, by prefix, checked in order
+const KNOWN_FAULTS: &[(&str, InternalErrorKind)] = &[
+    ("Invalid block device ID", InternalErrorKind::NotFound),
+    ("Invalid network interface ID", InternalErrorKind::NotFound),
+    ("No balloon device found", InternalErrorKind::NotFound),
+    (
+        "Cannot start microvm that was already started",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "Cannot enable statistics after boot",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "Statistics for the balloon device are not enabled",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "Loading a microVM snapshot not allowed",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "Logger is already configured",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "Reinitialization of metrics not allowed",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "The MMDS data store is not initialized",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "The requested operation is not allowed",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "The requested operation is not supported",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "The update operation is not allowed",
+        InternalErrorKind::InvalidState,
+    ),
+    (
+        "The MMDS could not be configured",
+        InternalErrorKind::InvalidParameter,
+    ),
+    (
+        "An error occurred when deserializing",
+        InternalErrorKind::InvalidParameter,
+    ),
+    ("Invalid", InternalErrorKind::InvalidParameter),
+    ("Internal error", InternalErrorKind::Internal),
+];
+
+impl InternalErrorKind {
+    /// Classify `fault_message` by its known prefix, else by the OS error
+    /// it ends with, e.g. `Open tap device failed: Resource busy (os error 16)`
+    pub fn classify(fault_message: &str) -> Self {
+        if let Some((_, kind)) = KNOWN_FAULTS
+            .iter()
+            .find(|(prefix, _)| fault_message.starts_with(prefix))
+        {
+            return *kind;
+        }
+        if fault_message.contains("unknown field") || fault_message.contains("missing field") {
+            return InternalErrorKind::InvalidParameter;
+        }
+        if fault_message.ends_with("Address in use") {
+            return InternalErrorKind::Busy;
+        }
+        let os_error = fault_message
+            .rsplit_once("(os error ")
+            .and_then(|(_, code)| code.strip_suffix(')'))
+            .and_then(|code| code.parse::<i32>().ok());
+        match os_error {
+            Some(libc::ENOENT) => InternalErrorKind::NotFound,
+            Some(libc::EBUSY | libc::EAGAIN | libc::EINTR | libc::EADDRINUSE) => {
+                InternalErrorKind::Busy
+            }
+            Some(libc::EACCES | libc::EPERM) => InternalErrorKind::PermissionDenied,
+            _ => InternalErrorKind::Other,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, InternalErrorKind::Busy)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{InternalError, InternalErrorKind};
+
+    fn kind(fault_message: &str) -> InternalErrorKind {
+        InternalError {
+            fault_message: fault_message.into(),
+        }
+        .kind()
+    }
+
+    #[test]
+    fn test_not_found() {
+        assert_eq!(
+            kind("Invalid block device ID!"),
+            InternalErrorKind::NotFound
+        );
+        assert_eq!(
+            kind("Invalid network interface ID!"),
+            InternalErrorKind::NotFound
+        );
+        assert_eq!(
+            kind("No balloon device found."),
+            InternalErrorKind::NotFound
+        );
+        assert_eq!(
+            kind("The kernel file cannot be opened: No such file or directory (os error 2)"),
+            InternalErrorKind::NotFound
+        );
+        assert_eq!(
+            kind("Unable to create the block device: No such file or directory (os error 2)"),
+            InternalErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_invalid_state() {
+        for fault_message in [
+            "Cannot start microvm that was already started",
+            "Cannot enable statistics after boot.",
+            "Statistics for the balloon device are not enabled",
+            "Loading a microVM snapshot not allowed after configuring boot-specific resources.",
+            "Logger is already configured.",
+            "Reinitialization of metrics not allowed.",
+            "The MMDS data store is not initialized.",
+            "The requested operation is not allowed after boot.",
+            "The requested operation is not supported in current state.",
+            "The update operation is not allowed after boot.",
+        ] {
+            assert_eq!(
+                kind(fault_message),
+                InternalErrorKind::InvalidState,
+                "{fault_message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_parameter() {
+        for fault_message in [
+            "The MMDS could not be configured to version V2: No network interfaces",
+            "An error occurred when deserializing the json body of a request: missing field `vcpu_count` at line 1 column 2.",
+            "unknown field `deflate_on_oom`, expected `amount_mib`",
+            "Invalid vCPU number",
+        ] {
+            assert_eq!(
+                kind(fault_message),
+                InternalErrorKind::InvalidParameter,
+                "{fault_message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_busy() {
+        assert_eq!(
+            kind("Open tap device failed: Resource busy (os error 16)"),
+            InternalErrorKind::Busy
+        );
+        assert_eq!(
+            kind("Cannot create backend for vsock device: Address in use"),
+            InternalErrorKind::Busy
+        );
+        assert!(InternalErrorKind::Busy.is_retryable());
+        assert!(!InternalErrorKind::InvalidState.is_retryable());
+    }
+
+    #[test]
+    fn test_other() {
+        assert_eq!(kind("Internal error"), InternalErrorKind::Internal);
+        assert_eq!(
+            kind("Open tap device failed: Permission denied (os error 13)"),
+            InternalErrorKind::PermissionDenied
+        );
+        assert_eq!(kind("Something new"), InternalErrorKind::Other);
+        assert_eq!(kind("Failed (os error 9999)"), InternalErrorKind::Other);
+    }
+}
@@ -1,7 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+/// Oldest firecracker rustcracker supports, see `GlobalConfig::check_version`
+pub const MIN_SUPPORTED_VERSION: (u64, u64, u64) = (1, 0, 0);
+
 /// Describes the Firecracker version.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
 pub struct FirecrackerVersion {
     /// Firecracker build version.
     #[serde(rename = "firecracker_version")]
@@ -15,3 +21,77 @@ impl Default for FirecrackerVersion {
         }
     }
 }
+
+impl FirecrackerVersion {
+    /// Parse the (major, minor, patch) numbers of the version, accepting a
+    /// leading `v` and ignoring suffixes such as `-dev`
+    pub fn semver(&self) -> RtckResult<(u64, u64, u64)> {
+        let malformed = || {
+            RtckError::new(
+                RtckErrorClass::RemoteError,
+                format!(
+                    "Malformed firecracker version {:?}",
+                    self.firecracker_version
+                ),
+            )
+        };
+        let version = self.firecracker_version.trim();
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let version = version.split(['-', '+']).next().unwrap_or_default();
+        let mut numbers = version.split('.').map(|number| number.parse::<u64>());
+        let mut next = || {
+            numbers
+                .next()
+                .ok_or_else(malformed)?
+                .map_err(|_| malformed())
+        };
+        Ok((next()?, next()?, next()?))
+    }
+
+    /// Whether the version is `major.minor` or later
+    pub fn at_least(&self, major: u64, minor: u64) -> RtckResult<bool> {
+        let (self_major, self_minor, _) = self.semver()?;
+        Ok((self_major, self_minor) >= (major, minor))
+    }
+
+    /// Check that the version is `min`, as (major, minor, patch), or later
+    pub fn check_compatible(&self, min: (u64, u64, u64)) -> RtckResult<()> {
+        if self.semver()? < min {
+            let (major, minor, patch) = min;
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Firecracker {} is not compatible, v{major}.{minor}.{patch} or later is required",
+                    self.firecracker_version
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FirecrackerVersion;
+
+    #[test]
+    fn test_semver() {
+        let version = |version: &str| FirecrackerVersion {
+            firecracker_version: version.into(),
+        };
+        assert_eq!(version("1.7.0").semver().unwrap(), (1, 7, 0));
+        assert_eq!(version("v1.10.1").semver().unwrap(), (1, 10, 1));
+        assert_eq!(version("1.8.0-dev").semver().unwrap(), (1, 8, 0));
+        for malformed in ["", "1.7", "one.7.0", "1..0"] {
+            assert!(version(malformed).semver().is_err(), "{malformed}");
+        }
+
+        assert!(version("1.10.0").at_least(1, 7).unwrap());
+        assert!(version("2.0.0").at_least(1, 7).unwrap());
+        assert!(!version("1.6.9").at_least(1, 7).unwrap());
+
+        assert!(version("1.0.0").check_compatible((1, 0, 0)).is_ok());
+        let e = version("0.25.2").check_compatible((1, 0, 0)).unwrap_err();
+        assert!(e.to_string().contains("v1.0.0 or later"), "{e}");
+    }
+}
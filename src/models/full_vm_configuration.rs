@@ -2,17 +2,21 @@ use serde::{Deserialize, Serialize};
 
 use super::*;
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
 pub struct FullVmConfiguration {
     #[serde(rename = "balloon", skip_serializing_if = "Option::is_none")]
     pub balloon: Option<balloon::Balloon>,
 
     /// Configurations for all block devices.
-    #[serde(rename = "drive", skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "drives", skip_serializing_if = "Option::is_none")]
     pub drives: Option<Vec<drive::Drive>>,
 
     #[serde(rename = "boot-source", skip_serializing_if = "Option::is_none")]
     pub boot_source: Option<boot_source::BootSource>,
 
+    #[serde(rename = "cpu-config", skip_serializing_if = "Option::is_none")]
+    pub cpu_config: Option<cpu_template::CPUConfig>,
+
     #[serde(rename = "logger", skip_serializing_if = "Option::is_none")]
     pub logger: Option<logger::Logger>,
 
@@ -31,6 +35,9 @@ pub struct FullVmConfiguration {
 
     #[serde(rename = "vsock", skip_serializing_if = "Option::is_none")]
     pub vsock: Option<vsock::Vsock>,
+
+    #[serde(rename = "entropy", skip_serializing_if = "Option::is_none")]
+    pub entropy: Option<entropy_device::EntropyDevice>,
 }
 
 impl Default for FullVmConfiguration {
@@ -39,12 +46,14 @@ impl Default for FullVmConfiguration {
             balloon: None,
             drives: None,
             boot_source: None,
+            cpu_config: None,
             logger: None,
             machine_config: None,
             metrics: None,
             mmds_config: None,
             network_interfaces: None,
             vsock: None,
+            entropy: None,
         }
     }
 }
@@ -68,6 +77,11 @@ impl FullVmConfiguration {
         self
     }
 
+    pub fn with_cpu_config(mut self, cpu_config: &cpu_template::CPUConfig) -> Self {
+        self.cpu_config = Some(cpu_config.to_owned());
+        self
+    }
+
     pub fn with_logger(mut self, logger: &logger::Logger) -> Self {
         self.logger = Some(logger.to_owned());
         self
@@ -109,4 +123,9 @@ impl FullVmConfiguration {
         self.vsock = Some(vsock.to_owned());
         self
     }
+
+    pub fn with_entropy(mut self, entropy: &entropy_device::EntropyDevice) -> Self {
+        self.entropy = Some(entropy.to_owned());
+        self
+    }
 }
@@ -0,0 +1,97 @@
+//! Models and endpoints generated by `build.rs` from the pinned
+//! `firecracker.yaml`, enabled by the `codegen` feature.
+//!
+//! Modules and types are named after the hand-written models, e.g.
+//! `generated::drive::Drive` for [`super::drive::Drive`], and serialize to
+//! the same JSON. They carry no builders nor validation: those stay with the
+//! hand-written models, which rustcracker keeps using. Drift between the two
+//! is reported by `tests/openapi_drift`.
+
+/// An endpoint of the firecracker API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    /// `operationId` of the endpoint in the spec
+    pub operation_id: &'static str,
+    /// HTTP method, in upper case
+    pub method: &'static str,
+    /// Path template, e.g. `/drives/{drive_id}`
+    pub path: &'static str,
+}
+
+impl Endpoint {
+    /// Whether `path` is an instance of the path template
+    pub fn matches(&self, path: &str) -> bool {
+        let mut template = self.path.split('/');
+        let mut path = path.split('/');
+        loop {
+            match (template.next(), path.next()) {
+                (None, None) => return true,
+                (Some(t), Some(p)) if t.starts_with('{') && t.ends_with('}') && !p.is_empty() => {}
+                (Some(t), Some(p)) if t == p => {}
+                _ => return false,
+            }
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/firecracker_api.rs"));
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use serde_json::json;
+
+    use super::endpoints;
+
+    #[test]
+    fn test_endpoints() {
+        assert_eq!(endpoints::PUT_GUEST_DRIVE_BY_ID.method, "PUT");
+        assert_eq!(endpoints::PUT_GUEST_DRIVE_BY_ID.path, "/drives/{drive_id}");
+        assert!(endpoints::PUT_GUEST_DRIVE_BY_ID.matches("/drives/rootfs"));
+        assert!(!endpoints::PUT_GUEST_DRIVE_BY_ID.matches("/drives/"));
+        assert!(!endpoints::PUT_GUEST_DRIVE_BY_ID.matches("/drives/rootfs/x"));
+        assert!(endpoints::DESCRIBE_INSTANCE.matches("/"));
+        assert!(!endpoints::DESCRIBE_INSTANCE.matches("/vm"));
+
+        let unique: HashSet<_> = endpoints::ALL
+            .iter()
+            .map(|endpoint| (endpoint.method, endpoint.path))
+            .collect();
+        assert_eq!(unique.len(), endpoints::ALL.len());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let drive = json!({
+            "drive_id": "rootfs",
+            "is_root_device": true,
+            "cache_type": "Writeback",
+            "is_read_only": false,
+            "path_on_host": "/tmp/rootfs.ext4",
+            "io_engine": "Async",
+            "rate_limiter": {"bandwidth": {"size": 1024, "refill_time": 100}},
+        });
+        let generated: super::drive::Drive = serde_json::from_value(drive.clone()).unwrap();
+        assert_eq!(
+            generated.cache_type,
+            Some(super::drive::CacheType::WriteBack)
+        );
+        assert_eq!(serde_json::to_value(generated).unwrap(), drive);
+
+        let machine_config = json!({
+            "vcpu_count": 2,
+            "mem_size_mib": 256,
+            "smt": true,
+            "cpu_template": "T2",
+            "huge_pages": "2M",
+        });
+        let generated: super::machine_configuration::MachineConfiguration =
+            serde_json::from_value(machine_config.clone()).unwrap();
+        assert_eq!(
+            generated.huge_pages,
+            Some(super::machine_configuration::HugePages::Hugetlbfs2M)
+        );
+        assert_eq!(serde_json::to_value(generated).unwrap(), machine_config);
+    }
+}
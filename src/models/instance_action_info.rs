@@ -1,13 +1,31 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::Validate;
+
+/// Action firecracker can perform on the instance, serialized as its
+/// name on the wire, e.g. `InstanceStart`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "PascalCase")]
 pub enum ActionType {
-    #[serde(rename = "FlushMetrics")]
+    /// Flush the metrics to the metrics file
     FlushMetrics,
-    #[serde(rename = "InstanceStart")]
+    /// Boot the configured machine
     InstanceStart,
-    #[serde(rename = "SendCtrlAltDel")]
-    SendCtrlAtlDel,
+    /// Ask the guest to shut down with a keyboard reset, x86_64 only
+    SendCtrlAltDel,
+}
+
+impl ActionType {
+    /// Whether firecracker supports the action on hosts of `arch`, as in
+    /// `std::env::consts::ARCH`
+    pub fn supported_on(&self, arch: &str) -> bool {
+        match self {
+            ActionType::SendCtrlAltDel => arch != "aarch64",
+            ActionType::FlushMetrics | ActionType::InstanceStart => true,
+        }
+    }
 }
 
 /// Variant wrapper containing the real action.
@@ -19,3 +37,78 @@ pub struct InstanceActionInfo {
     #[serde(rename = "action_type")]
     pub action_type: ActionType,
 }
+
+impl InstanceActionInfo {
+    pub fn new(action_type: ActionType) -> Self {
+        Self { action_type }
+    }
+
+    pub fn flush_metrics() -> Self {
+        Self::new(ActionType::FlushMetrics)
+    }
+
+    pub fn instance_start() -> Self {
+        Self::new(ActionType::InstanceStart)
+    }
+
+    /// Not available on aarch64, where firecracker has no keyboard
+    /// controller to send the keys to
+    #[cfg(not(target_arch = "aarch64"))]
+    pub fn send_ctrl_alt_del() -> Self {
+        Self::new(ActionType::SendCtrlAltDel)
+    }
+}
+
+impl Validate for InstanceActionInfo {
+    fn validate(&self) -> RtckResult<()> {
+        let arch = std::env::consts::ARCH;
+        if !self.action_type.supported_on(arch) {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Action {:?} is not supported on {arch}", self.action_type),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ActionType, InstanceActionInfo};
+
+    #[test]
+    fn test_action_type_wire_names() {
+        for (action_type, name) in [
+            (ActionType::FlushMetrics, "FlushMetrics"),
+            (ActionType::InstanceStart, "InstanceStart"),
+            (ActionType::SendCtrlAltDel, "SendCtrlAltDel"),
+        ] {
+            let json = serde_json::to_string(&InstanceActionInfo::new(action_type)).unwrap();
+            assert_eq!(json, format!(r#"{{"action_type":"{name}"}}"#));
+            assert_eq!(
+                serde_json::from_str::<InstanceActionInfo>(&json)
+                    .unwrap()
+                    .action_type,
+                action_type
+            );
+        }
+        assert!(serde_json::from_str::<ActionType>(r#""SendCtrlAtlDel""#).is_err());
+    }
+
+    #[test]
+    fn test_action_type_arch() {
+        use crate::models::Validate;
+
+        assert!(ActionType::SendCtrlAltDel.supported_on("x86_64"));
+        assert!(!ActionType::SendCtrlAltDel.supported_on("aarch64"));
+        assert!(ActionType::FlushMetrics.supported_on("aarch64"));
+        assert!(ActionType::InstanceStart.supported_on("aarch64"));
+
+        let send_ctrl_alt_del = InstanceActionInfo::new(ActionType::SendCtrlAltDel);
+        assert_eq!(
+            send_ctrl_alt_del.validate().is_ok(),
+            cfg!(not(target_arch = "aarch64"))
+        );
+        assert!(InstanceActionInfo::instance_start().validate().is_ok());
+    }
+}
@@ -14,6 +14,7 @@ pub enum State {
 
 /// Describes MicroVM instance information.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
 pub struct InstanceInfo {
     /// Application name.
     /// Required: true
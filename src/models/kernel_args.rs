@@ -1,39 +1,350 @@
-use std::collections::HashMap;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct KernelArgs(pub HashMap<String, Option<String>>);
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+/// Kernel command line, as bare flags (`quiet`) and `key=value` parameters
+/// kept in the order they were given, repeated keys and the arguments of
+/// init after `--` included, so that the guest always boots with the same
+/// command line.
+///
+/// # Example
+///
+/// ```
+/// use rustcracker::models::kernel_args::KernelArgs;
+///
+/// let mut args = KernelArgs::from(r#"console=ttyS0 quiet root="/dev/vda1""#);
+/// assert_eq!(args.get("root"), Some(Some("/dev/vda1")));
+/// args.insert("panic", Some("1"));
+/// assert_eq!(args.to_string(), "console=ttyS0 quiet root=/dev/vda1 panic=1");
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KernelArgs(pub Vec<(String, Option<String>)>);
+
+impl KernelArgs {
+    /// Value of `key`, `Some(None)` for a bare flag
+    pub fn get(&self, key: &str) -> Option<Option<&str>> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_deref())
+    }
+
+    /// Set the kernel parameter `key`, in place of its first occurrence
+    /// with the later ones removed if already present, and last otherwise.
+    /// The arguments of init after `--` are left alone.
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: Option<V>) {
+        let key = key.into();
+        let value = value.map(Into::into);
+        let end = self
+            .0
+            .iter()
+            .position(|(k, v)| k == "--" && v.is_none())
+            .unwrap_or(self.0.len());
+        match self.0[..end].iter().position(|(k, _)| *k == key) {
+            Some(first) => {
+                self.0[first].1 = value;
+                let mut index = 0;
+                self.0.retain(|(k, _)| {
+                    let keep = index <= first || index >= end || *k != key;
+                    index += 1;
+                    keep
+                });
+            }
+            None => self.0.insert(end, (key, value)),
+        }
+    }
+
+    /// Remove `key`, returning its value if it was present
+    pub fn remove(&mut self, key: &str) -> Option<Option<String>> {
+        let index = self.0.iter().position(|(k, _)| k == key)?;
+        Some(self.0.remove(index).1)
+    }
+
+    /// Set the `ip=` parameter configuring the guest network statically
+    pub fn set_ip_boot_param(&mut self, param: &IpBootParam) -> RtckResult<()> {
+        self.insert("ip", Some(param.to_value()?));
+        Ok(())
+    }
+}
 
 // serialize the kernelArgs back to a string that can be provided
-// to the kernel
-impl ToString for KernelArgs {
-    fn to_string(&self) -> String {
-        let mut fields: Vec<String> = Vec::new();
-        self.0.iter().for_each(|(key, value)| {
-            let mut field = key.to_owned();
-            if let Some(s) = value.as_ref() {
-                field.push_str("=");
-                field += s;
-            }
-            fields.push(field);
-        });
-        fields.join(" ")
+// to the kernel, quoting values containing whitespace
+impl fmt::Display for KernelArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(key)?;
+            match value {
+                None => (),
+                Some(value) if value.contains(char::is_whitespace) => write!(f, "=\"{value}\"")?,
+                Some(value) => write!(f, "={value}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// deserialize the provided string to a kernelArgs, the way the kernel
+// splits its command line: on whitespace outside of double quotes, which
+// are dropped
+impl From<&str> for KernelArgs {
+    fn from(raw_string: &str) -> Self {
+        let mut args = Self::default();
+        let mut chars = raw_string.chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+            let mut token = String::new();
+            let mut quoted = false;
+            while let Some(c) = chars.next_if(|c| quoted || !c.is_whitespace()) {
+                if c == '"' {
+                    quoted = !quoted;
+                } else {
+                    token.push(c);
+                }
+            }
+            match token.split_once('=') {
+                Some((key, value)) => args.0.push((key.to_string(), Some(value.to_string()))),
+                None => args.0.push((token, None)),
+            }
+        }
+        args
     }
 }
 
-// deserialize the provided string to a kernelArgs map
 impl From<String> for KernelArgs {
     fn from(raw_string: String) -> Self {
-        let mut arg_map: HashMap<String, Option<String>> = HashMap::new();
-        raw_string.split_ascii_whitespace().for_each(|kv_pair| {
-            if let Some((key, value)) = kv_pair.split_once("=") {
-                arg_map.insert(key.into(), Some(value.into()));
-            } else {
-                arg_map.insert(kv_pair.into(), None);
-            }
-        }); 
-        
-        Self(arg_map)
-    }
-}
\ No newline at end of file
+        Self::from(raw_string.as_str())
+    }
+}
+
+/// Static network configuration rendered as the kernel `ip=` boot parameter, i.e.
+/// `ip=<client-ip>::<gw-ip>:<netmask>:<hostname>:<device>:off[:<dns0-ip>[:<dns1-ip>]]`.
+/// IPv6 addresses are wrapped in brackets and the netmask is given as a prefix length.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct IpBootParam {
+    /// Address assigned to the guest interface
+    pub client_ip: IpAddr,
+
+    /// Default gateway, must be of the same family as `client_ip`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<IpAddr>,
+
+    /// Length of the network prefix, e.g. 24 for `255.255.255.0`
+    pub prefix_len: u8,
+
+    /// Hostname of the guest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+
+    /// Guest device name, e.g. `eth0`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+
+    /// At most two name servers, must be of the same family as `client_ip`
+    pub nameservers: Vec<IpAddr>,
+}
+
+impl IpBootParam {
+    pub fn new(client_ip: IpAddr, prefix_len: u8) -> Self {
+        Self {
+            client_ip,
+            gateway: None,
+            prefix_len,
+            hostname: None,
+            device: None,
+            nameservers: Vec::new(),
+        }
+    }
+
+    pub fn validate(&self) -> RtckResult<()> {
+        let max_prefix_len = if self.client_ip.is_ipv4() { 32 } else { 128 };
+        if self.prefix_len > max_prefix_len {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Prefix length {} out of range", self.prefix_len),
+            ));
+        }
+
+        let same_family = |addr: &IpAddr| addr.is_ipv4() == self.client_ip.is_ipv4();
+        if !self.gateway.iter().all(same_family) || !self.nameservers.iter().all(same_family) {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Mixed IPv4 and IPv6 addresses in ip boot parameter",
+            ));
+        }
+
+        if self.nameservers.len() > 2 {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Kernel accepts at most 2 name servers",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Render the value of the `ip=` parameter (without the `ip=` key).
+    pub fn to_value(&self) -> RtckResult<String> {
+        self.validate()?;
+
+        fn fmt_addr(addr: &IpAddr) -> String {
+            match addr {
+                IpAddr::V4(v4) => v4.to_string(),
+                IpAddr::V6(v6) => format!("[{v6}]"),
+            }
+        }
+
+        let netmask = match self.client_ip {
+            IpAddr::V4(_) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                Ipv4Addr::from(mask).to_string()
+            }
+            IpAddr::V6(_) => self.prefix_len.to_string(),
+        };
+
+        let mut fields = vec![
+            fmt_addr(&self.client_ip),
+            String::new(),
+            self.gateway.as_ref().map(fmt_addr).unwrap_or_default(),
+            netmask,
+            self.hostname.clone().unwrap_or_default(),
+            self.device.clone().unwrap_or_default(),
+            "off".to_string(),
+        ];
+        fields.extend(self.nameservers.iter().map(fmt_addr));
+
+        Ok(fields.join(":"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use super::{IpBootParam, KernelArgs};
+
+    #[test]
+    fn test_kernel_args_parse() {
+        let args = KernelArgs::from(r#"console=ttyS0 quiet root="/dev/vda1""#);
+        assert_eq!(
+            args.0,
+            [
+                ("console".to_string(), Some("ttyS0".to_string())),
+                ("quiet".to_string(), None),
+                ("root".to_string(), Some("/dev/vda1".to_string())),
+            ]
+        );
+        assert_eq!(args.to_string(), "console=ttyS0 quiet root=/dev/vda1");
+
+        // Whitespace inside quotes, empty values and repeated keys
+        let args =
+            KernelArgs::from("  init=/sbin/init  msg=\"hello  world\" empty= quiet=1 quiet ");
+        assert_eq!(args.get("msg"), Some(Some("hello  world")));
+        assert_eq!(args.get("empty"), Some(Some("")));
+        assert_eq!(args.get("quiet"), Some(Some("1")));
+        assert_eq!(args.get("missing"), None);
+        assert_eq!(
+            args.to_string(),
+            "init=/sbin/init msg=\"hello  world\" empty= quiet=1 quiet"
+        );
+        assert!(KernelArgs::from("").0.is_empty());
+    }
+
+    #[test]
+    fn test_kernel_args_round_trip() {
+        for raw in [
+            r#"console=ttyS0 quiet root="/dev/vda1""#,
+            "reboot=k panic=1 pci=off nomodules 8250.nr_uarts=0",
+            r#"msg="a b" -- init_arg"#,
+            "ip=172.16.0.2::172.16.0.1:255.255.255.0:vm0:eth0:off",
+            "console=ttyS0 console=tty0 reboot=k -- init_arg=1 init_arg=2",
+        ] {
+            let args = KernelArgs::from(raw);
+            assert_eq!(KernelArgs::from(args.to_string()), args, "{raw}");
+        }
+
+        // Repeated keys and init arguments are kept as given
+        let raw = "console=ttyS0 console=tty0 reboot=k -- init_arg=1 init_arg=2";
+        assert_eq!(KernelArgs::from(raw).to_string(), raw);
+    }
+
+    #[test]
+    fn test_kernel_args_set_ip_boot_param() {
+        let mut args = KernelArgs::from("console=ttyS0 ip=dhcp quiet");
+        let mut param = IpBootParam::new("172.16.0.2".parse().unwrap(), 24);
+        param.gateway = Some("172.16.0.1".parse().unwrap());
+        param.device = Some("eth0".to_string());
+        args.set_ip_boot_param(&param).unwrap();
+        assert_eq!(
+            args.to_string(),
+            "console=ttyS0 ip=172.16.0.2::172.16.0.1:255.255.255.0::eth0:off quiet"
+        );
+
+        param.prefix_len = 33;
+        assert!(args.set_ip_boot_param(&param).is_err());
+        assert_eq!(
+            args.remove("ip"),
+            Some(Some(
+                "172.16.0.2::172.16.0.1:255.255.255.0::eth0:off".to_string()
+            ))
+        );
+        assert_eq!(args.to_string(), "console=ttyS0 quiet");
+
+        // Set before the arguments of init, once
+        let mut args = KernelArgs::from("ip=dhcp console=ttyS0 ip=off -- ip=init");
+        param.prefix_len = 24;
+        args.set_ip_boot_param(&param).unwrap();
+        assert_eq!(
+            args.to_string(),
+            "ip=172.16.0.2::172.16.0.1:255.255.255.0::eth0:off console=ttyS0 -- ip=init"
+        );
+        let mut args = KernelArgs::from("console=ttyS0 -- init_arg=1");
+        args.set_ip_boot_param(&param).unwrap();
+        assert_eq!(
+            args.to_string(),
+            "console=ttyS0 ip=172.16.0.2::172.16.0.1:255.255.255.0::eth0:off -- init_arg=1"
+        );
+    }
+
+    #[test]
+    fn test_ip_boot_param_v4() {
+        let mut param = IpBootParam::new("172.16.0.2".parse().unwrap(), 24);
+        param.gateway = Some("172.16.0.1".parse().unwrap());
+        param.hostname = Some("vm0".to_string());
+        param.device = Some("eth0".to_string());
+        param.nameservers = vec!["8.8.8.8".parse().unwrap()];
+        assert_eq!(
+            param.to_value().unwrap(),
+            "172.16.0.2::172.16.0.1:255.255.255.0:vm0:eth0:off:8.8.8.8"
+        );
+    }
+
+    #[test]
+    fn test_ip_boot_param_v6() {
+        let mut param = IpBootParam::new("fd00::2".parse().unwrap(), 64);
+        param.gateway = Some("fd00::1".parse().unwrap());
+        param.device = Some("eth0".to_string());
+        assert_eq!(
+            param.to_value().unwrap(),
+            "[fd00::2]::[fd00::1]:64::eth0:off"
+        );
+
+        param.gateway = Some("172.16.0.1".parse::<IpAddr>().unwrap());
+        assert!(param.to_value().is_err());
+        param.gateway = None;
+        param.prefix_len = 129;
+        assert!(param.to_value().is_err());
+    }
+}
@@ -1,11 +1,25 @@
+use std::{fmt, str::FromStr};
+
 use serde::{Deserialize, Serialize};
 
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::firecracker_version::FirecrackerVersion;
+
+/// Level of the firecracker logger, serialized with the capitalized names
+/// firecracker expects but parsed case-insensitively, e.g. from config files.
+/// Firecracker v1.5 renamed "Warning" to "Warn" and added "Trace" and "Off",
+/// see `for_version`.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "String")]
 pub enum LogLevel {
     #[serde(rename = "Error")]
     Error,
     #[serde(rename = "Warning")]
     Warning,
+    /// `Warning`, as named by firecracker v1.5 and later
+    #[serde(rename = "Warn")]
+    Warn,
     #[serde(rename = "Info")]
     Info,
     #[serde(rename = "Debug")]
@@ -16,9 +30,105 @@ pub enum LogLevel {
     Off,
 }
 
+impl LogLevel {
+    /// Oldest firecracker naming the warning level "Warn", and knowing the
+    /// "Trace" and "Off" levels
+    pub const WARN_FIRECRACKER_VERSION: (u64, u64) = (1, 5);
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "Error",
+            LogLevel::Warning => "Warning",
+            LogLevel::Warn => "Warn",
+            LogLevel::Info => "Info",
+            LogLevel::Debug => "Debug",
+            LogLevel::Trace => "Trace",
+            LogLevel::Off => "Off",
+        }
+    }
+
+    /// Whether firecracker names the level differently, or does not know
+    /// it, across versions
+    pub fn depends_on_version(&self) -> bool {
+        matches!(
+            self,
+            LogLevel::Warning | LogLevel::Warn | LogLevel::Trace | LogLevel::Off
+        )
+    }
+
+    /// The level as firecracker `version` names it, failing if it does
+    /// not know the level
+    pub fn for_version(&self, version: &FirecrackerVersion) -> RtckResult<Self> {
+        let (major, minor) = Self::WARN_FIRECRACKER_VERSION;
+        let current = version.at_least(major, minor)?;
+        match self {
+            LogLevel::Warning | LogLevel::Warn if current => Ok(LogLevel::Warn),
+            LogLevel::Warning | LogLevel::Warn => Ok(LogLevel::Warning),
+            LogLevel::Trace | LogLevel::Off if !current => Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Log level {self} requires firecracker v{major}.{minor} or later, running {}",
+                    version.firecracker_version
+                ),
+            )),
+            level => Ok(level.clone()),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = RtckError;
+
+    /// Parse a level ignoring case, accepting "warn" for `Warning`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warning" | "warn" => Ok(LogLevel::Warning),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            "off" => Ok(LogLevel::Off),
+            _ => Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Unknown log level {s:?}"),
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for LogLevel {
+    type Error = RtckError;
+
+    fn try_from(value: String) -> Result<Self, RtckError> {
+        value.parse()
+    }
+}
+
 /// Describes the configuration option for the logging capability.
 /// logger can only be constructed once
 /// and cannot update after configuration
+///
+/// # Example
+///
+/// ```
+/// use rustcracker::models::logger::{LogLevel, Logger};
+///
+/// let logger = Logger::builder()
+///     .path("/tmp/firecracker.log")
+///     .level(LogLevel::Debug)
+///     .show_level(true)
+///     .show_log_origin(true)
+///     .module("api_server")
+///     .build()
+///     .unwrap();
+/// assert_eq!(logger.module.as_deref(), Some("api_server"));
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Logger {
     /// Set the level. The possible values are case-insensitive.
@@ -43,3 +153,194 @@ pub struct Logger {
     #[serde(rename = "module", skip_serializing_if = "Option::is_none")]
     pub module: Option<String>,
 }
+
+impl Logger {
+    pub fn builder() -> LoggerBuilder {
+        LoggerBuilder::default()
+    }
+}
+
+/// Builder of `Logger`, the path is required.
+#[derive(Debug, Clone, Default)]
+pub struct LoggerBuilder {
+    path: Option<String>,
+    level: Option<LogLevel>,
+    show_level: Option<bool>,
+    show_log_origin: Option<bool>,
+    module: Option<String>,
+}
+
+impl LoggerBuilder {
+    pub fn path<S: Into<String>>(mut self, path: S) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Same as `path`, named after the field of `Logger`
+    pub fn log_path<S: Into<String>>(self, log_path: S) -> Self {
+        self.path(log_path)
+    }
+
+    pub fn level(mut self, level: LogLevel) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    pub fn show_level(mut self, show_level: bool) -> Self {
+        self.show_level = Some(show_level);
+        self
+    }
+
+    pub fn show_log_origin(mut self, show_log_origin: bool) -> Self {
+        self.show_log_origin = Some(show_log_origin);
+        self
+    }
+
+    /// Same as `show_log_origin`
+    pub fn show_origin(self, show_origin: bool) -> Self {
+        self.show_log_origin(show_origin)
+    }
+
+    /// Only keep the messages of `module`, e.g. api_server::request
+    pub fn module<S: Into<String>>(mut self, module: S) -> Self {
+        self.module = Some(module.into());
+        self
+    }
+
+    pub fn build(self) -> RtckResult<Logger> {
+        let log_path = self
+            .path
+            .filter(|path| !path.is_empty())
+            .ok_or(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Invalid logger: missing path",
+            ))?;
+        Ok(Logger {
+            level: self.level,
+            log_path,
+            show_level: self.show_level,
+            show_log_origin: self.show_log_origin,
+            module: self.module,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LogLevel, Logger};
+
+    #[test]
+    fn test_log_level_parsing() {
+        for (s, level) in [
+            ("Error", LogLevel::Error),
+            ("warn", LogLevel::Warning),
+            ("WARNING", LogLevel::Warning),
+            ("info", LogLevel::Info),
+            ("Debug", LogLevel::Debug),
+            ("trace", LogLevel::Trace),
+            ("off", LogLevel::Off),
+        ] {
+            assert_eq!(s.parse::<LogLevel>().unwrap(), level);
+        }
+        assert!("verbose".parse::<LogLevel>().is_err());
+        assert_eq!(LogLevel::Warning.to_string(), "Warning");
+
+        // Parsed ignoring case, serialized the way firecracker expects
+        let level: LogLevel = serde_json::from_str(r#""debug""#).unwrap();
+        assert_eq!(serde_json::to_string(&level).unwrap(), r#""Debug""#);
+        assert!(serde_json::from_str::<LogLevel>(r#""verbose""#).is_err());
+    }
+
+    #[test]
+    fn test_log_level_for_version() {
+        use crate::models::firecracker_version::FirecrackerVersion;
+
+        let version = |version: &str| FirecrackerVersion {
+            firecracker_version: version.into(),
+        };
+        let serialized = |level: &LogLevel, version: &FirecrackerVersion| {
+            serde_json::to_string(&level.for_version(version).unwrap()).unwrap()
+        };
+
+        // Legacy casing
+        let legacy = version("v1.4.1");
+        assert_eq!(serialized(&LogLevel::Warning, &legacy), r#""Warning""#);
+        assert_eq!(serialized(&LogLevel::Warn, &legacy), r#""Warning""#);
+        assert_eq!(serialized(&LogLevel::Info, &legacy), r#""Info""#);
+        assert!(LogLevel::Trace.for_version(&legacy).is_err());
+        assert!(LogLevel::Off.for_version(&legacy).is_err());
+
+        // Current casing
+        let current = version("1.7.0");
+        assert_eq!(serialized(&LogLevel::Warning, &current), r#""Warn""#);
+        assert_eq!(serialized(&LogLevel::Warn, &current), r#""Warn""#);
+        assert_eq!(serialized(&LogLevel::Trace, &current), r#""Trace""#);
+        assert_eq!(serialized(&LogLevel::Off, &current), r#""Off""#);
+
+        assert!(!LogLevel::Debug.depends_on_version());
+        assert!(LogLevel::Warning.for_version(&version("dev")).is_err());
+        assert_eq!("Warn".parse::<LogLevel>().unwrap(), LogLevel::Warning);
+    }
+
+    #[test]
+    fn test_logger_builder() {
+        let logger = Logger::builder()
+            .path("/tmp/firecracker.log")
+            .level(LogLevel::Debug)
+            .show_level(true)
+            .show_log_origin(true)
+            .module("api_server")
+            .build()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(&logger).unwrap(),
+            serde_json::json!({
+                "level": "Debug",
+                "log_path": "/tmp/firecracker.log",
+                "show_level": true,
+                "show_log_origin": true,
+                "module": "api_server",
+            })
+        );
+
+        assert!(Logger::builder().level(LogLevel::Info).build().is_err());
+        assert!(Logger::builder().path("").build().is_err());
+    }
+
+    #[test]
+    fn test_logger_builder_literal() {
+        let logger = Logger::builder()
+            .log_path("/tmp/firecracker.log")
+            .level(LogLevel::Warning)
+            .show_level(false)
+            .show_origin(true)
+            .module("vmm::builder")
+            .build()
+            .unwrap();
+        assert_eq!(
+            logger,
+            Logger {
+                level: Some(LogLevel::Warning),
+                log_path: "/tmp/firecracker.log".into(),
+                show_level: Some(false),
+                show_log_origin: Some(true),
+                module: Some("vmm::builder".into()),
+            }
+        );
+
+        // Left out settings are left to firecracker
+        assert_eq!(
+            Logger::builder()
+                .log_path("/tmp/firecracker.log")
+                .build()
+                .unwrap(),
+            Logger {
+                level: None,
+                log_path: "/tmp/firecracker.log".into(),
+                show_level: None,
+                show_log_origin: None,
+                module: None,
+            }
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+/// Bit of the first octet marking a multicast address.
+pub const MULTICAST_BIT: u8 = 0x01;
+
+/// Bit of the first octet marking a locally administered address.
+pub const LOCALLY_ADMINISTERED_BIT: u8 = 0x02;
+
+/// Check a guest MAC in the `xx:xx:xx:xx:xx:xx` form firecracker accepts,
+/// refusing multicast addresses which cannot be assigned to an interface.
+pub fn validate_mac(mac: &str) -> RtckResult<()> {
+    let octets = parse_mac(mac).ok_or(RtckError::new(
+        RtckErrorClass::ConfigError,
+        format!("Malformed MAC address {mac:?}, expecting xx:xx:xx:xx:xx:xx"),
+    ))?;
+
+    if octets[0] & MULTICAST_BIT != 0 {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("MAC address {mac:?} is multicast"),
+        ));
+    }
+
+    Ok(())
+}
+
+fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let mut octets = [0u8; 6];
+    let mut groups = mac.split(':');
+    for octet in octets.iter_mut() {
+        let group = groups.next()?;
+        if group.len() != 2 || !group.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        *octet = u8::from_str_radix(group, 16).ok()?;
+    }
+    groups.next().is_none().then_some(octets)
+}
+
+/// Derive a MAC from a hash of `vmid`, `iface_id` and `salt`, as the
+/// `prefix` octet (`02` by default) and five hashed octets. The result is
+/// stable across runs and always a locally administered unicast address,
+/// whatever the prefix. Distinct salts (e.g. one per rustcracker instance)
+/// keep instances sharing vmids apart.
+///
+/// Uniqueness is probabilistic: with 40 hashed bits, a collision is about
+/// even odds only past a million interfaces sharing the prefix.
+pub fn generate_mac(vmid: &str, iface_id: &str, prefix: Option<u8>, salt: Option<&[u8]>) -> String {
+    let hash = match salt {
+        Some(salt) => fnv1a(&[vmid.as_bytes(), iface_id.as_bytes(), salt]),
+        None => fnv1a(&[vmid.as_bytes(), iface_id.as_bytes()]),
+    };
+    let first =
+        (prefix.unwrap_or(LOCALLY_ADMINISTERED_BIT) | LOCALLY_ADMINISTERED_BIT) & !MULTICAST_BIT;
+    let [_, _, _, a, b, c, d, e] = hash.to_be_bytes();
+    format!("{first:02x}:{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}")
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is stable across rust releases.
+/// Parts are separated so that ("ab", "c") and ("a", "bc") differ.
+pub(crate) fn fnv1a(parts: &[&[u8]]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    let mut hash = OFFSET_BASIS;
+    for part in parts {
+        for byte in part.iter().chain(&[0xff]) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::{generate_mac, validate_mac, LOCALLY_ADMINISTERED_BIT, MULTICAST_BIT};
+
+    #[test]
+    fn test_validate_mac() {
+        assert!(validate_mac("06:00:AC:10:00:02").is_ok());
+        assert!(validate_mac("02:fc:00:00:00:01").is_ok());
+        // Multicast
+        assert!(validate_mac("01:00:5e:00:00:01").is_err());
+        assert!(validate_mac("ff:ff:ff:ff:ff:ff").is_err());
+        // Malformed
+        for mac in [
+            "",
+            "06:00:AC:10:00",
+            "06:00:AC:10:00:02:03",
+            "06-00-AC-10-00-02",
+            "06:00:AC:10:00:2",
+            "06:00:AC:10:00:0g",
+            "+6:00:AC:10:00:02",
+        ] {
+            assert!(validate_mac(mac).is_err(), "{mac} accepted");
+        }
+    }
+
+    #[test]
+    fn test_generate_mac() {
+        let mac = generate_mac("vm0", "eth0", Some(0x06), None);
+        assert!(mac.starts_with("06:"));
+        assert!(validate_mac(&mac).is_ok());
+        // Deterministic
+        assert_eq!(mac, generate_mac("vm0", "eth0", Some(0x06), None));
+        // Distinct per vmid, interface and salt
+        assert_ne!(mac, generate_mac("vm1", "eth0", Some(0x06), None));
+        assert_ne!(mac, generate_mac("vm0", "eth1", Some(0x06), None));
+        assert_ne!(mac, generate_mac("vm0", "eth0", Some(0x06), Some(b"pool1")));
+        assert_ne!(
+            generate_mac("vm0", "eth", None, Some(b"0")),
+            generate_mac("vm0e", "th", None, Some(b"0"))
+        );
+
+        // Whatever the prefix, the address is locally administered unicast
+        for prefix in [None, Some(0xff), Some(0x00)] {
+            let mac = generate_mac("vm0", "eth0", prefix, None);
+            let first = u8::from_str_radix(&mac[..2], 16).unwrap();
+            assert_eq!(first & MULTICAST_BIT, 0);
+            assert_ne!(first & LOCALLY_ADMINISTERED_BIT, 0);
+        }
+        assert!(generate_mac("vm0", "eth0", None, None).starts_with("02:"));
+
+        // Five hashed octets, no collision among a fleet
+        let macs = (0..10000)
+            .map(|i| generate_mac(&format!("vm{i}"), "eth0", Some(0x06), Some(b"pool1")))
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(macs.len(), 10000);
+    }
+}
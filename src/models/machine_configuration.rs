@@ -1,14 +1,37 @@
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
-use super::cpu_template::CPUTemplate;
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::{
+    cpu_template::{CPUTemplate, CPUTemplateString},
+    firecracker_version::FirecrackerVersion,
+};
 
 /// # Example
+///
+/// ```
+/// use rustcracker::models::machine_configuration::MachineConfiguration;
+///
+/// let machine_config = MachineConfiguration::builder()
+///     .vcpu_count(2)
+///     .mem_size_mib(256)
+///     .smt(true)
+///     .build()
+///     .unwrap();
+/// assert_eq!(machine_config.vcpu_count, 2);
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MachineConfiguration {
     /// cpu template
     #[serde(rename = "cpu_template", skip_serializing_if = "Option::is_none")]
     pub cpu_template: Option<CPUTemplate>,
 
+    /// Which huge pages configuration (if any) should be used to back guest memory.
+    #[serde(rename = "huge_pages", skip_serializing_if = "Option::is_none")]
+    pub huge_pages: Option<HugePages>,
+
     /// Flag for enabling/disabling Hyperthreading
     /// Required: true
     #[serde(rename = "smt", skip_serializing_if = "Option::is_none")]
@@ -33,3 +56,346 @@ pub struct MachineConfiguration {
     #[serde(rename = "vcpu_count")]
     pub vcpu_count: isize,
 }
+
+impl MachineConfiguration {
+    pub const MAX_VCPU_COUNT: isize = 32;
+
+    pub fn builder() -> MachineConfigurationBuilder {
+        MachineConfigurationBuilder::default()
+    }
+
+    /// Check the configuration against the rules of firecracker,
+    /// reporting every failing field at once.
+    pub fn validate(&self) -> RtckResult<()> {
+        let mut violations = Vec::new();
+        if !(1..=Self::MAX_VCPU_COUNT).contains(&self.vcpu_count) {
+            violations.push(format!(
+                "vcpu_count {} out of range 1..={}",
+                self.vcpu_count,
+                Self::MAX_VCPU_COUNT
+            ));
+        } else if self.ht_enabled == Some(true) && self.vcpu_count != 1 && self.vcpu_count % 2 != 0
+        {
+            violations.push(format!(
+                "vcpu_count {} must be 1 or even with smt enabled",
+                self.vcpu_count
+            ));
+        }
+        if self.mem_size_mib < 1 {
+            violations.push(format!(
+                "mem_size_mib {} must be at least 1",
+                self.mem_size_mib
+            ));
+        } else if let Some(page_size_kib) = self.huge_pages.and_then(HugePages::page_size_kib) {
+            if !(self.mem_size_mib as u64 * 1024).is_multiple_of(page_size_kib) {
+                violations.push(format!(
+                    "mem_size_mib {} must be a multiple of the {} KiB huge page size",
+                    self.mem_size_mib, page_size_kib
+                ));
+            }
+        }
+        if let Some(CPUTemplate(template)) = &self.cpu_template {
+            let arch = std::env::consts::ARCH;
+            if !template.supported_on(arch) {
+                violations.push(format!(
+                    "cpu_template {template:?} is not supported on {arch}"
+                ));
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Invalid machine configuration: {}", violations.join(", ")),
+            ))
+        }
+    }
+
+    /// Whether guest memory is backed by huge pages
+    pub fn uses_huge_pages(&self) -> bool {
+        self.huge_pages
+            .is_some_and(|huge_pages| huge_pages != HugePages::None)
+    }
+}
+
+/// Huge pages backing guest memory, supported since firecracker v1.7
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum HugePages {
+    #[default]
+    #[serde(rename = "None")]
+    None,
+    #[serde(rename = "2M")]
+    Hugetlbfs2M,
+}
+
+impl HugePages {
+    /// First firecracker release (major, minor) supporting huge pages
+    pub const MIN_FIRECRACKER_VERSION: (u64, u64) = (1, 7);
+
+    pub fn page_size_kib(self) -> Option<u64> {
+        match self {
+            HugePages::None => None,
+            HugePages::Hugetlbfs2M => Some(2048),
+        }
+    }
+
+    /// Count of free huge pages of this size reported by the host kernel
+    pub fn free_pages_path(self) -> Option<PathBuf> {
+        self.page_size_kib().map(|page_size_kib| {
+            PathBuf::from(format!(
+                "/sys/kernel/mm/hugepages/hugepages-{page_size_kib}kB/free_hugepages"
+            ))
+        })
+    }
+
+    /// Check that `free_pages` huge pages are enough to back `mem_size_mib`
+    pub fn check_free_pages(self, mem_size_mib: u64, free_pages: u64) -> RtckResult<()> {
+        let Some(page_size_kib) = self.page_size_kib() else {
+            return Ok(());
+        };
+        let needed = (mem_size_mib * 1024).div_ceil(page_size_kib);
+        if free_pages < needed {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "{needed} huge pages of {page_size_kib} KiB needed for {mem_size_mib} MiB, only {free_pages} free on the host"
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that the host has enough free huge pages to back `mem_size_mib`
+    pub fn check_host(self, mem_size_mib: u64) -> RtckResult<()> {
+        let Some(path) = self.free_pages_path() else {
+            return Ok(());
+        };
+        let free_pages = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|free_pages| free_pages.trim().parse().ok())
+            .ok_or(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Huge pages unavailable on the host, cannot read {path:?}"),
+            ))?;
+        self.check_free_pages(mem_size_mib, free_pages)
+    }
+
+    /// Check that firecracker `version` supports huge pages
+    pub fn check_version(self, version: &FirecrackerVersion) -> RtckResult<()> {
+        if self == HugePages::None {
+            return Ok(());
+        }
+        let (major, minor) = Self::MIN_FIRECRACKER_VERSION;
+        if !version.at_least(major, minor)? {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Huge pages require firecracker v{major}.{minor} or later, running {}",
+                    version.firecracker_version
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Builder of `MachineConfiguration`, `vcpu_count` and `mem_size_mib` are required.
+#[derive(Debug, Clone, Default)]
+pub struct MachineConfigurationBuilder {
+    cpu_template: Option<CPUTemplate>,
+    huge_pages: Option<HugePages>,
+    smt: Option<bool>,
+    mem_size_mib: Option<u32>,
+    track_dirty_pages: Option<bool>,
+    vcpu_count: Option<u8>,
+}
+
+impl MachineConfigurationBuilder {
+    pub fn vcpu_count(mut self, vcpu_count: u8) -> Self {
+        self.vcpu_count = Some(vcpu_count);
+        self
+    }
+
+    pub fn mem_size_mib(mut self, mem_size_mib: u32) -> Self {
+        self.mem_size_mib = Some(mem_size_mib);
+        self
+    }
+
+    pub fn smt(mut self, smt: bool) -> Self {
+        self.smt = Some(smt);
+        self
+    }
+
+    pub fn track_dirty_pages(mut self, track_dirty_pages: bool) -> Self {
+        self.track_dirty_pages = Some(track_dirty_pages);
+        self
+    }
+
+    pub fn cpu_template(mut self, cpu_template: CPUTemplateString) -> Self {
+        self.cpu_template = Some(CPUTemplate(cpu_template));
+        self
+    }
+
+    pub fn huge_pages(mut self, huge_pages: HugePages) -> Self {
+        self.huge_pages = Some(huge_pages);
+        self
+    }
+
+    pub fn build(self) -> RtckResult<MachineConfiguration> {
+        let mut missing = Vec::new();
+        if self.vcpu_count.is_none() {
+            missing.push("vcpu_count");
+        }
+        if self.mem_size_mib.is_none() {
+            missing.push("mem_size_mib");
+        }
+        if !missing.is_empty() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Invalid machine configuration: missing {}",
+                    missing.join(", ")
+                ),
+            ));
+        }
+
+        let machine_config = MachineConfiguration {
+            cpu_template: self.cpu_template,
+            huge_pages: self.huge_pages,
+            ht_enabled: self.smt,
+            mem_size_mib: self.mem_size_mib.unwrap_or_default() as isize,
+            track_dirty_pages: self.track_dirty_pages,
+            vcpu_count: self.vcpu_count.unwrap_or_default() as isize,
+        };
+        machine_config.validate()?;
+        Ok(machine_config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HugePages, MachineConfiguration};
+    use crate::models::{
+        cpu_template::{CPUTemplate, CPUTemplateString},
+        firecracker_version::FirecrackerVersion,
+    };
+
+    #[test]
+    fn test_machine_configuration_builder() {
+        let machine_config = MachineConfiguration::builder()
+            .vcpu_count(4)
+            .mem_size_mib(1024)
+            .smt(true)
+            .track_dirty_pages(true)
+            .cpu_template(CPUTemplateString::T2)
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine_config,
+            MachineConfiguration {
+                cpu_template: Some(CPUTemplate(CPUTemplateString::T2)),
+                huge_pages: None,
+                ht_enabled: Some(true),
+                mem_size_mib: 1024,
+                track_dirty_pages: Some(true),
+                vcpu_count: 4,
+            }
+        );
+
+        // A single vcpu is fine with smt
+        assert!(MachineConfiguration::builder()
+            .vcpu_count(1)
+            .mem_size_mib(128)
+            .smt(true)
+            .build()
+            .is_ok());
+
+        let err = MachineConfiguration::builder().build().unwrap_err();
+        assert!(err.to_string().contains("vcpu_count"));
+        assert!(err.to_string().contains("mem_size_mib"));
+    }
+
+    #[test]
+    fn test_machine_configuration_validate() {
+        let mut machine_config = MachineConfiguration {
+            cpu_template: None,
+            huge_pages: None,
+            ht_enabled: Some(true),
+            mem_size_mib: 0,
+            track_dirty_pages: None,
+            vcpu_count: 3,
+        };
+        // Every failing field is reported
+        let err = machine_config.validate().unwrap_err().to_string();
+        assert!(err.contains("vcpu_count 3"));
+        assert!(err.contains("mem_size_mib 0"));
+
+        machine_config.ht_enabled = Some(false);
+        machine_config.mem_size_mib = 1;
+        assert!(machine_config.validate().is_ok());
+
+        // Templates of another architecture are refused
+        machine_config.cpu_template = Some(CPUTemplate(CPUTemplateString::T2S));
+        assert_eq!(
+            machine_config.validate().is_ok(),
+            cfg!(target_arch = "x86_64")
+        );
+        machine_config.cpu_template = Some(CPUTemplate(CPUTemplateString::V1N1));
+        assert_eq!(
+            machine_config.validate().is_ok(),
+            cfg!(target_arch = "aarch64")
+        );
+        machine_config.cpu_template = Some(CPUTemplate(CPUTemplateString::None));
+        assert!(machine_config.validate().is_ok());
+
+        for vcpu_count in [0, 33, -1] {
+            machine_config.vcpu_count = vcpu_count;
+            assert!(machine_config.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_huge_pages() {
+        let machine_config = MachineConfiguration::builder()
+            .vcpu_count(2)
+            .mem_size_mib(256)
+            .huge_pages(HugePages::Hugetlbfs2M)
+            .build()
+            .unwrap();
+        assert!(machine_config.uses_huge_pages());
+        assert_eq!(
+            serde_json::to_value(&machine_config).unwrap()["huge_pages"],
+            "2M"
+        );
+
+        // Memory must be made of whole huge pages
+        assert!(MachineConfiguration::builder()
+            .vcpu_count(2)
+            .mem_size_mib(257)
+            .huge_pages(HugePages::Hugetlbfs2M)
+            .build()
+            .is_err());
+        assert!(MachineConfiguration::builder()
+            .vcpu_count(2)
+            .mem_size_mib(257)
+            .huge_pages(HugePages::None)
+            .build()
+            .is_ok());
+
+        let huge_pages = HugePages::Hugetlbfs2M;
+        assert!(huge_pages.check_free_pages(256, 128).is_ok());
+        assert!(huge_pages.check_free_pages(256, 127).is_err());
+        assert!(HugePages::None.check_free_pages(256, 0).is_ok());
+
+        let version = |version: &str| FirecrackerVersion {
+            firecracker_version: version.into(),
+        };
+        assert!(huge_pages.check_version(&version("1.7.0")).is_ok());
+        assert!(huge_pages.check_version(&version("v1.10.1")).is_ok());
+        assert!(huge_pages.check_version(&version("1.6.0")).is_err());
+        assert!(HugePages::None.check_version(&version("1.4.1")).is_ok());
+    }
+}
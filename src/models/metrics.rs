@@ -1,4 +1,8 @@
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{RtckError, RtckErrorClass, RtckResult};
 
 /// Describes the configuration option for the metrics capability.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -8,3 +12,449 @@ pub struct Metrics {
     #[serde(rename = "metrics_path")]
     pub metrics_path: String,
 }
+
+/// One flush of the metrics firecracker writes to `metrics_path`, a JSON
+/// object per line. Counters hold what happened since the previous flush.
+/// Groups missing from older firecracker default to zero, and the groups
+/// of each device, e.g. `block_rootfs`, are left in `devices`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct FirecrackerMetricsSnapshot {
+    pub utc_timestamp_ms: u64,
+    pub api_server: ApiServerMetrics,
+    pub balloon: BalloonDeviceMetrics,
+    pub block: BlockDeviceMetrics,
+    pub deprecated_api: DeprecatedApiMetrics,
+    pub entropy: EntropyDeviceMetrics,
+    pub get_api_requests: GetRequestsMetrics,
+    pub i8042: I8042DeviceMetrics,
+    pub latencies_us: PerformanceMetrics,
+    pub logger: LoggerSystemMetrics,
+    pub mmds: MmdsMetrics,
+    pub net: NetDeviceMetrics,
+    pub patch_api_requests: PatchRequestsMetrics,
+    pub put_api_requests: PutRequestsMetrics,
+    pub rtc: RtcDeviceMetrics,
+    pub seccomp: SeccompMetrics,
+    pub signals: SignalMetrics,
+    pub uart: SerialDeviceMetrics,
+    pub vcpu: VcpuMetrics,
+    pub vmm: VmmMetrics,
+    pub vsock: VsockDeviceMetrics,
+
+    /// Groups of each device, and those unknown to rustcracker
+    #[serde(flatten)]
+    pub devices: BTreeMap<String, serde_json::Value>,
+}
+
+impl FirecrackerMetricsSnapshot {
+    /// Parse the metrics flushed to a file, one snapshot per line
+    pub fn parse_lines(content: &str) -> RtckResult<Vec<Self>> {
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    /// Metrics of each block device, by drive id
+    pub fn block_devices(&self) -> RtckResult<BTreeMap<String, BlockDeviceMetrics>> {
+        self.device_groups("block_")
+    }
+
+    /// Metrics of each network device, by interface id
+    pub fn net_devices(&self) -> RtckResult<BTreeMap<String, NetDeviceMetrics>> {
+        self.device_groups("net_")
+    }
+
+    fn device_groups<T: DeserializeOwned>(&self, prefix: &str) -> RtckResult<BTreeMap<String, T>> {
+        self.devices
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(prefix)?, value)))
+            .map(|(id, value)| {
+                let metrics = serde_json::from_value(value.clone()).map_err(|e| {
+                    RtckError::new(
+                        RtckErrorClass::SerdeError,
+                        format!("Malformed metrics of device {prefix}{id}, {e}"),
+                    )
+                    .with_source(e)
+                })?;
+                Ok((id.to_string(), metrics))
+            })
+            .collect()
+    }
+}
+
+/// Latencies in microseconds of an operation since the previous flush
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct LatencyAggMetrics {
+    pub min_us: u64,
+    pub max_us: u64,
+    pub sum_us: u64,
+}
+
+/// Metrics of the API server, under `api_server`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct ApiServerMetrics {
+    pub process_startup_time_us: u64,
+    pub process_startup_time_cpu_us: u64,
+    pub sync_response_fails: u64,
+    pub sync_vmm_send_timeout_count: u64,
+}
+
+/// Metrics of the balloon device, under `balloon`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct BalloonDeviceMetrics {
+    pub activate_fails: u64,
+    pub inflate_count: u64,
+    pub stats_updates_count: u64,
+    pub stats_update_fails: u64,
+    pub deflate_count: u64,
+    pub event_fails: u64,
+}
+
+/// Metrics of the block devices, aggregated under `block` and per drive under
+/// `block_<drive_id>`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct BlockDeviceMetrics {
+    pub activate_fails: u64,
+    pub cfg_fails: u64,
+    pub no_avail_buffer: u64,
+    pub event_fails: u64,
+    pub execute_fails: u64,
+    pub invalid_reqs_count: u64,
+    pub flush_count: u64,
+    pub queue_event_count: u64,
+    pub rate_limiter_event_count: u64,
+    pub update_count: u64,
+    pub update_fails: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_count: u64,
+    pub write_count: u64,
+    pub read_agg: LatencyAggMetrics,
+    pub write_agg: LatencyAggMetrics,
+    pub rate_limiter_throttled_events: u64,
+    pub io_engine_throttled_events: u64,
+}
+
+/// Uses of deprecated APIs, under `deprecated_api`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct DeprecatedApiMetrics {
+    pub deprecated_http_api_calls: u64,
+    pub deprecated_cmd_line_api_calls: u64,
+}
+
+/// Metrics of the entropy device, under `entropy`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct EntropyDeviceMetrics {
+    pub activate_fails: u64,
+    pub entropy_event_fails: u64,
+    pub entropy_event_count: u64,
+    pub entropy_bytes: u64,
+    pub host_rng_fails: u64,
+    pub entropy_rate_limiter_throttled: u64,
+    pub rate_limiter_event_count: u64,
+}
+
+/// `GET` requests to the API server, under `get_api_requests`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct GetRequestsMetrics {
+    pub instance_info_count: u64,
+    pub machine_cfg_count: u64,
+    pub mmds_count: u64,
+    pub vmm_version_count: u64,
+}
+
+/// Metrics of the i8042 keyboard controller, under `i8042`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct I8042DeviceMetrics {
+    pub error_count: u64,
+    pub missed_read_count: u64,
+    pub missed_write_count: u64,
+    pub read_count: u64,
+    pub reset_count: u64,
+    pub write_count: u64,
+}
+
+/// Metrics of the logging system itself, under `logger`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct LoggerSystemMetrics {
+    pub missed_metrics_count: u64,
+    pub metrics_fails: u64,
+    pub missed_log_count: u64,
+    pub log_fails: u64,
+}
+
+/// Metrics of the MMDS, under `mmds`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct MmdsMetrics {
+    pub rx_accepted: u64,
+    pub rx_accepted_err: u64,
+    pub rx_accepted_unusual: u64,
+    pub rx_bad_eth: u64,
+    pub rx_invalid_token: u64,
+    pub rx_no_token: u64,
+    pub rx_count: u64,
+    pub tx_bytes: u64,
+    pub tx_count: u64,
+    pub tx_errors: u64,
+    pub tx_frames: u64,
+    pub connections_created: u64,
+    pub connections_destroyed: u64,
+}
+
+/// Metrics of the network devices, aggregated under `net` and per interface
+/// under `net_<iface_id>`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct NetDeviceMetrics {
+    pub activate_fails: u64,
+    pub cfg_fails: u64,
+    pub mac_address_updates: u64,
+    pub no_rx_avail_buffer: u64,
+    pub no_tx_avail_buffer: u64,
+    pub event_fails: u64,
+    pub rx_queue_event_count: u64,
+    pub rx_event_rate_limiter_count: u64,
+    pub rx_partial_writes: u64,
+    pub rx_rate_limiter_throttled: u64,
+    pub rx_tap_event_count: u64,
+    pub rx_bytes_count: u64,
+    pub rx_packets_count: u64,
+    pub rx_fails: u64,
+    pub rx_count: u64,
+    pub tap_read_fails: u64,
+    pub tap_write_fails: u64,
+    pub tap_write_agg: LatencyAggMetrics,
+    pub tx_bytes_count: u64,
+    pub tx_malformed_frames: u64,
+    pub tx_fails: u64,
+    pub tx_count: u64,
+    pub tx_packets_count: u64,
+    pub tx_partial_reads: u64,
+    pub tx_queue_event_count: u64,
+    pub tx_rate_limiter_event_count: u64,
+    pub tx_rate_limiter_throttled: u64,
+    pub tx_spoofed_mac_count: u64,
+    pub tx_remaining_reqs_count: u64,
+}
+
+/// `PATCH` requests to the API server, under `patch_api_requests`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct PatchRequestsMetrics {
+    pub drive_count: u64,
+    pub drive_fails: u64,
+    pub network_count: u64,
+    pub network_fails: u64,
+    pub machine_cfg_count: u64,
+    pub machine_cfg_fails: u64,
+    pub mmds_count: u64,
+    pub mmds_fails: u64,
+}
+
+/// Latencies in microseconds of the slow operations, under `latencies_us`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct PerformanceMetrics {
+    pub full_create_snapshot: u64,
+    pub diff_create_snapshot: u64,
+    pub load_snapshot: u64,
+    pub pause_vm: u64,
+    pub resume_vm: u64,
+    pub vmm_full_create_snapshot: u64,
+    pub vmm_diff_create_snapshot: u64,
+    pub vmm_load_snapshot: u64,
+    pub vmm_pause_vm: u64,
+    pub vmm_resume_vm: u64,
+}
+
+/// `PUT` requests to the API server, under `put_api_requests`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct PutRequestsMetrics {
+    pub actions_count: u64,
+    pub actions_fails: u64,
+    pub boot_source_count: u64,
+    pub boot_source_fails: u64,
+    pub drive_count: u64,
+    pub drive_fails: u64,
+    pub logger_count: u64,
+    pub logger_fails: u64,
+    pub machine_cfg_count: u64,
+    pub machine_cfg_fails: u64,
+    pub cpu_cfg_count: u64,
+    pub cpu_cfg_fails: u64,
+    pub metrics_count: u64,
+    pub metrics_fails: u64,
+    pub network_count: u64,
+    pub network_fails: u64,
+    pub mmds_count: u64,
+    pub mmds_fails: u64,
+    pub vsock_count: u64,
+    pub vsock_fails: u64,
+}
+
+/// Metrics of the RTC device of aarch64, under `rtc`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct RtcDeviceMetrics {
+    pub error_count: u64,
+    pub missed_read_count: u64,
+    pub missed_write_count: u64,
+}
+
+/// Metrics of the seccomp filters, under `seccomp`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct SeccompMetrics {
+    pub num_faults: u64,
+}
+
+/// Metrics of the vCPUs, under `vcpu`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct VcpuMetrics {
+    pub exit_io_in: u64,
+    pub exit_io_out: u64,
+    pub exit_mmio_read: u64,
+    pub exit_mmio_write: u64,
+    pub failures: u64,
+    pub exit_io_in_agg: LatencyAggMetrics,
+    pub exit_io_out_agg: LatencyAggMetrics,
+    pub exit_mmio_read_agg: LatencyAggMetrics,
+    pub exit_mmio_write_agg: LatencyAggMetrics,
+}
+
+/// Metrics of the VMM, under `vmm`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct VmmMetrics {
+    pub device_events: u64,
+    pub panic_count: u64,
+}
+
+/// Metrics of the serial console, under `uart`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct SerialDeviceMetrics {
+    pub error_count: u64,
+    pub flush_count: u64,
+    pub missed_read_count: u64,
+    pub missed_write_count: u64,
+    pub read_count: u64,
+    pub write_count: u64,
+}
+
+/// Signals caught by firecracker, under `signals`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct SignalMetrics {
+    pub sigbus: u64,
+    pub sigsegv: u64,
+    pub sigxfsz: u64,
+    pub sigxcpu: u64,
+    pub sigpipe: u64,
+    pub sighup: u64,
+    pub sigill: u64,
+}
+
+/// Metrics of the vsock device, under `vsock`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+#[cfg_attr(feature = "strict-decode", serde(deny_unknown_fields))]
+pub struct VsockDeviceMetrics {
+    pub activate_fails: u64,
+    pub cfg_fails: u64,
+    pub rx_queue_event_fails: u64,
+    pub tx_queue_event_fails: u64,
+    pub ev_queue_event_fails: u64,
+    pub muxer_event_fails: u64,
+    pub conn_event_fails: u64,
+    pub rx_queue_event_count: u64,
+    pub tx_queue_event_count: u64,
+    pub rx_bytes_count: u64,
+    pub tx_bytes_count: u64,
+    pub rx_packets_count: u64,
+    pub tx_packets_count: u64,
+    pub conns_added: u64,
+    pub conns_killed: u64,
+    pub conns_removed: u64,
+    pub killq_resync: u64,
+    pub tx_flush_fails: u64,
+    pub tx_write_fails: u64,
+    pub rx_read_fails: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::FirecrackerMetricsSnapshot;
+
+    // Trimmed flush of firecracker 1.5 booting a machine with a rootfs drive
+    // and one network interface
+    const SAMPLE: &str = r#"{"utc_timestamp_ms":1700000000123,"api_server":{"process_startup_time_us":3014,"process_startup_time_cpu_us":2563,"sync_response_fails":0,"sync_vmm_send_timeout_count":0},"balloon":{"activate_fails":0,"inflate_count":0,"stats_updates_count":0,"stats_update_fails":0,"deflate_count":0,"event_fails":0},"block":{"activate_fails":0,"cfg_fails":0,"no_avail_buffer":0,"event_fails":0,"execute_fails":0,"invalid_reqs_count":0,"flush_count":1,"queue_event_count":212,"rate_limiter_event_count":0,"update_count":0,"update_fails":0,"read_bytes":21954560,"write_bytes":4096,"read_count":802,"write_count":1,"read_agg":{"min_us":7,"max_us":1902,"sum_us":47021},"write_agg":{"min_us":12,"max_us":12,"sum_us":12},"rate_limiter_throttled_events":0,"io_engine_throttled_events":0},"block_rootfs":{"activate_fails":0,"cfg_fails":0,"no_avail_buffer":0,"event_fails":0,"execute_fails":0,"invalid_reqs_count":0,"flush_count":1,"queue_event_count":212,"rate_limiter_event_count":0,"update_count":0,"update_fails":0,"read_bytes":21954560,"write_bytes":4096,"read_count":802,"write_count":1,"read_agg":{"min_us":7,"max_us":1902,"sum_us":47021},"write_agg":{"min_us":12,"max_us":12,"sum_us":12},"rate_limiter_throttled_events":0,"io_engine_throttled_events":0},"deprecated_api":{"deprecated_http_api_calls":0,"deprecated_cmd_line_api_calls":0},"get_api_requests":{"instance_info_count":1,"machine_cfg_count":0,"mmds_count":0,"vmm_version_count":1},"i8042":{"error_count":0,"missed_read_count":0,"missed_write_count":0,"read_count":0,"reset_count":0,"write_count":0},"latencies_us":{"full_create_snapshot":0,"diff_create_snapshot":0,"load_snapshot":0,"pause_vm":0,"resume_vm":0,"vmm_full_create_snapshot":0,"vmm_diff_create_snapshot":0,"vmm_load_snapshot":0,"vmm_pause_vm":0,"vmm_resume_vm":0},"logger":{"missed_metrics_count":0,"metrics_fails":0,"missed_log_count":0,"log_fails":0},"mmds":{"rx_accepted":0,"rx_accepted_err":0,"rx_accepted_unusual":0,"rx_bad_eth":0,"rx_invalid_token":0,"rx_no_token":0,"rx_count":0,"tx_bytes":0,"tx_count":0,"tx_errors":0,"tx_frames":0,"connections_created":0,"connections_destroyed":0},"net":{"activate_fails":0,"cfg_fails":0,"mac_address_updates":0,"no_rx_avail_buffer":0,"no_tx_avail_buffer":0,"event_fails":0,"rx_queue_event_count":0,"rx_event_rate_limiter_count":0,"rx_partial_writes":0,"rx_rate_limiter_throttled":0,"rx_tap_event_count":3,"rx_bytes_count":180,"rx_packets_count":3,"rx_fails":0,"rx_count":3,"tap_read_fails":0,"tap_write_fails":0,"tap_write_agg":{"min_us":4,"max_us":9,"sum_us":17},"tx_bytes_count":306,"tx_malformed_frames":0,"tx_fails":0,"tx_count":3,"tx_packets_count":3,"tx_partial_reads":0,"tx_queue_event_count":3,"tx_rate_limiter_event_count":0,"tx_rate_limiter_throttled":0,"tx_spoofed_mac_count":0,"tx_remaining_reqs_count":0},"net_eth0":{"rx_bytes_count":180,"rx_packets_count":3,"tx_bytes_count":306,"tx_packets_count":3,"tap_write_agg":{"min_us":4,"max_us":9,"sum_us":17}},"patch_api_requests":{"drive_count":0,"drive_fails":0,"network_count":0,"network_fails":0,"machine_cfg_count":0,"machine_cfg_fails":0,"mmds_count":0,"mmds_fails":0},"put_api_requests":{"actions_count":1,"actions_fails":0,"boot_source_count":1,"boot_source_fails":0,"drive_count":1,"drive_fails":0,"logger_count":1,"logger_fails":0,"machine_cfg_count":1,"machine_cfg_fails":0,"cpu_cfg_count":0,"cpu_cfg_fails":0,"metrics_count":1,"metrics_fails":0,"network_count":1,"network_fails":0,"mmds_count":0,"mmds_fails":0,"vsock_count":0,"vsock_fails":0},"seccomp":{"num_faults":0},"vcpu":{"exit_io_in":1044,"exit_io_out":6372,"exit_mmio_read":36,"exit_mmio_write":83,"failures":0,"exit_io_in_agg":{"min_us":1,"max_us":29,"sum_us":3090},"exit_io_out_agg":{"min_us":0,"max_us":51,"sum_us":8512},"exit_mmio_read_agg":{"min_us":1,"max_us":5,"sum_us":78},"exit_mmio_write_agg":{"min_us":1,"max_us":60,"sum_us":372}},"vmm":{"device_events":219,"panic_count":0},"uart":{"error_count":0,"flush_count":0,"missed_read_count":0,"missed_write_count":0,"read_count":0,"write_count":22817},"signals":{"sigbus":0,"sigsegv":0,"sigxfsz":0,"sigxcpu":0,"sigpipe":0,"sighup":0,"sigill":0},"vhost_user_block_scratch":{"activate_fails":0,"cfg_fails":0},"vsock":{"activate_fails":0,"cfg_fails":0,"rx_queue_event_fails":0,"tx_queue_event_fails":0,"ev_queue_event_fails":0,"muxer_event_fails":0,"conn_event_fails":0,"rx_queue_event_count":0,"tx_queue_event_count":0,"rx_bytes_count":0,"tx_bytes_count":0,"rx_packets_count":0,"tx_packets_count":0,"conns_added":0,"conns_killed":0,"conns_removed":0,"killq_resync":0,"tx_flush_fails":0,"tx_write_fails":0,"rx_read_fails":0},"entropy":{"activate_fails":0,"entropy_event_fails":0,"entropy_event_count":0,"entropy_bytes":0,"host_rng_fails":0,"entropy_rate_limiter_throttled":0,"rate_limiter_event_count":0}}"#;
+
+    #[test]
+    fn test_metrics_snapshot() {
+        let content = format!("{SAMPLE}\n{{\"utc_timestamp_ms\":1700000060123}}\n");
+        let snapshots = FirecrackerMetricsSnapshot::parse_lines(&content).unwrap();
+        assert_eq!(snapshots.len(), 2);
+
+        let metrics = &snapshots[0];
+        assert_eq!(metrics.utc_timestamp_ms, 1700000000123);
+        assert_eq!(metrics.api_server.process_startup_time_us, 3014);
+        assert_eq!(metrics.block.read_bytes, 21954560);
+        assert_eq!(metrics.block.read_agg.max_us, 1902);
+        assert_eq!(metrics.vcpu.exit_io_out_agg.sum_us, 8512);
+        assert_eq!(metrics.put_api_requests.drive_count, 1);
+        assert_eq!(metrics.uart.write_count, 22817);
+
+        let blocks = metrics.block_devices().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks["rootfs"], metrics.block);
+        let nets = metrics.net_devices().unwrap();
+        assert_eq!(nets["eth0"].tx_bytes_count, 306);
+        assert_eq!(nets["eth0"].tap_write_agg.sum_us, 17);
+        // Unknown groups are kept
+        assert!(metrics.devices.contains_key("vhost_user_block_scratch"));
+
+        // Older firecracker leave groups out
+        assert_eq!(snapshots[1].block, Default::default());
+
+        // Round trip
+        let json = serde_json::to_string(metrics).unwrap();
+        let metrics_: FirecrackerMetricsSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(metrics, &metrics_);
+    }
+}
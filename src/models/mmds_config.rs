@@ -1,4 +1,12 @@
+use std::net::Ipv4Addr;
+
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    micro_http::{Redact, RedactionPolicy},
+    RtckError, RtckErrorClass, RtckResult,
+};
 
 /// Defines the MMDS configuration.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -22,6 +30,41 @@ pub struct MmdsConfig {
     pub network_interfaces: Vec<String>,
 }
 
+impl MmdsConfig {
+    /// Set the address guest applications use to reach the MMDS
+    pub fn with_address(mut self, address: Ipv4Addr) -> RtckResult<Self> {
+        validate_mmds_address(&address)?;
+        self.ipv4_address = Some(address.to_string());
+        Ok(self)
+    }
+
+    /// Check that the configured address, if any, is a valid link-local address.
+    pub fn validate(&self) -> RtckResult<()> {
+        if let Some(address) = &self.ipv4_address {
+            let address = address.parse::<Ipv4Addr>().map_err(|_| {
+                RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    format!("Malformed MMDS address {address}"),
+                )
+            })?;
+            validate_mmds_address(&address)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that `address` is link-local, i.e. lies in `169.254.0.0/16`
+pub fn validate_mmds_address(address: &Ipv4Addr) -> RtckResult<()> {
+    if address.is_link_local() {
+        Ok(())
+    } else {
+        Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("MMDS address {address} is not link-local"),
+        ))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Version {
     #[serde(rename = "V1")]
@@ -30,4 +73,167 @@ pub enum Version {
     V2,
 }
 
+#[deprecated(note = "MMDS contents are structured JSON, use `MmdsContents`")]
 pub type MmdsContentsObject = String;
+
+/// Contents of the MMDS data store, an arbitrary JSON document
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct MmdsContents(pub Value);
+
+impl MmdsContents {
+    /// Largest contents firecracker accepts by default, once serialized
+    pub const DEFAULT_SIZE_LIMIT: usize = 51200;
+
+    pub fn new(contents: Value) -> Self {
+        Self(contents)
+    }
+
+    /// Apply `patch` the way firecracker handles `PATCH /mmds`, i.e. a JSON
+    /// merge patch (RFC 7396): objects are merged key by key, `null` removes
+    /// a key and any other value replaces the target.
+    pub fn merge(&mut self, patch: &Value) {
+        merge_value(&mut self.0, patch)
+    }
+
+    /// Size of the contents once serialized, as firecracker accounts them
+    pub fn size(&self) -> RtckResult<usize> {
+        Ok(serde_json::to_vec(&self.0)?.len())
+    }
+
+    /// Check the contents fit in an MMDS data store of `limit` bytes
+    pub fn check_size(&self, limit: usize) -> RtckResult<()> {
+        let size = self.size()?;
+        if size > limit {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("MMDS contents of {size} bytes exceed the limit of {limit} bytes"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Redact for MmdsContents {
+    fn redacted(&self, policy: &RedactionPolicy) -> Self {
+        let mut contents = self.0.clone();
+        policy.mask(&mut contents);
+        Self(contents)
+    }
+}
+
+fn merge_value(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        unreachable!()
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_value(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+impl From<Value> for MmdsContents {
+    fn from(contents: Value) -> Self {
+        Self(contents)
+    }
+}
+
+// `Value` is neither `Ord` nor `Hash`. Its serialization is canonical (object
+// keys are sorted), so configurations embedding contents keep their derives.
+impl PartialOrd for MmdsContents {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MmdsContents {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.to_string().cmp(&other.0.to_string())
+    }
+}
+
+impl std::hash::Hash for MmdsContents {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_string().hash(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use serde_json::json;
+
+    use super::{validate_mmds_address, MmdsConfig, MmdsContents};
+
+    #[test]
+    fn test_validate_mmds_address() {
+        assert!(validate_mmds_address(&Ipv4Addr::new(169, 254, 169, 254)).is_ok());
+        assert!(validate_mmds_address(&Ipv4Addr::new(10, 0, 0, 1)).is_err());
+
+        let config = MmdsConfig {
+            version: None,
+            ipv4_address: Some("fe80::a9fe:a9fe".to_string()),
+            network_interfaces: Vec::new(),
+        };
+        assert!(config.validate().is_err());
+        let config = MmdsConfig {
+            ipv4_address: Some("169.254.170.2".to_string()),
+            ..config
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_mmds_contents_merge() {
+        let mut contents = MmdsContents::new(json!({
+            "latest": {
+                "meta-data": {"ami-id": "ami-1", "hostname": "vm0"},
+                "tags": ["a", "b"],
+            },
+            "obsolete": 1,
+        }));
+        contents.merge(&json!({
+            "latest": {
+                "meta-data": {"hostname": "vm1", "ami-id": null},
+                "tags": ["c"],
+            },
+            "obsolete": null,
+            "user-data": "echo",
+        }));
+        assert_eq!(
+            contents,
+            MmdsContents::new(json!({
+                "latest": {"meta-data": {"hostname": "vm1"}, "tags": ["c"]},
+                "user-data": "echo",
+            }))
+        );
+
+        // Anything but an object replaces the target
+        contents.merge(&json!("flat"));
+        assert_eq!(contents.0, json!("flat"));
+        contents.merge(&json!({"key": {"nested": null, "kept": 1}}));
+        assert_eq!(contents.0, json!({"key": {"kept": 1}}));
+    }
+
+    #[test]
+    fn test_mmds_contents_size() {
+        let contents = MmdsContents::new(json!({"key": "x".repeat(100)}));
+        assert_eq!(contents.size().unwrap(), 110);
+        assert!(contents.check_size(110).is_ok());
+        let e = contents.check_size(109).unwrap_err();
+        assert!(e
+            .to_string()
+            .contains("110 bytes exceed the limit of 109 bytes"));
+    }
+}
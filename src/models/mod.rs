@@ -9,10 +9,13 @@ pub mod entropy_device;
 pub mod error;
 pub mod firecracker_version;
 pub mod full_vm_configuration;
+#[cfg(feature = "codegen")]
+pub mod generated;
 pub mod instance_action_info;
 pub mod instance_info;
 pub mod kernel_args;
 pub mod logger;
+pub mod mac_address;
 pub mod machine_configuration;
 pub mod memory_backend;
 pub mod metrics;
@@ -21,8 +24,102 @@ pub mod network_interface;
 pub mod partial_drive;
 pub mod partial_network_interface;
 pub mod rate_limiter;
+pub mod resource_id;
 pub mod snapshot_create_params;
 pub mod snapshot_load_params;
 pub mod token_bucket;
 pub mod vm;
 pub mod vsock;
+
+use crate::RtckResult;
+
+/// Local check of a model against the rules firecracker enforces, so that
+/// mistakes are reported precisely instead of by a generic `400 Bad Request`
+pub trait Validate {
+    fn validate(&self) -> RtckResult<()>;
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::{
+        balloon::Balloon, drive::Drive, machine_configuration::MachineConfiguration,
+        network_interface::NetworkInterface,
+    };
+
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_omit_unset_fields() {
+        let drive = serde_json::to_value(Drive {
+            drive_id: "rootfs".into(),
+            partuuid: None,
+            is_root_device: true,
+            cache_type: None,
+            is_read_only: false,
+            path_on_host: "/tmp/rootfs.ext4".into(),
+            rate_limiter: None,
+            io_engine: None,
+            socket: None,
+        })
+        .unwrap();
+        assert_eq!(
+            keys(&drive),
+            ["drive_id", "is_read_only", "is_root_device", "path_on_host"]
+        );
+
+        let iface = serde_json::to_value(NetworkInterface {
+            guest_mac: None,
+            host_dev_name: "tap0".into(),
+            iface_id: "eth0".into(),
+            num_queues: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            static_ip: None,
+        })
+        .unwrap();
+        assert_eq!(keys(&iface), ["host_dev_name", "iface_id"]);
+
+        let balloon = serde_json::to_value(Balloon {
+            amount_mib: 64,
+            deflate_on_oom: true,
+            stats_polling_interval_s: None,
+        })
+        .unwrap();
+        assert_eq!(keys(&balloon), ["amount_mib", "deflate_on_oom"]);
+
+        let machine_config = serde_json::to_value(
+            MachineConfiguration::builder()
+                .vcpu_count(1)
+                .mem_size_mib(128)
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(keys(&machine_config), ["mem_size_mib", "vcpu_count"]);
+
+        // Both explicit nulls and missing keys are still accepted
+        let with_nulls = json!({
+            "drive_id": "rootfs",
+            "partuuid": null,
+            "is_root_device": true,
+            "cache_type": null,
+            "is_read_only": false,
+            "path_on_host": "/tmp/rootfs.ext4",
+            "rate_limiter": null,
+        });
+        let with_nulls: Drive = serde_json::from_value(with_nulls).unwrap();
+        let without: Drive = serde_json::from_value(drive).unwrap();
+        assert_eq!(with_nulls, without);
+    }
+}
@@ -1,6 +1,59 @@
+use std::net::IpAddr;
+
 use serde::{Deserialize, Serialize};
 
-use super::rate_limiter;
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::{kernel_args::IpBootParam, mac_address, rate_limiter, resource_id};
+
+/// Static IP configuration of the guest side of a network interface,
+/// handed to the guest kernel as the `ip=` boot parameter
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StaticIpConfig {
+    /// Address of the guest
+    pub address: IpAddr,
+
+    /// Length of the network prefix, e.g. 24 for `255.255.255.0`
+    pub netmask: u8,
+
+    /// Default gateway of the guest
+    #[serde(default)]
+    pub gateway: Option<IpAddr>,
+
+    /// At most two name servers
+    #[serde(default)]
+    pub nameservers: Vec<IpAddr>,
+
+    /// Hostname of the guest
+    #[serde(default)]
+    pub hostname: Option<String>,
+}
+
+impl StaticIpConfig {
+    /// Boot parameter configuring the guest device `device`, e.g. `eth0`
+    pub fn ip_boot_param(&self, device: &str) -> IpBootParam {
+        IpBootParam {
+            client_ip: self.address,
+            gateway: self.gateway,
+            prefix_len: self.netmask,
+            hostname: self.hostname.clone(),
+            device: Some(device.to_string()),
+            nameservers: self.nameservers.clone(),
+        }
+    }
+}
+
+impl From<IpBootParam> for StaticIpConfig {
+    fn from(param: IpBootParam) -> Self {
+        Self {
+            address: param.client_ip,
+            netmask: param.prefix_len,
+            gateway: param.gateway,
+            nameservers: param.nameservers,
+            hostname: param.hostname,
+        }
+    }
+}
 
 /// Defines a network interface.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -26,6 +79,11 @@ pub struct NetworkInterface {
     #[serde(rename = "iface_id")]
     pub iface_id: String,
 
+    /// Number of queue pairs of the virtio-net device, a power of two up to
+    /// `MAX_NUM_QUEUES`. The tap device must have as many queues.
+    #[serde(rename = "num_queues", skip_serializing_if = "Option::is_none")]
+    pub num_queues: Option<u16>,
+
     /// rx rate limiter
     #[serde(rename = "rx_rate_limiter", skip_serializing_if = "Option::is_none")]
     pub rx_rate_limiter: Option<rate_limiter::RateLimiter>,
@@ -33,4 +91,132 @@ pub struct NetworkInterface {
     /// tx rate limiter
     #[serde(rename = "tx_rate_limiter", skip_serializing_if = "Option::is_none")]
     pub tx_rate_limiter: Option<rate_limiter::RateLimiter>,
+
+    /// Static IP configuration of the guest, merged into the kernel
+    /// arguments when configuring the machine. Rustcracker only, never
+    /// sent to firecracker.
+    #[serde(skip_serializing, default)]
+    pub static_ip: Option<StaticIpConfig>,
+}
+
+impl NetworkInterface {
+    pub const MAX_NUM_QUEUES: u16 = 16;
+
+    /// Check `iface_id`, `guest_mac` and `num_queues` so that they are
+    /// accepted by firecracker, and `static_ip` by the guest kernel
+    pub fn validate(&self) -> RtckResult<()> {
+        resource_id::validate_id(&self.iface_id)?;
+        if let Some(guest_mac) = &self.guest_mac {
+            mac_address::validate_mac(guest_mac)?;
+        }
+        if let Some(static_ip) = &self.static_ip {
+            static_ip.ip_boot_param("eth0").validate()?;
+        }
+        if let Some(num_queues) = self.num_queues {
+            if !num_queues.is_power_of_two() || num_queues > Self::MAX_NUM_QUEUES {
+                return Err(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    format!(
+                        "num_queues {num_queues} of {:?} must be a power of two up to {}",
+                        self.iface_id,
+                        Self::MAX_NUM_QUEUES
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Use `num_queues` queue pairs, see `num_queues`
+    pub fn with_num_queues(mut self, num_queues: u16) -> Self {
+        self.num_queues = Some(num_queues);
+        self
+    }
+
+    /// Set `guest_mac` to a locally administered unicast MAC derived from
+    /// `vmid` and the `iface_id`, so that guest interfaces can be told
+    /// apart, with an optional first octet `prefix` and per instance
+    /// `salt`. See `mac_address::generate_mac`.
+    pub fn with_generated_mac(
+        mut self,
+        vmid: &str,
+        prefix: Option<u8>,
+        salt: Option<&[u8]>,
+    ) -> Self {
+        self.guest_mac = Some(mac_address::generate_mac(
+            vmid,
+            &self.iface_id,
+            prefix,
+            salt,
+        ));
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::NetworkInterface;
+
+    #[test]
+    fn test_num_queues() {
+        let iface = NetworkInterface {
+            guest_mac: None,
+            host_dev_name: "tap0".into(),
+            iface_id: "eth0".into(),
+            num_queues: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            static_ip: None,
+        };
+        assert!(serde_json::to_value(&iface)
+            .unwrap()
+            .get("num_queues")
+            .is_none());
+
+        let iface = iface.with_num_queues(4);
+        assert!(iface.validate().is_ok());
+        assert_eq!(
+            serde_json::to_value(&iface).unwrap(),
+            json!({"host_dev_name": "tap0", "iface_id": "eth0", "num_queues": 4})
+        );
+
+        for num_queues in [1, 2, 16] {
+            assert!(iface.clone().with_num_queues(num_queues).validate().is_ok());
+        }
+        for num_queues in [0, 3, 6, 32] {
+            assert!(iface
+                .clone()
+                .with_num_queues(num_queues)
+                .validate()
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_generated_mac() {
+        let iface = NetworkInterface {
+            guest_mac: None,
+            host_dev_name: "tap0".into(),
+            iface_id: "eth0".into(),
+            num_queues: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            static_ip: None,
+        };
+        let first = iface.clone().with_generated_mac("vm0", None, None);
+        assert!(first.validate().is_ok());
+        assert!(first.guest_mac.as_ref().unwrap().starts_with("02:"));
+        assert_eq!(first, iface.clone().with_generated_mac("vm0", None, None));
+        assert_ne!(
+            first.guest_mac,
+            iface
+                .clone()
+                .with_generated_mac("vm1", None, None)
+                .guest_mac
+        );
+        let prefixed = iface.with_generated_mac("vm0", Some(0x06), Some(b"pool1"));
+        assert!(prefixed.guest_mac.unwrap().starts_with("06:"));
+    }
 }
@@ -1,6 +1,24 @@
 use serde::{Deserialize, Serialize};
 
-use super::rate_limiter;
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::{rate_limiter, resource_id};
+
+/// Update of a drive after boot, see `Machine::update_guest_drive_rate_limiter`
+/// and `Machine::activate_drive`
+///
+/// # Example
+///
+/// ```
+/// use rustcracker::models::partial_drive::PartialDrive;
+///
+/// let partial_drive = PartialDrive::builder()
+///     .drive_id("rootfs")
+///     .path_on_host("/srv/rootfs.ext4")
+///     .build()
+///     .unwrap();
+/// assert_eq!(partial_drive.rate_limiter, None);
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PartialDrive {
     /// drive id
@@ -18,3 +36,103 @@ pub struct PartialDrive {
     #[serde(rename = "rate_limiter", skip_serializing_if = "Option::is_none")]
     pub rate_limiter: Option<rate_limiter::RateLimiter>,
 }
+
+impl PartialDrive {
+    pub fn builder() -> PartialDriveBuilder {
+        PartialDriveBuilder::default()
+    }
+
+    /// Check `drive_id` so that it is accepted by firecracker
+    pub fn validate(&self) -> RtckResult<()> {
+        resource_id::validate_id(&self.drive_id)
+    }
+}
+
+/// Builder of `PartialDrive`, the drive id and at least one field to
+/// update are required: firecracker rejects an empty patch.
+#[derive(Debug, Clone, Default)]
+pub struct PartialDriveBuilder {
+    drive_id: Option<String>,
+    path_on_host: Option<String>,
+    rate_limiter: Option<rate_limiter::RateLimiter>,
+}
+
+impl PartialDriveBuilder {
+    pub fn drive_id<S: Into<String>>(mut self, drive_id: S) -> Self {
+        self.drive_id = Some(drive_id.into());
+        self
+    }
+
+    pub fn path_on_host<S: Into<String>>(mut self, path_on_host: S) -> Self {
+        self.path_on_host = Some(path_on_host.into());
+        self
+    }
+
+    pub fn rate_limiter(mut self, rate_limiter: rate_limiter::RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    pub fn build(self) -> RtckResult<PartialDrive> {
+        let drive_id = self.drive_id.ok_or(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "Invalid partial drive: missing drive_id",
+        ))?;
+        if self.path_on_host.is_none() && self.rate_limiter.is_none() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Invalid partial drive {drive_id}: nothing to update"),
+            ));
+        }
+        let partial_drive = PartialDrive {
+            drive_id,
+            path_on_host: self.path_on_host,
+            rate_limiter: self.rate_limiter,
+        };
+        partial_drive.validate()?;
+        Ok(partial_drive)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PartialDrive;
+    use crate::models::rate_limiter::RateLimiter;
+
+    #[test]
+    fn test_partial_drive_builder() {
+        let rate_limiter = RateLimiter::bandwidth_mbps(100.0).unwrap();
+        let partial_drive = PartialDrive::builder()
+            .drive_id("rootfs")
+            .rate_limiter(rate_limiter.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            partial_drive,
+            PartialDrive {
+                drive_id: "rootfs".into(),
+                path_on_host: None,
+                rate_limiter: Some(rate_limiter),
+            }
+        );
+
+        // Required drive id
+        let err = PartialDrive::builder()
+            .path_on_host("/srv/rootfs.ext4")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("drive_id"));
+        assert!(PartialDrive::builder()
+            .drive_id("root fs")
+            .path_on_host("/srv/rootfs.ext4")
+            .build()
+            .is_err());
+
+        // Empty patch
+        let err = PartialDrive::builder()
+            .drive_id("rootfs")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("nothing to update"));
+    }
+}
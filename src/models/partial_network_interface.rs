@@ -1,8 +1,25 @@
 use serde::{Deserialize, Serialize};
 
-use super::rate_limiter;
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::{rate_limiter, resource_id};
 /// PartialNetworkInterface Defines a partial network interface structure,
 /// used to update the rate limiters for that interface, after microvm start.
+///
+/// # Example
+///
+/// ```
+/// use rustcracker::models::{
+///     partial_network_interface::PartialNetworkInterface, rate_limiter::RateLimiter,
+/// };
+///
+/// let partial_iface = PartialNetworkInterface::builder()
+///     .iface_id("eth0")
+///     .rx_rate_limiter(RateLimiter::bandwidth_mbps(100.0).unwrap())
+///     .build()
+///     .unwrap();
+/// assert_eq!(partial_iface.tx_rate_limiter, None);
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PartialNetworkInterface {
     /// iface id
@@ -18,3 +35,98 @@ pub struct PartialNetworkInterface {
     #[serde(rename = "tx_rate_limiter", skip_serializing_if = "Option::is_none")]
     pub tx_rate_limiter: Option<rate_limiter::RateLimiter>,
 }
+
+impl PartialNetworkInterface {
+    pub fn builder() -> PartialNetworkInterfaceBuilder {
+        PartialNetworkInterfaceBuilder::default()
+    }
+
+    /// Check `iface_id` so that it is accepted by firecracker
+    pub fn validate(&self) -> RtckResult<()> {
+        resource_id::validate_id(&self.iface_id)
+    }
+}
+
+/// Builder of `PartialNetworkInterface`, the interface id and at least one
+/// rate limiter are required: firecracker rejects an empty patch.
+#[derive(Debug, Clone, Default)]
+pub struct PartialNetworkInterfaceBuilder {
+    iface_id: Option<String>,
+    rx_rate_limiter: Option<rate_limiter::RateLimiter>,
+    tx_rate_limiter: Option<rate_limiter::RateLimiter>,
+}
+
+impl PartialNetworkInterfaceBuilder {
+    pub fn iface_id<S: Into<String>>(mut self, iface_id: S) -> Self {
+        self.iface_id = Some(iface_id.into());
+        self
+    }
+
+    pub fn rx_rate_limiter(mut self, rx_rate_limiter: rate_limiter::RateLimiter) -> Self {
+        self.rx_rate_limiter = Some(rx_rate_limiter);
+        self
+    }
+
+    pub fn tx_rate_limiter(mut self, tx_rate_limiter: rate_limiter::RateLimiter) -> Self {
+        self.tx_rate_limiter = Some(tx_rate_limiter);
+        self
+    }
+
+    pub fn build(self) -> RtckResult<PartialNetworkInterface> {
+        let iface_id = self.iface_id.ok_or(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "Invalid partial network interface: missing iface_id",
+        ))?;
+        if self.rx_rate_limiter.is_none() && self.tx_rate_limiter.is_none() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Invalid partial network interface {iface_id}: nothing to update"),
+            ));
+        }
+        let partial_iface = PartialNetworkInterface {
+            iface_id,
+            rx_rate_limiter: self.rx_rate_limiter,
+            tx_rate_limiter: self.tx_rate_limiter,
+        };
+        partial_iface.validate()?;
+        Ok(partial_iface)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PartialNetworkInterface;
+    use crate::models::rate_limiter::RateLimiter;
+
+    #[test]
+    fn test_partial_network_interface_builder() {
+        let rate_limiter = RateLimiter::bandwidth_mbps(100.0).unwrap();
+        let partial_iface = PartialNetworkInterface::builder()
+            .iface_id("eth0")
+            .tx_rate_limiter(rate_limiter.clone())
+            .build()
+            .unwrap();
+        assert_eq!(
+            partial_iface,
+            PartialNetworkInterface {
+                iface_id: "eth0".into(),
+                rx_rate_limiter: None,
+                tx_rate_limiter: Some(rate_limiter.clone()),
+            }
+        );
+
+        // Required interface id
+        let err = PartialNetworkInterface::builder()
+            .rx_rate_limiter(rate_limiter)
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("iface_id"));
+
+        // Empty patch
+        let err = PartialNetworkInterface::builder()
+            .iface_id("eth0")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("nothing to update"));
+    }
+}
@@ -1,16 +1,18 @@
 use serde::{Deserialize, Serialize};
 
-use super::token_bucket;
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::{token_bucket, Validate};
 /// RateLimiter Defines an IO rate limiter with independent bytes/s and ops/s limits.
 /// Limits are defined by configuring each of the _bandwidth_ and _ops_ token buckets.
 /// This field is optional for virtio-block config and should be omitted for vhost-user-block configuration.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct RateLimiter {
     /// Token bucket with bytes as tokens
-    #[serde(rename = "banwidth")]
+    #[serde(rename = "bandwidth", skip_serializing_if = "Option::is_none")]
     pub bandwidth: Option<token_bucket::TokenBucket>,
     /// Token bucket with operations as tokens
-    #[serde(rename = "ops")]
+    #[serde(rename = "ops", skip_serializing_if = "Option::is_none")]
     pub ops: Option<token_bucket::TokenBucket>,
 }
 
@@ -21,6 +23,41 @@ impl RateLimiter {
             ops: Some(ops),
         }
     }
+
+    /// Limit the bandwidth to `mbps` megabits per second
+    pub fn bandwidth_mbps(mbps: f64) -> RtckResult<Self> {
+        let bytes_per_sec = mbps * 1_000_000.0 / 8.0;
+        if !bytes_per_sec.is_finite() || bytes_per_sec < 1.0 {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Bandwidth of {mbps} Mbps is out of range"),
+            ));
+        }
+        Ok(Self {
+            bandwidth: Some(token_bucket::TokenBucket::from_rate_per_sec(
+                bytes_per_sec as u64,
+                None,
+            )?),
+            ops: None,
+        })
+    }
+
+    /// Limit the operations to `ops` per second
+    pub fn ops_per_sec(ops: u64) -> RtckResult<Self> {
+        Ok(Self {
+            bandwidth: None,
+            ops: Some(token_bucket::TokenBucket::from_rate_per_sec(ops, None)?),
+        })
+    }
+}
+
+impl Validate for RateLimiter {
+    fn validate(&self) -> RtckResult<()> {
+        for bucket in self.bandwidth.iter().chain(&self.ops) {
+            bucket.validate()?;
+        }
+        Ok(())
+    }
 }
 
 /// RateLimiterSet represents a pair of RateLimiters (inbound and outbound)
@@ -34,3 +71,79 @@ pub struct RateLimiterSet {
     #[serde(rename = "out_rate_limiter", skip_serializing_if = "Option::is_none")]
     pub out_rate_limiter: Option<RateLimiter>,
 }
+
+impl RateLimiterSet {
+    /// Apply the same limits to both directions
+    pub fn symmetric(rate_limiter: RateLimiter) -> Self {
+        Self {
+            in_rate_limiter: Some(rate_limiter.clone()),
+            out_rate_limiter: Some(rate_limiter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{token_bucket::TokenBucket, RateLimiter, RateLimiterSet};
+
+    #[test]
+    fn test_token_bucket_from_rate() {
+        let bucket = TokenBucket::from_rate_per_sec(1000, Some(5000)).unwrap();
+        assert_eq!(
+            serde_json::to_value(&bucket).unwrap(),
+            json!({"one_time_burst": 5000, "refill_time": 1000, "size": 1000})
+        );
+
+        assert!(TokenBucket::from_rate_per_sec(0, None).is_err());
+        assert!(TokenBucket::from_rate_per_sec(1000, Some(10)).is_err());
+        assert!(TokenBucket {
+            one_time_burst: None,
+            refill_time: 0,
+            size: 1000,
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_units() {
+        let limiter = RateLimiter::bandwidth_mbps(8.0).unwrap();
+        let json = serde_json::to_value(&limiter).unwrap();
+        assert_eq!(
+            json,
+            json!({"bandwidth": {"refill_time": 1000, "size": 1000000}})
+        );
+        assert_eq!(
+            serde_json::from_value::<RateLimiter>(json).unwrap(),
+            limiter
+        );
+
+        let limiter = RateLimiter::ops_per_sec(500).unwrap();
+        assert_eq!(
+            serde_json::to_value(&limiter).unwrap(),
+            json!({"ops": {"refill_time": 1000, "size": 500}})
+        );
+
+        for mbps in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(
+                RateLimiter::bandwidth_mbps(mbps).is_err(),
+                "{mbps} accepted"
+            );
+        }
+        assert!(RateLimiter::ops_per_sec(0).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_set_symmetric() {
+        let set = RateLimiterSet::symmetric(RateLimiter::ops_per_sec(100).unwrap());
+        let json = serde_json::to_value(&set).unwrap();
+        let ops = json!({"ops": {"refill_time": 1000, "size": 100}});
+        assert_eq!(
+            json,
+            json!({"in_rate_limiter": ops, "out_rate_limiter": ops})
+        );
+        assert_eq!(serde_json::from_value::<RateLimiterSet>(json).unwrap(), set);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+/// Firecracker refuses resource ids (drive_id, iface_id, ...) longer than this.
+pub const MAX_ID_LENGTH: usize = 64;
+
+/// Check a resource id against the charset firecracker accepts,
+/// i.e. non-empty, at most 64 characters, ASCII alphanumerics and `_` only.
+pub fn validate_id(id: &str) -> RtckResult<()> {
+    if id.is_empty() {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "Resource id must not be empty",
+        ));
+    }
+
+    if id.len() > MAX_ID_LENGTH {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Resource id {id:?} longer than {MAX_ID_LENGTH} characters"),
+        ));
+    }
+
+    if !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Resource id {id:?} contains characters other than [a-zA-Z0-9_]"),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::validate_id;
+
+    #[test]
+    fn test_validate_id() {
+        assert!(validate_id("rootfs").is_ok());
+        assert!(validate_id("net_1").is_ok());
+        assert!(validate_id(&"a".repeat(64)).is_ok());
+
+        assert!(validate_id("").is_err());
+        assert!(validate_id(&"a".repeat(65)).is_err());
+        assert!(validate_id("root fs").is_err());
+        assert!(validate_id("../vm").is_err());
+        assert!(validate_id("drive/1").is_err());
+        assert!(validate_id("disque_é").is_err());
+    }
+}
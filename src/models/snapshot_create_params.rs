@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct SnapshotCreateParams {
     /// Path to the file that will contain the guest memory.
     /// Required: true
@@ -23,7 +23,7 @@ pub struct SnapshotCreateParams {
     pub version: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub enum SnapshotType {
     #[serde(rename = "Full")]
     Full,
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-use super::memory_backend;
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::memory_backend::{self, BackendType, MemoryBackend};
 
 /// Defines the configuration used for handling snapshot resume. Exactly one of
 /// the two `mem_*` fields must be present in the body of the request.
@@ -27,6 +29,11 @@ pub struct SnapshotLoadParams {
     #[serde(rename = "mem_backend", skip_serializing_if = "Option::is_none")]
     pub mem_backend: Option<memory_backend::MemoryBackend>,
 
+    /// Tap devices to attach network interfaces of the snapshot to, instead
+    /// of the ones they were created with. Requires firecracker 1.12 or later.
+    #[serde(rename = "network_overrides", skip_serializing_if = "Option::is_none")]
+    pub network_overrides: Option<Vec<NetworkOverride>>,
+
     /// When set to true, the vm is also resumed
     /// if the snapshot load is successful.
     #[serde(rename = "resume_vm", skip_serializing_if = "Option::is_none")]
@@ -37,3 +44,113 @@ pub struct SnapshotLoadParams {
     #[serde(rename = "snapshot_path")]
     pub snapshot_path: String,
 }
+
+/// Tap device of a network interface of a loaded snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NetworkOverride {
+    /// Id of the interface in the snapshot
+    #[serde(rename = "iface_id")]
+    pub iface_id: String,
+
+    /// Tap device to attach the interface to
+    #[serde(rename = "host_dev_name")]
+    pub host_dev_name: String,
+}
+
+impl SnapshotLoadParams {
+    /// Load the guest memory from the file `mem_path`
+    pub fn file<S: Into<String>, T: Into<String>>(state_path: S, mem_path: T) -> Self {
+        Self::with_backend(state_path.into(), BackendType::File, mem_path.into())
+    }
+
+    /// Serve the guest memory page faults through the userfaultfd handler
+    /// listening on `uds_path`
+    pub fn uffd<S: Into<String>, T: Into<String>>(state_path: S, uds_path: T) -> Self {
+        Self::with_backend(state_path.into(), BackendType::Uffd, uds_path.into())
+    }
+
+    fn with_backend(
+        snapshot_path: String,
+        backend_type: BackendType,
+        backend_path: String,
+    ) -> Self {
+        Self {
+            enable_diff_snapshots: None,
+            mem_file_path: None,
+            mem_backend: Some(MemoryBackend {
+                backend_type,
+                backend_path,
+            }),
+            network_overrides: None,
+            resume_vm: None,
+            snapshot_path,
+        }
+    }
+
+    /// Attach the network interfaces to other tap devices
+    pub fn network_overrides(mut self, network_overrides: Vec<NetworkOverride>) -> Self {
+        self.network_overrides = Some(network_overrides);
+        self
+    }
+
+    /// Resume the machine once loaded
+    pub fn resume(mut self, resume_vm: bool) -> Self {
+        self.resume_vm = Some(resume_vm);
+        self
+    }
+
+    /// Keep tracking dirty pages for further diff snapshots
+    pub fn enable_diff_snapshots(mut self, enable_diff_snapshots: bool) -> Self {
+        self.enable_diff_snapshots = Some(enable_diff_snapshots);
+        self
+    }
+
+    /// Check exactly one source of guest memory is given
+    pub fn validate(&self) -> RtckResult<()> {
+        if self.mem_file_path.is_some() == self.mem_backend.is_some() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Exactly one of `mem_file_path` and `mem_backend` must be given",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{NetworkOverride, SnapshotLoadParams};
+
+    #[test]
+    fn test_uffd_builder() {
+        let params = SnapshotLoadParams::uffd("/snapshots/vmstate", "/run/uffd.sock").resume(true);
+        assert!(params.validate().is_ok());
+        assert_eq!(
+            serde_json::to_value(&params).unwrap(),
+            json!({
+                "mem_backend": {"backend_type": "Uffd", "backend_path": "/run/uffd.sock"},
+                "resume_vm": true,
+                "snapshot_path": "/snapshots/vmstate",
+            })
+        );
+
+        let params = SnapshotLoadParams::file("/vm.state", "/vm.mem").network_overrides(vec![
+            NetworkOverride {
+                iface_id: "eth0".into(),
+                host_dev_name: "tap1".into(),
+            },
+        ]);
+        assert_eq!(
+            serde_json::to_value(&params).unwrap()["network_overrides"],
+            json!([{"iface_id": "eth0", "host_dev_name": "tap1"}])
+        );
+
+        let mut params = SnapshotLoadParams::file("/snapshots/vmstate", "/snapshots/mem");
+        params.mem_file_path = Some("/snapshots/mem".into());
+        assert!(params.validate().is_err());
+        params.mem_backend = None;
+        assert!(params.validate().is_ok());
+    }
+}
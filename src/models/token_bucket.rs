@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
 /// TokenBucket Defines a token bucket with a maximum capacity (size),
 /// an initial burst size (one_time_burst) and an interval for refilling purposes (refill_time).
 /// The refill-rate is derived from size and refill_time,
@@ -12,6 +14,7 @@ use serde::{Deserialize, Serialize};
 pub struct TokenBucket {
     /// The initial size of a token bucket.
     /// Minimum: 0
+    #[serde(rename = "one_time_burst", skip_serializing_if = "Option::is_none")]
     pub one_time_burst: Option<u64>,
 
     /// The amount of milliseconds it takes for the bucket to refill.
@@ -24,3 +27,45 @@ pub struct TokenBucket {
     /// Minimum: 0
     pub size: u64,
 }
+
+impl TokenBucket {
+    /// Bucket refilling `rate` tokens (bytes or operations) every second,
+    /// with an optional one time burst on top of it
+    pub fn from_rate_per_sec(rate: u64, burst: Option<u64>) -> RtckResult<Self> {
+        let bucket = Self {
+            one_time_burst: burst,
+            refill_time: 1000,
+            size: rate,
+        };
+        bucket.validate()?;
+        Ok(bucket)
+    }
+
+    /// Firecracker disables a bucket with zero size or refill time instead
+    /// of refusing it, and a one time burst below the size is hardly a burst
+    /// since the bucket starts full.
+    pub fn validate(&self) -> RtckResult<()> {
+        if self.refill_time == 0 {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Token bucket refill time must be non-zero",
+            ));
+        }
+        if self.size == 0 {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Token bucket size must be non-zero",
+            ));
+        }
+        match self.one_time_burst {
+            Some(burst) if burst < self.size => Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Token bucket one time burst {burst} is smaller than its size {}",
+                    self.size
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
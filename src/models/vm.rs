@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::instance_info;
+
 // VM Defines the microVM running state.
 // It is especially useful in the snapshotting context.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -24,3 +28,56 @@ pub const VM_STATE_PAUSED: Vm = Vm {
 pub const VM_STATE_RESUMED: Vm = Vm {
     state: State::Resumed,
 };
+
+impl State {
+    /// Instance state reached once the VM is set to this state
+    pub fn instance_state(self) -> instance_info::State {
+        match self {
+            State::Paused => instance_info::State::Paused,
+            State::Resumed => instance_info::State::Running,
+        }
+    }
+
+    /// Check that an instance in state `from` may be set to this state:
+    /// only a running instance pauses and only a paused one resumes
+    pub fn check_transition(self, from: instance_info::State) -> RtckResult<()> {
+        match (from, self) {
+            (instance_info::State::Running, State::Paused)
+            | (instance_info::State::Paused, State::Resumed) => Ok(()),
+            (instance_info::State::NotStarted, _) => Err(RtckError::new(
+                RtckErrorClass::MachineError,
+                format!("Cannot set a not started instance to {self:?}"),
+            )),
+            _ => Err(RtckError::new(
+                RtckErrorClass::MachineError,
+                format!("Instance is already {from:?}"),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::models::instance_info;
+
+    use super::State;
+
+    #[test]
+    fn test_check_transition() {
+        use instance_info::State::{NotStarted, Paused, Running};
+
+        assert!(State::Paused.check_transition(Running).is_ok());
+        assert!(State::Resumed.check_transition(Paused).is_ok());
+        for (from, to) in [
+            (NotStarted, State::Paused),
+            (NotStarted, State::Resumed),
+            (Paused, State::Paused),
+            (Running, State::Resumed),
+        ] {
+            assert!(to.check_transition(from).is_err(), "{from:?} -> {to:?}");
+        }
+
+        assert_eq!(State::Paused.instance_state(), Paused);
+        assert_eq!(State::Resumed.instance_state(), Running);
+    }
+}
@@ -1,5 +1,11 @@
+use std::path::{Component, Path, PathBuf};
+
 use serde::{Deserialize, Serialize};
 
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::{mac_address, Validate};
+
 /*
 Vsock Defines a vsock device, backed by a set of Unix Domain Sockets, on the host side.
 
@@ -50,3 +56,167 @@ pub struct Vsock {
     #[serde(rename = "vsock_id", skip_serializing_if = "Option::is_none")]
     pub vsock_id: Option<String>,
 }
+
+impl Vsock {
+    /// Context identifiers 0, 1 and 2 are reserved (hypervisor, local and host).
+    pub const MIN_GUEST_CID: u32 = 3;
+
+    pub fn new<S: Into<String>>(guest_cid: u32, uds_path: S) -> RtckResult<Self> {
+        let vsock = Self {
+            guest_cid,
+            uds_path: uds_path.into(),
+            vsock_id: None,
+        };
+        vsock.validate()?;
+        Ok(vsock)
+    }
+
+    /// Set `guest_cid` to a CID derived from `vmid`, stable across runs.
+    /// Distinct vmids may collide, unlike with the CIDs handed out by
+    /// `VsockCidAllocator` which are unique among the machines of the process.
+    pub fn with_generated_cid(mut self, vmid: &str) -> Self {
+        self.guest_cid = generate_cid(vmid);
+        self
+    }
+
+    /// Path of `uds_path` seen from the host when firecracker runs
+    /// in a jail rooted at `jailer_workspace_dir`, refused if it would
+    /// climb out of the jail.
+    pub fn jailed_path<P: AsRef<Path>>(&self, jailer_workspace_dir: P) -> RtckResult<PathBuf> {
+        let mut path = jailer_workspace_dir.as_ref().to_path_buf();
+        for component in Path::new(&self.uds_path).components() {
+            match component {
+                Component::Normal(name) => path.push(name),
+                Component::RootDir | Component::CurDir => (),
+                Component::ParentDir | Component::Prefix(_) => {
+                    return Err(RtckError::new(
+                        RtckErrorClass::ConfigError,
+                        format!("Vsock uds_path {} escapes the jail", self.uds_path),
+                    ))
+                }
+            }
+        }
+        Ok(path)
+    }
+}
+
+/// CID derived from a hash of `vmid`, at least `Vsock::MIN_GUEST_CID` and
+/// never `u32::MAX`, which stands for any CID
+pub fn generate_cid(vmid: &str) -> u32 {
+    let span = (u32::MAX - Vsock::MIN_GUEST_CID) as u64;
+    let hash = mac_address::fnv1a(&[vmid.as_bytes()]);
+    Vsock::MIN_GUEST_CID + (hash % span) as u32
+}
+
+impl Validate for Vsock {
+    /// `uds_path` must not climb out of the directory it is resolved in,
+    /// i.e. the jail when firecracker is jailed
+    fn validate(&self) -> RtckResult<()> {
+        if self.guest_cid < Self::MIN_GUEST_CID {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "Vsock guest_cid {} is reserved, must be at least {}",
+                    self.guest_cid,
+                    Self::MIN_GUEST_CID
+                ),
+            ));
+        }
+        if self.uds_path.is_empty() {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                "Vsock uds_path is empty",
+            ));
+        }
+        if Path::new(&self.uds_path)
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Vsock uds_path {} escapes the jail", self.uds_path),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{generate_cid, Validate, Vsock};
+
+    #[test]
+    fn test_generated_cid() {
+        let vsock = Vsock::new(3, "/tmp/v.sock")
+            .unwrap()
+            .with_generated_cid("vm0");
+        assert!(vsock.validate().is_ok());
+        assert_eq!(vsock.guest_cid, generate_cid("vm0"));
+        assert_ne!(generate_cid("vm0"), generate_cid("vm1"));
+        for i in 0..1000 {
+            let cid = generate_cid(&format!("vm{i}"));
+            assert!((Vsock::MIN_GUEST_CID..u32::MAX).contains(&cid));
+        }
+    }
+
+    #[test]
+    fn test_vsock_validate_cid() {
+        let mut vsock = Vsock {
+            guest_cid: 3,
+            uds_path: "/tmp/vsock.sock".to_string(),
+            vsock_id: None,
+        };
+        assert!(vsock.validate().is_ok());
+        for cid in 0..3 {
+            vsock.guest_cid = cid;
+            assert!(vsock.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_vsock_new() {
+        let vsock = Vsock::new(3, "/run/vsock.sock").unwrap();
+        assert_eq!(vsock.guest_cid, 3);
+        assert!(Vsock::new(2, "/run/vsock.sock").is_err());
+        assert!(Vsock::new(3, "").is_err());
+        assert!(Vsock::new(3, "../vsock.sock").is_err());
+        assert!(Vsock::new(3, "/run/../../vsock.sock").is_err());
+
+        // Optional fields are omitted
+        assert_eq!(
+            serde_json::to_value(&vsock).unwrap(),
+            serde_json::json!({"guest_cid": 3, "uds_path": "/run/vsock.sock"})
+        );
+    }
+
+    #[test]
+    fn test_vsock_jailed_path() {
+        let workspace = "/srv/jailer/firecracker/vm0/root";
+        let mut vsock = Vsock {
+            guest_cid: 3,
+            uds_path: "/run/vsock.sock".to_string(),
+            vsock_id: None,
+        };
+        assert_eq!(
+            vsock.jailed_path(workspace).unwrap(),
+            PathBuf::from("/srv/jailer/firecracker/vm0/root/run/vsock.sock")
+        );
+        vsock.uds_path = "vsock.sock".to_string();
+        assert_eq!(
+            vsock.jailed_path(workspace).unwrap(),
+            PathBuf::from("/srv/jailer/firecracker/vm0/root/vsock.sock")
+        );
+        vsock.uds_path = "//run/./vsock.sock".to_string();
+        assert_eq!(
+            vsock.jailed_path(workspace).unwrap(),
+            PathBuf::from("/srv/jailer/firecracker/vm0/root/run/vsock.sock")
+        );
+        // Climbing out of the jail, even when validation is skipped
+        for uds_path in ["../vsock.sock", "/run/../../vsock.sock", ".."] {
+            vsock.uds_path = uds_path.to_string();
+            assert!(vsock.jailed_path(workspace).is_err(), "{uds_path}");
+        }
+    }
+}
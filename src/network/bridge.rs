@@ -0,0 +1,150 @@
+use std::{net::Ipv4Addr, path::Path, process::Command};
+
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::{ip, NetworkResource};
+
+/// Parse `cidr` such as `172.20.0.1/24` into the address and prefix length
+pub(crate) fn parse_cidr(cidr: &str) -> RtckResult<(Ipv4Addr, u8)> {
+    let invalid = |reason: &str| {
+        RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Invalid CIDR {cidr:?}, {reason}"),
+        )
+    };
+    let (addr, prefix_len) = cidr
+        .split_once('/')
+        .ok_or_else(|| invalid("expecting <address>/<prefix length>"))?;
+    let addr = addr.parse().map_err(|_| invalid("not an IPv4 address"))?;
+    let prefix_len = prefix_len
+        .parse()
+        .ok()
+        .filter(|len| *len <= 32)
+        .ok_or_else(|| invalid("malformed prefix length"))?;
+    Ok((addr, prefix_len))
+}
+
+/// Bridge on the host, with an address serving as the gateway of the guests
+/// whose tap devices are attached to it. Bridges are shared between machines
+/// and never deleted on their own, see `rollback`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bridge {
+    name: String,
+    address: Ipv4Addr,
+    prefix_len: u8,
+    created: bool,
+}
+
+impl Bridge {
+    /// Make sure the bridge `name` exists, is up, and has the address `cidr`,
+    /// e.g. `172.20.0.1/24`, creating what is missing
+    pub fn ensure<S: Into<String>>(name: S, cidr: &str) -> RtckResult<Self> {
+        let name = name.into();
+        let (address, prefix_len) = parse_cidr(cidr)?;
+        let sys = Path::new("/sys/class/net").join(&name);
+        let created = if sys.exists() {
+            if !sys.join("bridge").is_dir() {
+                return Err(RtckError::new(
+                    RtckErrorClass::NetworkError,
+                    format!("Network device {name} exists but is not a bridge"),
+                ));
+            }
+            false
+        } else {
+            ip(&["link", "add", "name", &name, "type", "bridge"])?;
+            true
+        };
+        let bridge = Self {
+            name,
+            address,
+            prefix_len,
+            created,
+        };
+        if let Err(e) = bridge.configure() {
+            if created {
+                let _ = bridge.resource().remove();
+            }
+            return Err(e);
+        }
+        Ok(bridge)
+    }
+
+    fn configure(&self) -> RtckResult<()> {
+        let cidr = format!("{}/{}", self.address, self.prefix_len);
+        let output = Command::new("ip")
+            .args(["-o", "-4", "addr", "show", "dev", &self.name])
+            .output()?;
+        let assigned = String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .any(|word| word == cidr);
+        if !assigned {
+            ip(&["addr", "add", &cidr, "dev", &self.name])?;
+        }
+        ip(&["link", "set", "dev", &self.name, "up"])
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Address of the bridge, the gateway of the guests
+    pub fn address(&self) -> Ipv4Addr {
+        self.address
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Whether `addr` is in the subnet of the bridge
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        let mask = u32::MAX
+            .checked_shl(32 - self.prefix_len as u32)
+            .unwrap_or(0);
+        u32::from(addr) & mask == u32::from(self.address) & mask
+    }
+
+    /// Whether `ensure` created the bridge
+    pub fn created(&self) -> bool {
+        self.created
+    }
+
+    /// The bridge as a resource to delete
+    pub fn resource(&self) -> NetworkResource {
+        NetworkResource::Bridge(self.name.clone())
+    }
+
+    /// What undoes `ensure`, deleting the bridge if it created it
+    pub fn rollback(&self) -> Option<NetworkResource> {
+        self.created.then(|| self.resource())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_cidr, Bridge};
+
+    #[test]
+    fn test_bridge() {
+        assert!(parse_cidr("172.20.0.1/33").is_err());
+        assert!(parse_cidr("172.20.0.1").is_err());
+
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        if !super::super::tap::has_net_admin(&status) {
+            return;
+        }
+        let name = format!("rtckbr{}", std::process::id() % 100_000);
+        let bridge = Bridge::ensure(&name, "172.30.250.1/24").unwrap();
+        assert!(bridge.created());
+        assert!(bridge.contains("172.30.250.77".parse().unwrap()));
+        assert!(!bridge.contains("172.30.251.1".parse().unwrap()));
+
+        // Idempotent, nothing to undo the second time
+        let again = Bridge::ensure(&name, "172.30.250.1/24").unwrap();
+        assert!(!again.created());
+        assert!(again.rollback().is_none());
+
+        bridge.rollback().unwrap().remove().unwrap();
+        assert!(!std::path::Path::new("/sys/class/net").join(&name).exists());
+    }
+}
@@ -0,0 +1,795 @@
+use std::{
+    io::Write,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    config::FirecrackerConfig,
+    models::{kernel_args::IpBootParam, network_interface::NetworkInterface},
+    RtckError, RtckErrorClass, RtckResult,
+};
+
+use super::{ip, NetworkResource};
+
+/// Directory searched for CNI plugins unless told otherwise
+pub const DEFAULT_BIN_DIR: &str = "/opt/cni/bin";
+
+/// Directory of the network configurations unless told otherwise
+pub const DEFAULT_CONF_DIR: &str = "/etc/cni/net.d";
+
+pub use super::NETNS_DIR;
+
+/// Interface reported by a CNI plugin
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CniInterface {
+    pub name: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+
+    /// Network namespace path of the interface, or the container id for
+    /// the interface of the guest itself (e.g. with `tc-redirect-tap`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<String>,
+}
+
+/// Address reported by a CNI plugin
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CniIp {
+    /// Address with its prefix length, e.g. `10.0.0.2/24`
+    pub address: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<IpAddr>,
+
+    /// Index of the interface in `CniResult::interfaces`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CniDns {
+    #[serde(default)]
+    pub nameservers: Vec<IpAddr>,
+}
+
+/// Result of the ADD command, as printed by the last plugin of the chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CniResult {
+    #[serde(rename = "cniVersion", default)]
+    pub cni_version: String,
+
+    #[serde(default)]
+    pub interfaces: Vec<CniInterface>,
+
+    #[serde(default)]
+    pub ips: Vec<CniIp>,
+
+    #[serde(default)]
+    pub dns: CniDns,
+}
+
+impl CniResult {
+    /// Interface of the guest: the one sandboxed in the container itself,
+    /// or else the interface of the first address
+    pub fn vm_interface(&self, container_id: &str) -> Option<&CniInterface> {
+        self.interfaces
+            .iter()
+            .find(|iface| iface.sandbox.as_deref() == Some(container_id))
+            .or_else(|| {
+                let index = self.ips.first()?.interface?;
+                self.interfaces.get(index)
+            })
+    }
+
+    /// Tap device firecracker is to use, the interface of the namespace
+    /// `netns` other than `if_name`
+    pub fn tap_device(&self, netns: &Path, if_name: &str) -> Option<&CniInterface> {
+        self.interfaces.iter().find(|iface| {
+            iface.name != if_name && iface.sandbox.as_deref().map(Path::new) == Some(netns)
+        })
+    }
+
+    /// Static configuration of the guest network from the first address
+    /// of the guest interface, `None` if the plugins assigned none
+    pub fn ip_boot_param(&self, container_id: &str) -> RtckResult<Option<IpBootParam>> {
+        let vm_index = self
+            .vm_interface(container_id)
+            .and_then(|vm| self.interfaces.iter().position(|iface| iface == vm));
+        let ip = self
+            .ips
+            .iter()
+            .find(|ip| ip.interface.is_some() && ip.interface == vm_index)
+            .or(self.ips.first());
+        let Some(ip) = ip else {
+            return Ok(None);
+        };
+
+        let malformed = || {
+            RtckError::new(
+                RtckErrorClass::NetworkError,
+                format!("Malformed CNI address {:?}", ip.address),
+            )
+        };
+        let (address, prefix_len) = ip.address.split_once('/').ok_or_else(malformed)?;
+        let mut param = IpBootParam::new(
+            address.parse().map_err(|_| malformed())?,
+            prefix_len.parse().map_err(|_| malformed())?,
+        );
+        param.gateway = ip.gateway;
+        param.nameservers = self
+            .dns
+            .nameservers
+            .iter()
+            .filter(|ns| ns.is_ipv4() == param.client_ip.is_ipv4())
+            .take(2)
+            .cloned()
+            .collect();
+        param.validate()?;
+        Ok(Some(param))
+    }
+}
+
+/// Network configuration, a single plugin or a chain of them
+#[derive(Debug, Clone)]
+struct NetConf {
+    name: String,
+    cni_version: String,
+    plugins: Vec<Value>,
+}
+
+impl NetConf {
+    fn parse(content: &[u8]) -> RtckResult<Self> {
+        let conf: Value = serde_json::from_slice(content)?;
+        let field = |key: &str| conf.get(key).and_then(Value::as_str).unwrap_or_default();
+        let plugins = match conf.get("plugins") {
+            Some(Value::Array(plugins)) => plugins.clone(),
+            Some(_) => {
+                return Err(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "CNI plugins must be a list",
+                ))
+            }
+            None => vec![conf.clone()],
+        };
+        Ok(Self {
+            name: field("name").to_string(),
+            cni_version: field("cniVersion").to_string(),
+            plugins,
+        })
+    }
+
+    /// Load the network `net_name` from the file `path`, or from the first
+    /// configuration file of the directory `path` defining it
+    fn load(path: &Path, net_name: &str) -> RtckResult<Self> {
+        let files = if path.is_dir() {
+            let mut files = std::fs::read_dir(path)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<RtckResult<Vec<_>>>()?;
+            files.retain(|file| {
+                matches!(
+                    file.extension().and_then(|ext| ext.to_str()),
+                    Some("conf" | "conflist" | "json")
+                )
+            });
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+        for file in files {
+            let conf = Self::parse(&std::fs::read(&file)?)?;
+            if conf.name == net_name {
+                return Ok(conf);
+            }
+        }
+        Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("No CNI network {net_name:?} in {path:?}"),
+        ))
+    }
+
+    fn to_value(&self) -> Value {
+        serde_json::json!({
+            "name": self.name,
+            "cniVersion": self.cni_version,
+            "plugins": self.plugins,
+        })
+    }
+
+    /// Configuration given to the plugin on stdin
+    fn plugin_conf(&self, plugin: &Value, prev_result: Option<&Value>) -> RtckResult<Value> {
+        let mut conf = plugin.clone();
+        let Some(object) = conf.as_object_mut() else {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Malformed CNI plugin configuration of {:?}", self.name),
+            ));
+        };
+        object.insert("name".into(), self.name.clone().into());
+        object.insert("cniVersion".into(), self.cni_version.clone().into());
+        if let Some(prev_result) = prev_result {
+            object.insert("prevResult".into(), prev_result.clone());
+        }
+        Ok(conf)
+    }
+}
+
+/// Network of a machine set up by CNI plugins. The container id given to
+/// the plugins is the vmid. The namespace, `/var/run/netns/<vmid>` unless
+/// set otherwise, must exist and firecracker run in it to reach the tap.
+///
+/// Once set up, the network is torn down with the DEL command by `teardown`,
+/// or when dropped.
+#[derive(Debug)]
+pub struct CniNetwork {
+    container_id: String,
+    conf_path: PathBuf,
+    net_name: String,
+    bin_dirs: Vec<PathBuf>,
+    netns: PathBuf,
+    if_name: String,
+    iface_id: String,
+    // Configuration and ADD result the DEL is given, set while set up
+    attachment: Option<(NetConf, Value)>,
+}
+
+impl CniNetwork {
+    pub fn new<S: Into<String>, P: Into<PathBuf>, N: Into<String>>(
+        vmid: S,
+        cni_conf_path: P,
+        net_name: N,
+    ) -> Self {
+        let container_id = vmid.into();
+        Self {
+            netns: Path::new(NETNS_DIR).join(&container_id),
+            container_id,
+            conf_path: cni_conf_path.into(),
+            net_name: net_name.into(),
+            bin_dirs: vec![DEFAULT_BIN_DIR.into()],
+            if_name: "eth0".into(),
+            iface_id: "eth0".into(),
+            attachment: None,
+        }
+    }
+
+    /// Directories searched for the plugins, in order
+    pub fn with_bin_dirs(mut self, bin_dirs: Vec<PathBuf>) -> Self {
+        self.bin_dirs = bin_dirs;
+        self
+    }
+
+    pub fn with_netns<P: Into<PathBuf>>(mut self, netns: P) -> Self {
+        self.netns = netns.into();
+        self
+    }
+
+    /// Name of the interface the plugins create in the namespace
+    pub fn with_if_name<S: Into<String>>(mut self, if_name: S) -> Self {
+        self.if_name = if_name.into();
+        self
+    }
+
+    /// Id of the firecracker network interface built by `setup`
+    pub fn with_iface_id<S: Into<String>>(mut self, iface_id: S) -> Self {
+        self.iface_id = iface_id.into();
+        self
+    }
+
+    pub fn netns(&self) -> &Path {
+        &self.netns
+    }
+
+    /// Run the ADD command of the plugins and build the network interface
+    /// of the machine on the tap device they created. Plugins which
+    /// succeeded are given the DEL command if a later one fails.
+    pub fn setup(&mut self) -> RtckResult<NetworkInterface> {
+        if self.attachment.is_some() {
+            return Err(RtckError::new(
+                RtckErrorClass::NetworkError,
+                format!("CNI network of {:?} already set up", self.container_id),
+            ));
+        }
+
+        let conf = NetConf::load(&self.conf_path, &self.net_name)?;
+        let mut prev_result = None;
+        for (i, plugin) in conf.plugins.iter().enumerate() {
+            let plugin_conf = conf.plugin_conf(plugin, prev_result.as_ref())?;
+            match self.exec("ADD", &plugin_conf) {
+                Ok(result) => prev_result = Some(serde_json::from_slice(&result)?),
+                Err(e) => {
+                    let added = NetConf {
+                        plugins: conf.plugins[..=i].to_vec(),
+                        ..conf
+                    };
+                    if let Err(e) = self.del(&added, prev_result.as_ref()) {
+                        log::error!("Fail to roll back CNI network, {e}");
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        let result = prev_result.ok_or(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("CNI network {:?} has no plugin", self.net_name),
+        ))?;
+        self.attachment = Some((conf, result));
+
+        let network_interface = self.network_interface();
+        if network_interface.is_err() {
+            let _ = self.teardown();
+        }
+        network_interface
+    }
+
+    /// Result of the ADD command, `None` unless set up
+    pub fn result(&self) -> Option<CniResult> {
+        let (_, result) = self.attachment.as_ref()?;
+        serde_json::from_value(result.clone()).ok()
+    }
+
+    /// Kernel `ip=` parameter matching the address the plugins assigned
+    pub fn ip_boot_param(&self) -> RtckResult<Option<IpBootParam>> {
+        match self.result() {
+            Some(result) => result.ip_boot_param(&self.container_id),
+            None => Ok(None),
+        }
+    }
+
+    /// Run the DEL command of the plugins, in reverse order, doing nothing
+    /// unless set up
+    pub fn teardown(&mut self) -> RtckResult<()> {
+        match self.attachment.take() {
+            Some((conf, result)) => self.del(&conf, Some(&result)),
+            None => Ok(()),
+        }
+    }
+
+    /// Keep the network, handing it over to be torn down along with a
+    /// machine, see `Machine::clear_network_on_delete`. `None` unless set up.
+    pub fn into_resource(mut self) -> Option<NetworkResource> {
+        let (conf, result) = self.attachment.take()?;
+        Some(NetworkResource::Cni(CniAttachment {
+            container_id: std::mem::take(&mut self.container_id),
+            net_name: std::mem::take(&mut self.net_name),
+            bin_dirs: std::mem::take(&mut self.bin_dirs),
+            netns: std::mem::take(&mut self.netns),
+            if_name: std::mem::take(&mut self.if_name),
+            conf: conf.to_value().to_string(),
+            result: result.to_string(),
+        }))
+    }
+
+    fn network_interface(&self) -> RtckResult<NetworkInterface> {
+        let result = self.result().ok_or(RtckError::new(
+            RtckErrorClass::NetworkError,
+            "Malformed CNI result",
+        ))?;
+        let tap = result
+            .tap_device(&self.netns, &self.if_name)
+            .ok_or(RtckError::new(
+                RtckErrorClass::NetworkError,
+                format!("No tap device in the CNI result of {:?}", self.net_name),
+            ))?;
+        let network_interface = NetworkInterface {
+            guest_mac: result
+                .vm_interface(&self.container_id)
+                .and_then(|vm| vm.mac.clone()),
+            host_dev_name: tap.name.clone(),
+            iface_id: self.iface_id.clone(),
+            num_queues: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            static_ip: None,
+        };
+        network_interface.validate()?;
+        Ok(network_interface)
+    }
+
+    /// Run DEL for every plugin of `conf`, carrying on after failures
+    /// and reporting the first one
+    fn del(&self, conf: &NetConf, result: Option<&Value>) -> RtckResult<()> {
+        let mut first_error = None;
+        for plugin in conf.plugins.iter().rev() {
+            let outcome = conf
+                .plugin_conf(plugin, result)
+                .and_then(|plugin_conf| self.exec("DEL", &plugin_conf));
+            if let Err(e) = outcome {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    fn find_plugin(&self, plugin_type: &str) -> RtckResult<PathBuf> {
+        self.bin_dirs
+            .iter()
+            .map(|dir| dir.join(plugin_type))
+            .find(|path| path.is_file())
+            .ok_or(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!(
+                    "CNI plugin {plugin_type:?} not found in {:?}",
+                    self.bin_dirs
+                ),
+            ))
+    }
+
+    /// Run `command` of the plugin of `plugin_conf`, returning its stdout
+    fn exec(&self, command: &str, plugin_conf: &Value) -> RtckResult<Vec<u8>> {
+        let plugin_type = plugin_conf
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("CNI plugin of {:?} without type", self.net_name),
+            ))?;
+        let cni_path = std::env::join_paths(&self.bin_dirs).map_err(|e| {
+            RtckError::new(RtckErrorClass::ConfigError, format!("Bad CNI path, {e}")).with_source(e)
+        })?;
+
+        let mut child = Command::new(self.find_plugin(plugin_type)?)
+            .env("CNI_COMMAND", command)
+            .env("CNI_CONTAINERID", &self.container_id)
+            .env("CNI_NETNS", &self.netns)
+            .env("CNI_IFNAME", &self.if_name)
+            .env("CNI_PATH", cni_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&serde_json::to_vec(plugin_conf)?)?;
+        }
+        let output = child.wait_with_output()?;
+        if output.status.success() {
+            return Ok(output.stdout);
+        }
+
+        // Plugins report errors as {"code": .., "msg": .., "details": ..} on stdout
+        let msg = serde_json::from_slice::<Value>(&output.stdout)
+            .ok()
+            .and_then(|error| error.get("msg").and_then(Value::as_str).map(String::from))
+            .unwrap_or_else(|| String::from_utf8_lossy(&output.stdout).trim().to_string());
+        Err(RtckError::new(
+            RtckErrorClass::NetworkError,
+            format!(
+                "CNI plugin {plugin_type} failed on {command} ({}): {msg} {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+impl Drop for CniNetwork {
+    fn drop(&mut self) {
+        if let Err(e) = self.teardown() {
+            log::error!("Fail to tear down CNI network, {e}");
+        }
+    }
+}
+
+/// CNI network set up for a machine, what its DEL command is given
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CniAttachment {
+    container_id: String,
+    net_name: String,
+    bin_dirs: Vec<PathBuf>,
+    netns: PathBuf,
+    if_name: String,
+    // Network configuration and result of the ADD command, as JSON
+    conf: String,
+    result: String,
+}
+
+impl CniAttachment {
+    pub fn container_id(&self) -> &str {
+        &self.container_id
+    }
+
+    /// Run the DEL command of the plugins, in reverse order
+    pub fn teardown(&self) -> RtckResult<()> {
+        let conf = NetConf::parse(self.conf.as_bytes())?;
+        let result = serde_json::from_str(&self.result)?;
+        let mut network = CniNetwork::new(&self.container_id, PathBuf::new(), &self.net_name)
+            .with_bin_dirs(self.bin_dirs.clone())
+            .with_netns(&self.netns)
+            .with_if_name(&self.if_name);
+        network.attachment = Some((conf, result));
+        network.teardown()
+    }
+}
+
+/// Network a machine asks for in `cni` of its configuration, set up before
+/// launching firecracker and torn down when dropped, unless handed over
+/// with `into_resources`
+pub(crate) struct CniSetup {
+    // Namespace created for the machine
+    netns: Option<PathBuf>,
+    network: Option<CniNetwork>,
+}
+
+impl CniSetup {
+    /// Set the network of `frck_config` up, if it asks for one: create the
+    /// namespace named after the vmid unless `net_ns` is set, run the ADD
+    /// command, and add the network interface on the tap device created by
+    /// the plugins together with the `ip=` kernel parameter. Failures of
+    /// the plugins are reported with their output.
+    pub(crate) fn run(frck_config: &mut FirecrackerConfig) -> RtckResult<Option<Self>> {
+        let Some(cni) = frck_config.cni.clone() else {
+            return Ok(None);
+        };
+        let vmid = frck_config.vmid.clone().ok_or(RtckError::new(
+            RtckErrorClass::ConfigError,
+            "CNI network set up without vmid",
+        ))?;
+
+        let mut setup = Self {
+            netns: None,
+            network: None,
+        };
+        let netns = match &frck_config.net_ns {
+            Some(netns) => PathBuf::from(netns),
+            None => {
+                let netns = Path::new(NETNS_DIR).join(&vmid);
+                if !netns.exists() {
+                    ip(&["netns", "add", &vmid])?;
+                    setup.netns = Some(netns.clone());
+                }
+                netns
+            }
+        };
+
+        let conf_path = cni.config_dir.unwrap_or(DEFAULT_CONF_DIR.into());
+        let bin_dir = cni.bin_dir.unwrap_or(DEFAULT_BIN_DIR.into());
+        let mut network = CniNetwork::new(&vmid, conf_path, cni.network_name)
+            .with_bin_dirs(vec![bin_dir])
+            .with_netns(&netns);
+        if let Some(if_name) = cni.if_name {
+            network = network.with_if_name(if_name);
+        }
+        let network_interface = network.setup()?;
+        let param = network.ip_boot_param();
+        setup.network = Some(network);
+
+        frck_config.add_network_interface(NetworkInterface {
+            static_ip: param?.map(Into::into),
+            ..network_interface
+        })?;
+        frck_config.net_ns = Some(netns.to_string_lossy().into_owned());
+        Ok(Some(setup))
+    }
+
+    /// Resources to delete along with the machine, the CNI network being
+    /// torn down before the namespace is deleted
+    pub(crate) fn into_resources(mut self) -> Vec<NetworkResource> {
+        let mut resources: Vec<_> = self
+            .netns
+            .take()
+            .map(NetworkResource::Netns)
+            .into_iter()
+            .collect();
+        resources.extend(self.network.take().and_then(CniNetwork::into_resource));
+        resources
+    }
+}
+
+impl Drop for CniSetup {
+    fn drop(&mut self) {
+        // Tear the network down before deleting its namespace
+        self.network = None;
+        if let Some(netns) = self.netns.take() {
+            if let Err(e) = NetworkResource::Netns(netns).remove() {
+                log::error!("Fail to delete network namespace, {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use crate::{
+        config::{CniConfig, FirecrackerConfig},
+        models::boot_source::BootSource,
+        network::NetworkResource,
+        RtckErrorClass,
+    };
+
+    use super::{CniNetwork, CniSetup};
+
+    /// Plugin logging its commands and config, answering ADD like
+    /// `tc-redirect-tap` and failing the command named by its `fail` field
+    const FAKE_PLUGIN: &str = r#"#!/bin/sh
+conf=$(cat)
+echo "$CNI_COMMAND $CNI_CONTAINERID $CNI_NETNS $CNI_IFNAME $conf" >> "$(dirname "$0")/calls.log"
+case "$conf" in *"\"fail\":\"$CNI_COMMAND\""*)
+    echo '{"code":11,"msg":"no more addresses"}'
+    echo "ipam exhausted" >&2
+    exit 1
+esac
+if [ "$CNI_COMMAND" = ADD ]; then
+    cat <<EOF
+{"cniVersion":"1.0.0",
+ "interfaces":[
+  {"name":"veth0","mac":"aa:bb:cc:dd:ee:01"},
+  {"name":"$CNI_IFNAME","mac":"aa:bb:cc:dd:ee:02","sandbox":"$CNI_NETNS"},
+  {"name":"tap0","mac":"aa:bb:cc:dd:ee:03","sandbox":"$CNI_NETNS"},
+  {"name":"tap0","mac":"06:00:ac:10:00:02","sandbox":"$CNI_CONTAINERID"}],
+ "ips":[{"address":"10.168.0.2/16","gateway":"10.168.0.1","interface":3}],
+ "dns":{"nameservers":["1.1.1.1"]}}
+EOF
+fi
+"#;
+
+    fn fake_cni() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rtck-cni-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let plugin = dir.join("fake-cni");
+        std::fs::write(&plugin, FAKE_PLUGIN).unwrap();
+        std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(
+            dir.join("fcnet.conflist"),
+            r#"{"cniVersion":"1.0.0","name":"fcnet","plugins":[{"type":"fake-cni"},{"type":"fake-cni","chained":true}]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("failnet.conflist"),
+            r#"{"cniVersion":"1.0.0","name":"failnet","plugins":[{"type":"fake-cni"},{"type":"fake-cni","fail":"ADD"}]}"#,
+        )
+        .unwrap();
+        let log = dir.join("calls.log");
+        (dir, log)
+    }
+
+    fn calls(log: &PathBuf) -> Vec<String> {
+        std::fs::read_to_string(log)
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_cni_setup() {
+        let (dir, log) = fake_cni();
+        let mut network = CniNetwork::new("vm0", &dir, "fcnet")
+            .with_bin_dirs(vec![dir.clone()])
+            .with_netns("/var/run/netns/vm0");
+        let network_interface = network.setup().unwrap();
+        assert_eq!(network_interface.host_dev_name, "tap0");
+        assert_eq!(network_interface.iface_id, "eth0");
+        assert_eq!(
+            network_interface.guest_mac.as_deref(),
+            Some("06:00:ac:10:00:02")
+        );
+        assert_eq!(
+            network
+                .ip_boot_param()
+                .unwrap()
+                .unwrap()
+                .to_value()
+                .unwrap(),
+            "10.168.0.2::10.168.0.1:255.255.0.0:::off:1.1.1.1"
+        );
+
+        // The chained plugin is given the result of the first one
+        let adds = calls(&log);
+        assert_eq!(adds.len(), 2);
+        assert!(adds[0].starts_with("ADD vm0 /var/run/netns/vm0 eth0 {"));
+        assert!(adds[0].contains(r#""name":"fcnet""#));
+        assert!(!adds[0].contains("prevResult"));
+        assert!(adds[1].contains("prevResult"));
+
+        // Torn down when dropped, in reverse order
+        drop(network);
+        let calls = calls(&log);
+        assert_eq!(calls.len(), 4);
+        assert!(calls[2].starts_with("DEL vm0") && calls[2].contains("chained"));
+        assert!(calls[3].starts_with("DEL vm0") && calls[3].contains("prevResult"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cni_failure() {
+        let (dir, log) = fake_cni();
+        // Unknown network
+        let mut network = CniNetwork::new("vm1", &dir, "other").with_bin_dirs(vec![dir.clone()]);
+        assert_eq!(
+            network.setup().unwrap_err().class(),
+            &RtckErrorClass::ConfigError
+        );
+
+        // Failing plugin, the chain is rolled back with DEL
+        let mut network = CniNetwork::new("vm1", &dir, "failnet").with_bin_dirs(vec![dir.clone()]);
+        let e = network.setup().unwrap_err();
+        assert_eq!(e.class(), &RtckErrorClass::NetworkError);
+        assert!(e.to_string().contains("no more addresses"));
+        assert!(e.to_string().contains("ipam exhausted"));
+        let calls = calls(&log);
+        assert_eq!(calls.len(), 4);
+        assert!(calls[0].starts_with("ADD vm1"));
+        assert!(calls[1].starts_with("ADD vm1"));
+        assert!(calls[2].starts_with("DEL vm1") && calls[2].contains("fail"));
+        assert!(calls[3].starts_with("DEL vm1"));
+        assert!(network.result().is_none());
+
+        // Missing plugin
+        let mut network = CniNetwork::new("vm1", &dir, "fcnet").with_bin_dirs(Vec::new());
+        assert!(network.setup().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_cni_setup_config() {
+        let (dir, log) = fake_cni();
+        let frck_config = |network_name: &str, net_ns: Option<&str>| FirecrackerConfig {
+            boot_source: Some(BootSource {
+                boot_args: Some("console=ttyS0".into()),
+                initrd_path: None,
+                kernel_image_path: "/tmp/vmlinux".into(),
+            }),
+            vmid: Some(format!("rtck-cni-{}", std::process::id())),
+            net_ns: net_ns.map(String::from),
+            cni: Some(CniConfig {
+                config_dir: Some(dir.clone()),
+                bin_dir: Some(dir.clone()),
+                network_name: network_name.into(),
+                if_name: None,
+            }),
+            ..Default::default()
+        };
+
+        // Nothing asked
+        let mut config = FirecrackerConfig::default();
+        assert!(CniSetup::run(&mut config).unwrap().is_none());
+
+        let mut config = frck_config("fcnet", Some("/var/run/netns/vm2"));
+        let setup = CniSetup::run(&mut config).unwrap().unwrap();
+        let iface = &config.network_interfaces.as_ref().unwrap()[0];
+        assert_eq!(iface.host_dev_name, "tap0");
+        assert_eq!(iface.guest_mac.as_deref(), Some("06:00:ac:10:00:02"));
+        assert_eq!(
+            config
+                .effective_boot_source()
+                .unwrap()
+                .unwrap()
+                .boot_args
+                .as_deref(),
+            Some("console=ttyS0 ip=10.168.0.2::10.168.0.1:255.255.0.0::eth0:off:1.1.1.1")
+        );
+        assert_eq!(config.net_ns.as_deref(), Some("/var/run/netns/vm2"));
+
+        // Handed over, torn down along with the machine only
+        let resources = setup.into_resources();
+        assert_eq!(resources.len(), 1);
+        assert!(matches!(resources[0], NetworkResource::Cni(_)));
+        assert_eq!(calls(&log).len(), 2);
+        resources[0].remove().unwrap();
+        let dels = calls(&log);
+        assert_eq!(dels.len(), 4);
+        assert!(dels[2].starts_with("DEL rtck-cni-") && dels[2].contains("chained"));
+
+        // Failures abort with the output of the plugin, leaving the
+        // configuration and the namespace alone
+        let net_admin = std::fs::read_to_string("/proc/self/status")
+            .is_ok_and(|status| super::super::tap::has_net_admin(&status));
+        let mut config = frck_config("failnet", (!net_admin).then_some("/var/run/netns/vm2"));
+        let e = CniSetup::run(&mut config).err().unwrap();
+        assert!(e.to_string().contains("ipam exhausted"), "{e}");
+        assert!(config.network_interfaces.is_none());
+        let netns = std::path::Path::new(super::NETNS_DIR).join(config.vmid.unwrap());
+        assert!(!netns.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
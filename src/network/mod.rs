@@ -0,0 +1,184 @@
+pub mod bridge;
+#[cfg(feature = "cni")]
+pub mod cni;
+#[cfg(feature = "nat")]
+pub mod nat;
+pub mod tap;
+
+pub use bridge::Bridge;
+#[cfg(feature = "nat")]
+pub use nat::Nat;
+pub use tap::{TapDevice, TapSubnet};
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use parking_lot::Mutex;
+
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+/// Directory of the network namespaces named by `ip netns`
+pub const NETNS_DIR: &str = "/var/run/netns";
+
+/// Network resource of a machine on the host
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NetworkResource {
+    /// Tap device, by name
+    Tap(String),
+    /// Network namespace, by the path of its handle, e.g. `/var/run/netns/vm0`
+    Netns(PathBuf),
+    /// Bridge, by name
+    Bridge(String),
+    /// CNI network, torn down with the DEL command
+    #[cfg(feature = "cni")]
+    Cni(cni::CniAttachment),
+    /// Rule of iptables, by table, chain and rule specification
+    #[cfg(feature = "nat")]
+    IptablesRule {
+        table: String,
+        chain: String,
+        rule: Vec<String>,
+    },
+}
+
+impl NetworkResource {
+    /// Delete the resource from the host, nothing if it is gone already
+    pub fn remove(&self) -> RtckResult<()> {
+        match self {
+            NetworkResource::Tap(name) | NetworkResource::Bridge(name) => {
+                if !Path::new("/sys/class/net").join(name).exists() {
+                    return Ok(());
+                }
+                ip(&["link", "delete", name])
+            }
+            NetworkResource::Netns(path) => {
+                if !path.exists() {
+                    return Ok(());
+                }
+                match (path.parent(), path.file_name()) {
+                    (Some(dir), Some(name)) if dir == Path::new(NETNS_DIR) => {
+                        ip(&["netns", "delete", &name.to_string_lossy()])
+                    }
+                    _ => remove_netns_handle(path),
+                }
+            }
+            #[cfg(feature = "cni")]
+            NetworkResource::Cni(attachment) => attachment.teardown(),
+            #[cfg(feature = "nat")]
+            NetworkResource::IptablesRule { table, chain, rule } => {
+                nat::remove_rule(table, chain, rule)
+            }
+        }
+    }
+}
+
+/// Run `ip` with `args`
+fn ip(args: &[&str]) -> RtckResult<()> {
+    let output = Command::new("ip").args(args).output()?;
+    if !output.status.success() {
+        return Err(RtckError::new(
+            RtckErrorClass::NetworkError,
+            format!(
+                "ip {} failed with {}, {}",
+                args.join(" "),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Unmount the bind mount keeping a namespace alive at `path`, if any,
+/// and remove the handle
+fn remove_netns_handle(path: &Path) -> RtckResult<()> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        RtckError::new(
+            RtckErrorClass::NetworkError,
+            format!("Malformed namespace path {path:?}"),
+        )
+    })?;
+    if unsafe { libc::umount2(c_path.as_ptr(), libc::MNT_DETACH) } != 0 {
+        let e = std::io::Error::last_os_error();
+        // Not a mount point, a mere file
+        if e.raw_os_error() != Some(libc::EINVAL) {
+            return Err(RtckError::new(
+                RtckErrorClass::NetworkError,
+                format!("Fail to unmount namespace {path:?}, {e}"),
+            )
+            .with_source(e));
+        }
+    }
+    Ok(std::fs::remove_file(path)?)
+}
+
+/// Network resources deleted along with a machine when `network_clear` is
+/// set. Those rustcracker creates are tracked on their own, the ones created
+/// by the user only once handed over with `track`.
+#[derive(Debug, Default)]
+pub struct NetworkCleanup {
+    resources: Mutex<Vec<NetworkResource>>,
+}
+
+impl NetworkCleanup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delete `resource` when cleaning up
+    pub fn track(&self, resource: NetworkResource) {
+        let mut resources = self.resources.lock();
+        if !resources.contains(&resource) {
+            resources.push(resource);
+        }
+    }
+
+    /// Resources yet to be deleted, in the order they were tracked
+    pub fn tracked(&self) -> Vec<NetworkResource> {
+        self.resources.lock().clone()
+    }
+
+    /// Delete the tracked resources, the last tracked first. Every resource
+    /// is tried, those failing are kept and the first failure reported.
+    pub fn run(&self) -> RtckResult<()> {
+        let mut resources = self.resources.lock();
+        let mut first_error = None;
+        let mut failed = Vec::new();
+        while let Some(resource) = resources.pop() {
+            if let Err(e) = resource.remove() {
+                log::error!("[NetworkCleanup::run fail to remove {resource:?}, {e}]");
+                first_error.get_or_insert(e);
+                failed.push(resource);
+            }
+        }
+        failed.reverse();
+        *resources = failed;
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NetworkCleanup, NetworkResource};
+
+    #[test]
+    fn test_network_cleanup() {
+        let handle = std::env::temp_dir().join(format!("rtck-netns-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&handle, "").unwrap();
+
+        let cleanup = NetworkCleanup::new();
+        cleanup.track(NetworkResource::Netns(handle.clone()));
+        cleanup.track(NetworkResource::Tap("rtck-gone0".to_string()));
+        cleanup.track(NetworkResource::Tap("rtck-gone0".to_string()));
+        assert_eq!(cleanup.tracked().len(), 2);
+
+        // Gone resources are fine, handles out of /var/run/netns are removed
+        cleanup.run().unwrap();
+        assert!(!handle.exists());
+        assert!(cleanup.tracked().is_empty());
+        cleanup.run().unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use std::process::Command;
+
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::NetworkResource;
+
+/// Switch turning forwarding of IPv4 packets on
+const IP_FORWARD: &str = "/proc/sys/net/ipv4/ip_forward";
+
+/// Run `iptables -t <table> <action> <chain> <rule>`, whether it succeeded
+fn iptables(table: &str, action: &str, chain: &str, rule: &[String]) -> RtckResult<bool> {
+    let output = Command::new("iptables")
+        .args(["-w", "-t", table, action, chain])
+        .args(rule)
+        .output()
+        .map_err(|e| {
+            RtckError::new(
+                RtckErrorClass::NetworkError,
+                format!("Fail to run iptables, is it installed on the host? {e}"),
+            )
+            .with_source(e)
+        })?;
+    match output.status.code() {
+        Some(0) => Ok(true),
+        // `-C` reports a missing rule with 1
+        Some(1) if action == "-C" => Ok(false),
+        _ => Err(RtckError::new(
+            RtckErrorClass::NetworkError,
+            format!(
+                "iptables -t {table} {action} {chain} {} failed with {}, {}",
+                rule.join(" "),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        )),
+    }
+}
+
+/// Delete the rule, nothing if it is gone already
+pub(super) fn remove_rule(table: &str, chain: &str, rule: &[String]) -> RtckResult<()> {
+    if iptables(table, "-C", chain, rule)? {
+        iptables(table, "-D", chain, rule)?;
+    }
+    Ok(())
+}
+
+/// Rules giving the guests behind `bridge` outbound connectivity through
+/// `egress_iface`, as (table, chain, rule)
+fn nat_rules(bridge: &str, egress_iface: &str) -> Vec<(&'static str, &'static str, Vec<String>)> {
+    let rule = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+    vec![
+        (
+            "nat",
+            "POSTROUTING",
+            rule(&["-o", egress_iface, "-j", "MASQUERADE"]),
+        ),
+        (
+            "filter",
+            "FORWARD",
+            rule(&["-i", bridge, "-o", egress_iface, "-j", "ACCEPT"]),
+        ),
+        (
+            "filter",
+            "FORWARD",
+            rule(&[
+                "-i",
+                egress_iface,
+                "-o",
+                bridge,
+                "-m",
+                "conntrack",
+                "--ctstate",
+                "RELATED,ESTABLISHED",
+                "-j",
+                "ACCEPT",
+            ]),
+        ),
+    ]
+}
+
+/// Masquerading of the traffic of the guests behind a bridge, managed with
+/// `iptables(8)`. Rules are shared between machines and never deleted on
+/// their own, see `rollback`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nat {
+    bridge: String,
+    egress_iface: String,
+    added: Vec<NetworkResource>,
+}
+
+impl Nat {
+    /// Make sure packets are forwarded between `bridge` and `egress_iface`
+    /// and masqueraded on the way out, adding the rules that are missing
+    pub fn ensure<S: Into<String>, T: Into<String>>(
+        bridge: S,
+        egress_iface: T,
+    ) -> RtckResult<Self> {
+        let (bridge, egress_iface) = (bridge.into(), egress_iface.into());
+        let mut nat = Self {
+            bridge,
+            egress_iface,
+            added: Vec::new(),
+        };
+        for (table, chain, rule) in nat_rules(&nat.bridge, &nat.egress_iface) {
+            let added = iptables(table, "-C", chain, &rule)
+                .and_then(|present| Ok(!present && iptables(table, "-A", chain, &rule)?));
+            match added {
+                Ok(true) => nat.added.push(NetworkResource::IptablesRule {
+                    table: table.into(),
+                    chain: chain.into(),
+                    rule,
+                }),
+                Ok(false) => (),
+                Err(e) => {
+                    // Leave nothing half done
+                    for resource in nat.added.iter().rev() {
+                        let _ = resource.remove();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        if std::fs::read_to_string(IP_FORWARD)?.trim() != "1" {
+            std::fs::write(IP_FORWARD, "1")?;
+        }
+        Ok(nat)
+    }
+
+    pub fn bridge(&self) -> &str {
+        &self.bridge
+    }
+
+    pub fn egress_iface(&self) -> &str {
+        &self.egress_iface
+    }
+
+    /// What undoes `ensure`, deleting the rules it added, to be removed in
+    /// reverse order
+    pub fn rollback(&self) -> Vec<NetworkResource> {
+        self.added.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{nat_rules, Nat};
+
+    #[test]
+    fn test_nat() {
+        let rules = nat_rules("rtbr0", "eth0");
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].0, "nat");
+        assert_eq!(rules[0].2.join(" "), "-o eth0 -j MASQUERADE");
+        assert_eq!(rules[1].2.join(" "), "-i rtbr0 -o eth0 -j ACCEPT");
+
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        if !super::super::tap::has_net_admin(&status) {
+            return;
+        }
+        let egress = format!("rtcknat{}", std::process::id() % 100_000);
+        match Nat::ensure("rtck-nat-br", &egress) {
+            Ok(nat) => {
+                assert_eq!(nat.rollback().len(), 3);
+                // Idempotent, nothing to undo the second time
+                let again = Nat::ensure("rtck-nat-br", &egress).unwrap();
+                assert!(again.rollback().is_empty());
+                for resource in nat.rollback().iter().rev() {
+                    resource.remove().unwrap();
+                }
+            }
+            Err(e) => assert!(e.to_string().contains("iptables"), "{e}"),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+use super::{ip, NetworkResource};
+
+/// Linux refuses interface names longer than this
+pub const MAX_IFNAME_LENGTH: usize = 15;
+
+/// Bit of `CAP_NET_ADMIN` in the capability sets
+const CAP_NET_ADMIN: u32 = 12;
+
+/// Whether the effective capabilities in `status`, the content of
+/// `/proc/<pid>/status`, include `CAP_NET_ADMIN`
+pub(super) fn has_net_admin(status: &str) -> bool {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_NET_ADMIN) != 0)
+}
+
+/// Error of rustcracker lacking the privileges to manage `name`
+fn insufficient_privileges(name: &str) -> RtckError {
+    RtckError::new(
+        RtckErrorClass::NetworkError,
+        format!("Insufficient privileges to manage tap device {name}, CAP_NET_ADMIN is required"),
+    )
+}
+
+/// Tap device created on the host for a machine, deleted when dropped
+/// unless handed over with `into_resource`. Managed with `ip(8)`.
+#[derive(Debug)]
+pub struct TapDevice {
+    name: String,
+    deleted: bool,
+}
+
+impl TapDevice {
+    /// Create the tap device `name`, usable by firecracker running as
+    /// `owner_uid` and `owner_gid`
+    pub fn create<S: Into<String>>(name: S, owner_uid: u32, owner_gid: u32) -> RtckResult<Self> {
+        let name = name.into();
+        if name.is_empty() || name.len() > MAX_IFNAME_LENGTH || name.contains(['/', ' ']) {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Invalid tap device name {name:?}"),
+            ));
+        }
+        let status = std::fs::read_to_string("/proc/self/status")?;
+        if !has_net_admin(&status) {
+            return Err(insufficient_privileges(&name));
+        }
+
+        let (uid, gid) = (owner_uid.to_string(), owner_gid.to_string());
+        ip(&[
+            "tuntap", "add", "dev", &name, "mode", "tap", "user", &uid, "group", &gid,
+        ])?;
+        let tap = Self {
+            name,
+            deleted: false,
+        };
+        tap.set_up()?;
+        Ok(tap)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Assign `addr`/`prefix_len` to the device on the host
+    pub fn add_address(&self, addr: IpAddr, prefix_len: u8) -> RtckResult<()> {
+        self.ip(&[
+            "addr",
+            "add",
+            &format!("{addr}/{prefix_len}"),
+            "dev",
+            &self.name,
+        ])
+    }
+
+    /// Attach the device to the existing `bridge`
+    pub fn attach_to_bridge(&self, bridge: &str) -> RtckResult<()> {
+        self.ip(&["link", "set", "dev", &self.name, "master", bridge])
+    }
+
+    /// Bring the device up
+    pub fn set_up(&self) -> RtckResult<()> {
+        self.ip(&["link", "set", "dev", &self.name, "up"])
+    }
+
+    /// Delete the device now
+    pub fn delete(mut self) -> RtckResult<()> {
+        self.deleted = true;
+        NetworkResource::Tap(self.name.clone()).remove()
+    }
+
+    /// Keep the device, handing it over to be deleted along with a machine,
+    /// see `Machine::clear_network_on_delete`
+    pub fn into_resource(mut self) -> NetworkResource {
+        self.deleted = true;
+        NetworkResource::Tap(std::mem::take(&mut self.name))
+    }
+
+    fn ip(&self, args: &[&str]) -> RtckResult<()> {
+        ip(args).map_err(|e| {
+            if e.to_string().contains("Operation not permitted") {
+                insufficient_privileges(&self.name)
+            } else {
+                e
+            }
+        })
+    }
+}
+
+impl Drop for TapDevice {
+    fn drop(&mut self) {
+        if self.deleted {
+            return;
+        }
+        if let Err(e) = NetworkResource::Tap(self.name.clone()).remove() {
+            log::error!("[TapDevice::drop fail to delete {}, {e}]", self.name);
+        }
+    }
+}
+
+/// Addresses of a point-to-point link between the host and a guest in an
+/// IPv4 subnet such as `172.16.0.0/30`: the host takes the first address,
+/// also the gateway of the guest, and the guest the second one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapSubnet {
+    pub host: Ipv4Addr,
+    pub guest: Ipv4Addr,
+    pub prefix_len: u8,
+}
+
+impl std::str::FromStr for TapSubnet {
+    type Err = RtckError;
+
+    fn from_str(subnet: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| {
+            RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Invalid tap subnet {subnet:?}, {reason}"),
+            )
+        };
+        let (addr, prefix_len) = subnet
+            .split_once('/')
+            .ok_or_else(|| invalid("expecting <address>/<prefix length>"))?;
+        let addr: Ipv4Addr = addr.parse().map_err(|_| invalid("not an IPv4 address"))?;
+        let prefix_len: u8 = prefix_len
+            .parse()
+            .map_err(|_| invalid("malformed prefix length"))?;
+        if prefix_len > 30 {
+            return Err(invalid("no room for two hosts"));
+        }
+        let network = u32::from(addr) & (u32::MAX << (32 - prefix_len));
+        Ok(Self {
+            host: Ipv4Addr::from(network + 1),
+            guest: Ipv4Addr::from(network + 2),
+            prefix_len,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::{has_net_admin, TapDevice, TapSubnet};
+
+    #[test]
+    fn test_tap_subnet() {
+        let subnet: TapSubnet = "172.16.0.9/24".parse().unwrap();
+        assert_eq!(subnet.host, Ipv4Addr::new(172, 16, 0, 1));
+        assert_eq!(subnet.guest, Ipv4Addr::new(172, 16, 0, 2));
+        assert_eq!(subnet.prefix_len, 24);
+
+        assert!("172.16.0.0/31".parse::<TapSubnet>().is_err());
+        assert!("172.16.0.0".parse::<TapSubnet>().is_err());
+        assert!("fc00::/64".parse::<TapSubnet>().is_err());
+    }
+
+    #[test]
+    fn test_tap_device() {
+        // Capabilities of root, and of a user
+        assert!(has_net_admin("Name:\tfoo\nCapEff:\t000001ffffffffff\n"));
+        assert!(!has_net_admin("Name:\tfoo\nCapEff:\t0000000000000000\n"));
+
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let name = format!("rtck{}", std::process::id() % 100_000);
+        if !has_net_admin(&status) {
+            let e = TapDevice::create(&name, 0, 0).unwrap_err();
+            assert!(e.to_string().contains("Insufficient privileges"), "{e}");
+            return;
+        }
+        if !std::path::Path::new("/dev/net/tun").exists() {
+            eprintln!("no /dev/net/tun, skipping");
+            return;
+        }
+
+        let sys = std::path::Path::new("/sys/class/net").join(&name);
+        let tap = TapDevice::create(&name, 0, 0).unwrap();
+        assert!(sys.exists());
+        tap.add_address("172.16.0.1".parse().unwrap(), 30).unwrap();
+        assert!(tap.attach_to_bridge("rtck-no-such-bridge").is_err());
+
+        // Deleted once dropped
+        drop(tap);
+        assert!(!sys.exists());
+    }
+}
@@ -4,6 +4,7 @@ use crate::{command::Command, models::{balloon::Balloon, error::InternalError},
 
 use super::{Operation, Response};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct DescribeBalloonConfigOps {
     data: Empty,
 }
@@ -24,6 +25,7 @@ impl Operation for DescribeBalloonConfigOps {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct DescribeBalloonConfigRes {
     data: Either<Balloon, InternalError>,
 }
@@ -37,10 +39,26 @@ impl DescribeBalloonConfigRes {
         self.data.is_right()
     }
 
+    /// The payload, `None` if firecracker rejected the request
+    pub fn as_succ(&self) -> Option<&Balloon> {
+        self.data.as_ref().left()
+    }
+
+    /// The fault, `None` if firecracker accepted the request
+    pub fn as_err(&self) -> Option<&InternalError> {
+        self.data.as_ref().right()
+    }
+
+    pub fn into_result(self) -> Result<Balloon, InternalError> {
+        self.data.either(Ok, Err)
+    }
+
+    #[deprecated(note = "panics on a rejected request, use `as_succ` or `into_result`")]
     pub fn succ(&self) -> &Balloon {
         self.data.as_ref().left().expect("Response is InternalError")
     }
 
+    #[deprecated(note = "panics on an accepted request, use `as_err` or `into_result`")]
     pub fn err(&self) -> &InternalError {
         self.data.as_ref().right().expect("Response is successful")
     }
@@ -60,11 +78,15 @@ impl Response for DescribeBalloonConfigRes {
     fn blank() -> Self {
         Self {
             data: Either::Right(InternalError {
-                fault_message: "Rustcracker: initial empty response".into(),
+                fault_message: super::NOT_RECEIVED.into(),
             }),
         }
     }
 
+    fn is_received(&self) -> bool {
+        !matches!(&self.data, Either::Right(e) if e.fault_message == super::NOT_RECEIVED)
+    }
+
     fn decode(res: &crate::micro_http::HttpResponse) -> crate::RtckResult<Self> {
         if res.is_fine() {
             Ok(Self {
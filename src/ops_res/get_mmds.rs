@@ -1,9 +1,14 @@
 use either::Either;
 
-use crate::{command::Command, models::error::InternalError, ser::Empty};
+use crate::{
+    command::Command,
+    models::{error::InternalError, mmds_config::MmdsContents},
+    ser::Empty,
+};
 
 use super::{Operation, Response};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct GetMmdsOps {
     data: Empty,
 }
@@ -24,8 +29,9 @@ impl Operation for GetMmdsOps {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct GetMmdsRes {
-    data: Either<String, InternalError>,
+    data: Either<MmdsContents, InternalError>,
 }
 
 impl GetMmdsRes {
@@ -37,13 +43,46 @@ impl GetMmdsRes {
         self.data.is_right()
     }
 
-    pub fn succ(&self) -> &String {
+    /// The payload, `None` if firecracker rejected the request
+    pub fn as_succ(&self) -> Option<&MmdsContents> {
+        self.data.as_ref().left()
+    }
+
+    /// The fault, `None` if firecracker accepted the request
+    pub fn as_err(&self) -> Option<&InternalError> {
+        self.data.as_ref().right()
+    }
+
+    pub fn into_result(self) -> Result<MmdsContents, InternalError> {
+        self.data.either(Ok, Err)
+    }
+
+    #[deprecated(note = "panics on a rejected request, use `as_succ` or `into_result`")]
+    pub fn succ(&self) -> &MmdsContents {
         self.data.as_ref().left().expect("Response is InternalError")
     }
 
+    #[deprecated(note = "panics on an accepted request, use `as_err` or `into_result`")]
     pub fn err(&self) -> &InternalError {
         self.data.as_ref().right().expect("Response is successful")
     }
+
+    /// Decode the response straight from the body of the stream, without
+    /// buffering the whole MMDS contents in memory first.
+    pub fn decode_streaming<R: std::io::Read>(
+        head: &crate::micro_http::HttpHead,
+        body: R,
+    ) -> crate::RtckResult<Self> {
+        if head.status().is_success() {
+            Ok(Self {
+                data: either::Left(serde_json::from_reader(body)?),
+            })
+        } else {
+            Ok(Self {
+                data: either::Right(serde_json::from_reader(body)?),
+            })
+        }
+    }
 }
 
 impl Response for GetMmdsRes {
@@ -60,11 +99,15 @@ impl Response for GetMmdsRes {
     fn blank() -> Self {
         Self {
             data: Either::Right(InternalError {
-                fault_message: "Rustcracker: initial empty response".into(),
+                fault_message: super::NOT_RECEIVED.into(),
             }),
         }
     }
 
+    fn is_received(&self) -> bool {
+        !matches!(&self.data, Either::Right(e) if e.fault_message == super::NOT_RECEIVED)
+    }
+
     fn decode(res: &crate::micro_http::HttpResponse) -> crate::RtckResult<Self> {
         if res.is_fine() {
             Ok(Self {
@@ -6,11 +6,29 @@ pub trait Operation {
     fn encode(&self) -> Command;
 }
 
+/// Fault message of a blank response, i.e. one not received from firecracker yet.
+pub const NOT_RECEIVED: &str = "Rustcracker: initial empty response";
+
+/// Response of an operation, either the successful payload or an `InternalError`.
+/// Every response derives `Debug`, `Clone` and `PartialEq`:
+///
+/// ```
+/// use rustcracker::ops_res::{put_logger::PutLoggerRes, Response};
+///
+/// let res = PutLoggerRes::blank();
+/// assert_eq!(res.clone(), PutLoggerRes::blank());
+/// assert!(res.is_err());
+/// assert!(!res.is_received());
+/// ```
 pub trait Response {
     type Data;
     fn is_succ(&self) -> bool;
     fn is_err(&self) -> bool;
     fn blank() -> Self where Self: Sized;
+    /// Whether the response was received, `false` while it is still `blank()`.
+    fn is_received(&self) -> bool;
+    /// Decode by status code: 2xx is a success, anything else an `InternalError`.
+    /// Never guess from the body, error bodies may deserialize as success payloads.
     fn decode(res: &HttpResponse) -> RtckResult<Self> where Self: Sized;
 }
 
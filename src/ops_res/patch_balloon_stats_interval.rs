@@ -8,6 +8,7 @@ use crate::{
 
 use super::{Operation, Response};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct PatchBalloonStatsIntervalOps {
     data: BalloonStatsUpdate,
 }
@@ -28,6 +29,7 @@ impl Operation for PatchBalloonStatsIntervalOps {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct PatchBalloonStatsIntervalRes {
     data: Either<Empty, InternalError>,
 }
@@ -41,10 +43,26 @@ impl PatchBalloonStatsIntervalRes {
         self.data.is_right()
     }
 
+    /// The payload, `None` if firecracker rejected the request
+    pub fn as_succ(&self) -> Option<&Empty> {
+        self.data.as_ref().left()
+    }
+
+    /// The fault, `None` if firecracker accepted the request
+    pub fn as_err(&self) -> Option<&InternalError> {
+        self.data.as_ref().right()
+    }
+
+    pub fn into_result(self) -> Result<Empty, InternalError> {
+        self.data.either(Ok, Err)
+    }
+
+    #[deprecated(note = "panics on a rejected request, use `as_succ` or `into_result`")]
     pub fn succ(&self) -> &Empty {
         self.data.as_ref().left().expect("Response is InternalError")
     }
 
+    #[deprecated(note = "panics on an accepted request, use `as_err` or `into_result`")]
     pub fn err(&self) -> &InternalError {
         self.data.as_ref().right().expect("Response is successful")
     }
@@ -64,15 +82,20 @@ impl Response for PatchBalloonStatsIntervalRes {
     fn blank() -> Self {
         Self {
             data: Either::Right(InternalError {
-                fault_message: "Rustcracker: initial empty response".into(),
+                fault_message: super::NOT_RECEIVED.into(),
             }),
         }
     }
 
+    fn is_received(&self) -> bool {
+        !matches!(&self.data, Either::Right(e) if e.fault_message == super::NOT_RECEIVED)
+    }
+
     fn decode(res: &crate::micro_http::HttpResponse) -> crate::RtckResult<Self> {
         if res.is_fine() {
+            // Firecracker answers 204 without body on success
             Ok(Self {
-                data: either::Left(serde_json::from_slice(res.body().as_bytes())?),
+                data: either::Left(Empty {}),
             })
         } else {
             Ok(Self {
@@ -8,6 +8,7 @@ use crate::{
 
 use super::{Operation, Response};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct PatchGuestNetworkInterfaceByIdOps {
     data: PartialNetworkInterface,
 }
@@ -20,7 +21,7 @@ impl PatchGuestNetworkInterfaceByIdOps {
 
 impl Operation for PatchGuestNetworkInterfaceByIdOps {
     fn encode(&self) -> crate::command::Command {
-        let iface_id = &self.data.iface_id;
+        let iface_id = crate::micro_http::encode_path_segment(&self.data.iface_id);
         Command {
             method: crate::micro_http::HttpMethod::PATCH,
             url: format!("/network-interfaces/{iface_id}"),
@@ -29,6 +30,7 @@ impl Operation for PatchGuestNetworkInterfaceByIdOps {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct PatchGuestNetworkInterfaceByIdRes {
     data: Either<Empty, InternalError>,
 }
@@ -42,10 +44,26 @@ impl PatchGuestNetworkInterfaceByIdRes {
         self.data.is_right()
     }
 
+    /// The payload, `None` if firecracker rejected the request
+    pub fn as_succ(&self) -> Option<&Empty> {
+        self.data.as_ref().left()
+    }
+
+    /// The fault, `None` if firecracker accepted the request
+    pub fn as_err(&self) -> Option<&InternalError> {
+        self.data.as_ref().right()
+    }
+
+    pub fn into_result(self) -> Result<Empty, InternalError> {
+        self.data.either(Ok, Err)
+    }
+
+    #[deprecated(note = "panics on a rejected request, use `as_succ` or `into_result`")]
     pub fn succ(&self) -> &Empty {
         self.data.as_ref().left().expect("Response is InternalError")
     }
 
+    #[deprecated(note = "panics on an accepted request, use `as_err` or `into_result`")]
     pub fn err(&self) -> &InternalError {
         self.data.as_ref().right().expect("Response is successful")
     }
@@ -65,15 +83,20 @@ impl Response for PatchGuestNetworkInterfaceByIdRes {
     fn blank() -> Self {
         Self {
             data: Either::Right(InternalError {
-                fault_message: "Rustcracker: initial empty response".into(),
+                fault_message: super::NOT_RECEIVED.into(),
             }),
         }
     }
 
+    fn is_received(&self) -> bool {
+        !matches!(&self.data, Either::Right(e) if e.fault_message == super::NOT_RECEIVED)
+    }
+
     fn decode(res: &crate::micro_http::HttpResponse) -> crate::RtckResult<Self> {
         if res.is_fine() {
+            // Firecracker answers 204 without body on success
             Ok(Self {
-                data: either::Left(serde_json::from_slice(res.body().as_bytes())?),
+                data: either::Left(Empty {}),
             })
         } else {
             Ok(Self {
@@ -0,0 +1,231 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{micro_http::HttpMethod, RtckError, RtckErrorClass, RtckResult};
+
+/// How the agent retries a request which failed at the connection level,
+/// e.g. reset right after firecracker restarted. Only idempotent requests
+/// are retried, those getting or putting configuration, and never once
+/// firecracker answered, even with an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts in all, the first one included
+    pub max_attempts: u32,
+    /// Delay before the first retry in milliseconds, doubled for each next one
+    pub backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff_ms: 50,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            backoff_ms: backoff.as_millis().try_into().unwrap_or(u64::MAX),
+        }
+    }
+
+    /// Delay before retrying once `attempt`, counted from 1, failed with `e`,
+    /// `None` if the request is not to be retried
+    pub(crate) fn retry_after(&self, attempt: u32, e: &RtckError) -> Option<Duration> {
+        if attempt >= self.max_attempts || !is_connection_error(e) {
+            return None;
+        }
+        Some(self.backoff(attempt))
+    }
+
+    /// Delay before retrying once `attempt`, counted from 1, failed
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64 << (attempt - 1).min(16);
+        Duration::from_millis(self.backoff_ms.saturating_mul(factor))
+    }
+}
+
+/// Run `op` until it succeeds, fails with an error which is not transient
+/// (see `RtckError::is_transient`) or has been attempted as many times as
+/// `policy` allows, returning the last error then
+pub fn retry<T, F: FnMut() -> RtckResult<T>>(policy: &RetryPolicy, mut op: F) -> RtckResult<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if attempt < policy.max_attempts && e.is_transient() => {
+                log::warn!("[retry attempt {attempt} failed, retrying, {e}]");
+                std::thread::sleep(policy.backoff(attempt));
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// `retry` for tokio
+#[cfg(feature = "tokio")]
+pub async fn retry_async<T, F, Fut>(policy: &RetryPolicy, mut op: F) -> RtckResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = RtckResult<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Err(e) if attempt < policy.max_attempts && e.is_transient() => {
+                log::warn!("[retry_async attempt {attempt} failed, retrying, {e}]");
+                tokio::time::sleep(policy.backoff(attempt)).await;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Whether the request `method url` may be sent again: reading anything,
+/// or putting configuration as opposed to actions, e.g. `InstanceStart`,
+/// and snapshots
+pub(crate) fn is_idempotent(method: &HttpMethod, url: &str) -> bool {
+    match method {
+        HttpMethod::GET => true,
+        HttpMethod::PUT => !url.starts_with("/actions") && !url.starts_with("/snapshot"),
+        _ => false,
+    }
+}
+
+/// Errors of the connection itself, as opposed to answers of firecracker,
+/// which no retry would change, and to timeouts, after which firecracker
+/// may still carry out the request
+fn is_connection_error(e: &RtckError) -> bool {
+    e.class() == &RtckErrorClass::IoError
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{is_idempotent, retry, retry_async, RetryPolicy};
+    use crate::{
+        micro_http::HttpMethod,
+        models::error::{InternalError, InternalErrorKind},
+        RtckError, RtckErrorClass,
+    };
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(10));
+        let reset = RtckError::new(RtckErrorClass::IoError, "Connection reset by peer");
+        assert_eq!(
+            policy.retry_after(1, &reset),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            policy.retry_after(2, &reset),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(policy.retry_after(3, &reset), None);
+
+        let timeout = RtckError::new(RtckErrorClass::RemoteError, "Request timeout");
+        assert_eq!(policy.retry_after(1, &timeout), None);
+
+        assert!(is_idempotent(&HttpMethod::GET, "/machine-config"));
+        assert!(is_idempotent(&HttpMethod::PUT, "/drives/rootfs"));
+        assert!(!is_idempotent(&HttpMethod::PUT, "/actions"));
+        assert!(!is_idempotent(&HttpMethod::PUT, "/snapshot/load"));
+        assert!(!is_idempotent(&HttpMethod::PATCH, "/vm"));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        use std::io::{Error, ErrorKind};
+
+        for kind in [
+            ErrorKind::WouldBlock,
+            ErrorKind::ConnectionRefused,
+            ErrorKind::ConnectionReset,
+        ] {
+            assert!(
+                RtckError::from(Error::from(kind)).is_transient(),
+                "{kind:?}"
+            );
+        }
+        assert!(!RtckError::from(Error::from(ErrorKind::NotFound)).is_transient());
+        assert!(!RtckError::from(Error::from(ErrorKind::PermissionDenied)).is_transient());
+
+        let fault = |fault_message: &str| {
+            RtckError::remote(
+                "Fail to put drive",
+                &InternalError {
+                    fault_message: fault_message.to_string(),
+                },
+            )
+        };
+        let busy = fault("Open tap device failed: Resource busy (os error 16)");
+        assert_eq!(busy.fault_kind(), Some(InternalErrorKind::Busy));
+        assert!(busy.is_transient());
+        assert!(fault("Internal error while starting microVM").is_transient());
+        assert!(!fault("Invalid block device ID").is_transient());
+
+        assert!(RtckError::timeout("Request timeout").is_transient());
+        // Same class and message, but not known to be a timeout
+        assert!(!RtckError::new(RtckErrorClass::RemoteError, "Request timeout").is_transient());
+        assert!(
+            !RtckError::new(RtckErrorClass::ConfigError, "Kernel image file missing")
+                .is_transient()
+        );
+    }
+
+    #[test]
+    fn test_retry() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let refused =
+            || RtckError::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused));
+
+        let mut attempts = 0;
+        let res = retry(&policy, || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(refused())
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(res.unwrap(), 3);
+
+        // Given up after the last attempt
+        let mut attempts = 0;
+        let res: Result<(), _> = retry(&policy, || {
+            attempts += 1;
+            Err(refused())
+        });
+        assert!(res.unwrap_err().is_transient());
+        assert_eq!(attempts, 3);
+
+        // Permanent errors are not retried
+        let mut attempts = 0;
+        let res: Result<(), _> = retry(&policy, || {
+            attempts += 1;
+            Err(RtckError::new(RtckErrorClass::ConfigError, "Bad config"))
+        });
+        assert!(res.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_async() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let res = retry_async(&policy, || async {
+            match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => Err(RtckError::timeout("Request timeout")),
+                n => Ok(n),
+            }
+        })
+        .await;
+        assert_eq!(res.unwrap(), 1);
+    }
+}
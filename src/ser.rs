@@ -20,7 +20,7 @@ use crate::{
         machine_configuration::MachineConfiguration,
         memory_backend::MemoryBackend,
         metrics::Metrics,
-        mmds_config::{MmdsConfig, MmdsContentsObject},
+        mmds_config::{MmdsConfig, MmdsContents},
         network_interface::NetworkInterface,
         partial_drive::PartialDrive,
         partial_network_interface::PartialNetworkInterface,
@@ -43,7 +43,7 @@ pub(crate) trait Serde {
 }
 
 #[repr(transparent)]
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Empty {}
 
 impl Serde for Empty {
@@ -318,7 +318,7 @@ impl Serde for MmdsConfig {
     }
 }
 
-impl Serde for MmdsContentsObject {
+impl Serde for MmdsContents {
     fn decode<S: AsRef<str>>(line: &S) -> RtckResult<Self>
     where
         Self: Sized,
@@ -447,3 +447,16 @@ impl Serde for Vsock {
         Ok(serde_json::to_string(&self)?)
     }
 }
+
+impl Serde for serde_json::Value {
+    fn decode<S: AsRef<str>>(line: &S) -> RtckResult<Self>
+    where
+        Self: Sized,
+    {
+        Ok(serde_json::from_str(line.as_ref())?)
+    }
+
+    fn encode(&self) -> RtckResult<String> {
+        Ok(serde_json::to_string(&self)?)
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    fs::File,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use crate::{RtckError, RtckErrorClass, RtckResult};
+
+/// Guest memory region described by firecracker when handing over its
+/// userfaultfd, see `SnapshotLoadParams::uffd`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestRegionUffdMapping {
+    /// Start of the region in the address space of firecracker
+    pub base_host_virt_addr: u64,
+    /// Size of the region in bytes
+    pub size: u64,
+    /// Offset of the region in the memory file
+    pub offset: u64,
+    /// Page size in bytes, sent by recent firecracker releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<u64>,
+    /// Page size in KiB, sent by older firecracker releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_size_kib: Option<u64>,
+}
+
+impl GuestRegionUffdMapping {
+    const DEFAULT_PAGE_SIZE: u64 = 4096;
+
+    fn page_size(&self) -> u64 {
+        self.page_size
+            .or(self.page_size_kib.map(|kib| kib * 1024))
+            .unwrap_or(Self::DEFAULT_PAGE_SIZE)
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        addr >= self.base_host_virt_addr && addr - self.base_host_virt_addr < self.size
+    }
+}
+
+// From <linux/userfaultfd.h>
+const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+const UFFDIO_COPY: u64 = 0xc028_aa03;
+
+#[repr(C)]
+struct UffdioCopy {
+    dst: u64,
+    src: u64,
+    len: u64,
+    mode: u64,
+    copy: i64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct UffdMsg {
+    event: u8,
+    reserved1: u8,
+    reserved2: u16,
+    reserved3: u32,
+    // For page faults: flags, address and thread id
+    arg: [u64; 3],
+}
+
+/// How often the handler checks whether it is shut down, in milliseconds
+const POLL_INTERVAL_MS: i32 = 100;
+
+/// Serves the guest memory of a snapshot to firecracker page by page,
+/// instead of firecracker copying the whole memory file. The handler runs
+/// in a background thread until firecracker exits or the handler is shut
+/// down (or dropped).
+#[derive(Debug)]
+pub struct UffdHandler {
+    uds_path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<RtckResult<()>>>,
+}
+
+impl UffdHandler {
+    /// Listen on `uds_path` and serve the memory file `mem_file` to the
+    /// firecracker connecting to it
+    pub fn serve(mem_file: PathBuf, uds_path: PathBuf) -> RtckResult<Self> {
+        let file = File::open(mem_file)?;
+        // The memory file of a snapshot is not expected to change under us
+        let mem = unsafe { Mmap::map(&file)? };
+        Self::serve_mapping(Arc::new(mem), uds_path)
+    }
+
+    /// Same as `serve`, sharing a mapping of the memory file between the
+    /// machines restored from the same snapshot
+    pub fn serve_mapping(mem: Arc<Mmap>, uds_path: PathBuf) -> RtckResult<Self> {
+        let listener = UnixListener::bind(&uds_path)?;
+        listener.set_nonblocking(true)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_peer = Arc::clone(&stop);
+        let thread = std::thread::spawn(move || {
+            let Some(stream) = accept(&listener, &stop_peer)? else {
+                return Ok(());
+            };
+            let (mappings, uffd) = receive_uffd(&stream)?;
+            serve_faults(&mem, &mappings, &uffd, &stream, &stop_peer)
+        });
+
+        Ok(Self {
+            uds_path,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Path of the socket firecracker connects to
+    pub fn uds_path(&self) -> &Path {
+        &self.uds_path
+    }
+
+    /// Whether the handler stopped, e.g. since firecracker exited
+    pub fn is_finished(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_none_or(|thread| thread.is_finished())
+    }
+
+    /// Stop serving, reporting whether the handler failed meanwhile
+    pub fn shutdown(mut self) -> RtckResult<()> {
+        self.stop_thread()
+    }
+
+    fn stop_thread(&mut self) -> RtckResult<()> {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = std::fs::remove_file(&self.uds_path);
+        match self.thread.take() {
+            None => Ok(()),
+            Some(thread) => thread.join().map_err(|_| {
+                RtckError::new(RtckErrorClass::GenericError, "Userfaultfd handler panicked")
+            })?,
+        }
+    }
+}
+
+impl Drop for UffdHandler {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop_thread() {
+            log::error!("[UffdHandler::drop handler failed, error = {e}]");
+        }
+    }
+}
+
+/// Wait for firecracker to connect, unless shut down meanwhile
+fn accept(listener: &UnixListener, stop: &AtomicBool) -> RtckResult<Option<UnixStream>> {
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                return Ok(Some(stream));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if stop.load(Ordering::SeqCst) {
+                    return Ok(None);
+                }
+                std::thread::sleep(std::time::Duration::from_millis(POLL_INTERVAL_MS as u64));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Receive the memory mappings of firecracker, along with its userfaultfd
+/// passed as SCM_RIGHTS ancillary data
+fn receive_uffd(stream: &UnixStream) -> RtckResult<(Vec<GuestRegionUffdMapping>, OwnedFd)> {
+    let mut buf = vec![0u8; 1 << 16];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    // Room for a few descriptors, aligned as a cmsghdr
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    let len = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    if len < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let mut uffd = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let fd = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+                uffd = Some(OwnedFd::from_raw_fd(fd));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    let uffd = uffd.ok_or(RtckError::new(
+        RtckErrorClass::RemoteError,
+        "No userfaultfd received from firecracker",
+    ))?;
+    let mappings = serde_json::from_slice(&buf[..len as usize])?;
+    Ok((mappings, uffd))
+}
+
+/// Resolve page faults until firecracker exits, which closes `stream`
+fn serve_faults(
+    mem: &Mmap,
+    mappings: &[GuestRegionUffdMapping],
+    uffd: &OwnedFd,
+    stream: &UnixStream,
+    stop: &AtomicBool,
+) -> RtckResult<()> {
+    let mut fds = [
+        libc::pollfd {
+            fd: uffd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        // Firecracker sends nothing more, so this only wakes up once closed
+        libc::pollfd {
+            fd: stream.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    while !stop.load(Ordering::SeqCst) {
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, POLL_INTERVAL_MS) } < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e.into());
+        }
+        if fds[1].revents != 0 {
+            return Ok(());
+        }
+        if fds[0].revents & libc::POLLIN != 0 {
+            while let Some(msg) = read_msg(uffd)? {
+                if msg.event == UFFD_EVENT_PAGEFAULT {
+                    copy_page(mem, mappings, uffd, msg.arg[1])?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Next event of the (non-blocking) userfaultfd, if any
+fn read_msg(uffd: &OwnedFd) -> RtckResult<Option<UffdMsg>> {
+    let mut msg = UffdMsg::default();
+    let size = std::mem::size_of::<UffdMsg>();
+    let len = unsafe { libc::read(uffd.as_raw_fd(), (&mut msg as *mut UffdMsg).cast(), size) };
+    if len < 0 {
+        let e = std::io::Error::last_os_error();
+        if e.kind() == std::io::ErrorKind::WouldBlock {
+            return Ok(None);
+        }
+        return Err(e.into());
+    }
+    if len as usize != size {
+        return Err(RtckError::new(
+            RtckErrorClass::RemoteError,
+            "Truncated userfaultfd event",
+        ));
+    }
+    Ok(Some(msg))
+}
+
+/// Copy the page of the memory file backing `addr` into the guest
+fn copy_page(
+    mem: &Mmap,
+    mappings: &[GuestRegionUffdMapping],
+    uffd: &OwnedFd,
+    addr: u64,
+) -> RtckResult<()> {
+    let region = mappings
+        .iter()
+        .find(|region| region.contains(addr))
+        .ok_or(RtckError::new(
+            RtckErrorClass::RemoteError,
+            format!("Page fault at {addr:#x} outside of the guest memory"),
+        ))?;
+    let page_size = region.page_size();
+    let dst = addr & !(page_size - 1);
+    let offset = region.offset + (dst - region.base_host_virt_addr);
+    let src = mem
+        .get(offset as usize..(offset + page_size) as usize)
+        .ok_or(RtckError::new(
+            RtckErrorClass::RemoteError,
+            format!("Page fault at {addr:#x} beyond the memory file"),
+        ))?;
+
+    let mut copy = UffdioCopy {
+        dst,
+        src: src.as_ptr() as u64,
+        len: page_size,
+        mode: 0,
+        copy: 0,
+    };
+    if unsafe { libc::ioctl(uffd.as_raw_fd(), UFFDIO_COPY as _, &mut copy) } < 0 {
+        let e = std::io::Error::last_os_error();
+        // Another vCPU faulted on the same page meanwhile
+        if e.raw_os_error() != Some(libc::EEXIST) {
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::{fd::AsRawFd, unix::net::UnixStream};
+
+    use super::UffdHandler;
+
+    // From <linux/userfaultfd.h>
+    const UFFD_API: u64 = 0xaa;
+    const UFFDIO_API: u64 = 0xc018_aa3f;
+    const UFFDIO_REGISTER: u64 = 0xc020_aa00;
+    const UFFDIO_REGISTER_MODE_MISSING: u64 = 1;
+
+    const PAGE_SIZE: usize = 4096;
+
+    /// Hand `uffd` over to the handler listening on `stream` the way
+    /// firecracker does
+    fn send_uffd(stream: &UnixStream, mappings: &str, uffd: i32) {
+        let mut iov = libc::iovec {
+            iov_base: mappings.as_ptr() as *mut _,
+            iov_len: mappings.len(),
+        };
+        let mut control = [0u64; 8];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(4) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(4) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut i32, uffd);
+            assert!(libc::sendmsg(stream.as_raw_fd(), &msg, 0) > 0);
+        }
+    }
+
+    #[test]
+    fn test_uffd_handler() {
+        let id = uuid::Uuid::new_v4();
+        let mem_file = std::env::temp_dir().join(format!("rtck-uffd-{id}.mem"));
+        let uds_path = std::env::temp_dir().join(format!("rtck-uffd-{id}.sock"));
+        let mut content = vec![1u8; PAGE_SIZE];
+        content.extend(vec![2u8; PAGE_SIZE]);
+        std::fs::write(&mem_file, &content).unwrap();
+        let handler = UffdHandler::serve(mem_file.clone(), uds_path.clone()).unwrap();
+
+        let uffd =
+            unsafe { libc::syscall(libc::SYS_userfaultfd, libc::O_CLOEXEC | libc::O_NONBLOCK) }
+                as i32;
+        let mut api = [UFFD_API, 0, 0];
+        if uffd < 0 || unsafe { libc::ioctl(uffd, UFFDIO_API as _, api.as_mut_ptr()) } < 0 {
+            eprintln!("userfaultfd unavailable, skipping");
+            handler.shutdown().unwrap();
+            std::fs::remove_file(mem_file).unwrap();
+            return;
+        }
+
+        // Guest memory of a pretended firecracker, missing until served
+        let len = 2 * PAGE_SIZE;
+        let region = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(region, libc::MAP_FAILED);
+        let mut register = [region as u64, len as u64, UFFDIO_REGISTER_MODE_MISSING, 0];
+        assert_eq!(
+            unsafe { libc::ioctl(uffd, UFFDIO_REGISTER as _, register.as_mut_ptr()) },
+            0
+        );
+
+        let stream = UnixStream::connect(&uds_path).unwrap();
+        let mappings = format!(
+            r#"[{{"base_host_virt_addr":{},"size":{len},"offset":0,"page_size_kib":4}}]"#,
+            region as u64
+        );
+        send_uffd(&stream, &mappings, uffd);
+
+        // Faults block until served, so read from another thread
+        let addr = region as usize;
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let pages = unsafe { std::slice::from_raw_parts(addr as *const u8, len) };
+            tx.send((pages[PAGE_SIZE + 7], pages[0])).unwrap();
+        });
+        let served = rx
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("page faults not served");
+        assert_eq!(served, (2, 1));
+
+        // Firecracker exiting stops the handler
+        drop(stream);
+        while !handler.is_finished() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        handler.shutdown().unwrap();
+        assert!(!uds_path.exists());
+
+        unsafe {
+            libc::munmap(region, len);
+            libc::close(uffd);
+        }
+        std::fs::remove_file(mem_file).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    models::{drive::Drive, resource_id::MAX_ID_LENGTH},
+    RtckError, RtckErrorClass, RtckResult,
+};
+
+/// File system of an image packed by `pack_dir_to_image`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsKind {
+    /// ext4, built by `mke2fs` of e2fsprogs
+    Ext4,
+    /// FAT, built by `mkfs.vfat` of dosfstools and filled by `mcopy` of mtools
+    Vfat,
+}
+
+impl FsKind {
+    /// Host tools building images of this kind
+    pub fn tools(&self) -> &'static [&'static str] {
+        match self {
+            FsKind::Ext4 => &["mke2fs"],
+            FsKind::Vfat => &["mkfs.vfat", "mcopy"],
+        }
+    }
+
+    /// Whether the host tools building images of this kind are installed
+    pub fn available(&self) -> bool {
+        self.tools().iter().all(|tool| find_tool(tool).is_some())
+    }
+}
+
+/// Images are never smaller than this, leaving room for the metadata
+const MIN_IMAGE_SIZE: u64 = 8 << 20;
+
+/// Directories holding the file system tools, which are often not in the
+/// `PATH` of unprivileged users
+const SBIN_DIRS: &[&str] = &["/usr/local/sbin", "/usr/sbin", "/sbin"];
+
+/// Look `name` up in the `PATH` and the sbin directories
+fn find_tool(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .chain(SBIN_DIRS.iter().map(PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Run `tool` with `args`, reporting its output on failure
+fn run_tool<I, S>(tool: &str, args: I) -> RtckResult<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let bin = find_tool(tool).ok_or(RtckError::new(
+        RtckErrorClass::ConfigError,
+        format!("{tool} not found, is it installed on the host?"),
+    ))?;
+    let output = Command::new(&bin).args(args).output()?;
+    if !output.status.success() {
+        return Err(RtckError::new(
+            RtckErrorClass::IoError,
+            format!(
+                "{tool} failed with {}, {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Bytes taken by the files under `dir`, counting whole blocks
+fn dir_size(dir: &Path) -> RtckResult<u64> {
+    const BLOCK_SIZE: u64 = 4096;
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += BLOCK_SIZE;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE;
+        }
+    }
+    Ok(size)
+}
+
+/// Drive id out of the name of the image, e.g. `config_img` for `config.img`
+fn image_drive_id(out_img: &Path) -> String {
+    let mut id: String = out_img
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(MAX_ID_LENGTH)
+        .collect();
+    if id.is_empty() {
+        id.push_str("image");
+    }
+    id
+}
+
+/// Pack the files under `src_dir` into a new `fs` image at `out_img`, e.g.
+/// to hand configuration over to the guest without the size limit of MMDS,
+/// and return the read-only drive to attach it with. The image is sized
+/// after the files, with some room for the file system itself.
+pub fn pack_dir_to_image<P: AsRef<Path>, Q: AsRef<Path>>(
+    src_dir: P,
+    out_img: Q,
+    fs: FsKind,
+) -> RtckResult<Drive> {
+    let (src_dir, out_img) = (src_dir.as_ref(), out_img.as_ref());
+    if !src_dir.is_dir() {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Source {src_dir:?} of the image is not a directory"),
+        ));
+    }
+    if out_img.exists() {
+        return Err(RtckError::new(
+            RtckErrorClass::ConfigError,
+            format!("Image {out_img:?} already exists"),
+        ));
+    }
+
+    let size = (dir_size(src_dir)? * 5 / 4 + (1 << 20)).max(MIN_IMAGE_SIZE);
+    let size_kib = (size >> 10).to_string();
+    let packed = match fs {
+        FsKind::Ext4 => run_tool(
+            "mke2fs",
+            [
+                OsStr::new("-q"),
+                OsStr::new("-t"),
+                OsStr::new("ext4"),
+                OsStr::new("-d"),
+                src_dir.as_os_str(),
+                out_img.as_os_str(),
+                OsStr::new(&size_kib),
+            ],
+        ),
+        FsKind::Vfat => run_tool(
+            "mkfs.vfat",
+            [OsStr::new("-C"), out_img.as_os_str(), OsStr::new(&size_kib)],
+        )
+        .and_then(|_| {
+            let mut args = vec![
+                OsStr::new("-s").to_os_string(),
+                OsStr::new("-i").to_os_string(),
+                out_img.as_os_str().to_os_string(),
+            ];
+            for entry in std::fs::read_dir(src_dir)? {
+                args.push(entry?.path().into_os_string());
+            }
+            args.push(OsStr::new("::").to_os_string());
+            run_tool("mcopy", args)
+        }),
+    };
+    if let Err(e) = packed {
+        let _ = std::fs::remove_file(out_img);
+        return Err(e);
+    }
+
+    Ok(Drive {
+        drive_id: image_drive_id(out_img),
+        partuuid: None,
+        is_root_device: false,
+        cache_type: None,
+        is_read_only: true,
+        path_on_host: out_img.to_string_lossy().into_owned(),
+        rate_limiter: None,
+        io_engine: None,
+        socket: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::{find_tool, pack_dir_to_image, FsKind};
+
+    #[test]
+    fn test_pack_dir_to_image() {
+        let debugfs = find_tool("debugfs");
+        if !FsKind::Ext4.available() || debugfs.is_none() {
+            eprintln!("e2fsprogs not installed, skipping");
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("rtck-pack-{}", uuid::Uuid::new_v4()));
+        let src = dir.join("src");
+        std::fs::create_dir_all(src.join("etc")).unwrap();
+        std::fs::write(src.join("hello.txt"), "hello guest\n").unwrap();
+        std::fs::write(src.join("etc/app.conf"), "key = value\n").unwrap();
+
+        let img = dir.join("config.img");
+        let drive = pack_dir_to_image(&src, &img, FsKind::Ext4).unwrap();
+        assert_eq!(drive.drive_id, "config_img");
+        assert!(drive.is_read_only);
+        assert!(!drive.is_root_device);
+        assert_eq!(drive.path_on_host, img.to_string_lossy());
+        drive.validate().unwrap();
+
+        // Read the files back from the image
+        let cat = |path: &str| {
+            let output = Command::new(debugfs.as_ref().unwrap())
+                .arg("-R")
+                .arg(format!("cat {path}"))
+                .arg(&img)
+                .output()
+                .unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+        assert_eq!(cat("/hello.txt"), "hello guest\n");
+        assert_eq!(cat("/etc/app.conf"), "key = value\n");
+
+        // Never overwrite an image
+        let e = pack_dir_to_image(&src, &img, FsKind::Ext4).unwrap_err();
+        assert!(e.to_string().contains("already exists"), "{e}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
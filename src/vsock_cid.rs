@@ -0,0 +1,218 @@
+use std::collections::BTreeSet;
+
+use parking_lot::Mutex;
+
+use crate::{
+    config::GlobalConfig,
+    models::{vsock::Vsock, Validate},
+    RtckError, RtckErrorClass, RtckResult,
+};
+
+#[derive(Debug)]
+struct CidState {
+    // Lowest CID never handed out
+    next: u32,
+    // CIDs handed out then released, below `next`
+    free: BTreeSet<u32>,
+    // CIDs in use, allocated or reserved
+    live: BTreeSet<u32>,
+}
+
+/// Vsock guest CIDs are global to the host, two machines sharing one collide.
+/// The allocator hands out CIDs unique among the machines of the process.
+#[derive(Debug)]
+pub struct VsockCidAllocator {
+    state: Mutex<CidState>,
+}
+
+impl Default for VsockCidAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VsockCidAllocator {
+    pub const fn new() -> Self {
+        Self {
+            state: parking_lot::const_mutex(CidState {
+                next: Vsock::MIN_GUEST_CID,
+                free: BTreeSet::new(),
+                live: BTreeSet::new(),
+            }),
+        }
+    }
+
+    /// Allocator shared by every machine of the process
+    pub fn global() -> &'static Self {
+        static GLOBAL: VsockCidAllocator = VsockCidAllocator::new();
+        &GLOBAL
+    }
+
+    /// Hand out the lowest CID not in use
+    pub fn allocate(&self) -> RtckResult<u32> {
+        let mut state = self.state.lock();
+        let cid = match state.free.pop_first() {
+            Some(cid) => cid,
+            None => loop {
+                let cid = state.next;
+                state.next = cid.checked_add(1).ok_or(RtckError::new(
+                    RtckErrorClass::ConfigError,
+                    "Vsock guest CIDs exhausted",
+                ))?;
+                if !state.live.contains(&cid) {
+                    break cid;
+                }
+            },
+        };
+        state.live.insert(cid);
+        Ok(cid)
+    }
+
+    /// Mark `cid`, chosen by the user, as in use
+    pub fn reserve(&self, cid: u32) -> RtckResult<()> {
+        let mut state = self.state.lock();
+        if !state.live.insert(cid) {
+            return Err(RtckError::new(
+                RtckErrorClass::ConfigError,
+                format!("Vsock guest CID {cid} already in use"),
+            ));
+        }
+        state.free.remove(&cid);
+        Ok(())
+    }
+
+    /// Give `cid` back, doing nothing if it is not in use
+    pub fn release(&self, cid: u32) {
+        let mut state = self.state.lock();
+        if state.live.remove(&cid) && cid < state.next {
+            state.free.insert(cid);
+        }
+    }
+
+    /// Claim the CIDs of the vsock devices in `config`, allocating them if
+    /// `auto_cid` is set and reserving the configured ones otherwise
+    pub fn claim(&'static self, config: &mut GlobalConfig) -> RtckResult<CidClaim> {
+        let mut claim = CidClaim {
+            allocator: self,
+            cids: Vec::new(),
+        };
+        let Some(frck_config) = config.frck_config.as_mut() else {
+            return Ok(claim);
+        };
+        let auto_cid = frck_config.auto_cid;
+        for vsock in frck_config.vsock_devices.iter_mut().flatten() {
+            if auto_cid {
+                vsock.guest_cid = self.allocate()?;
+            } else {
+                vsock.validate()?;
+                self.reserve(vsock.guest_cid)?;
+            }
+            claim.cids.push(vsock.guest_cid);
+        }
+        Ok(claim)
+    }
+}
+
+/// CIDs claimed by a machine, released when dropped
+#[derive(Debug)]
+pub struct CidClaim {
+    allocator: &'static VsockCidAllocator,
+    cids: Vec<u32>,
+}
+
+impl CidClaim {
+    /// Claim of no CID at all
+    pub fn empty() -> Self {
+        Self {
+            allocator: VsockCidAllocator::global(),
+            cids: Vec::new(),
+        }
+    }
+
+    pub fn cids(&self) -> &[u32] {
+        &self.cids
+    }
+
+    /// Release the CIDs early, e.g. once the machine is deleted
+    pub fn release(&mut self) {
+        for cid in self.cids.drain(..) {
+            self.allocator.release(cid);
+        }
+    }
+}
+
+impl Drop for CidClaim {
+    fn drop(&mut self) {
+        self.release()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        config::{FirecrackerConfig, GlobalConfig},
+        models::vsock::Vsock,
+    };
+
+    use super::VsockCidAllocator;
+
+    fn vsock_config(guest_cid: u32, auto_cid: bool) -> GlobalConfig {
+        GlobalConfig {
+            frck_config: Some(FirecrackerConfig {
+                vsock_devices: Some(vec![Vsock {
+                    guest_cid,
+                    uds_path: "/tmp/v.sock".into(),
+                    vsock_id: None,
+                }]),
+                ..FirecrackerConfig::default().with_auto_cid(auto_cid)
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn guest_cid(config: &GlobalConfig) -> u32 {
+        config
+            .frck_config
+            .as_ref()
+            .unwrap()
+            .vsock_devices
+            .as_ref()
+            .unwrap()[0]
+            .guest_cid
+    }
+
+    #[test]
+    fn test_auto_cid() {
+        static ALLOCATOR: VsockCidAllocator = VsockCidAllocator::new();
+
+        let mut first = vsock_config(0, true);
+        let mut second = vsock_config(0, true);
+        let first_claim = ALLOCATOR.claim(&mut first).unwrap();
+        let second_claim = ALLOCATOR.claim(&mut second).unwrap();
+        assert_eq!(first_claim.cids(), [3]);
+        assert_eq!(second_claim.cids(), [4]);
+        assert_eq!(guest_cid(&first), 3);
+        assert_eq!(guest_cid(&second), 4);
+
+        // Released CIDs are handed out again
+        drop(first_claim);
+        let mut third = vsock_config(0, true);
+        let _third_claim = ALLOCATOR.claim(&mut third).unwrap();
+        assert_eq!(guest_cid(&third), 3);
+        assert_eq!(ALLOCATOR.allocate().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_cid_collision() {
+        static ALLOCATOR: VsockCidAllocator = VsockCidAllocator::new();
+
+        let mut claim = ALLOCATOR.claim(&mut vsock_config(3, false)).unwrap();
+        assert!(ALLOCATOR.claim(&mut vsock_config(3, false)).is_err());
+        assert!(ALLOCATOR.claim(&mut vsock_config(2, false)).is_err());
+        // Reserved CIDs are skipped by the allocation
+        assert_eq!(ALLOCATOR.allocate().unwrap(), 4);
+
+        claim.release();
+        assert!(ALLOCATOR.claim(&mut vsock_config(3, false)).is_ok());
+    }
+}
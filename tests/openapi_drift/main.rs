@@ -0,0 +1,429 @@
+//! Drift of the hand-written models against the pinned `firecracker.yaml`.
+//!
+//! For every definition of the spec, sample payloads are built from its
+//! schema (every value of its enums in turn, and with only the required
+//! properties) then decoded into the hand-written model and encoded again.
+//! Properties the model rejects, requires, drops, alters or adds are drift.
+//! Known drift is listed in `KNOWN_DRIFT` with the reason it is kept, so that
+//! new drift and fixed drift both fail the test. With the `codegen` feature
+//! the generated models are held to no drift at all.
+//!
+//! The request fixtures of `tests/reqres_fixtures` must also cover every
+//! endpoint of the spec, with bodies the spec knows every key of.
+
+use std::{collections::BTreeSet, path::PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use serde_yaml::Mapping;
+
+type Roundtrip = fn(Value) -> Result<Value, String>;
+
+fn roundtrip<T: DeserializeOwned + Serialize>(value: Value) -> Result<Value, String> {
+    let decoded: T = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    serde_json::to_value(decoded).map_err(|e| e.to_string())
+}
+
+/// Model of every definition of the spec, by path under `models`, which is
+/// also the path of the generated model under `models::generated`
+macro_rules! models {
+    ($($def:literal => $($path:ident)::+;)*) => {
+        const MODELS: &[(&str, Roundtrip)] =
+            &[$(($def, roundtrip::<rustcracker::models::$($path)::+>),)*];
+        #[cfg(feature = "codegen")]
+        const GENERATED: &[(&str, Roundtrip)] =
+            &[$(($def, roundtrip::<rustcracker::models::generated::$($path)::+>),)*];
+    };
+}
+
+models! {
+    "Balloon" => balloon::Balloon;
+    "BalloonUpdate" => balloon_update::BalloonUpdate;
+    "BalloonStats" => balloon_stats::BalloonStatistics;
+    "BalloonStatsUpdate" => balloon_stats_update::BalloonStatsUpdate;
+    "BootSource" => boot_source::BootSource;
+    "CpuTemplate" => cpu_template::CPUTemplateString;
+    "CpuConfig" => cpu_template::CPUConfig;
+    "Drive" => drive::Drive;
+    "EntropyDevice" => entropy_device::EntropyDevice;
+    "Error" => error::InternalError;
+    "FirecrackerVersion" => firecracker_version::FirecrackerVersion;
+    "FullVmConfiguration" => full_vm_configuration::FullVmConfiguration;
+    "InstanceActionInfo" => instance_action_info::InstanceActionInfo;
+    "InstanceInfo" => instance_info::InstanceInfo;
+    "Logger" => logger::Logger;
+    "MachineConfiguration" => machine_configuration::MachineConfiguration;
+    "MemoryBackend" => memory_backend::MemoryBackend;
+    "Metrics" => metrics::Metrics;
+    "MmdsConfig" => mmds_config::MmdsConfig;
+    "MmdsContentsObject" => mmds_config::MmdsContents;
+    "NetworkInterface" => network_interface::NetworkInterface;
+    "PartialDrive" => partial_drive::PartialDrive;
+    "PartialNetworkInterface" => partial_network_interface::PartialNetworkInterface;
+    "RateLimiter" => rate_limiter::RateLimiter;
+    "SnapshotCreateParams" => snapshot_create_params::SnapshotCreateParams;
+    "SnapshotLoadParams" => snapshot_load_params::SnapshotLoadParams;
+    "TokenBucket" => token_bucket::TokenBucket;
+    "Vm" => vm::Vm;
+    "Vsock" => vsock::Vsock;
+}
+
+/// Known drift of the hand-written models, with the reason it is kept
+const KNOWN_DRIFT: &[&str] = &[
+    // Vhost-user-block drives, with a `socket`, leave out the virtio-block
+    // fields since firecracker refuses them
+    "Drive.is_read_only dropped",
+    "Drive.path_on_host dropped",
+    "FullVmConfiguration.drives[].is_read_only dropped",
+    "FullVmConfiguration.drives[].path_on_host dropped",
+    // Firecracker always sends the fault message
+    "Error.fault_message required",
+    // Machines always log to a file, which rustcracker reads back
+    "Logger.log_path required",
+];
+
+struct Spec(serde_yaml::Value);
+
+impl Spec {
+    fn load() -> Self {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("firecracker.yaml");
+        let spec = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Fail to read {}: {e}", path.display()));
+        Spec(serde_yaml::from_str(&spec).expect("Fail to parse firecracker.yaml"))
+    }
+
+    fn definitions(&self) -> &Mapping {
+        self.0["definitions"].as_mapping().unwrap()
+    }
+
+    fn resolve<'a>(&'a self, schema: &'a serde_yaml::Value) -> &'a serde_yaml::Value {
+        match schema.get("$ref").and_then(serde_yaml::Value::as_str) {
+            Some(reference) => {
+                let def = reference.strip_prefix("#/definitions/").unwrap();
+                &self.0["definitions"][def]
+            }
+            None => schema,
+        }
+    }
+
+    /// Sample of `schema`, with the first value of enums and every property
+    /// but the free-form objects, the spec saying nothing of their contents
+    fn sample(&self, schema: &serde_yaml::Value) -> Value {
+        let schema = self.resolve(schema);
+        if let Some(values) = schema.get("enum") {
+            return yaml_to_json(&values[0]);
+        }
+        if let Some(example) = schema.get("example").or_else(|| schema.get("default")) {
+            return yaml_to_json(example);
+        }
+        match schema.get("type").and_then(serde_yaml::Value::as_str) {
+            Some("string") => Value::from("x"),
+            Some("integer") => Value::from(schema["minimum"].as_i64().unwrap_or(1)),
+            Some("number") => Value::from(1.5),
+            Some("boolean") => Value::from(true),
+            Some("array") => Value::Array(vec![self.sample(&schema["items"])]),
+            _ => Value::Object(
+                properties(schema)
+                    .filter(|(_, schema)| !free_form(schema))
+                    .map(|(property, schema)| (property, self.sample(schema)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Samples of definition `def`, by the name they are reported with
+    fn samples(&self, def: &str) -> Vec<(String, Value)> {
+        let schema = &self.0["definitions"][def];
+        let base = self.sample(schema);
+        let mut samples = vec![(def.to_string(), base.clone())];
+        if let Some(values) = schema.get("enum") {
+            for value in values.as_sequence().unwrap().iter().skip(1) {
+                let value = yaml_to_json(value);
+                samples.push((format!("{def}={value}"), value));
+            }
+        }
+        for (property, schema) in properties(schema) {
+            let Some(values) = schema.get("enum") else {
+                continue;
+            };
+            for value in values.as_sequence().unwrap().iter().skip(1) {
+                let value = yaml_to_json(value);
+                let mut sample = base.clone();
+                sample[&property] = value.clone();
+                samples.push((format!("{def}.{property}={value}"), sample));
+            }
+        }
+        samples
+    }
+
+    /// Differences between a sample of `schema` and the model's encoding of
+    /// it, keys the spec knows of being free to appear in the encoding
+    fn compare(
+        &self,
+        schema: &serde_yaml::Value,
+        path: &str,
+        sample: &Value,
+        encoded: &Value,
+        drift: &mut BTreeSet<String>,
+    ) {
+        let schema = self.resolve(schema);
+        match (sample, encoded) {
+            (Value::Object(sample), Value::Object(encoded)) => {
+                for (key, value) in sample {
+                    match encoded.get(key) {
+                        Some(encoded) => self.compare(
+                            &schema["properties"][key.as_str()],
+                            &format!("{path}.{key}"),
+                            value,
+                            encoded,
+                            drift,
+                        ),
+                        None => {
+                            drift.insert(format!("{path}.{key} dropped"));
+                        }
+                    }
+                }
+                for (key, value) in encoded {
+                    let known = schema["properties"].get(key.as_str()).is_some();
+                    if !sample.contains_key(key) && !known && !value.is_null() {
+                        drift.insert(format!("{path}.{key} added"));
+                    }
+                }
+            }
+            (Value::Array(sample), Value::Array(encoded)) if sample.len() == encoded.len() => {
+                for (sample, encoded) in sample.iter().zip(encoded) {
+                    self.compare(
+                        &schema["items"],
+                        &format!("{path}[]"),
+                        sample,
+                        encoded,
+                        drift,
+                    );
+                }
+            }
+            _ if sample == encoded => {}
+            _ => {
+                drift.insert(format!("{path} changed to {encoded}"));
+            }
+        }
+    }
+
+    /// Keys of `value` the spec does not know of
+    fn unknown_keys(
+        &self,
+        schema: &serde_yaml::Value,
+        value: &Value,
+        path: &str,
+        drift: &mut BTreeSet<String>,
+    ) {
+        let schema = self.resolve(schema);
+        match value {
+            Value::Object(object) if schema.get("properties").is_some() => {
+                for (key, value) in object {
+                    match schema["properties"].get(key.as_str()) {
+                        Some(schema) => {
+                            self.unknown_keys(schema, value, &format!("{path}.{key}"), drift)
+                        }
+                        None => {
+                            drift.insert(format!("{path}.{key} not in spec"));
+                        }
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.unknown_keys(&schema["items"], item, &format!("{path}[]"), drift);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn properties(schema: &serde_yaml::Value) -> impl Iterator<Item = (String, &serde_yaml::Value)> {
+    schema
+        .get("properties")
+        .and_then(serde_yaml::Value::as_mapping)
+        .into_iter()
+        .flatten()
+        .map(|(property, schema)| (property.as_str().unwrap().to_string(), schema))
+}
+
+/// Whether `schema` is an object of unspecified properties
+fn free_form(schema: &serde_yaml::Value) -> bool {
+    schema.get("type").and_then(serde_yaml::Value::as_str) == Some("object")
+        && schema.get("properties").is_none()
+}
+
+fn yaml_to_json(value: &serde_yaml::Value) -> Value {
+    serde_json::to_value(value).unwrap()
+}
+
+/// Property named by a serde "missing field" error
+fn missing_field(error: &str) -> Option<&str> {
+    error.strip_prefix("missing field `")?.split('`').next()
+}
+
+fn drift(spec: &Spec, models: &[(&str, Roundtrip)]) -> BTreeSet<String> {
+    let mut drift = BTreeSet::new();
+    for def in spec.definitions().keys() {
+        let def = def.as_str().unwrap();
+        let (_, roundtrip) = models
+            .iter()
+            .find(|(d, _)| *d == def)
+            .unwrap_or_else(|| panic!("No model for definition {def}, add it to `models!`"));
+
+        for (name, sample) in spec.samples(def) {
+            match roundtrip(sample.clone()) {
+                Ok(encoded) => spec.compare(
+                    &spec.0["definitions"][def],
+                    def,
+                    &sample,
+                    &encoded,
+                    &mut drift,
+                ),
+                Err(_) => {
+                    drift.insert(format!("{name} rejected"));
+                }
+            }
+        }
+
+        // Only the required properties, adding back those the model requires
+        let schema = &spec.0["definitions"][def];
+        let Value::Object(full) = spec.sample(schema) else {
+            continue;
+        };
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(serde_yaml::Value::as_sequence)
+            .map(|required| required.iter().map(|r| r.as_str().unwrap()).collect())
+            .unwrap_or_default();
+        let mut sample: Map<String, Value> = full
+            .iter()
+            .filter(|(property, _)| required.contains(&property.as_str()))
+            .map(|(property, value)| (property.clone(), value.clone()))
+            .collect();
+        loop {
+            match roundtrip(Value::Object(sample.clone())) {
+                Ok(_) => break,
+                Err(e) => match missing_field(&e).filter(|field| full.contains_key(*field)) {
+                    Some(field) => {
+                        drift.insert(format!("{def}.{field} required"));
+                        sample.insert(field.to_string(), full[field].clone());
+                    }
+                    None => {
+                        drift.insert(format!("{def} rejected without optional properties"));
+                        break;
+                    }
+                },
+            }
+        }
+    }
+    drift
+}
+
+#[test]
+fn test_models_drift() {
+    let spec = Spec::load();
+    let drift: Vec<String> = drift(&spec, MODELS).into_iter().collect();
+    let mut known: Vec<&str> = KNOWN_DRIFT.to_vec();
+    known.sort();
+    assert_eq!(
+        drift, known,
+        "hand-written models drift from firecracker.yaml, fix them or update KNOWN_DRIFT"
+    );
+}
+
+#[cfg(feature = "codegen")]
+#[test]
+fn test_generated_models_drift() {
+    let spec = Spec::load();
+    assert_eq!(drift(&spec, GENERATED), BTreeSet::new());
+}
+
+/// Method, path and JSON body of a request fixture
+fn read_request(path: &std::path::Path) -> (String, String, Option<Value>) {
+    let request = std::fs::read_to_string(path).unwrap();
+    let (head, body) = request.split_once("\r\n\r\n").unwrap();
+    let mut request_line = head.lines().next().unwrap().split(' ');
+    let method = request_line.next().unwrap().to_string();
+    let target = request_line.next().unwrap().to_string();
+    let body = (!body.is_empty()).then(|| serde_json::from_str(body).unwrap());
+    (method, target, body)
+}
+
+/// Whether `path` is an instance of the path template
+fn matches(template: &str, path: &str) -> bool {
+    let mut template = template.split('/');
+    let mut path = path.split('/');
+    loop {
+        match (template.next(), path.next()) {
+            (None, None) => return true,
+            (Some(t), Some(p)) if t.starts_with('{') && !p.is_empty() => {}
+            (Some(t), Some(p)) if t == p => {}
+            _ => return false,
+        }
+    }
+}
+
+#[test]
+fn test_fixtures_cover_spec() {
+    let spec = Spec::load();
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/reqres_fixtures/fixtures");
+    let mut uncovered: BTreeSet<String> = BTreeSet::new();
+    for (path, operations) in spec.0["paths"].as_mapping().unwrap() {
+        for (method, operation) in operations.as_mapping().unwrap() {
+            uncovered.insert(format!(
+                "{} {} ({})",
+                method.as_str().unwrap().to_ascii_uppercase(),
+                path.as_str().unwrap(),
+                operation["operationId"].as_str().unwrap()
+            ));
+        }
+    }
+
+    let mut drift = BTreeSet::new();
+    let mut entries: Vec<_> = std::fs::read_dir(&fixtures)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "request"))
+        .collect();
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_stem().unwrap().to_str().unwrap();
+        let (method, target, body) = read_request(&entry);
+        let operation = spec.0["paths"]
+            .as_mapping()
+            .unwrap()
+            .iter()
+            .find(|(path, _)| matches(path.as_str().unwrap(), &target))
+            .and_then(|(path, operations)| {
+                let operation = operations.get(method.to_ascii_lowercase())?;
+                uncovered
+                    .retain(|e| !e.starts_with(&format!("{method} {} ", path.as_str().unwrap())));
+                Some(operation)
+            })
+            .unwrap_or_else(|| panic!("Fixture {name}: {method} {target} not in firecracker.yaml"));
+
+        let schema = operation
+            .get("parameters")
+            .and_then(serde_yaml::Value::as_sequence)
+            .into_iter()
+            .flatten()
+            .find(|parameter| parameter["in"] == "body")
+            .map(|parameter| &parameter["schema"]);
+        if let (Some(schema), Some(body)) = (schema, body) {
+            spec.unknown_keys(schema, &body, name, &mut drift);
+        }
+    }
+    assert_eq!(
+        uncovered,
+        BTreeSet::new(),
+        "endpoints without request fixture"
+    );
+    assert_eq!(
+        drift,
+        BTreeSet::new(),
+        "request fixtures with keys unknown to firecracker.yaml"
+    );
+}
@@ -0,0 +1,514 @@
+//! Golden fixtures for every firecracker endpoint.
+//!
+//! For each endpoint `fixtures/` holds the canonical request rustcracker
+//! sends (`<name>.request`) and canned firecracker answers (`<name>.success`,
+//! `<name>.failure`). Run with `RUSTCRACKER_BLESS=1` to rewrite the request
+//! fixtures after an intended change of the wire format.
+//!
+//! To cover a new endpoint, add the three fixture files and a `fixture!` line.
+
+use std::{io::Cursor, path::PathBuf};
+
+use serde_json::json;
+
+use rustcracker::{
+    micro_http::{http_io, Http},
+    models::{
+        balloon::Balloon,
+        balloon_stats::BalloonStatistics,
+        balloon_stats_update::BalloonStatsUpdate,
+        balloon_update::BalloonUpdate,
+        boot_source::BootSource,
+        cpu_template::{CPUConfig, CpuIdModifier, ModifierRegisterName, Modifiers},
+        drive::{CacheType, Drive, IoEngine},
+        entropy_device::EntropyDevice,
+        firecracker_version::FirecrackerVersion,
+        full_vm_configuration::FullVmConfiguration,
+        instance_action_info::{ActionType, InstanceActionInfo},
+        instance_info::{InstanceInfo, State},
+        logger::{LogLevel, Logger},
+        machine_configuration::MachineConfiguration,
+        metrics::Metrics,
+        mmds_config::{MmdsConfig, MmdsContents, Version},
+        network_interface::NetworkInterface,
+        partial_drive::PartialDrive,
+        partial_network_interface::PartialNetworkInterface,
+        snapshot_create_params::{SnapshotCreateParams, SnapshotType},
+        snapshot_load_params::SnapshotLoadParams,
+        vm::{self, Vm},
+        vsock::Vsock,
+    },
+    ops_res::{
+        create_snapshot::*, create_sync_action::*, describe_balloon_config::*,
+        describe_balloon_stats::*, describe_instance::*, get_export_vm_config::*,
+        get_firecracker_version::*, get_machine_configuration::*, get_mmds::*, load_snapshot::*,
+        patch_balloon::*, patch_balloon_stats_interval::*, patch_guest_drive_by_id::*,
+        patch_guest_network_interface_by_id::*, patch_machine_configuration::*, patch_mmds::*,
+        patch_vm::*, put_balloon::*, put_cpu_configuration::*, put_entropy::*,
+        put_guest_boot_source::*, put_guest_drive_by_id::*, put_guest_network_interface_by_id::*,
+        put_guest_vsock::*, put_logger::*, put_machine_configuration::*, put_metrics::*,
+        put_mmds::*, put_mmds_config::*, Operation, Response,
+    },
+    ser::Empty,
+};
+
+fn fixture_path(name: &str, kind: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/reqres_fixtures/fixtures")
+        .join(format!("{name}.{kind}"))
+}
+
+fn read_fixture(name: &str, kind: &str) -> Vec<u8> {
+    let path = fixture_path(name, kind);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Fail to read {}: {e}", path.display()))
+}
+
+fn check_request<O: Operation>(name: &str, ops: &O) {
+    let request = Http::encode(&ops.encode()).expect("Fail to encode request");
+    if std::env::var_os("RUSTCRACKER_BLESS").is_some() {
+        std::fs::write(fixture_path(name, "request"), &request).expect("Fail to bless fixture");
+    }
+    let expected = String::from_utf8(read_fixture(name, "request")).unwrap();
+    assert_eq!(request, expected, "request of {name} differs from fixture");
+}
+
+fn decode<R: Response>(name: &str, kind: &str) -> R {
+    let res = http_io::read_response(&mut Cursor::new(read_fixture(name, kind)))
+        .expect("Fail to read response fixture");
+    R::decode(&res).expect("Fail to decode response fixture")
+}
+
+macro_rules! fixture {
+    ($name:ident, $ops:expr, $res:ty, $succ:expr, $fault:literal) => {
+        #[test]
+        fn $name() {
+            check_request(stringify!($name), &$ops);
+
+            let res: $res = decode(stringify!($name), "success");
+            assert!(res.is_succ());
+            assert_eq!(res.into_result(), Ok($succ));
+
+            let res: $res = decode(stringify!($name), "failure");
+            assert!(res.is_err());
+            assert_eq!(res.as_err().unwrap().fault_message, $fault);
+        }
+    };
+}
+
+fn machine_configuration() -> MachineConfiguration {
+    MachineConfiguration {
+        cpu_template: None,
+        huge_pages: None,
+        ht_enabled: Some(false),
+        mem_size_mib: 256,
+        track_dirty_pages: Some(true),
+        vcpu_count: 2,
+    }
+}
+
+fn mmds_contents() -> MmdsContents {
+    MmdsContents::new(json!({"latest": {"meta-data": {"ami-id": "ami-12345678"}}}))
+}
+
+fn balloon() -> Balloon {
+    Balloon {
+        amount_mib: 64,
+        deflate_on_oom: true,
+        stats_polling_interval_s: Some(1),
+    }
+}
+
+fn boot_source() -> BootSource {
+    BootSource {
+        boot_args: Some("console=ttyS0 reboot=k panic=1 pci=off".into()),
+        initrd_path: None,
+        kernel_image_path: "/var/lib/rustcracker/vmlinux".into(),
+    }
+}
+
+fixture!(
+    create_snapshot,
+    CreateSnapshotOps::new(SnapshotCreateParams {
+        mem_file_path: "/snapshots/mem".into(),
+        snapshot_path: "/snapshots/vmstate".into(),
+        snapshot_type: Some(SnapshotType::Full),
+        version: None,
+    }),
+    CreateSnapshotRes,
+    Empty {},
+    "The requested operation is not supported in current state."
+);
+
+fixture!(
+    create_sync_action,
+    CreateSyncActionOps::new(InstanceActionInfo {
+        action_type: ActionType::InstanceStart,
+    }),
+    CreateSyncActionRes,
+    Empty {},
+    "Cannot start microvm that was already started"
+);
+
+fixture!(
+    describe_balloon_config,
+    DescribeBalloonConfigOps::new(),
+    DescribeBalloonConfigRes,
+    balloon(),
+    "No balloon device found."
+);
+
+fixture!(
+    describe_balloon_stats,
+    DescribeBalloonStatsOps::new(),
+    DescribeBalloonStatsRes,
+    BalloonStatistics {
+        target_pages: 16384,
+        actual_pages: 16384,
+        target_mib: 64,
+        actual_mib: 64,
+        swap_in: Some(0),
+        swap_out: Some(0),
+        major_faults: Some(12),
+        minor_faults: Some(3456),
+        free_memory: Some(123_456_789),
+        total_memory: Some(234_567_890),
+        available_memory: Some(200_000_000),
+        disk_caches: Some(1_234_567),
+        hugetlb_allocations: None,
+        hugetlb_failures: None,
+    },
+    "Statistics for the balloon device are not enabled"
+);
+
+fixture!(
+    describe_instance,
+    DescribeInstanceOps::new(),
+    DescribeInstanceRes,
+    InstanceInfo {
+        app_name: "Firecracker".into(),
+        id: "anonymous-instance".into(),
+        state: State::Running,
+        vmm_version: "1.7.0".into(),
+    },
+    "Internal error"
+);
+
+fixture!(
+    get_export_vm_config,
+    GetExportVmConfigOps::new(),
+    GetExportVmConfigRes,
+    FullVmConfiguration {
+        drives: Some(vec![]),
+        network_interfaces: Some(vec![]),
+        ..FullVmConfiguration::default()
+            .with_boot_source(&boot_source())
+            .with_machine_config(&machine_configuration())
+    },
+    "Internal error"
+);
+
+fixture!(
+    get_firecracker_version,
+    GetFirecrackerVersionOps::new(),
+    GetFirecrackerVersionRes,
+    FirecrackerVersion {
+        firecracker_version: "1.7.0".into(),
+    },
+    "Internal error"
+);
+
+fixture!(
+    get_machine_configuration,
+    GetMachineConfigurationOps::new(),
+    GetMachineConfigurationRes,
+    machine_configuration(),
+    "Internal error"
+);
+
+fixture!(
+    get_mmds,
+    GetMmdsOps::new(),
+    GetMmdsRes,
+    mmds_contents(),
+    "The MMDS data store is not initialized."
+);
+
+fixture!(
+    load_snapshot,
+    LoadSnapshotOps::new(SnapshotLoadParams {
+        enable_diff_snapshots: Some(false),
+        mem_file_path: Some("/snapshots/mem".into()),
+        mem_backend: None,
+        network_overrides: None,
+        resume_vm: Some(true),
+        snapshot_path: "/snapshots/vmstate".into(),
+    }),
+    LoadSnapshotRes,
+    Empty {},
+    "Loading a microVM snapshot not allowed after configuring boot-specific resources."
+);
+
+fixture!(
+    patch_balloon,
+    PatchBalloonOps::new(BalloonUpdate {
+        amount_mib: 128,
+        deflate_on_oom: None,
+    }),
+    PatchBalloonRes,
+    Empty {},
+    "No balloon device found."
+);
+
+fixture!(
+    patch_balloon_stats_interval,
+    PatchBalloonStatsIntervalOps::new(BalloonStatsUpdate {
+        stats_polling_interval_s: 5,
+    }),
+    PatchBalloonStatsIntervalRes,
+    Empty {},
+    "Cannot enable statistics after boot."
+);
+
+fixture!(
+    patch_guest_drive_by_id,
+    PatchGuestDriveByIdOps::new(PartialDrive {
+        drive_id: "scratch".into(),
+        path_on_host: Some("/var/lib/rustcracker/scratch.ext4".into()),
+        rate_limiter: None,
+    }),
+    PatchGuestDriveByIdRes,
+    Empty {},
+    "Invalid block device ID!"
+);
+
+fixture!(
+    patch_guest_network_interface_by_id,
+    PatchGuestNetworkInterfaceByIdOps::new(PartialNetworkInterface {
+        iface_id: "eth0".into(),
+        rx_rate_limiter: None,
+        tx_rate_limiter: None,
+    }),
+    PatchGuestNetworkInterfaceByIdRes,
+    Empty {},
+    "Invalid network interface ID!"
+);
+
+fixture!(
+    patch_machine_configuration,
+    PatchMachineConfigurationOps::new(machine_configuration()),
+    PatchMachineConfigurationRes,
+    Empty {},
+    "The update operation is not allowed after boot."
+);
+
+fixture!(
+    patch_mmds,
+    PatchMmdsOps::new(mmds_contents()),
+    PatchMmdsRes,
+    Empty {},
+    "The MMDS data store is not initialized."
+);
+
+fixture!(
+    patch_vm,
+    PatchVmOps::new(Vm {
+        state: vm::State::Paused,
+    }),
+    PatchVmRes,
+    Empty {},
+    "The requested operation is not supported in current state."
+);
+
+fixture!(
+    put_balloon,
+    PutBalloonOps::new(balloon()),
+    PutBalloonRes,
+    Empty {},
+    "The requested operation is not allowed after boot."
+);
+
+fixture!(
+    put_cpu_configuration,
+    PutCpuConfigurationOps::new(CPUConfig {
+        cpuid_modifiers: vec![CpuIdModifier {
+            leaf: "0x1".into(),
+            subleaf: "0x0".into(),
+            flags: 0,
+            modifiers: vec![Modifiers {
+                register: ModifierRegisterName::ECX,
+                bitmap: "0bxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx0".into(),
+            }],
+        }],
+        msr_modifiers: vec![],
+        reg_modifiers: vec![],
+    }),
+    PutCpuConfigurationRes,
+    Empty {},
+    "The requested operation is not allowed after boot."
+);
+
+fixture!(
+    put_entropy,
+    PutEntropyOps::new(EntropyDevice { rate_limiter: None }),
+    PutEntropyRes,
+    Empty {},
+    "The requested operation is not allowed after boot."
+);
+
+fixture!(
+    put_guest_boot_source,
+    PutGuestBootSourceOps::new(boot_source()),
+    PutGuestBootSourceRes,
+    Empty {},
+    "The kernel file cannot be opened: No such file or directory (os error 2)"
+);
+
+fixture!(
+    put_guest_drive_by_id,
+    PutGuestDriveByIdOps::new(Drive {
+        drive_id: "rootfs".into(),
+        partuuid: None,
+        is_root_device: true,
+        cache_type: Some(CacheType::Unsafe),
+        is_read_only: false,
+        path_on_host: "/var/lib/rustcracker/rootfs.ext4".into(),
+        rate_limiter: None,
+        io_engine: Some(IoEngine::Sync),
+        socket: None,
+    }),
+    PutGuestDriveByIdRes,
+    Empty {},
+    "Unable to create the block device: No such file or directory (os error 2)"
+);
+
+fixture!(
+    put_guest_network_interface_by_id,
+    PutGuestNetworkInterfaceByIdOps::new(NetworkInterface {
+        guest_mac: Some("06:00:AC:10:00:02".into()),
+        host_dev_name: "tap0".into(),
+        iface_id: "eth0".into(),
+        num_queues: None,
+        rx_rate_limiter: None,
+        tx_rate_limiter: None,
+        static_ip: None,
+    }),
+    PutGuestNetworkInterfaceByIdRes,
+    Empty {},
+    "Open tap device failed: Resource busy (os error 16)"
+);
+
+fixture!(
+    put_guest_vsock,
+    PutGuestVsockOps::new(Vsock {
+        guest_cid: 3,
+        uds_path: "/tmp/v.sock".into(),
+        vsock_id: None,
+    }),
+    PutGuestVsockRes,
+    Empty {},
+    "Cannot create backend for vsock device: Address in use"
+);
+
+fixture!(
+    put_logger,
+    PutLoggerOps::new(Logger {
+        level: Some(LogLevel::Info),
+        log_path: "/var/log/firecracker.log".into(),
+        show_level: Some(true),
+        show_log_origin: Some(false),
+        module: None,
+    }),
+    PutLoggerRes,
+    Empty {},
+    "Logger is already configured."
+);
+
+fixture!(
+    put_machine_configuration,
+    PutMachineConfigurationOps::new(machine_configuration()),
+    PutMachineConfigurationRes,
+    Empty {},
+    "The update operation is not allowed after boot."
+);
+
+fixture!(
+    put_metrics,
+    PutMetricsOps::new(Metrics {
+        metrics_path: "/var/log/firecracker.metrics".into(),
+    }),
+    PutMetricsRes,
+    Empty {},
+    "Reinitialization of metrics not allowed."
+);
+
+fixture!(
+    put_mmds,
+    PutMmdsOps::new(mmds_contents()),
+    PutMmdsRes,
+    Empty {},
+    "The MMDS data store is not initialized."
+);
+
+fixture!(
+    put_mmds_config,
+    PutMmdsConfigOps::new(MmdsConfig {
+        version: Some(Version::V2),
+        network_interfaces: vec!["eth0".into()],
+        ipv4_address: None,
+    }),
+    PutMmdsConfigRes,
+    Empty {},
+    "The MMDS could not be configured to version V2: No network interfaces"
+);
+
+/// Every field of `FullVmConfiguration` is optional, so an error body also
+/// deserializes as a (blank) success. Decoding must go by the status code.
+#[test]
+fn error_body_parsing_as_success() {
+    let res = http_io::read_response(&mut Cursor::new(read_fixture(
+        "get_export_vm_config",
+        "failure",
+    )))
+    .unwrap();
+    assert_eq!(
+        serde_json::from_str::<FullVmConfiguration>(res.body()).is_ok(),
+        !cfg!(feature = "strict-decode")
+    );
+
+    let res = GetExportVmConfigRes::decode(&res).unwrap();
+    assert!(res.is_err());
+    assert_eq!(res.as_err().unwrap().fault_message, "Internal error");
+}
+
+/// Body of the success fixture of `name` with a field firecracker may add
+/// in a future release
+fn body_with_unknown_field(name: &str) -> serde_json::Value {
+    let res = http_io::read_response(&mut Cursor::new(read_fixture(name, "success"))).unwrap();
+    let mut body: serde_json::Value = serde_json::from_str(res.body()).unwrap();
+    body["unknown_field"] = json!(true);
+    body
+}
+
+/// Unknown fields of responses are ignored, unless decoding with the
+/// `strict-decode` feature which refuses them.
+#[test]
+fn unknown_response_fields() {
+    fn decodes<T: serde::de::DeserializeOwned>(name: &str) -> bool {
+        serde_json::from_value::<T>(body_with_unknown_field(name)).is_ok()
+    }
+
+    let lenient = !cfg!(feature = "strict-decode");
+    assert_eq!(decodes::<InstanceInfo>("describe_instance"), lenient);
+    assert_eq!(
+        decodes::<FullVmConfiguration>("get_export_vm_config"),
+        lenient
+    );
+    assert_eq!(
+        decodes::<FirecrackerVersion>("get_firecracker_version"),
+        lenient
+    );
+    assert_eq!(
+        decodes::<BalloonStatistics>("describe_balloon_stats"),
+        lenient
+    );
+}